@@ -19,6 +19,11 @@ pub fn embedded_adapter_catalog() -> AdapterCatalog {
             ]),
             prompt_transport: Some(crate::config::PromptTransport::Auto),
             prompt_max_chars: Some(32768),
+            prompt_layout: None,
+            max_output_chars: None,
+            probe_args: None,
+            readonly_guardrail: None,
+            session_id_pattern_validate: None,
             args_template: v(&[
                 "exec",
                 "{% if capabilities.filesystem == 'read-only' %}--sandbox{% endif %}",
@@ -61,6 +66,11 @@ pub fn embedded_adapter_catalog() -> AdapterCatalog {
             ]),
             prompt_transport: Some(crate::config::PromptTransport::Auto),
             prompt_max_chars: Some(32768),
+            prompt_layout: None,
+            max_output_chars: None,
+            probe_args: None,
+            readonly_guardrail: None,
+            session_id_pattern_validate: None,
             args_template: v(&[
                 "--print",
                 "{% if prompt_transport != 'stdin' %}{{ prompt }}{% endif %}",
@@ -90,6 +100,11 @@ pub fn embedded_adapter_catalog() -> AdapterCatalog {
             ]),
             prompt_transport: Some(crate::config::PromptTransport::Auto),
             prompt_max_chars: Some(32768),
+            prompt_layout: None,
+            max_output_chars: None,
+            probe_args: None,
+            readonly_guardrail: None,
+            session_id_pattern_validate: None,
             args_template: v(&[
                 "--output-format",
                 "json",
@@ -119,6 +134,11 @@ pub fn embedded_adapter_catalog() -> AdapterCatalog {
             filesystem_capabilities: Some(vec![FilesystemCapability::ReadWrite]),
             prompt_transport: Some(crate::config::PromptTransport::Auto),
             prompt_max_chars: Some(32768),
+            prompt_layout: None,
+            max_output_chars: None,
+            probe_args: None,
+            readonly_guardrail: None,
+            session_id_pattern_validate: None,
             args_template: v(&[
                 "run",
                 "{% if model != 'default' %}-m{% endif %}",
@@ -144,6 +164,11 @@ pub fn embedded_adapter_catalog() -> AdapterCatalog {
             filesystem_capabilities: Some(vec![FilesystemCapability::ReadWrite]),
             prompt_transport: Some(crate::config::PromptTransport::Auto),
             prompt_max_chars: Some(32768),
+            prompt_layout: None,
+            max_output_chars: None,
+            probe_args: None,
+            readonly_guardrail: None,
+            session_id_pattern_validate: None,
             args_template: v(&[
                 "--print",
                 "--thinking",
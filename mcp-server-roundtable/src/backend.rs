@@ -4,14 +4,17 @@ use crate::config::{
 };
 use anyhow::{anyhow, Context, Result};
 use minijinja::{context, Environment};
-use regex::Regex;
+use regex::RegexBuilder;
 use serde_json::Value;
 use std::collections::{BTreeMap, BTreeSet};
 use std::path::{Path, PathBuf};
-use std::process::Stdio;
-use tokio::io::AsyncWriteExt;
-use tokio::process::Command;
-use tokio::time::{timeout, Duration};
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::{timeout, timeout_at, Duration, Instant};
 
 #[derive(Debug, Clone)]
 pub struct GenericOptions {
@@ -26,35 +29,260 @@ pub struct GenericOptions {
     pub capabilities: Capabilities,
     pub fallback_error_patterns: Vec<String>,
     pub timeout_secs: u64,
+    /// Requested number of candidate answers, exposed to templates as `{{ n }}`.
+    pub n: Option<u32>,
+    /// Config `client_id`: templated as `{{ three_client_id }}` and set as `THREE_CLIENT_ID`.
+    pub client_id: Option<String>,
+    /// Role `env`: set on the child process after `${VAR}` interpolation. Never echoed back.
+    pub env: BTreeMap<String, String>,
+    /// When set and the parser is `json_stream`, each message is sent here as its stdout line
+    /// arrives (the final result is unchanged).
+    pub progress: Option<UnboundedSender<String>>,
+    /// Extra read roots from the call (already canonicalized), merged with the ones detected in
+    /// the prompt into `{{ include_directories }}`.
+    pub include_directories: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct GenericResult {
+    /// Executable that was spawned (`CODEX_BIN`-style override or the backend's default name).
+    pub command: String,
+    /// Args it ran with, with `${VAR}` references left as written (the prompt is absent when it
+    /// went over stdin).
+    pub args: Vec<String>,
+    /// `arg` or `stdin`.
+    pub prompt_transport: &'static str,
     pub session_id: String,
+    /// Session id the parser extracted but `session_id_pattern_validate` rejected; `session_id` is
+    /// empty then, so the session is not resumed.
+    pub rejected_session_id: Option<String>,
     pub agent_messages: String,
+    /// Every extracted answer (one entry unless the parser uses `pick: all`).
+    pub candidates: Vec<String>,
     pub warnings: Option<String>,
+    /// Whether any answer was cut to `max_output_chars`.
+    pub truncated: bool,
+    /// Char count of `agent_messages` before truncation.
+    pub original_len: usize,
+}
+
+/// What `probe` ran and how it went.
+#[derive(Debug)]
+pub struct Probe {
+    pub command: String,
+    pub args: Vec<String>,
+    /// What the probe printed (stdout, or stderr when stdout is empty, cut to 2048 chars), or why
+    /// it failed.
+    pub outcome: Result<String>,
+}
+
+/// Context on a `run` error whose CLI could not be started at all (missing or not executable);
+/// find it with `err.downcast_ref::<SpawnFailed>()`.
+#[derive(Debug)]
+pub struct SpawnFailed;
+
+impl std::fmt::Display for SpawnFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("failed to spawn backend")
+    }
+}
+
+/// What `run` would execute for a set of options, without spawning anything.
+#[derive(Debug, Clone)]
+pub struct DryRun {
+    pub command: String,
+    /// With `${VAR}` references left as written, so secrets never reach the preview.
+    pub args: Vec<String>,
+    /// `arg` or `stdin`.
+    pub prompt_transport: &'static str,
 }
 
 const DEFAULT_PROMPT_MAX_CHARS: usize = 32 * 1024;
-const KIMI_READONLY_GUARDRAIL: &str = "不允许写文件";
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Linux rejects any single argument over 128 KiB (`MAX_ARG_STRLEN`) with `E2BIG`, so the whole
+/// rendered argv is kept under that.
+const MAX_ARGV_BYTES: usize = 128 * 1024;
+const DEFAULT_MAX_OUTPUT_CHARS: usize = 512 * 1024;
+const OUTPUT_TRUNCATION_MARKER: &str = "\n[... output truncated ...]";
+/// How much backend stderr (from the end) is kept in errors and warnings.
+const STDERR_TAIL_BYTES: usize = 2 * 1024;
+/// How long a timed-out backend gets to exit after SIGTERM before it is SIGKILLed.
+const KILL_GRACE: Duration = Duration::from_secs(3);
+/// How often `terminate` checks whether the process group has emptied during the grace period.
+#[cfg(unix)]
+const KILL_GROUP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Separates the args a `join_tools` or `repeat_flag` token expands to.
+const TOOL_ARG_SEPARATOR: char = '\u{1f}';
+/// Brackets a `verbatim` value so the arg is kept as is instead of trimmed.
+const QUOTE_MARK: char = '\u{1e}';
 
 pub async fn run(opts: GenericOptions) -> Result<GenericResult> {
-    let timeout_duration = Duration::from_secs(opts.timeout_secs);
-    timeout(timeout_duration, run_internal(opts))
+    let deadline = Instant::now() + Duration::from_secs(opts.timeout_secs);
+    run_internal(opts, deadline).await
+}
+
+/// Checks that a backend's CLI starts by running it with the adapter's `probe_args` (default
+/// `--version`). No prompt is sent.
+pub async fn probe(backend_id: &str, adapter: &AdapterConfig, workdir: &Path) -> Probe {
+    let command = resolve_command(backend_id);
+    let args = adapter
+        .probe_args
+        .clone()
+        .unwrap_or_else(|| vec!["--version".to_string()]);
+    let outcome = run_probe(&command, &args, workdir).await;
+    Probe {
+        command,
+        args,
+        outcome,
+    }
+}
+
+async fn run_probe(command: &str, args: &[String], workdir: &Path) -> Result<String> {
+    let output = Command::new(command)
+        .args(args)
+        .current_dir(workdir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output();
+    let output = timeout(PROBE_TIMEOUT, output)
         .await
-        .context("backend command timed out")?
+        .map_err(|_| {
+            anyhow!(
+                "{command} did not answer within {}s",
+                PROBE_TIMEOUT.as_secs()
+            )
+        })?
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => anyhow!("command not found: {command}"),
+            _ => anyhow!("failed to run {command}: {e}"),
+        })?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        let code = output.status.code().unwrap_or(-1);
+        return Err(anyhow!(
+            "{command} exited with status {code}. stderr: {}",
+            stderr_tail(&stderr)
+        ));
+    }
+    let printed = if stdout.trim().is_empty() {
+        stderr.trim()
+    } else {
+        stdout.trim()
+    };
+    Ok(truncate_output(printed, STDERR_TAIL_BYTES).unwrap_or_else(|| printed.to_string()))
+}
+
+pub fn dry_run(opts: &GenericOptions) -> Result<DryRun> {
+    let (args, transport) = render_args_and_transport(opts, unexpanded_env)?;
+    Ok(DryRun {
+        command: resolve_command(&opts.backend_id),
+        args,
+        prompt_transport: transport.as_str(),
+    })
 }
 
 pub fn render_args(opts: &GenericOptions) -> Result<Vec<String>> {
-    let prompt = apply_prompt_guardrails(&opts.backend_id, &opts.capabilities, &opts.prompt);
+    render_args_with(opts, |name| std::env::var(name).ok())
+}
+
+/// `render_args` for display: `${VAR}` references are left as written instead of expanded, so
+/// secrets never show up and unset variables are not an error.
+pub fn render_args_redacted(opts: &GenericOptions) -> Result<Vec<String>> {
+    render_args_with(opts, unexpanded_env)
+}
+
+/// Env lookup that leaves `${VAR}` as written.
+fn unexpanded_env(name: &str) -> Option<String> {
+    Some(format!("${{{name}}}"))
+}
+
+fn render_args_with(
+    opts: &GenericOptions,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<String>> {
+    Ok(render_args_and_transport(opts, lookup)?.0)
+}
+
+/// Renders the args and settles the prompt transport. When passing the prompt as an arg would push
+/// argv over `MAX_ARGV_BYTES` (the prompt can fit `prompt_max_chars` and still be too large, e.g.
+/// with multibyte text), an `auto` adapter switches to stdin and any other adapter is an error.
+fn render_args_and_transport(
+    opts: &GenericOptions,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<(Vec<String>, ResolvedPromptTransport)> {
+    let prompt = apply_prompt_guardrails(
+        &opts.backend_id,
+        &opts.adapter,
+        &opts.capabilities,
+        &opts.prompt,
+    );
     let transport = resolve_prompt_transport(&opts.adapter, &prompt);
-    let env = Environment::new();
+    let args = render_args_for(opts, &prompt, transport, &lookup)?;
+    if transport == ResolvedPromptTransport::Arg {
+        let argv_bytes: usize = args.iter().map(|arg| arg.len() + 1).sum();
+        if argv_bytes > MAX_ARGV_BYTES {
+            if opts.adapter.prompt_transport == Some(PromptTransport::Auto) {
+                let transport = ResolvedPromptTransport::Stdin;
+                return Ok((
+                    render_args_for(opts, &prompt, transport, &lookup)?,
+                    transport,
+                ));
+            }
+            return Err(anyhow!(
+                "rendered args are {argv_bytes} bytes, over the {MAX_ARGV_BYTES}-byte argv limit; set prompt_transport to stdin or auto for backend {}",
+                opts.backend_id
+            ));
+        }
+    }
+    Ok((args, transport))
+}
+
+fn render_args_for(
+    opts: &GenericOptions,
+    prompt: &str,
+    transport: ResolvedPromptTransport,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<Vec<String>> {
+    // Separators emitted by `join_tools`/`repeat_flag` and marks emitted by `verbatim` while
+    // rendering the current token. Only those split or unquote a token, so the control characters
+    // arriving through the prompt or an option value cannot inject extra args or strip text.
+    let separators = Arc::new(AtomicUsize::new(0));
+    let marks = Arc::new(AtomicUsize::new(0));
+    let mut env = Environment::new();
+    {
+        let separators = separators.clone();
+        env.add_filter(
+            "join_tools",
+            move |tools: Vec<String>, flag: Option<String>| {
+                count_emitted(join_tools(tools, flag), TOOL_ARG_SEPARATOR, &separators)
+            },
+        );
+    }
+    {
+        let marks = marks.clone();
+        env.add_filter("verbatim", move |value: minijinja::Value| {
+            count_emitted(verbatim(value), QUOTE_MARK, &marks)
+        });
+    }
+    {
+        let separators = separators.clone();
+        env.add_filter(
+            "repeat_flag",
+            move |values: minijinja::Value, flag: String| {
+                count_emitted(repeat_flag(values, flag), TOOL_ARG_SEPARATOR, &separators)
+            },
+        );
+    }
     let options_val = serde_json::to_value(&opts.options).context("serialize options")?;
     let capabilities_val =
         serde_json::to_value(&opts.capabilities).context("serialize capabilities")?;
-    let include_directories = detect_include_directories(&opts.prompt, &opts.workdir);
+    let include_directories =
+        detect_include_directories(&opts.prompt, &opts.workdir, &opts.include_directories);
     let prompt_for_args = match transport {
-        ResolvedPromptTransport::Arg => prompt.as_str(),
+        ResolvedPromptTransport::Arg => prompt,
         ResolvedPromptTransport::Stdin => "",
     };
     let ctx = context! {
@@ -67,13 +295,50 @@ pub fn render_args(opts: &GenericOptions) -> Result<Vec<String>> {
         capabilities => capabilities_val,
         include_directories => include_directories,
         prompt_transport => transport.as_str(),
+        n => opts.n,
+        three_client_id => opts.client_id,
     };
 
     let mut args: Vec<String> = Vec::new();
     for token in &opts.adapter.args_template {
+        let expanded = interpolate_env(token, lookup).map_err(|name| {
+            anyhow!(
+                "environment variable {name} is not set (referenced by template token: {token})"
+            )
+        })?;
+        separators.store(0, Ordering::Relaxed);
+        marks.store(0, Ordering::Relaxed);
         let rendered = env
-            .render_str(token, &ctx)
+            .render_str(&expanded, &ctx)
             .with_context(|| format!("failed to render template token: {token}"))?;
+        let emitted_separators = separators.load(Ordering::Relaxed);
+        let emitted_marks = marks.load(Ordering::Relaxed);
+        if emitted_separators > 0
+            && rendered.matches(TOOL_ARG_SEPARATOR).count() != emitted_separators
+        {
+            return Err(anyhow!(
+                "template token {token} has a U+001F character outside join_tools/repeat_flag"
+            ));
+        }
+        if emitted_marks > 0 && rendered.matches(QUOTE_MARK).count() != emitted_marks {
+            return Err(anyhow!(
+                "template token {token} has a U+001E character outside verbatim"
+            ));
+        }
+        if emitted_separators > 0 {
+            args.extend(rendered.split(TOOL_ARG_SEPARATOR).filter_map(|piece| {
+                if emitted_marks > 0 && piece.contains(QUOTE_MARK) {
+                    return Some(unquote_arg(piece));
+                }
+                let piece = piece.trim();
+                (!piece.is_empty()).then(|| piece.to_string())
+            }));
+            continue;
+        }
+        if emitted_marks > 0 {
+            args.push(unquote_arg(&rendered));
+            continue;
+        }
         let trimmed = rendered.trim();
         if !trimmed.is_empty() {
             args.push(trimmed.to_string());
@@ -83,13 +348,116 @@ pub fn render_args(opts: &GenericOptions) -> Result<Vec<String>> {
     Ok(args)
 }
 
-async fn run_internal(opts: GenericOptions) -> Result<GenericResult> {
-    let command = resolve_command(&opts.backend_id);
-    let prompt = apply_prompt_guardrails(&opts.backend_id, &opts.capabilities, &opts.prompt);
-    let transport = resolve_prompt_transport(&opts.adapter, &prompt);
-    let args = render_args(&opts)?;
+/// Adds the `ch` characters in a filter's output to the per-token count.
+fn count_emitted(
+    rendered: Result<String, minijinja::Error>,
+    ch: char,
+    count: &AtomicUsize,
+) -> Result<String, minijinja::Error> {
+    let rendered = rendered?;
+    count.fetch_add(rendered.matches(ch).count(), Ordering::Relaxed);
+    Ok(rendered)
+}
+
+/// Refuses a value for a splitting filter that already contains the arg separator.
+fn check_no_separator(value: &str) -> Result<(), minijinja::Error> {
+    if value.contains(TOOL_ARG_SEPARATOR) {
+        return Err(minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("value {value:?} contains the reserved U+001F character"),
+        ));
+    }
+    Ok(())
+}
+
+/// Template filter for `capabilities.tools`. Without an argument it renders one comma-separated
+/// arg (`read,grep`); with a flag it renders a flag/tool pair per tool (`--allow-tool read
+/// --allow-tool grep`), which `render_args_with` splits into separate args. `*` (any tool) is
+/// skipped, so the default allowlist renders nothing.
+fn join_tools(tools: Vec<String>, flag: Option<String>) -> Result<String, minijinja::Error> {
+    for value in tools.iter().chain(flag.as_ref()) {
+        check_no_separator(value)?;
+    }
+    let tools = tools
+        .iter()
+        .map(|tool| tool.trim())
+        .filter(|tool| !tool.is_empty() && *tool != "*");
+    Ok(match flag {
+        None => tools.collect::<Vec<_>>().join(","),
+        Some(flag) => tools
+            .flat_map(|tool| [flag.as_str(), tool])
+            .collect::<Vec<_>>()
+            .join(&TOOL_ARG_SEPARATOR.to_string()),
+    })
+}
 
-    let mut cmd = Command::new(command);
+/// Template filter for list options (`{{ options.tool | repeat_flag('--tool') }}`): renders a
+/// flag/value pair per entry as separate args. A scalar counts as a one-entry list; an unset
+/// option renders nothing.
+fn repeat_flag(values: minijinja::Value, flag: String) -> Result<String, minijinja::Error> {
+    if values.is_undefined() || values.is_none() {
+        return Ok(String::new());
+    }
+    let values: Vec<minijinja::Value> = match values.kind() {
+        minijinja::value::ValueKind::Seq => values.try_iter()?.collect(),
+        _ => vec![values],
+    };
+    check_no_separator(&flag)?;
+    let mut parts = Vec::with_capacity(values.len() * 2);
+    for value in &values {
+        let value = value.to_string();
+        check_no_separator(&value)?;
+        parts.extend([flag.clone(), value]);
+    }
+    Ok(parts.join(&TOOL_ARG_SEPARATOR.to_string()))
+}
+
+/// Template filter that keeps a value as one exact arg: args never go through a shell, so instead
+/// of adding quote characters it marks the value so surrounding whitespace (and an empty value)
+/// survive the per-token trim. Undefined/none values render nothing, like a plain `{{ x }}`;
+/// values containing the mark itself are refused.
+fn verbatim(value: minijinja::Value) -> Result<String, minijinja::Error> {
+    if value.is_undefined() || value.is_none() {
+        return Ok(String::new());
+    }
+    let value = value.to_string();
+    if value.contains(QUOTE_MARK) {
+        return Err(minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation,
+            format!("value {value:?} contains the reserved U+001E character"),
+        ));
+    }
+    Ok(format!("{QUOTE_MARK}{value}{QUOTE_MARK}"))
+}
+
+/// Trims a rendered token outside its `verbatim` values and drops the marks; the marked values
+/// (and anything between them) stay as rendered.
+fn unquote_arg(rendered: &str) -> String {
+    let (Some(start), Some(end)) = (rendered.find(QUOTE_MARK), rendered.rfind(QUOTE_MARK)) else {
+        return rendered.trim().to_string();
+    };
+    let inner = &rendered[start..end + QUOTE_MARK.len_utf8()];
+    format!(
+        "{}{}{}",
+        rendered[..start].trim_start(),
+        inner.replace(QUOTE_MARK, ""),
+        rendered[end + QUOTE_MARK.len_utf8()..].trim_end()
+    )
+}
+
+async fn run_internal(opts: GenericOptions, deadline: Instant) -> Result<GenericResult> {
+    let command = resolve_command(&opts.backend_id);
+    let prompt = apply_prompt_guardrails(
+        &opts.backend_id,
+        &opts.adapter,
+        &opts.capabilities,
+        &opts.prompt,
+    );
+    let (args, transport) = render_args_and_transport(&opts, |name| std::env::var(name).ok())?;
+    // What gets reported back: the same args without expanded secrets.
+    let recorded_args = render_args_for(&opts, &prompt, transport, &unexpanded_env)?;
+
+    let mut cmd = Command::new(&command);
     cmd.args(&args)
         .current_dir(&opts.workdir)
         .stdin(match transport {
@@ -99,63 +467,304 @@ async fn run_internal(opts: GenericOptions) -> Result<GenericResult> {
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .kill_on_drop(true);
-
-    let mut child = cmd.spawn().context("failed to spawn backend")?;
-    if let ResolvedPromptTransport::Stdin = transport {
-        if let Some(mut stdin) = child.stdin.take() {
-            stdin
-                .write_all(prompt.as_bytes())
-                .await
-                .context("failed to write prompt to stdin")?;
-        }
+    for (key, value) in &opts.env {
+        // The error names the variable, not the value: env values are often secrets.
+        let value = interpolate_env(value, |name| std::env::var(name).ok()).map_err(|name| {
+            anyhow!("environment variable {name} is not set (referenced by role env {key})")
+        })?;
+        cmd.env(key, value);
     }
-
-    let output = child
-        .wait_with_output()
-        .await
-        .context("failed to spawn backend")?;
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    if let Some(client_id) = opts.client_id.as_deref() {
+        cmd.env("THREE_CLIENT_ID", client_id);
+    }
+    // Own process group, so a timeout can signal the CLI together with anything it spawned.
+    #[cfg(unix)]
+    cmd.process_group(0);
+
+    let mut child = cmd.spawn().context(SpawnFailed)?;
+    let stdin_prompt = match transport {
+        ResolvedPromptTransport::Arg => None,
+        ResolvedPromptTransport::Stdin => Some(prompt.as_str()),
+    };
+    let stream = match (&opts.progress, &opts.adapter.output_parser) {
+        (Some(progress), OutputParserConfig::JsonStream { message_path, .. }) => {
+            Some((message_path.as_str(), progress))
+        }
+        _ => None,
+    };
+    let group = ProcessGroupGuard(child.id().and_then(|pid| i32::try_from(pid).ok()));
+    let waited = timeout_at(deadline, wait_output(&mut child, stdin_prompt, stream)).await;
+    group.disarm();
+    let (status, stdout, stderr) = match waited {
+        Ok(output) => output?,
+        Err(_) => {
+            let outcome = terminate(&mut child).await;
+            return Err(anyhow!(
+                "backend command timed out after {}s; {outcome}",
+                opts.timeout_secs
+            ));
+        }
+    };
 
     if let Some(model_err) = detect_model_error(
         &stdout,
         &stderr,
         &opts.fallback_error_patterns,
-        output.status.success(),
+        status.success(),
     ) {
         return Err(anyhow!("model_not_found: {model_err}"));
     }
 
-    if !output.status.success() {
-        let code = output.status.code().unwrap_or(-1);
+    if !status.success() {
+        let code = status.code().unwrap_or(-1);
         return Err(anyhow!(
-            "backend exited with status {code}. stderr: {stderr}"
+            "backend exited with status {code}. stderr: {}",
+            stderr_tail(&stderr)
         ));
     }
 
-    let (session_id, agent_messages) = parse_output(&opts.adapter.output_parser, &stdout)?;
+    let (mut session_id, mut candidates) = parse_output(&opts.adapter.output_parser, &stdout)
+        .map_err(|e| {
+            if stderr.trim().is_empty() {
+                e
+            } else {
+                anyhow!("{e:#}. stderr: {}", stderr_tail(&stderr))
+            }
+        })?;
+    let mut rejected_session_id = None;
+    if let Some(pattern) = opts.adapter.session_id_pattern_validate.as_deref() {
+        let re = RegexBuilder::new(pattern)
+            .build()
+            .context("invalid session_id_pattern_validate")?;
+        if !session_id.is_empty() && !re.is_match(&session_id) {
+            rejected_session_id = Some(std::mem::take(&mut session_id));
+        }
+    }
+
+    let max_output_chars = opts
+        .adapter
+        .max_output_chars
+        .unwrap_or(DEFAULT_MAX_OUTPUT_CHARS);
+    let original_len = candidates.first().map_or(0, |c| c.chars().count());
+    let mut truncated = false;
+    for candidate in &mut candidates {
+        if let Some(cut) = truncate_output(candidate, max_output_chars) {
+            *candidate = cut;
+            truncated = true;
+        }
+    }
 
     Ok(GenericResult {
+        command,
+        args: recorded_args,
+        prompt_transport: transport.as_str(),
         session_id,
-        agent_messages,
+        rejected_session_id,
+        agent_messages: candidates.first().cloned().unwrap_or_default(),
+        candidates,
         warnings: if stderr.trim().is_empty() {
             None
         } else {
-            Some(stderr)
+            Some(stderr_tail(&stderr))
         },
+        truncated,
+        original_len,
     })
 }
 
+/// First `max_chars` chars of `text` plus a marker, or `None` when it already fits.
+fn truncate_output(text: &str, max_chars: usize) -> Option<String> {
+    let (cut, _) = text.char_indices().nth(max_chars)?;
+    Some(format!("{}{OUTPUT_TRUNCATION_MARKER}", &text[..cut]))
+}
+
+/// Last `STDERR_TAIL_BYTES` of `stderr` (trimmed), where CLIs usually print the actual error.
+fn stderr_tail(stderr: &str) -> String {
+    let trimmed = stderr.trim();
+    if trimmed.len() <= STDERR_TAIL_BYTES {
+        return trimmed.to_string();
+    }
+    let mut start = trimmed.len() - STDERR_TAIL_BYTES;
+    while !trimmed.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("[... stderr truncated ...]\n{}", &trimmed[start..])
+}
+
+/// Like `wait_with_output`, but borrows `child` so a timeout can still signal it. Writes
+/// `stdin_prompt` first; with `stream`, reads stdout line by line and sends every `message_path`
+/// value as soon as it arrives. stderr is drained concurrently so the child never blocks on it.
+async fn wait_output(
+    child: &mut Child,
+    stdin_prompt: Option<&str>,
+    stream: Option<(&str, &UnboundedSender<String>)>,
+) -> Result<(ExitStatus, String, String)> {
+    if let (Some(prompt), Some(mut stdin)) = (stdin_prompt, child.stdin.take()) {
+        stdin
+            .write_all(prompt.as_bytes())
+            .await
+            .context("failed to write prompt to stdin")?;
+    }
+
+    let mut stdout_pipe = child.stdout.take().context("backend stdout not captured")?;
+    let mut stderr_pipe = child.stderr.take().context("backend stderr not captured")?;
+    let stderr_task = tokio::spawn(async move {
+        let mut buf = Vec::new();
+        let _ = stderr_pipe.read_to_end(&mut buf).await;
+        buf
+    });
+
+    let Some((message_path, progress)) = stream else {
+        let mut buf = Vec::new();
+        stdout_pipe
+            .read_to_end(&mut buf)
+            .await
+            .context("failed to read backend stdout")?;
+        let status = child.wait().await.context("failed to wait for backend")?;
+        let stderr = stderr_task.await.unwrap_or_default();
+        return Ok((
+            status,
+            String::from_utf8_lossy(&buf).to_string(),
+            String::from_utf8_lossy(&stderr).to_string(),
+        ));
+    };
+
+    let mut reader = BufReader::new(stdout_pipe);
+    let mut stdout = String::new();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = reader
+            .read_until(b'\n', &mut line)
+            .await
+            .context("failed to read backend stdout")?;
+        if read == 0 {
+            break;
+        }
+        let text = String::from_utf8_lossy(&line);
+        if let Ok(v) = serde_json::from_str::<Value>(text.trim()) {
+            if let Some(message) = json_path_get(&v, message_path).and_then(Value::as_str) {
+                if !message.trim().is_empty() {
+                    let _ = progress.send(message.to_string());
+                }
+            }
+        }
+        stdout.push_str(&text);
+    }
+
+    let status = child.wait().await.context("failed to wait for backend")?;
+    let stderr = stderr_task.await.unwrap_or_default();
+    Ok((status, stdout, String::from_utf8_lossy(&stderr).to_string()))
+}
+
+/// Kills the backend's process group if the call is dropped while the backend is still running
+/// (e.g. an aborted batch task). `kill_on_drop` alone only reaches the CLI, not what it spawned.
+struct ProcessGroupGuard(#[cfg_attr(not(unix), allow(dead_code))] Option<i32>);
+
+impl ProcessGroupGuard {
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for ProcessGroupGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pgid) = self.0 {
+            // SAFETY: kill(2) only sends a signal; a negative pid targets the group created at spawn.
+            unsafe { libc::kill(-pgid, libc::SIGKILL) };
+        }
+    }
+}
+
+/// Stops a timed-out backend: SIGTERM to its process group, then SIGKILL to the group if anything
+/// in it (the CLI or a subprocess that outlived it) is still running after `KILL_GRACE`. Returns
+/// how it ended, for the timeout error.
+///
+/// The group id stays safe to signal after the CLI (the group leader) is reaped: a pid is not
+/// handed out again while a process group with that id still has members.
+#[cfg(unix)]
+async fn terminate(child: &mut Child) -> &'static str {
+    let Some(pgid) = child.id().and_then(|pid| i32::try_from(pid).ok()) else {
+        return "process had already exited";
+    };
+    let deadline = Instant::now() + KILL_GRACE;
+    // SAFETY: kill(2) only sends a signal; a negative pid targets the group created at spawn, and
+    // `child.id()` is `Some` only while the leader has not been reaped.
+    unsafe { libc::kill(-pgid, libc::SIGTERM) };
+    let _ = timeout_at(deadline, child.wait()).await;
+    while group_has_members(pgid) && Instant::now() < deadline {
+        tokio::time::sleep(KILL_GROUP_POLL_INTERVAL).await;
+    }
+    if !group_has_members(pgid) {
+        let _ = child.wait().await;
+        return "process stopped with SIGTERM";
+    }
+    // SAFETY: as above; the group still has members, so its id has not been reused.
+    unsafe { libc::kill(-pgid, libc::SIGKILL) };
+    let _ = child.wait().await;
+    "process force-killed with SIGKILL after the SIGTERM grace period; a partial run may have changed files"
+}
+
+/// Whether any process (zombies included) is still in process group `pgid`.
+#[cfg(unix)]
+fn group_has_members(pgid: i32) -> bool {
+    // SAFETY: signal 0 only checks that the group exists and can be signalled.
+    unsafe { libc::kill(-pgid, 0) == 0 }
+}
+
+#[cfg(not(unix))]
+async fn terminate(child: &mut Child) -> &'static str {
+    let _ = child.kill().await;
+    "process killed; a partial run may have changed files"
+}
+
+/// Expands `${NAME}` references from the environment; on failure returns the first unset name.
+/// For `args_template` this runs on the raw token before the MiniJinja render, so values
+/// substituted via `{{ prompt }}` are never touched. Text that is not `${` + a valid variable
+/// name + `}` is left as is.
+fn interpolate_env(
+    value: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> std::result::Result<String, String> {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let name = after.find('}').map(|end| &after[..end]).filter(|name| {
+            let mut chars = name.chars();
+            chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+        let Some(name) = name else {
+            out.push_str("${");
+            rest = after;
+            continue;
+        };
+        let Some(resolved) = lookup(name) else {
+            return Err(name.to_string());
+        };
+        out.push_str(&resolved);
+        rest = &after[name.len() + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
 fn resolve_command(backend_id: &str) -> String {
     match backend_id {
         "codex" => std::env::var("CODEX_BIN").unwrap_or_else(|_| "codex".to_string()),
         "gemini" => std::env::var("GEMINI_BIN").unwrap_or_else(|_| "gemini".to_string()),
+        "kimi" => std::env::var("KIMI_BIN").unwrap_or_else(|_| "kimi".to_string()),
         _ => backend_id.to_string(),
     }
 }
 
-fn detect_include_directories(prompt: &str, workdir: &Path) -> String {
-    let mut dirs = BTreeSet::new();
+fn detect_include_directories(prompt: &str, workdir: &Path, explicit: &[String]) -> String {
+    let mut dirs: BTreeSet<String> = explicit.iter().cloned().collect();
     let workdir_norm = workdir
         .canonicalize()
         .unwrap_or_else(|_| workdir.to_path_buf());
@@ -238,22 +847,52 @@ fn resolve_prompt_transport(adapter: &AdapterConfig, prompt: &str) -> ResolvedPr
     }
 }
 
-fn apply_prompt_guardrails(backend_id: &str, capabilities: &Capabilities, prompt: &str) -> String {
-    if backend_id == "kimi" && capabilities.filesystem == FilesystemCapability::ReadOnly {
-        if prompt.contains(KIMI_READONLY_GUARDRAIL) {
-            return prompt.to_string();
-        }
-        if prompt.ends_with('\n') {
-            format!("{prompt}{KIMI_READONLY_GUARDRAIL}")
-        } else {
-            format!("{prompt}\n{KIMI_READONLY_GUARDRAIL}")
-        }
-    } else {
-        prompt.to_string()
+/// Guardrail added to read-only prompts when the adapter sets no `readonly_guardrail`.
+pub const DEFAULT_READONLY_GUARDRAIL: &str = "Do not create, modify, or delete any files.";
+
+/// Built-in guardrails that replace the default for a backend. Keyed by backend id rather than
+/// stored in the adapter catalog, so a user adapter override keeps them.
+fn builtin_readonly_guardrail(backend_id: &str) -> Option<&'static str> {
+    match backend_id {
+        "kimi" => Some("不允许写文件"),
+        _ => None,
     }
 }
 
-fn parse_output(parser: &OutputParserConfig, stdout: &str) -> Result<(String, String)> {
+/// Guardrail text for a backend running with these capabilities: the adapter's
+/// `readonly_guardrail` (blank opts out), else the backend's built-in one, else the default.
+pub fn prompt_guardrail<'a>(
+    backend_id: &str,
+    adapter: &'a AdapterConfig,
+    capabilities: &Capabilities,
+) -> Option<&'a str> {
+    if capabilities.filesystem != FilesystemCapability::ReadOnly {
+        return None;
+    }
+    match adapter.readonly_guardrail.as_deref() {
+        Some(text) => Some(text.trim()).filter(|text| !text.is_empty()),
+        None => Some(builtin_readonly_guardrail(backend_id).unwrap_or(DEFAULT_READONLY_GUARDRAIL)),
+    }
+}
+
+/// Prepends the guardrail from `prompt_guardrail` unless the prompt already carries it.
+pub fn apply_prompt_guardrails(
+    backend_id: &str,
+    adapter: &AdapterConfig,
+    capabilities: &Capabilities,
+    prompt: &str,
+) -> String {
+    match prompt_guardrail(backend_id, adapter, capabilities) {
+        // Already placed by a custom prompt_layout.
+        Some(guardrail) if prompt.contains(guardrail) => prompt.to_string(),
+        Some(guardrail) => format!("{guardrail}\n{prompt}"),
+        None => prompt.to_string(),
+    }
+}
+
+/// Returns the session id and the extracted messages (never empty; one entry unless `pick: all`).
+fn parse_output(parser: &OutputParserConfig, stdout: &str) -> Result<(String, Vec<String>)> {
+    let single = |(session_id, message): (String, String)| (session_id, vec![message]);
     match parser {
         OutputParserConfig::JsonStream {
             session_id_path,
@@ -270,13 +909,62 @@ fn parse_output(parser: &OutputParserConfig, stdout: &str) -> Result<(String, St
         OutputParserConfig::JsonObject {
             message_path,
             session_id_path,
-        } => parse_json_object(stdout, session_id_path.as_deref(), message_path),
+        } => parse_json_object(stdout, session_id_path.as_deref(), message_path).map(single),
         OutputParserConfig::Regex {
             session_id_pattern,
             message_capture_group,
-        } => parse_regex(stdout, session_id_pattern, *message_capture_group),
-        OutputParserConfig::Text => parse_text(stdout),
+            multiline,
+        } => parse_regex(
+            stdout,
+            session_id_pattern,
+            *message_capture_group,
+            *multiline,
+        )
+        .map(single),
+        OutputParserConfig::Sse {
+            session_id_path,
+            message_path,
+            pick,
+        } => parse_json_stream(
+            &sse_to_json_lines(stdout)?,
+            session_id_path,
+            message_path,
+            pick.unwrap_or(OutputPick::Last),
+            None,
+        ),
+        OutputParserConfig::Text => parse_text(stdout).map(single),
+    }
+}
+
+/// Unwraps an SSE stream into one compact JSON payload per line. An event's `data:` lines are
+/// joined with newlines (per the SSE spec) and dispatched at the next blank line or at EOF.
+fn sse_to_json_lines(stdout: &str) -> Result<String> {
+    let mut out = String::new();
+    let mut data: Vec<&str> = Vec::new();
+    let mut dispatch = |data: &mut Vec<&str>| -> Result<()> {
+        let payload = data.join("\n");
+        data.clear();
+        let payload = payload.trim();
+        if payload.is_empty() || payload == "[DONE]" {
+            return Ok(());
+        }
+        let v: Value = serde_json::from_str(payload)
+            .with_context(|| format!("failed to parse SSE data: {payload}"))?;
+        out.push_str(&v.to_string());
+        out.push('\n');
+        Ok(())
+    };
+    for line in stdout.lines() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.is_empty() {
+            dispatch(&mut data)?;
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data.push(value.strip_prefix(' ').unwrap_or(value));
+        }
+        // `event:`, `id:`, `retry:`, and `:` comment lines carry nothing to extract.
     }
+    dispatch(&mut data)?;
+    Ok(out)
 }
 
 fn detect_model_error(
@@ -348,9 +1036,9 @@ fn parse_json_stream(
     message_path: &str,
     pick: OutputPick,
     fallback: Option<JsonStreamFallback>,
-) -> Result<(String, String)> {
+) -> Result<(String, Vec<String>)> {
     let mut session_id: Option<String> = None;
-    let mut message: Option<String> = None;
+    let mut messages: Vec<String> = Vec::new();
 
     for line in stdout.lines() {
         let trimmed = line.trim();
@@ -380,25 +1068,42 @@ fn parse_json_stream(
 
         if let Some(val) = json_path_get(&v, message_path) {
             if let Some(s) = val.as_str() {
-                if pick == OutputPick::First {
-                    message.get_or_insert_with(|| s.to_string());
-                } else {
-                    message = Some(s.to_string());
+                match pick {
+                    OutputPick::First if !messages.is_empty() => {}
+                    OutputPick::Last => messages = vec![s.to_string()],
+                    _ => messages.push(s.to_string()),
                 }
             }
         }
     }
 
-    let session_id = session_id.ok_or_else(|| anyhow!("failed to get session_id from output"))?;
-    let mut message = message.unwrap_or_default();
-    if message.trim().is_empty() {
+    if pick == OutputPick::Concat {
+        let chunks: Vec<&str> = messages
+            .iter()
+            .map(String::as_str)
+            .filter(|m| !m.trim().is_empty())
+            .collect();
+        messages = vec![chunks.join("\n")];
+    }
+    if messages.iter().all(|m| m.trim().is_empty()) {
+        messages.clear();
         if let Some(JsonStreamFallback::Codex) = fallback {
             if let Some(fallback_message) = parse_codex_jsonl_message(stdout) {
-                message = fallback_message;
+                messages.push(fallback_message);
             }
         }
     }
-    Ok((session_id, message))
+    if messages.is_empty() {
+        messages.push(String::new());
+    }
+    // A missing session id is only fatal when nothing else was extracted; otherwise the caller
+    // reports the empty id (the response is still usable, it just cannot be resumed).
+    let session_id = match session_id {
+        Some(id) => id,
+        None if messages.iter().any(|m| !m.trim().is_empty()) => String::new(),
+        None => return Err(anyhow!("failed to get session_id from output")),
+    };
+    Ok((session_id, messages))
 }
 
 fn parse_codex_jsonl_message(stdout: &str) -> Option<String> {
@@ -486,8 +1191,13 @@ fn parse_regex(
     stdout: &str,
     pattern: &str,
     message_capture_group: usize,
+    multiline: bool,
 ) -> Result<(String, String)> {
-    let re = Regex::new(pattern).with_context(|| format!("invalid regex: {pattern}"))?;
+    let re = RegexBuilder::new(pattern)
+        .multi_line(multiline)
+        .dot_matches_new_line(multiline)
+        .build()
+        .with_context(|| format!("invalid regex: {pattern}"))?;
     let caps = re
         .captures(stdout)
         .ok_or_else(|| anyhow!("failed to match regex: {pattern}"))?;
@@ -549,6 +1259,11 @@ mod tests {
             capabilities: rp.profile.capabilities.clone(),
             fallback_error_patterns: Vec::new(),
             timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
         })
         .unwrap()
     }
@@ -616,6 +1331,11 @@ mod tests {
             capabilities: base_capabilities(filesystem),
             fallback_error_patterns: Vec::new(),
             timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
         })
         .unwrap()
     }
@@ -633,6 +1353,11 @@ mod tests {
             capabilities: base_capabilities(FilesystemCapability::ReadOnly),
             fallback_error_patterns: Vec::new(),
             timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
         })
         .unwrap()
     }
@@ -642,7 +1367,7 @@ mod tests {
         let (session_id, message) =
             parse_output(&OutputParserConfig::Text, "hello\n").expect("parse text");
         assert_eq!(session_id, "stateless");
-        assert_eq!(message, "hello");
+        assert_eq!(message, vec!["hello"]);
     }
 
     #[test]
@@ -661,7 +1386,86 @@ mod tests {
         )
         .expect("parse json stream");
         assert_eq!(session_id, "sess-1");
-        assert_eq!(message, "hi");
+        assert_eq!(message, vec!["hi"]);
+    }
+
+    #[test]
+    fn cfgtest_json_stream_missing_session_id_is_empty_unless_nothing_parsed() {
+        let parser = OutputParserConfig::JsonStream {
+            session_id_path: "thread_id".to_string(),
+            message_path: "item.text".to_string(),
+            pick: Some(OutputPick::Last),
+            fallback: None,
+        };
+        let stdout = r#"{"type":"item.completed","item":{"type":"agent_message","text":"hi"}}"#;
+        let (session_id, message) = parse_output(&parser, stdout).expect("parse json stream");
+        assert_eq!(session_id, "");
+        assert_eq!(message, vec!["hi"]);
+
+        assert!(parse_output(&parser, r#"{"type":"turn.started"}"#).is_err());
+    }
+
+    #[test]
+    fn cfgtest_sse_parses_data_frames() {
+        let parser = OutputParserConfig::Sse {
+            session_id_path: "session.id".to_string(),
+            message_path: "delta.text".to_string(),
+            pick: Some(OutputPick::Concat),
+        };
+        let stdout = ": keep-alive\r
+event: session\r
+data: {\"session\":{\"id\":\"sess-sse\"}}\r
+\r
+event: message\r
+data: {\"delta\":\r
+data: {\"text\":\"hello\"}}\r
+\r
+event: message\r
+data: {\"delta\":{\"text\":\"world\"}}\r
+\r
+data: [DONE]\r
+";
+        let (session_id, messages) = parse_output(&parser, stdout).expect("parse sse");
+        assert_eq!(session_id, "sess-sse");
+        assert_eq!(messages, vec!["hello\nworld"]);
+
+        assert!(parse_output(&parser, "data: not json\n\n").is_err());
+    }
+
+    #[test]
+    fn cfgtest_json_stream_pick_all_keeps_every_message() {
+        let parser = OutputParserConfig::JsonStream {
+            session_id_path: "thread_id".to_string(),
+            message_path: "item.text".to_string(),
+            pick: Some(OutputPick::All),
+            fallback: None,
+        };
+        let stdout = r#"{"type":"thread.started","thread_id":"sess-1"}
+{"type":"item.completed","item":{"type":"agent_message","text":"a"}}
+{"type":"item.completed","item":{"type":"agent_message","text":"b"}}
+"#;
+        let (session_id, messages) = parse_output(&parser, stdout).expect("parse json stream");
+        assert_eq!(session_id, "sess-1");
+        assert_eq!(messages, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn cfgtest_json_stream_pick_concat_joins_chunks() {
+        let parser = OutputParserConfig::JsonStream {
+            session_id_path: "thread_id".to_string(),
+            message_path: "item.text".to_string(),
+            pick: Some(OutputPick::Concat),
+            fallback: None,
+        };
+        let stdout = r#"{"type":"thread.started","thread_id":"sess-1"}
+{"type":"item.completed","item":{"type":"agent_message","text":"part one"}}
+{"type":"item.completed","item":{"type":"agent_message","text":""}}
+{"type":"item.completed","item":{"type":"agent_message","text":"part two"}}
+{"type":"item.completed","item":{"type":"agent_message","text":"part three"}}
+"#;
+        let (session_id, messages) = parse_output(&parser, stdout).expect("parse json stream");
+        assert_eq!(session_id, "sess-1");
+        assert_eq!(messages, vec!["part one\npart two\npart three"]);
     }
 
     #[test]
@@ -684,6 +1488,11 @@ mod tests {
             capabilities: base_capabilities(FilesystemCapability::ReadOnly),
             fallback_error_patterns: Vec::new(),
             timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
         })
         .unwrap();
 
@@ -708,14 +1517,48 @@ mod tests {
 
     #[test]
     fn cfgtest_kimi_readonly_guardrail_applies_to_prompt() {
-        let prompt = "ping";
-        let guarded = apply_prompt_guardrails(
-            "kimi",
-            &base_capabilities(FilesystemCapability::ReadOnly),
-            prompt,
+        let catalog = embedded_adapter_catalog();
+        let adapter = catalog.adapters.get("kimi").expect("kimi adapter");
+        let read_only = base_capabilities(FilesystemCapability::ReadOnly);
+        assert_eq!(
+            apply_prompt_guardrails("kimi", adapter, &read_only, "ping"),
+            "不允许写文件\nping"
+        );
+
+        // A user adapter for kimi that sets no guardrail keeps the built-in one.
+        let mut custom = adapter.clone();
+        custom.args_template = vec!["--prompt".to_string(), "{{ prompt }}".to_string()];
+        assert_eq!(
+            apply_prompt_guardrails("kimi", &custom, &read_only, "ping"),
+            "不允许写文件\nping"
+        );
+    }
+
+    #[test]
+    fn readonly_guardrail_applies_to_any_adapter_only_when_read_only() {
+        let mut adapter = load_codex_adapter();
+        let read_only = base_capabilities(FilesystemCapability::ReadOnly);
+        let read_write = base_capabilities(FilesystemCapability::ReadWrite);
+        assert_eq!(
+            apply_prompt_guardrails("codex", &adapter, &read_only, "ping"),
+            format!("{DEFAULT_READONLY_GUARDRAIL}\nping")
+        );
+
+        adapter.readonly_guardrail = Some("Do not modify files.".to_string());
+        assert_eq!(
+            apply_prompt_guardrails("codex", &adapter, &read_only, "ping"),
+            "Do not modify files.\nping"
+        );
+        assert_eq!(
+            apply_prompt_guardrails("codex", &adapter, &read_write, "ping"),
+            "ping"
+        );
+
+        adapter.readonly_guardrail = Some("  ".to_string());
+        assert_eq!(
+            apply_prompt_guardrails("codex", &adapter, &read_only, "ping"),
+            "ping"
         );
-        assert!(guarded.contains("ping"));
-        assert!(guarded.contains("不允许写文件"));
     }
 
     #[test]
@@ -738,6 +1581,11 @@ mod tests {
             capabilities: base_capabilities(FilesystemCapability::ReadWrite),
             fallback_error_patterns: Vec::new(),
             timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
         })
         .unwrap();
 
@@ -771,6 +1619,11 @@ mod tests {
             capabilities: base_capabilities(FilesystemCapability::ReadWrite),
             fallback_error_patterns: Vec::new(),
             timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
         })
         .unwrap();
 
@@ -802,6 +1655,11 @@ mod tests {
             capabilities: base_capabilities(FilesystemCapability::ReadOnly),
             fallback_error_patterns: Vec::new(),
             timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
         })
         .unwrap();
 
@@ -833,6 +1691,11 @@ mod tests {
             capabilities: base_capabilities(FilesystemCapability::ReadOnly),
             fallback_error_patterns: Vec::new(),
             timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
         })
         .unwrap();
 
@@ -879,6 +1742,11 @@ mod tests {
             capabilities: base_capabilities(FilesystemCapability::ReadOnly),
             fallback_error_patterns: Vec::new(),
             timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
         })
         .unwrap();
 
@@ -944,6 +1812,55 @@ mod tests {
         assert!(args.contains(&outside.to_string_lossy().to_string()));
     }
 
+    #[test]
+    fn cfgtest_render_gemini_merges_explicit_include_directories() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        let explicit = td.path().join("vendor");
+        let detected = td.path().join("outside");
+        std::fs::create_dir_all(&explicit).unwrap();
+        std::fs::create_dir_all(&detected).unwrap();
+
+        let cfg_path = crate::test_utils::example_config_path();
+        let loader = ConfigLoader::new(Some(cfg_path));
+        let cfg = loader.load_for_repo(&repo).unwrap().unwrap();
+        let rp = cfg.resolve_profile(Some("researcher")).unwrap();
+        let args = render_args(&GenericOptions {
+            backend_id: rp.profile.backend_id.clone(),
+            adapter: rp.profile.adapter.clone(),
+            prompt: format!("Compare with {}", detected.display()),
+            workdir: repo.clone(),
+            session_id: None,
+            resume: false,
+            model: rp.profile.model.clone(),
+            options: rp.profile.options.clone(),
+            capabilities: rp.profile.capabilities.clone(),
+            fallback_error_patterns: Vec::new(),
+            timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: vec![explicit.to_string_lossy().to_string()],
+        })
+        .unwrap();
+
+        let idx = args
+            .iter()
+            .position(|a| a == "--include-directories")
+            .expect("include flag");
+        let dirs: Vec<&str> = args[idx + 1].split(',').collect();
+        assert!(
+            dirs.contains(&explicit.to_string_lossy().as_ref()),
+            "{args:?}"
+        );
+        assert!(
+            dirs.contains(&detected.to_string_lossy().as_ref()),
+            "{args:?}"
+        );
+    }
+
     #[test]
     fn cfgtest_render_gemini_include_directories_ignores_persona_tags() {
         let td = tempfile::tempdir().unwrap();
@@ -986,12 +1903,56 @@ mod tests {
             capabilities: base_capabilities(FilesystemCapability::ReadWrite),
             fallback_error_patterns: Vec::new(),
             timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
         })
         .unwrap();
 
         assert!(!args.contains(&prompt));
     }
 
+    #[test]
+    fn oversized_argv_switches_auto_to_stdin_and_rejects_arg() {
+        let td = tempfile::tempdir().unwrap();
+        let mut adapter = embedded_adapter_catalog().adapters["codex"].clone();
+        // Under prompt_max_chars, but 140 KB of argv once rendered.
+        adapter.prompt_max_chars = Some(1 << 20);
+        let prompt = "é".repeat(70_000);
+        let mut opts = GenericOptions {
+            backend_id: "codex".to_string(),
+            adapter,
+            prompt: prompt.clone(),
+            workdir: td.path().to_path_buf(),
+            session_id: None,
+            resume: false,
+            model: "gpt-5.2".to_string(),
+            options: BTreeMap::new(),
+            capabilities: base_capabilities(FilesystemCapability::ReadWrite),
+            fallback_error_patterns: Vec::new(),
+            timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
+        };
+
+        let preview = dry_run(&opts).unwrap();
+        assert_eq!(preview.prompt_transport, "stdin");
+        assert!(!preview.args.contains(&prompt));
+
+        opts.adapter.prompt_transport = Some(PromptTransport::Arg);
+        let err = render_args(&opts).unwrap_err().to_string();
+        assert!(err.contains("over the 131072-byte argv limit"), "{err}");
+        assert!(
+            err.contains("set prompt_transport to stdin or auto"),
+            "{err}"
+        );
+    }
+
     #[test]
     fn cfgtest_prompt_transport_auto_omits_prompt_for_gemini() {
         let td = tempfile::tempdir().unwrap();
@@ -1017,6 +1978,11 @@ mod tests {
             capabilities: base_capabilities(FilesystemCapability::ReadWrite),
             fallback_error_patterns: Vec::new(),
             timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
         })
         .unwrap();
 
@@ -1045,6 +2011,11 @@ mod tests {
             capabilities: base_capabilities(FilesystemCapability::ReadWrite),
             fallback_error_patterns: Vec::new(),
             timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
         })
         .unwrap();
 
@@ -1077,6 +2048,11 @@ mod tests {
             capabilities: base_capabilities(FilesystemCapability::ReadWrite),
             fallback_error_patterns: Vec::new(),
             timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
         })
         .unwrap();
 
@@ -1117,6 +2093,266 @@ mod tests {
         assert!(!args.iter().any(|t| t.starts_with("model=")));
     }
 
+    #[test]
+    fn interpolate_env_expands_only_well_formed_references() {
+        let lookup = |name: &str| (name == "THREE_HOME").then(|| "/opt/three".to_string());
+        assert_eq!(
+            interpolate_env("--bin=${THREE_HOME}/bin", lookup).unwrap(),
+            "--bin=/opt/three/bin"
+        );
+        assert_eq!(
+            interpolate_env("$5 ${ not-a-name } ${{ model }}", lookup).unwrap(),
+            "$5 ${ not-a-name } ${{ model }}"
+        );
+        assert_eq!(
+            interpolate_env("--x=${THREE_MISSING}", lookup).unwrap_err(),
+            "THREE_MISSING"
+        );
+    }
+
+    #[test]
+    fn render_args_interpolates_env_in_tokens_but_not_in_prompt() {
+        let td = tempfile::tempdir().unwrap();
+        let mut adapter = load_codex_adapter();
+        adapter.args_template = vec!["--path=${PATH}".to_string(), "{{ prompt }}".to_string()];
+        let mut opts = GenericOptions {
+            backend_id: "codex".to_string(),
+            adapter,
+            prompt: "costs $5 and ${THREE_UNSET_IN_PROMPT}".to_string(),
+            workdir: td.path().to_path_buf(),
+            session_id: None,
+            resume: false,
+            model: "gpt-5.2-codex".to_string(),
+            options: BTreeMap::new(),
+            capabilities: base_capabilities(FilesystemCapability::ReadWrite),
+            fallback_error_patterns: Vec::new(),
+            timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
+        };
+        let args = render_args(&opts).unwrap();
+        assert_eq!(
+            args[0],
+            format!("--path={}", std::env::var("PATH").unwrap())
+        );
+        assert_eq!(args[1], "costs $5 and ${THREE_UNSET_IN_PROMPT}");
+        assert_eq!(dry_run(&opts).unwrap().args[0], "--path=${PATH}");
+
+        opts.adapter.args_template = vec!["--x=${THREE_UNSET_IN_TEMPLATE}".to_string()];
+        let err = render_args(&opts).unwrap_err();
+        assert!(format!("{err:#}").contains("THREE_UNSET_IN_TEMPLATE"));
+        assert_eq!(
+            dry_run(&opts).unwrap().args,
+            vec!["--x=${THREE_UNSET_IN_TEMPLATE}".to_string()]
+        );
+    }
+
+    #[test]
+    fn render_args_expands_tools_allowlist() {
+        let td = tempfile::tempdir().unwrap();
+        let mut adapter = load_codex_adapter();
+        adapter.args_template = vec![
+            "{{ capabilities.tools | join_tools('--allow-tool') }}".to_string(),
+            "{% for t in capabilities.tools %}{{ t }}+{% endfor %}".to_string(),
+            "--tools={{ capabilities.tools | join_tools }}".to_string(),
+        ];
+        let mut capabilities = base_capabilities(FilesystemCapability::ReadOnly);
+        capabilities.tools = vec!["read".to_string(), "grep".to_string()];
+        let mut opts = GenericOptions {
+            backend_id: "codex".to_string(),
+            adapter,
+            prompt: "ping".to_string(),
+            workdir: td.path().to_path_buf(),
+            session_id: None,
+            resume: false,
+            model: "gpt-5.2-codex".to_string(),
+            options: BTreeMap::new(),
+            capabilities,
+            fallback_error_patterns: Vec::new(),
+            timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
+        };
+        assert_eq!(
+            render_args(&opts).unwrap(),
+            vec![
+                "--allow-tool",
+                "read",
+                "--allow-tool",
+                "grep",
+                "read+grep+",
+                "--tools=read,grep"
+            ]
+        );
+
+        opts.capabilities.tools = vec!["*".to_string()];
+        assert_eq!(render_args(&opts).unwrap(), vec!["*+", "--tools="]);
+    }
+
+    #[test]
+    fn render_args_expands_array_options() {
+        let td = tempfile::tempdir().unwrap();
+        let mut adapter = load_codex_adapter();
+        adapter.args_template = vec![
+            "{{ options.tool | repeat_flag('--tool') }}".to_string(),
+            "--ids={% for id in options.ids %}{{ id }}{% if not loop.last %},{% endif %}{% endfor %}"
+                .to_string(),
+            "{{ options.unset | repeat_flag('--x') }}".to_string(),
+        ];
+        let opts = GenericOptions {
+            backend_id: "codex".to_string(),
+            adapter,
+            prompt: "ping".to_string(),
+            workdir: td.path().to_path_buf(),
+            session_id: None,
+            resume: false,
+            model: "gpt-5.2-codex".to_string(),
+            options: BTreeMap::from([
+                (
+                    "tool".to_string(),
+                    OptionValue::Array(vec![
+                        OptionValue::String("read".to_string()),
+                        OptionValue::String("web search".to_string()),
+                    ]),
+                ),
+                (
+                    "ids".to_string(),
+                    serde_json::from_value(serde_json::json!([1, 2])).unwrap(),
+                ),
+            ]),
+            capabilities: base_capabilities(FilesystemCapability::ReadOnly),
+            fallback_error_patterns: Vec::new(),
+            timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
+        };
+        assert_eq!(
+            render_args(&opts).unwrap(),
+            vec!["--tool", "read", "--tool", "web search", "--ids=1,2"]
+        );
+    }
+
+    #[test]
+    fn render_args_splits_only_on_filter_separators() {
+        let td = tempfile::tempdir().unwrap();
+        let mut adapter = load_codex_adapter();
+        adapter.args_template = vec![
+            "--prompt".to_string(),
+            "{{ prompt }}".to_string(),
+            "{{ options.tool | repeat_flag('--tool') }}".to_string(),
+        ];
+        let mut opts = GenericOptions {
+            backend_id: "codex".to_string(),
+            adapter,
+            prompt: "hi\u{1f}--dangerously-bypass".to_string(),
+            workdir: td.path().to_path_buf(),
+            session_id: None,
+            resume: false,
+            model: "gpt-5.2-codex".to_string(),
+            options: BTreeMap::from([(
+                "tool".to_string(),
+                OptionValue::String("read".to_string()),
+            )]),
+            capabilities: base_capabilities(FilesystemCapability::ReadWrite),
+            fallback_error_patterns: Vec::new(),
+            timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
+        };
+        assert_eq!(
+            render_args(&opts).unwrap(),
+            vec!["--prompt", "hi\u{1f}--dangerously-bypass", "--tool", "read"]
+        );
+
+        // A separator smuggled into a value, or next to a filter in the same token, is refused.
+        opts.options.insert(
+            "tool".to_string(),
+            OptionValue::String("read\u{1f}--yolo".to_string()),
+        );
+        assert!(render_args(&opts).is_err());
+        opts.options
+            .insert("tool".to_string(), OptionValue::String("read".to_string()));
+        opts.adapter.args_template =
+            vec!["{{ prompt }}{{ options.tool | repeat_flag('--tool') }}".to_string()];
+        assert!(render_args(&opts).is_err());
+    }
+
+    #[test]
+    fn render_args_verbatim_keeps_option_values_as_given() {
+        let td = tempfile::tempdir().unwrap();
+        let mut adapter = load_codex_adapter();
+        adapter.args_template = vec![
+            "--title".to_string(),
+            " {{ options.title | verbatim }} ".to_string(),
+            "--quote={{ options.quote | verbatim }}".to_string(),
+            "{{ options.empty | verbatim }}".to_string(),
+            "{{ options.missing | verbatim }}".to_string(),
+            "{{ options.title }}".to_string(),
+        ];
+        let options = BTreeMap::from([
+            (
+                "title".to_string(),
+                OptionValue::String("  my title ".to_string()),
+            ),
+            (
+                "quote".to_string(),
+                OptionValue::String(r#"it's "fine""#.to_string()),
+            ),
+            ("empty".to_string(), OptionValue::String(String::new())),
+        ]);
+        let mut opts = GenericOptions {
+            backend_id: "codex".to_string(),
+            adapter,
+            prompt: "ping".to_string(),
+            workdir: td.path().to_path_buf(),
+            session_id: None,
+            resume: false,
+            model: "gpt-5.2-codex".to_string(),
+            options,
+            capabilities: base_capabilities(FilesystemCapability::ReadWrite),
+            fallback_error_patterns: Vec::new(),
+            timeout_secs: 5,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
+        };
+        assert_eq!(
+            render_args(&opts).unwrap(),
+            vec![
+                "--title",
+                "  my title ",
+                r#"--quote=it's "fine""#,
+                "",
+                "my title"
+            ]
+        );
+
+        // Marks only come from the filter: one in the prompt is kept, one in a value is refused.
+        opts.adapter.args_template = vec!["{{ prompt }}".to_string()];
+        opts.prompt = " a\u{1e}b ".to_string();
+        assert_eq!(render_args(&opts).unwrap(), vec!["a\u{1e}b"]);
+        opts.adapter.args_template = vec!["{{ options.title | verbatim }}".to_string()];
+        opts.options.insert(
+            "title".to_string(),
+            OptionValue::String("x\u{1e}y".to_string()),
+        );
+        assert!(render_args(&opts).is_err());
+    }
+
     #[test]
     fn cfgtest_render_codex_readwrite_resume_uses_config_model() {
         let td = tempfile::tempdir().unwrap();
@@ -1149,4 +2385,285 @@ mod tests {
         assert!(args.contains(&"resume".to_string()));
         assert!(args.contains(&"sess-1".to_string()));
     }
+
+    #[tokio::test]
+    async fn run_streams_json_stream_messages_as_they_arrive() {
+        let td = tempfile::tempdir().unwrap();
+        let fake = td.path().join("fake-stream.sh");
+        std::fs::write(
+            &fake,
+            "#!/bin/sh\nprintf '%s\\n' '{\"type\":\"thread.started\",\"thread_id\":\"sess-1\"}'\nprintf '%s\\n' '{\"type\":\"item.completed\",\"item\":{\"text\":\"step one\"}}'\nsleep 1\nprintf '%s\\n' '{\"type\":\"item.completed\",\"item\":{\"text\":\"final answer\"}}'\n",
+        )
+        .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let mut adapter = load_codex_adapter();
+        adapter.args_template = Vec::new();
+        adapter.output_parser = OutputParserConfig::JsonStream {
+            session_id_path: "thread_id".to_string(),
+            message_path: "item.text".to_string(),
+            pick: None,
+            fallback: None,
+        };
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        // Unknown backend ids are spawned as-is, so the script path doubles as the command.
+        let run_task = tokio::spawn(run(GenericOptions {
+            backend_id: fake.to_string_lossy().to_string(),
+            adapter,
+            prompt: "ping".to_string(),
+            workdir: td.path().to_path_buf(),
+            session_id: None,
+            resume: false,
+            model: "m".to_string(),
+            options: BTreeMap::new(),
+            capabilities: base_capabilities(FilesystemCapability::ReadOnly),
+            fallback_error_patterns: Vec::new(),
+            timeout_secs: 10,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: Some(tx),
+            include_directories: Vec::new(),
+        }));
+
+        // The first message is relayed while the backend is still running.
+        let first = tokio::time::timeout(Duration::from_millis(800), rx.recv())
+            .await
+            .expect("first message should stream before the backend exits");
+        assert_eq!(first.as_deref(), Some("step one"));
+        assert!(!run_task.is_finished());
+
+        let result = run_task.await.unwrap().unwrap();
+        assert_eq!(rx.recv().await.as_deref(), Some("final answer"));
+        assert_eq!(result.session_id, "sess-1");
+        assert_eq!(result.agent_messages, "final answer");
+    }
+
+    #[test]
+    fn stderr_tail_keeps_the_end_on_a_char_boundary() {
+        assert_eq!(stderr_tail("  short error\n"), "short error");
+
+        let long = format!("{}\nfatal: bad flag", "é".repeat(STDERR_TAIL_BYTES));
+        let tail = stderr_tail(&long);
+        assert!(tail.starts_with("[... stderr truncated ...]\n"));
+        assert!(tail.ends_with("fatal: bad flag"));
+        let kept = tail.trim_start_matches("[... stderr truncated ...]\n");
+        assert!(kept.len() <= STDERR_TAIL_BYTES);
+    }
+
+    #[tokio::test]
+    async fn run_failure_reports_stderr_tail() {
+        let td = tempfile::tempdir().unwrap();
+        let fake = td.path().join("fake-fail.sh");
+        std::fs::write(
+            &fake,
+            "#!/bin/sh\ni=0\nwhile [ $i -lt 200 ]; do echo \"noise line $i\" >&2; i=$((i+1)); done\necho 'error: unknown flag --frobnicate' >&2\nexit 2\n",
+        )
+        .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let mut adapter = load_codex_adapter();
+        adapter.args_template = Vec::new();
+        let err = run(GenericOptions {
+            backend_id: fake.to_string_lossy().to_string(),
+            adapter,
+            prompt: "ping".to_string(),
+            workdir: td.path().to_path_buf(),
+            session_id: None,
+            resume: false,
+            model: "m".to_string(),
+            options: BTreeMap::new(),
+            capabilities: base_capabilities(FilesystemCapability::ReadOnly),
+            fallback_error_patterns: Vec::new(),
+            timeout_secs: 10,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
+        })
+        .await
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("status 2"), "{err}");
+        assert!(err.contains("error: unknown flag --frobnicate"), "{err}");
+        assert!(err.contains("[... stderr truncated ...]"), "{err}");
+        assert!(!err.contains("noise line 0\n"), "{err}");
+        assert!(err.len() < STDERR_TAIL_BYTES + 200, "{}", err.len());
+    }
+
+    #[tokio::test]
+    async fn run_truncates_answers_past_max_output_chars() {
+        let td = tempfile::tempdir().unwrap();
+        let fake = td.path().join("fake-long.sh");
+        std::fs::write(
+            &fake,
+            "#!/bin/sh\nprintf '%s\\n' '{\"type\":\"thread.started\",\"thread_id\":\"s1\"}'\nprintf '%s\\n' '{\"type\":\"item.completed\",\"item\":{\"type\":\"agent_message\",\"text\":\"héllo wörld\"}}'\n",
+        )
+        .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let mut adapter = load_codex_adapter();
+        adapter.args_template = Vec::new();
+        adapter.max_output_chars = Some(5);
+        let out = run(GenericOptions {
+            backend_id: fake.to_string_lossy().to_string(),
+            adapter,
+            prompt: "ping".to_string(),
+            workdir: td.path().to_path_buf(),
+            session_id: None,
+            resume: false,
+            model: "m".to_string(),
+            options: BTreeMap::new(),
+            capabilities: base_capabilities(FilesystemCapability::ReadOnly),
+            fallback_error_patterns: Vec::new(),
+            timeout_secs: 10,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
+        })
+        .await
+        .unwrap();
+
+        assert!(out.truncated);
+        assert_eq!(out.original_len, 11);
+        assert_eq!(
+            out.agent_messages,
+            format!("héllo{OUTPUT_TRUNCATION_MARKER}")
+        );
+        assert_eq!(out.candidates, vec![out.agent_messages.clone()]);
+        assert_eq!(truncate_output("héllo", 5), None);
+    }
+
+    #[cfg(unix)]
+    async fn run_script_with_timeout(dir: &Path, script: &str, timeout_secs: u64) -> String {
+        use std::os::unix::fs::PermissionsExt;
+        let fake = dir.join("fake-hang.sh");
+        std::fs::write(&fake, script).unwrap();
+        std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut adapter = load_codex_adapter();
+        adapter.args_template = Vec::new();
+        run(GenericOptions {
+            backend_id: fake.to_string_lossy().to_string(),
+            adapter,
+            prompt: "ping".to_string(),
+            workdir: dir.to_path_buf(),
+            session_id: None,
+            resume: false,
+            model: "m".to_string(),
+            options: BTreeMap::new(),
+            capabilities: base_capabilities(FilesystemCapability::ReadOnly),
+            fallback_error_patterns: Vec::new(),
+            timeout_secs,
+            n: None,
+            client_id: None,
+            env: BTreeMap::new(),
+            progress: None,
+            include_directories: Vec::new(),
+        })
+        .await
+        .unwrap_err()
+        .to_string()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn run_timeout_stops_cooperative_backend_with_sigterm() {
+        let td = tempfile::tempdir().unwrap();
+        let started = std::time::Instant::now();
+        let err = run_script_with_timeout(td.path(), "#!/bin/sh\nexec sleep 30\n", 1).await;
+
+        assert!(err.contains("backend command timed out after 1s"), "{err}");
+        assert!(err.contains("stopped with SIGTERM"), "{err}");
+        assert!(started.elapsed() < KILL_GRACE + Duration::from_secs(1));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn run_timeout_force_kills_backend_ignoring_sigterm() {
+        let td = tempfile::tempdir().unwrap();
+        let pidfile = td.path().join("child.pid");
+        let script = format!(
+            "#!/bin/sh\ntrap '' TERM\nsleep 30 &\necho $! > {}\nwait\n",
+            pidfile.display()
+        );
+        let err = run_script_with_timeout(td.path(), &script, 1).await;
+
+        assert!(err.contains("backend command timed out after 1s"), "{err}");
+        assert!(err.contains("force-killed with SIGKILL"), "{err}");
+        assert!(err.contains("partial run may have changed files"), "{err}");
+
+        // The CLI's own subprocess went down with it.
+        assert_process_stops(&pidfile).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn run_timeout_force_kills_grandchild_ignoring_sigterm_after_cli_exits() {
+        let td = tempfile::tempdir().unwrap();
+        let pidfile = td.path().join("grandchild.pid");
+        // The CLI itself exits on SIGTERM; the subprocess it started does not.
+        let script = format!(
+            "#!/bin/sh\nsh -c 'trap \"\" TERM; echo $$ > {}; exec sleep 30' &\nwait\n",
+            pidfile.display()
+        );
+        let err = run_script_with_timeout(td.path(), &script, 1).await;
+
+        assert!(err.contains("backend command timed out after 1s"), "{err}");
+        assert!(err.contains("force-killed with SIGKILL"), "{err}");
+        assert_process_stops(&pidfile).await;
+    }
+
+    /// Waits for the process whose pid is in `pidfile` to stop running, or fails.
+    #[cfg(unix)]
+    async fn assert_process_stops(pidfile: &Path) {
+        let pid: i32 = std::fs::read_to_string(pidfile)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        for _ in 0..20 {
+            // A reaped or zombie process no longer runs; only the latter still answers kill(0).
+            let zombie = std::fs::read_to_string(format!("/proc/{pid}/stat"))
+                .is_ok_and(|stat| stat.contains(") Z "));
+            if zombie || unsafe { libc::kill(pid, 0) } != 0 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("process {pid} survived the timeout");
+    }
+
+    #[test]
+    fn regex_parser_multiline_spans_banner_and_message_block() {
+        let stdout = "mycli v1.2\nsession: abc-123\nthinking...\n---BEGIN---\nline one\nline two\n---END---\nbye\n";
+        let parser = |multiline| OutputParserConfig::Regex {
+            session_id_pattern: r"^session: (\S+)$.*?^---BEGIN---\n(.*?)\n---END---$".to_string(),
+            message_capture_group: 2,
+            multiline,
+        };
+
+        let (session_id, messages) = parse_output(&parser(true), stdout).unwrap();
+        assert_eq!(session_id, "abc-123");
+        assert_eq!(messages, vec!["line one\nline two".to_string()]);
+
+        // Without the flag the same pattern cannot cross lines.
+        assert!(parse_output(&parser(false), stdout).is_err());
+    }
 }
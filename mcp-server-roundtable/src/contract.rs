@@ -1,6 +1,10 @@
 use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
 use std::path::Path;
 use std::process::Stdio;
+use std::sync::OnceLock;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PatchFormat {
@@ -14,22 +18,69 @@ pub enum PatchFormat {
 pub struct ContractCheck {
     pub has_patch: bool,
     pub has_citations: bool,
+    /// File references parsed from the citation regions, in first-seen order.
+    pub citations: Vec<Citation>,
     pub patch_format: PatchFormat,
     pub extracted_patch: Option<String>,
+    /// Every unified diff in the response: each fenced `diff` block, or the single unfenced
+    /// `extracted_patch`.
+    pub extracted_patches: Vec<String>,
     pub apply_check: Option<ApplyCheck>,
     pub errors: Vec<String>,
 }
 
+/// One `path` or `path:line` reference from the citations (`path:10-20` keeps the start line).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Citation {
+    pub path: String,
+    pub line: Option<u32>,
+}
+
 #[derive(Debug, Clone)]
 pub struct ApplyCheck {
     pub ok: bool,
     pub output: String,
 }
 
+/// `git apply --check` result for one of the `extracted_patches`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PatchCheck {
+    /// Position of the patch in the response (0-based).
+    pub index: usize,
+    /// Files the patch touches.
+    pub files: Vec<String>,
+    pub ok: bool,
+    pub output: String,
+}
+
+/// What the extracted unified diffs touch, summed over all of them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PatchStats {
+    /// Paths touched, in first-seen order (the old path for deleted files).
+    pub files_changed: Vec<String>,
+    /// Added lines.
+    pub insertions: usize,
+    /// Removed lines.
+    pub deletions: usize,
+}
+
 pub fn check_patch_with_citations(text: &str) -> ContractCheck {
     let citations = has_citations(text);
     let patch = detect_patch_format(text);
     let (has_patch, extracted) = extract_patch(text, patch);
+    let extracted_patches = if patch == PatchFormat::UnifiedDiff {
+        let fenced: Vec<String> = extract_fenced_all(text, "diff")
+            .into_iter()
+            .filter(|p| is_unified_diff(p))
+            .collect();
+        if fenced.is_empty() {
+            extracted.iter().cloned().collect()
+        } else {
+            fenced
+        }
+    } else {
+        Vec::new()
+    };
 
     let mut errors = Vec::new();
     if !has_patch {
@@ -42,13 +93,189 @@ pub fn check_patch_with_citations(text: &str) -> ContractCheck {
     ContractCheck {
         has_patch,
         has_citations: citations,
+        citations: parse_citations(text),
         patch_format: patch,
         extracted_patch: extracted,
+        extracted_patches,
         apply_check: None,
         errors,
     }
 }
 
+/// Like `check_patch_with_citations`, but additionally requires every file touched by a
+/// unified diff to be one of the parsed citations (a citation may carry a longer prefix, e.g. an
+/// absolute path).
+pub fn check_patch_with_citations_strict(text: &str) -> ContractCheck {
+    let mut check = check_patch_with_citations(text);
+    if !check.has_citations {
+        return check;
+    }
+    let mut changed: Vec<String> = Vec::new();
+    for patch in &check.extracted_patches {
+        for path in patch_changed_files(patch) {
+            if !changed.contains(&path) {
+                changed.push(path);
+            }
+        }
+    }
+    for path in changed {
+        let cited = check.citations.iter().any(|c| {
+            let cited = c.path.trim_start_matches("./");
+            cited == path || cited.ends_with(&format!("/{path}"))
+        });
+        if !cited {
+            check.errors.push(format!("uncited file: {path}"));
+        }
+    }
+    check
+}
+
+#[derive(Debug, Clone)]
+pub struct JsonSchemaCheck {
+    /// The extracted JSON value (`None` when no JSON object/array could be parsed).
+    pub value: Option<Value>,
+    pub errors: Vec<String>,
+}
+
+/// Extracts the first JSON object or array from `text` and validates it against `schema`.
+/// Each schema violation is reported as `<instance path>: <message>` (`/` for the root).
+pub fn check_json_schema(text: &str, schema: &Value) -> JsonSchemaCheck {
+    let validator = match jsonschema::validator_for(schema) {
+        Ok(v) => v,
+        Err(e) => {
+            return JsonSchemaCheck {
+                value: None,
+                errors: vec![format!("invalid json schema: {e}")],
+            }
+        }
+    };
+    let Some(value) = extract_json(text) else {
+        return JsonSchemaCheck {
+            value: None,
+            errors: vec!["missing JSON".to_string()],
+        };
+    };
+    let errors = validator
+        .iter_errors(&value)
+        .map(|e| {
+            let path = e.instance_path.to_string();
+            let path = if path.is_empty() { "/" } else { path.as_str() };
+            format!("{path}: {e}")
+        })
+        .collect();
+    JsonSchemaCheck {
+        value: Some(value),
+        errors,
+    }
+}
+
+/// Returns the first JSON object or array embedded in `text` (fenced or inline). Candidates start
+/// at each `{` / `[`; the first one that parses as a complete value wins.
+pub fn extract_json(text: &str) -> Option<Value> {
+    text.char_indices()
+        .filter(|(_, c)| matches!(c, '{' | '['))
+        .find_map(|(i, _)| {
+            serde_json::Deserializer::from_str(&text[i..])
+                .into_iter::<Value>()
+                .next()
+                .and_then(|v| v.ok())
+        })
+}
+
+/// Heuristic language check: the share of letters written in the script expected for `expected`
+/// (an ISO 639-1 code such as `en`, `zh`, `ja`) must reach `LANGUAGE_MIN_SCRIPT_RATIO`.
+/// Fenced code blocks are ignored. Returns an empty list when the text matches.
+pub fn check_language(text: &str, expected: &str) -> Vec<String> {
+    let code = expected.trim().to_ascii_lowercase();
+    let Some(script) = script_for_language(&code) else {
+        return vec![format!("unsupported language: {expected}")];
+    };
+
+    let mut total = 0usize;
+    let mut matching = 0usize;
+    let mut in_fence = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        for c in line.chars().filter(|c| c.is_alphabetic()) {
+            total += 1;
+            if script.contains(c) {
+                matching += 1;
+            }
+        }
+    }
+    if total == 0 {
+        return Vec::new();
+    }
+
+    let ratio = matching as f64 / total as f64;
+    if ratio < LANGUAGE_MIN_SCRIPT_RATIO {
+        vec![format!(
+            "language mismatch: expected {code} ({:.0}% of letters in {} script)",
+            ratio * 100.0,
+            script.name()
+        )]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Collects the bodies of all fenced code blocks whose info string names `lang`
+/// (case-insensitive, e.g. `python` matches ```` ```Python ````). Unterminated blocks are skipped.
+pub fn extract_code_blocks(text: &str, lang: &str) -> Vec<String> {
+    let lang = lang.trim();
+    let mut blocks = Vec::new();
+    let mut current: Option<(bool, Vec<&str>)> = None;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        match current.take() {
+            None => {
+                if let Some(info) = trimmed.strip_prefix("```") {
+                    let tag = info.split_whitespace().next().unwrap_or("");
+                    current = Some((tag.eq_ignore_ascii_case(lang), Vec::new()));
+                }
+            }
+            Some((wanted, mut body)) => {
+                if trimmed.starts_with("```") {
+                    if wanted {
+                        blocks.push(body.join("\n"));
+                    }
+                } else {
+                    body.push(line);
+                    current = Some((wanted, body));
+                }
+            }
+        }
+    }
+    blocks
+}
+
+/// Runs `git apply --check` on each patch independently against the current tree, so patches
+/// that build on one another are not supported.
+pub fn validate_patches(repo_root: &Path, patches: &[String]) -> Vec<PatchCheck> {
+    patches
+        .iter()
+        .enumerate()
+        .map(|(index, patch)| {
+            let (ok, output) = match validate_git_apply_check(repo_root, patch) {
+                Ok(apply) => (apply.ok, apply.output),
+                Err(e) => (false, e.to_string()),
+            };
+            PatchCheck {
+                index,
+                files: patch_changed_files(patch),
+                ok,
+                output,
+            }
+        })
+        .collect()
+}
+
 pub fn validate_git_apply_check(repo_root: &Path, patch: &str) -> Result<ApplyCheck> {
     // Validate we are inside a git repo.
     let mut rev = std::process::Command::new("git");
@@ -94,6 +321,77 @@ pub fn validate_git_apply_check(repo_root: &Path, patch: &str) -> Result<ApplyCh
     })
 }
 
+const LANGUAGE_MIN_SCRIPT_RATIO: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Latin,
+    Cyrillic,
+    Greek,
+    Arabic,
+    Hebrew,
+    Devanagari,
+    Thai,
+    Han,
+    Japanese,
+    Hangul,
+}
+
+impl Script {
+    fn name(&self) -> &'static str {
+        match self {
+            Script::Latin => "latin",
+            Script::Cyrillic => "cyrillic",
+            Script::Greek => "greek",
+            Script::Arabic => "arabic",
+            Script::Hebrew => "hebrew",
+            Script::Devanagari => "devanagari",
+            Script::Thai => "thai",
+            Script::Han => "han",
+            Script::Japanese => "japanese",
+            Script::Hangul => "hangul",
+        }
+    }
+
+    fn contains(&self, c: char) -> bool {
+        let cp = c as u32;
+        let is_han = matches!(cp, 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF);
+        match self {
+            Script::Latin => c.is_ascii_alphabetic() || matches!(cp, 0x00C0..=0x024F),
+            Script::Cyrillic => matches!(cp, 0x0400..=0x052F),
+            Script::Greek => matches!(cp, 0x0370..=0x03FF),
+            Script::Arabic => matches!(cp, 0x0600..=0x06FF | 0x0750..=0x077F),
+            Script::Hebrew => matches!(cp, 0x0590..=0x05FF),
+            Script::Devanagari => matches!(cp, 0x0900..=0x097F),
+            Script::Thai => matches!(cp, 0x0E00..=0x0E7F),
+            Script::Han => is_han,
+            // Japanese mixes kana with kanji.
+            Script::Japanese => is_han || matches!(cp, 0x3040..=0x30FF | 0x31F0..=0x31FF),
+            Script::Hangul => matches!(cp, 0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF),
+        }
+    }
+}
+
+fn script_for_language(code: &str) -> Option<Script> {
+    // Accept region-qualified codes such as `en-US` or `zh_CN`.
+    let base = code.split(['-', '_']).next().unwrap_or(code);
+    let script = match base {
+        "en" | "fr" | "de" | "es" | "it" | "pt" | "nl" | "sv" | "da" | "no" | "nb" | "fi"
+        | "pl" | "cs" | "ro" | "hu" | "tr" | "id" | "vi" => Script::Latin,
+        "ru" | "uk" | "bg" | "sr" => Script::Cyrillic,
+        "el" => Script::Greek,
+        "ar" | "fa" => Script::Arabic,
+        "he" => Script::Hebrew,
+        "hi" => Script::Devanagari,
+        "th" => Script::Thai,
+        "zh" => Script::Han,
+        "ja" => Script::Japanese,
+        "ko" => Script::Hangul,
+        _ => return None,
+    };
+    Some(script)
+}
+
 fn has_citations(text: &str) -> bool {
     // Intentionally conservative: allow a few common citation conventions.
     // - Markdown: "> Source: path:line" or "CITATIONS:" section
@@ -111,7 +409,200 @@ fn has_citations(text: &str) -> bool {
     false
 }
 
-fn detect_patch_format(text: &str) -> PatchFormat {
+/// Collects the text of every citation region: the `CITATIONS:` block (up to the next fence or
+/// diff marker), `> Source:` lines, and inline `[cite:...]` markers.
+pub fn citation_text(text: &str) -> String {
+    let mut out = String::new();
+    let mut in_block = false;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        let lower = trimmed.to_ascii_lowercase();
+        if in_block {
+            if trimmed.starts_with("```") || trimmed.starts_with("diff --git ") {
+                in_block = false;
+            } else {
+                out.push_str(line);
+                out.push('\n');
+                continue;
+            }
+        }
+        if let Some(idx) = lower.find("citations:") {
+            in_block = true;
+            out.push_str(&trimmed[idx + "citations:".len()..]);
+            out.push('\n');
+            continue;
+        }
+        if lower.starts_with("> source:") {
+            out.push_str(line);
+            out.push('\n');
+        }
+        let mut rest = line;
+        while let Some(i) = rest.find("[cite:") {
+            let after = &rest[i..];
+            let end = after.find(']').map(|e| e + 1).unwrap_or(after.len());
+            out.push_str(&after[..end]);
+            out.push('\n');
+            rest = &after[end..];
+        }
+    }
+    out
+}
+
+/// Parses file references out of `citation_text`: each list item or comma-separated entry is
+/// read as `path`, `path:line`, or `path:start-end` followed by optional prose. Entries whose
+/// first word does not look like a file path (no `/` or `.`) are skipped, as are URLs.
+pub fn parse_citations(text: &str) -> Vec<Citation> {
+    static CITATION_RE: OnceLock<Regex> = OnceLock::new();
+    let re = CITATION_RE.get_or_init(|| {
+        Regex::new(r"^(?P<path>[^\s:]+)(?::(?P<line>\d+)(?:-\d+)?)?$").expect("valid regex")
+    });
+
+    let mut out: Vec<Citation> = Vec::new();
+    for line in citation_text(text).lines() {
+        let line = line.trim();
+        let line = line
+            .strip_prefix("[cite:")
+            .map(|rest| rest.trim_end_matches(']'))
+            .unwrap_or(line);
+        let lower = line.to_ascii_lowercase();
+        let line = match lower.strip_prefix("> source:") {
+            Some(_) => &line["> source:".len()..],
+            None => line,
+        };
+        for entry in line.split(',') {
+            let entry = entry.trim_start_matches(|c: char| {
+                c.is_whitespace() || matches!(c, '-' | '*' | '+' | '>')
+            });
+            // Numbered list marker ("1." / "2)").
+            let entry = match entry.split_once(char::is_whitespace) {
+                Some((marker, rest))
+                    if marker.len() > 1
+                        && marker.ends_with(['.', ')'])
+                        && marker[..marker.len() - 1]
+                            .chars()
+                            .all(|c| c.is_ascii_digit()) =>
+                {
+                    rest
+                }
+                _ => entry,
+            };
+            let Some(token) = entry.split_whitespace().next() else {
+                continue;
+            };
+            let token = token
+                .trim_matches(|c: char| matches!(c, '`' | '"' | '\'' | '(' | ')' | '[' | ']'))
+                .trim_end_matches(['.', ';', ':']);
+            if token.contains("://") {
+                continue;
+            }
+            let Some(caps) = re.captures(token) else {
+                continue;
+            };
+            let path = &caps["path"];
+            if !path.contains('/') && !path.contains('.') {
+                continue;
+            }
+            let citation = Citation {
+                path: path.to_string(),
+                line: caps.name("line").and_then(|l| l.as_str().parse().ok()),
+            };
+            if !out.contains(&citation) {
+                out.push(citation);
+            }
+        }
+    }
+    out
+}
+
+/// Sums `PatchStats` over `patches` (e.g. `ContractCheck::extracted_patches`); `None` when there
+/// are none.
+pub fn patch_stats(patches: &[String]) -> Option<PatchStats> {
+    if patches.is_empty() {
+        return None;
+    }
+    let mut stats = PatchStats {
+        files_changed: Vec::new(),
+        insertions: 0,
+        deletions: 0,
+    };
+    for patch in patches {
+        for file in patch_changed_files(patch) {
+            if !stats.files_changed.contains(&file) {
+                stats.files_changed.push(file);
+            }
+        }
+        let (insertions, deletions) = count_hunk_lines(patch);
+        stats.insertions += insertions;
+        stats.deletions += deletions;
+    }
+    Some(stats)
+}
+
+/// Counts added and removed lines, walking each hunk by the line counts in its `@@` header so
+/// content lines that look like `---` / `+++` file headers are not miscounted.
+fn count_hunk_lines(patch: &str) -> (usize, usize) {
+    static HUNK_RE: OnceLock<Regex> = OnceLock::new();
+    let re = HUNK_RE.get_or_init(|| {
+        Regex::new(r"^@@ -\d+(?:,(\d+))? \+\d+(?:,(\d+))? @@").expect("valid hunk regex")
+    });
+    let count = |m: Option<regex::Match>| m.map_or(1, |m| m.as_str().parse::<usize>().unwrap_or(0));
+    let (mut insertions, mut deletions) = (0, 0);
+    let (mut old_left, mut new_left) = (0usize, 0usize);
+    for line in patch.lines() {
+        if old_left == 0 && new_left == 0 {
+            if let Some(caps) = re.captures(line) {
+                old_left = count(caps.get(1));
+                new_left = count(caps.get(2));
+            }
+            continue;
+        }
+        if line.starts_with('+') {
+            insertions += 1;
+            new_left = new_left.saturating_sub(1);
+        } else if line.starts_with('-') {
+            deletions += 1;
+            old_left = old_left.saturating_sub(1);
+        } else if !line.starts_with('\\') {
+            old_left = old_left.saturating_sub(1);
+            new_left = new_left.saturating_sub(1);
+        }
+    }
+    (insertions, deletions)
+}
+
+/// Returns the (post-image) paths touched by a unified diff, in first-seen order.
+fn patch_changed_files(patch: &str) -> Vec<String> {
+    let mut files: Vec<String> = Vec::new();
+    let mut push = |path: &str| {
+        let path = path.trim();
+        if !path.is_empty() && path != "/dev/null" && !files.iter().any(|f| f == path) {
+            files.push(path.to_string());
+        }
+    };
+    let mut pending_old: Option<String> = None;
+    for line in patch.lines() {
+        if let Some(rest) = line.strip_prefix("diff --git ") {
+            if let Some((_, b)) = rest.split_once(" b/") {
+                push(b);
+            }
+        } else if let Some(rest) = line.strip_prefix("--- ") {
+            pending_old = rest.strip_prefix("a/").map(|p| p.trim().to_string());
+        } else if let Some(rest) = line.strip_prefix("+++ ") {
+            match rest.strip_prefix("b/") {
+                Some(p) => push(p),
+                None => {
+                    // Deleted file: the post-image is /dev/null, so report the old path.
+                    if let Some(old) = pending_old.take() {
+                        push(&old);
+                    }
+                }
+            }
+        }
+    }
+    files
+}
+
+pub fn detect_patch_format(text: &str) -> PatchFormat {
     if text.contains("diff --git ") || (text.contains("--- a/") && text.contains("+++ b/")) {
         return PatchFormat::UnifiedDiff;
     }
@@ -129,9 +620,7 @@ fn extract_patch(text: &str, format: PatchFormat) -> (bool, Option<String>) {
         PatchFormat::UnifiedDiff => {
             // Prefer fenced code block ```diff ...```
             if let Some(p) = extract_fenced(text, "diff") {
-                let has =
-                    p.contains("diff --git ") || (p.contains("--- a/") && p.contains("+++ b/"));
-                return (has, Some(p));
+                return (is_unified_diff(&p), Some(p));
             }
             // Fallback: try from first diff marker to end.
             if let Some(idx) = text.find("diff --git ") {
@@ -151,8 +640,18 @@ fn extract_patch(text: &str, format: PatchFormat) -> (bool, Option<String>) {
     }
 }
 
+fn is_unified_diff(patch: &str) -> bool {
+    patch.contains("diff --git ") || (patch.contains("--- a/") && patch.contains("+++ b/"))
+}
+
 fn extract_fenced(text: &str, info: &str) -> Option<String> {
+    extract_fenced_all(text, info).into_iter().next()
+}
+
+/// All non-empty fenced blocks opened with ```` ```{info} ````, in order.
+fn extract_fenced_all(text: &str, info: &str) -> Vec<String> {
     let start = format!("```{}", info);
+    let mut blocks = Vec::new();
     let mut rest = text;
     while let Some(i) = rest.find(&start) {
         let after = &rest[i + start.len()..];
@@ -161,15 +660,16 @@ fn extract_fenced(text: &str, info: &str) -> Option<String> {
             .strip_prefix('\n')
             .or_else(|| after.strip_prefix("\r\n"))
             .unwrap_or(after);
-        if let Some(end) = after.find("```") {
-            let block = after[..end].trim().to_string();
-            if !block.is_empty() {
-                return Some(block);
-            }
+        let Some(end) = after.find("```") else {
+            break;
+        };
+        let block = after[..end].trim().to_string();
+        if !block.is_empty() {
+            blocks.push(block);
         }
-        rest = &after;
+        rest = &after[end + 3..];
     }
-    None
+    blocks
 }
 
 #[cfg(test)]
@@ -194,6 +694,110 @@ mod tests {
         assert!(c.extracted_patch.unwrap().contains("diff --git"));
     }
 
+    #[test]
+    fn strict_citations_reports_uncited_files() {
+        let s = "PATCH\n```diff\ndiff --git a/a.rs b/a.rs\n--- a/a.rs\n+++ b/a.rs\n@@\n-1\n+2\ndiff --git a/b.rs b/b.rs\n--- a/b.rs\n+++ b/b.rs\n@@\n-1\n+2\n```\nCITATIONS:\n- a.rs:1\n";
+        let lenient = check_patch_with_citations(s);
+        assert!(lenient.errors.is_empty(), "errors={:?}", lenient.errors);
+
+        let strict = check_patch_with_citations_strict(s);
+        assert_eq!(strict.errors, vec!["uncited file: b.rs".to_string()]);
+
+        // A longer path ending in the file counts; a name that merely contains it does not.
+        let s = s.replace("- a.rs:1", "- /repo/a.rs:1, xb.rs");
+        let strict = check_patch_with_citations_strict(&s);
+        assert_eq!(strict.errors, vec!["uncited file: b.rs".to_string()]);
+    }
+
+    #[test]
+    fn parses_citation_paths_and_lines() {
+        let s = "PATCH\n```diff\ndiff --git a/src/lib.rs b/src/lib.rs\n--- a/src/lib.rs\n+++ b/src/lib.rs\n@@\n-1\n+2\n```\nCITATIONS:\n- `src/lib.rs:12` (the parser)\n1. src/main.rs:3-9 entry point\n* docs/a.md, Cargo.toml\n- see https://example.com/x.rs\nSome closing prose.\n> Source: ./src/util.rs:40\n[cite:src/lib.rs:12]";
+        let check = check_patch_with_citations(s);
+        let cite = |path: &str, line: Option<u32>| Citation {
+            path: path.to_string(),
+            line,
+        };
+        assert_eq!(
+            check.citations,
+            vec![
+                cite("src/lib.rs", Some(12)),
+                cite("src/main.rs", Some(3)),
+                cite("docs/a.md", None),
+                cite("Cargo.toml", None),
+                cite("./src/util.rs", Some(40)),
+            ]
+        );
+        assert!(check.errors.is_empty(), "errors={:?}", check.errors);
+        assert!(check_patch_with_citations("no refs").citations.is_empty());
+    }
+
+    #[test]
+    fn strict_citations_match_parsed_paths_not_substrings() {
+        // `src/a.rs` only appears as part of a longer path, which does not count as citing it.
+        let s = "PATCH\n```diff\ndiff --git a/src/a.rs b/src/a.rs\n--- a/src/a.rs\n+++ b/src/a.rs\n@@\n-1\n+2\n```\nCITATIONS:\n- old/src/a.rs.bak:1\n";
+        let strict = check_patch_with_citations_strict(s);
+        assert_eq!(strict.errors, vec!["uncited file: src/a.rs".to_string()]);
+
+        let absolute = s.replace("old/src/a.rs.bak:1", "/repo/src/a.rs:1");
+        assert!(check_patch_with_citations_strict(&absolute)
+            .errors
+            .is_empty());
+
+        // Missing patch keeps its own error and nothing else.
+        let no_patch = check_patch_with_citations_strict("CITATIONS:\n- src/a.rs:1");
+        assert_eq!(no_patch.errors, vec!["missing PATCH".to_string()]);
+    }
+
+    #[test]
+    fn language_check_flags_mismatched_script() {
+        assert!(check_language("Looks good to me, ship it.", "en").is_empty());
+        assert!(check_language("这个补丁看起来没有问题。", "zh-CN").is_empty());
+
+        let errors = check_language("这个补丁看起来没有问题，可以合并。", "en");
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0].starts_with("language mismatch: expected en"),
+            "{errors:?}"
+        );
+
+        // Code blocks don't count towards the ratio.
+        let mixed = "结论如下：\n```rust\nfn main() { println!(\"hello world\"); }\n```\n";
+        assert!(check_language(mixed, "zh").is_empty());
+
+        assert_eq!(
+            check_language("hi", "xx"),
+            vec!["unsupported language: xx".to_string()]
+        );
+    }
+
+    #[test]
+    fn json_schema_check_extracts_and_validates_first_value() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "required": ["verdict"],
+            "properties": { "verdict": { "enum": ["approve", "reject"] } }
+        });
+
+        let ok = check_json_schema(
+            "Review below.\n```json\n{\"verdict\": \"approve\"}\n```\n{\"ignored\": 1}",
+            &schema,
+        );
+        assert!(ok.errors.is_empty(), "{:?}", ok.errors);
+        assert_eq!(ok.value, Some(serde_json::json!({"verdict": "approve"})));
+
+        let bad = check_json_schema("{\"verdict\": \"maybe\"}", &schema);
+        assert_eq!(bad.errors.len(), 1);
+        assert!(bad.errors[0].starts_with("/verdict: "), "{:?}", bad.errors);
+        assert!(bad.value.is_some());
+
+        let missing = check_json_schema("no json {here", &schema);
+        assert_eq!(missing.errors, vec!["missing JSON".to_string()]);
+        assert!(missing.value.is_none());
+
+        let invalid = check_json_schema("{}", &serde_json::json!({"type": 5}));
+        assert!(invalid.errors[0].starts_with("invalid json schema: "));
+    }
+
     #[test]
     fn git_apply_check_accepts_valid_patch() {
         let td = tempfile::tempdir().unwrap();
@@ -244,4 +848,97 @@ mod tests {
         let res = validate_git_apply_check(repo, &patch).unwrap();
         assert!(res.ok, "apply-check failed: {}", res.output);
     }
+
+    #[test]
+    fn validates_each_fenced_patch() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path();
+        let git = |args: &[&str]| {
+            let out = Command::new("git")
+                .args(args)
+                .current_dir(repo)
+                .output()
+                .unwrap();
+            assert!(out.status.success(), "git {args:?} failed");
+            String::from_utf8_lossy(&out.stdout).to_string()
+        };
+
+        git(&["init"]);
+        std::fs::write(repo.join("a.txt"), "a\n").unwrap();
+        std::fs::write(repo.join("b.txt"), "b\n").unwrap();
+        git(&["add", "."]);
+        git(&[
+            "-c",
+            "user.email=test@example.com",
+            "-c",
+            "user.name=test",
+            "-c",
+            "commit.gpgsign=false",
+            "commit",
+            "-m",
+            "init",
+        ]);
+        std::fs::write(repo.join("a.txt"), "aa\n").unwrap();
+        std::fs::write(repo.join("b.txt"), "bb\n").unwrap();
+        let patch_a = git(&["diff", "--", "a.txt"]);
+        let patch_b = git(&["diff", "--", "b.txt"]);
+        git(&["checkout", "--", "."]);
+
+        let response = |second: &str| {
+            format!(
+                "PATCH\n```diff\n{patch_a}```\nand\n```diff\n{second}```\nCITATIONS: a.txt, b.txt"
+            )
+        };
+
+        let check = check_patch_with_citations_strict(&response(&patch_b));
+        assert!(check.errors.is_empty(), "{:?}", check.errors);
+        assert_eq!(check.extracted_patches.len(), 2);
+        let results = validate_patches(repo, &check.extracted_patches);
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.ok), "{results:?}");
+        assert_eq!(results[1].files, vec!["b.txt".to_string()]);
+
+        let broken = patch_b.replace("-b\n", "-nope\n");
+        let check = check_patch_with_citations(&response(&broken));
+        let results = validate_patches(repo, &check.extracted_patches);
+        assert!(results[0].ok, "{results:?}");
+        assert!(!results[1].ok);
+        assert_eq!(results[1].index, 1);
+    }
+
+    #[test]
+    fn patch_stats_counts_lines_across_hunks() {
+        let patch = "diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,4 @@
+ use std::fmt;
+-use std::io;
++use std::io::{self, Write};
++use std::path::Path;
+ 
+@@ -20,2 +21,2 @@ fn run() {
+---- old banner
++++++ new banner
+ }
+";
+        let check =
+            check_patch_with_citations(&format!("```diff\n{patch}```\nCITATIONS: src/lib.rs"));
+        let stats = patch_stats(&check.extracted_patches).unwrap();
+        assert_eq!(stats.files_changed, vec!["src/lib.rs".to_string()]);
+        assert_eq!(stats.insertions, 3);
+        assert_eq!(stats.deletions, 2);
+
+        assert_eq!(patch_stats(&[]), None);
+    }
+
+    #[test]
+    fn extracts_code_blocks_by_language() {
+        let s = "```rust\nfn a() {}\n```\n```python3\nx\n```\n```RUST title\nfn b() {}\n```\n```rust\nunterminated";
+        assert_eq!(
+            extract_code_blocks(s, "rust"),
+            vec!["fn a() {}", "fn b() {}"]
+        );
+        assert!(extract_code_blocks(s, "python").is_empty());
+    }
 }
@@ -0,0 +1,91 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Notify;
+
+/// Tracks tool activity for `--idle-shutdown-secs`.
+///
+/// Every tool call holds an [`IdleGuard`] while it runs; the server counts as idle only when no
+/// guard is alive and none has been taken or dropped for the whole idle period.
+#[derive(Clone, Default)]
+pub struct IdleTracker {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    active: AtomicUsize,
+    activity: Notify,
+}
+
+/// Held for the duration of a tool call; see [`IdleTracker::begin`].
+pub struct IdleGuard {
+    inner: Arc<Inner>,
+}
+
+impl Drop for IdleGuard {
+    fn drop(&mut self) {
+        self.inner.active.fetch_sub(1, Ordering::SeqCst);
+        self.inner.activity.notify_one();
+    }
+}
+
+impl IdleTracker {
+    /// Marks the start of a tool call; the returned guard keeps the server busy until dropped.
+    pub fn begin(&self) -> IdleGuard {
+        self.inner.active.fetch_add(1, Ordering::SeqCst);
+        self.inner.activity.notify_one();
+        IdleGuard {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Resolves once `idle` has elapsed with no activity and no call in flight.
+    pub async fn wait_idle(&self, idle: Duration) {
+        loop {
+            let quiet = tokio::time::timeout(idle, self.inner.activity.notified())
+                .await
+                .is_err();
+            if quiet && self.inner.active.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn fires_only_after_idle_period_without_calls() {
+        let tracker = IdleTracker::default();
+        let idle = Duration::from_millis(300);
+        let waiter = {
+            let tracker = tracker.clone();
+            tokio::spawn(async move { tracker.wait_idle(idle).await })
+        };
+
+        // Calls every 100ms keep resetting the timer.
+        for _ in 0..5 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            drop(tracker.begin());
+        }
+        assert!(!waiter.is_finished());
+
+        // A call in flight longer than the idle period also keeps the server alive.
+        let guard = tracker.begin();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(!waiter.is_finished());
+        drop(guard);
+
+        let last_activity = Instant::now();
+        tokio::time::timeout(Duration::from_secs(5), waiter)
+            .await
+            .expect("idle timer should fire")
+            .unwrap();
+        assert!(last_activity.elapsed() >= idle);
+    }
+}
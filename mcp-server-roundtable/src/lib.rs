@@ -2,6 +2,8 @@ pub mod adapter_catalog;
 pub mod backend;
 pub mod config;
 pub mod contract;
+pub mod idle;
+pub mod metrics;
 pub mod personas;
 pub mod server;
 pub mod session_store;
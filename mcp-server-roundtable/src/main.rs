@@ -1,12 +1,13 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
 use mcp_server_roundtable::{
     config::{ConfigLoader, VibeConfig},
-    server::VibeServer,
+    server::{InfoArgs, VibeServer},
     session_store::SessionStore,
 };
 use rmcp::{transport::stdio, ServiceExt};
 use std::path::PathBuf;
+use std::time::Duration;
 
 /// Roundtable MCP router: multi-LLM, session-aware delegator.
 #[derive(Parser, Debug)]
@@ -21,14 +22,87 @@ struct Cli {
     #[arg(long)]
     config: Option<PathBuf>,
 
+    /// Print the JSON Schema of the config file format and exit.
+    #[arg(long)]
+    print_config_schema: bool,
+
     /// Optional session store path (JSON). If omitted, uses ~/.local/share/roundtable/sessions.json.
     #[arg(long)]
     sessions: Option<PathBuf>,
+
+    /// Use a SQLite session database at this path instead of the JSON store (safe to share
+    /// between several server processes).
+    #[cfg(feature = "sqlite")]
+    #[arg(long, conflicts_with = "sessions")]
+    sessions_db: Option<PathBuf>,
+
+    /// Resume stored sessions but never write the session store
+    /// (env: ROUNDTABLE_SESSIONS_READONLY=1).
+    #[arg(long)]
+    sessions_readonly: bool,
+
+    /// Sessions not updated within this many seconds are not resumed, and are pruned at startup.
+    #[arg(long)]
+    session_ttl_secs: Option<u64>,
+
+    /// Optional default working directory for requests that omit `cd` (env: ROUNDTABLE_DEFAULT_CD).
+    #[arg(long)]
+    default_cd: Option<PathBuf>,
+
+    /// Send a trivial prompt to each enabled backend at startup to prime cold model loads.
+    #[arg(long)]
+    warmup: bool,
+
+    /// Exit after this many seconds without tool calls (background roundtables count as busy).
+    #[arg(long)]
+    idle_shutdown_secs: Option<u64>,
+
+    /// Pretty-print tool output JSON (easier to read in clients that show raw tool text).
+    #[arg(long)]
+    pretty_output: bool,
+
+    /// Max tool calls processed at once; further calls queue (fan-out children are not counted).
+    #[arg(long)]
+    max_inflight: Option<usize>,
+
+    /// With --max-inflight, reject calls over the limit with a busy error instead of queueing.
+    #[arg(long, requires = "max_inflight")]
+    max_inflight_nonblocking: bool,
+
+    /// Serve Prometheus metrics at http://<addr>/metrics (e.g. 127.0.0.1:9464).
+    #[cfg(feature = "metrics")]
+    #[arg(long)]
+    metrics_addr: Option<std::net::SocketAddr>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the resolved roles with their backends/models (same as the `info` tool) and exit.
+    /// Exits non-zero when the config is missing or a role does not resolve.
+    PrintConfig {
+        /// Directory whose project config is merged over the user config.
+        #[arg(long, default_value = ".")]
+        cd: PathBuf,
+
+        /// Client identifier used to pick client-specific configs and overrides.
+        #[arg(long)]
+        client: Option<String>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    if cli.print_config_schema {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&VibeConfig::json_schema())?
+        );
+        return Ok(());
+    }
 
     // Config precedence is implemented per-request in `ConfigLoader`:
     // - user config: ~/.config/roundtable/config.json (or --config)
@@ -36,16 +110,122 @@ async fn main() -> Result<()> {
     let user_cfg_path = cli.config.or_else(VibeConfig::default_path);
     let loader = ConfigLoader::new(user_cfg_path);
 
+    if let Some(Command::PrintConfig { cd, client }) = cli.command {
+        // Goes through the `info` tool's loading path; the session store is never touched.
+        let server = VibeServer::new(loader, SessionStore::new(SessionStore::default_path()));
+        let (ok, text) = server
+            .info_text(InfoArgs {
+                cd: cd.to_string_lossy().to_string(),
+                client,
+            })
+            .map_err(|e| anyhow!("{}", e.message))?;
+        print!("{text}");
+        if !ok {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let store_path = cli.sessions.unwrap_or_else(SessionStore::default_path);
-    let store = SessionStore::new(store_path);
+    let mut store = SessionStore::new(store_path);
+    #[cfg(feature = "sqlite")]
+    if let Some(db_path) = cli.sessions_db {
+        store = SessionStore::sqlite(db_path);
+    }
+    let sessions_readonly = cli.sessions_readonly
+        || std::env::var("ROUNDTABLE_SESSIONS_READONLY")
+            .is_ok_and(|v| matches!(v.trim(), "1" | "true" | "yes"));
+    store = store.with_readonly(sessions_readonly);
+    if let Some(ttl) = cli.session_ttl_secs {
+        match store.prune_expired(ttl) {
+            Ok(0) => {}
+            Ok(n) => eprintln!("pruned {n} expired session(s)"),
+            Err(e) => eprintln!("warning: failed to prune expired sessions: {e}"),
+        }
+        store = store.with_ttl_secs(ttl);
+    }
+
+    let mut server = VibeServer::new(loader, store);
+    let default_cd = cli
+        .default_cd
+        .or_else(|| std::env::var_os("ROUNDTABLE_DEFAULT_CD").map(PathBuf::from))
+        .filter(|p| !p.as_os_str().is_empty());
+    if let Some(cd) = default_cd {
+        let canonical = cd
+            .canonicalize()
+            .map_err(|e| anyhow!("invalid --default-cd {}: {e}", cd.display()))?;
+        if !canonical.is_dir() {
+            return Err(anyhow!(
+                "--default-cd is not a directory: {}",
+                canonical.display()
+            ));
+        }
+        server = server.with_default_cd(canonical);
+    }
+
+    if cli.pretty_output {
+        server = server.with_pretty_output(true);
+    }
+    if let Some(max) = cli.max_inflight {
+        if max == 0 {
+            return Err(anyhow!("--max-inflight must be at least 1"));
+        }
+        server = server.with_max_inflight(max, cli.max_inflight_nonblocking);
+    }
+
+    #[cfg(feature = "metrics")]
+    if let Some(addr) = cli.metrics_addr {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|e| anyhow!("failed to bind --metrics-addr {addr}: {e}"))?;
+        let metrics = server.metrics();
+        tokio::spawn(async move {
+            if let Err(e) = mcp_server_roundtable::metrics::serve(metrics, listener).await {
+                eprintln!("metrics server stopped: {e}");
+            }
+        });
+    }
+
+    // Runs in the background so the MCP handshake is not delayed; failures are only logged.
+    // Without --warmup, only backends with `prewarm: true` in the config are primed.
+    let warm = server.clone();
+    let warm_all = cli.warmup;
+    tokio::spawn(async move {
+        let results = if warm_all {
+            warm.warmup().await
+        } else {
+            warm.prewarm().await
+        };
+        for r in results {
+            match r.error {
+                None => eprintln!(
+                    "warmup ok: {} (role {}) in {} ms",
+                    r.backend, r.role, r.elapsed_ms
+                ),
+                Some(e) => eprintln!("warmup failed: {} (role {}): {e}", r.backend, r.role),
+            }
+        }
+    });
 
-    let service = VibeServer::new(loader, store)
-        .serve(stdio())
-        .await
-        .inspect_err(|e| {
-            eprintln!("serving error: {e:?}");
-        })?;
+    let idle = server.idle_tracker();
+    let service = server.serve(stdio()).await.inspect_err(|e| {
+        eprintln!("serving error: {e:?}");
+    })?;
 
-    service.waiting().await?;
+    match cli.idle_shutdown_secs {
+        Some(secs) => {
+            tokio::select! {
+                res = service.waiting() => {
+                    res?;
+                }
+                _ = idle.wait_idle(Duration::from_secs(secs)) => {
+                    eprintln!("idle for {secs}s; shutting down");
+                }
+            }
+        }
+        None => {
+            service.waiting().await?;
+        }
+    }
     Ok(())
 }
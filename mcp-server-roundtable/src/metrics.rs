@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+
+/// Per-backend counters kept by `run_vibe_internal`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Counter {
+    /// Calls dispatched to a role's primary backend.
+    Calls,
+    /// Calls that got an answer from a backend (primary or fallback).
+    Successes,
+    /// Calls where every backend attempt failed.
+    Failures,
+    /// Calls answered by a fallback model instead of the primary.
+    Fallbacks,
+    /// Re-invocations after a model error (one per extra attempt).
+    Retries,
+    /// Backend attempts that hit the call timeout.
+    Timeouts,
+}
+
+impl Counter {
+    const ALL: [Counter; 6] = [
+        Counter::Calls,
+        Counter::Successes,
+        Counter::Failures,
+        Counter::Fallbacks,
+        Counter::Retries,
+        Counter::Timeouts,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Counter::Calls => "roundtable_calls_total",
+            Counter::Successes => "roundtable_successes_total",
+            Counter::Failures => "roundtable_failures_total",
+            Counter::Fallbacks => "roundtable_fallbacks_total",
+            Counter::Retries => "roundtable_retries_total",
+            Counter::Timeouts => "roundtable_timeouts_total",
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            Counter::Calls => "Calls dispatched to a role's primary backend.",
+            Counter::Successes => "Calls answered by a backend.",
+            Counter::Failures => "Calls where every backend attempt failed.",
+            Counter::Fallbacks => "Calls answered by a fallback model.",
+            Counter::Retries => "Backend re-invocations after a model error.",
+            Counter::Timeouts => "Backend attempts that timed out.",
+        }
+    }
+}
+
+/// Shared counter registry; clones share the same counts.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    counts: Arc<Mutex<BTreeMap<(Counter, String), u64>>>,
+}
+
+impl Metrics {
+    pub fn inc(&self, counter: Counter, backend: &str) {
+        if let Ok(mut counts) = self.counts.lock() {
+            *counts.entry((counter, backend.to_string())).or_insert(0) += 1;
+        }
+    }
+
+    pub fn get(&self, counter: Counter, backend: &str) -> u64 {
+        self.counts
+            .lock()
+            .ok()
+            .and_then(|counts| counts.get(&(counter, backend.to_string())).copied())
+            .unwrap_or(0)
+    }
+
+    /// Prometheus text exposition format (version 0.0.4).
+    pub fn render(&self) -> String {
+        let counts = match self.counts.lock() {
+            Ok(counts) => counts.clone(),
+            Err(_) => return String::new(),
+        };
+        let mut out = String::new();
+        for counter in Counter::ALL {
+            let name = counter.name();
+            let _ = writeln!(out, "# HELP {name} {}", counter.help());
+            let _ = writeln!(out, "# TYPE {name} counter");
+            for ((c, backend), value) in &counts {
+                if *c == counter {
+                    let _ = writeln!(out, "{name}{{backend=\"{backend}\"}} {value}");
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Serves `GET /metrics` on `listener` until the task is dropped. Only writes to the TCP socket
+/// (never stdout), so it cannot interfere with the stdio MCP transport.
+#[cfg(feature = "metrics")]
+pub async fn serve(metrics: Metrics, listener: tokio::net::TcpListener) -> std::io::Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 4096];
+            let mut len = 0;
+            while len < buf.len() {
+                match stream.read(&mut buf[len..]).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => len += n,
+                }
+                if buf[..len].windows(4).any(|w| w == b"\r\n\r\n") {
+                    break;
+                }
+            }
+            let head = String::from_utf8_lossy(&buf[..len]);
+            let response = if head.starts_with("GET /metrics ") {
+                let body = metrics.render();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string()
+            };
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_lists_counters_per_backend() {
+        let metrics = Metrics::default();
+        metrics.inc(Counter::Calls, "codex");
+        metrics.inc(Counter::Calls, "codex");
+        metrics.inc(Counter::Timeouts, "kimi");
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE roundtable_calls_total counter"));
+        assert!(text.contains("roundtable_calls_total{backend=\"codex\"} 2\n"));
+        assert!(text.contains("roundtable_timeouts_total{backend=\"kimi\"} 1\n"));
+        assert!(!text.contains("roundtable_failures_total{"));
+        assert_eq!(metrics.get(Counter::Calls, "codex"), 2);
+    }
+}
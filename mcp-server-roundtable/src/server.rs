@@ -1,12 +1,16 @@
 use crate::{
+    adapter_catalog::embedded_adapter_catalog,
     backend,
     config::{
         parse_role_model_ref, resolve_model_options, AdapterConfig, Backend, ConfigLoader,
-        OptionValue,
+        OptionValue, OutputParserConfig, OversizePrompt, PromptSegment, PromptTransport,
+        ResolvedProfile, RoleProfile, VibeConfig,
     },
     contract,
+    idle::IdleTracker,
+    metrics::{Counter, Metrics},
     personas::resolve_persona,
-    session_store::{now_unix_secs, SessionRecord, SessionStore},
+    session_store::{now_unix_secs, CachedResponse, SessionRecord, SessionStore},
 };
 use rmcp::{
     handler::server::{router::tool::ToolRouter, wrapper::Parameters},
@@ -15,32 +19,35 @@ use rmcp::{
     ServerHandler,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 /// Input parameters for a single routed task (used by batch/roundtable internals).
-#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[derive(Debug, Clone, Deserialize, schemars::JsonSchema)]
 pub struct VibeArgs {
-    /// Task instruction
-    #[serde(rename = "PROMPT")]
+    /// Task instruction (may be empty when prompt_file is set)
+    #[serde(rename = "PROMPT", default)]
     pub prompt: String,
 
-    /// Working directory (repo root recommended)
+    /// Working directory (repo root recommended; defaults to the server's --default-cd)
+    #[serde(default)]
     pub cd: String,
 
-    /// Role name (used in session key + optional config mapping)
+    /// Role name (used in session key + config mapping); defaults to the config's `default_role`
     #[serde(default)]
     pub role: Option<String>,
 
-    /// Backend override when not using config (codex|gemini)
+    /// Backend override; must match a configured role's backend, else runs an ad-hoc role on it
     #[serde(default)]
     pub backend: Option<String>,
 
-    /// Model override when not using config
+    /// Model override (replaces the role's model and length routing for this call)
     #[serde(default)]
     pub model: Option<String>,
 
-    /// Reasoning effort override for codex when not using config (low|medium|high|xhigh)
+    /// Reasoning effort override (`model_reasoning_effort` option: low|medium|high|xhigh)
     #[serde(default)]
     pub reasoning_effort: Option<String>,
 
@@ -68,6 +75,36 @@ pub struct VibeArgs {
     #[serde(default)]
     pub validate_patch: bool,
 
+    /// If true, `patch_with_citations` also requires every patched file to be cited.
+    #[serde(default)]
+    pub strict_citations: bool,
+
+    /// What to return as `agent_messages` under `patch_with_citations` (default: message).
+    /// The full response is kept in `raw_message` when a projection applies.
+    #[serde(default)]
+    pub contract_output: Option<ContractOutput>,
+
+    /// Advanced/debug: replaces the role's adapter output parser for this call only
+    /// (same shape as `backend.<id>.adapter.output_parser`).
+    #[serde(default)]
+    #[schemars(with = "Option<serde_json::Value>")]
+    pub output_parser: Option<OutputParserConfig>,
+
+    /// Resolve symlinks in `cd` (default: config `canonicalize_cd`, else true). When false, the
+    /// directory is still validated but the lexically cleaned path is used as the workdir.
+    #[serde(default)]
+    pub canonicalize_cd: Option<bool>,
+
+    /// If set (e.g. `python`), fenced code blocks in that language are returned in `code_blocks`.
+    /// Extraction never affects `success`.
+    #[serde(default)]
+    pub extract_code_lang: Option<String>,
+
+    /// Number of candidate answers to request (`{{ n }}` in adapter templates). Candidates are
+    /// returned in `candidates` when the adapter's parser uses `pick: all`.
+    #[serde(default)]
+    pub n: Option<u32>,
+
     /// Optional client identifier (e.g., claude|codex|opencode)
     #[serde(default)]
     pub client: Option<String>,
@@ -75,6 +112,54 @@ pub struct VibeArgs {
     /// Optional main conversation/session identifier from the host CLI.
     #[serde(default)]
     pub conversation_id: Option<String>,
+
+    /// Roundtable participant name, set internally (not part of the tool schema). When it differs
+    /// from the role, it is folded into the default session key so participants sharing a role
+    /// keep separate sessions.
+    #[serde(skip)]
+    pub participant: Option<String>,
+
+    /// Session group from a roundtable participant or batch task, set internally (not part of the
+    /// tool schema). Calls in the same group share one default session key (it replaces the role
+    /// and participant in the key), so they resume the same backend session.
+    #[serde(skip)]
+    pub session_group: Option<String>,
+
+    /// Set internally for warmup calls (not part of the tool schema): the call runs as usual but
+    /// writes no session record, so throwaway warmup sessions never show up or get resumed.
+    #[serde(skip)]
+    pub ephemeral: bool,
+
+    /// Resolve the backend command and args (persona and guardrails applied) without running it.
+    /// No session record is written.
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// Replaces the role's persona prompt for this call (new sessions only; still skipped on resume).
+    #[serde(default)]
+    pub persona_override: Option<String>,
+
+    /// Forward each agent message to the client as a log notification while the backend runs
+    /// (json_stream parsers only). The final agent_messages is unchanged.
+    #[serde(default)]
+    pub stream: bool,
+
+    /// Read the prompt from this file (relative to `cd`, must stay inside it) when PROMPT is empty
+    #[serde(default)]
+    pub prompt_file: Option<String>,
+
+    /// Override the adapter's prompt_transport for this call (arg|stdin|auto)
+    #[serde(default)]
+    pub prompt_transport: Option<PromptTransport>,
+
+    /// Extra directories the backend may read (relative to `cd` or absolute; must exist)
+    #[serde(default)]
+    pub include_directories: Option<Vec<String>>,
+
+    /// Reuse a stored answer for an identical repo/role/model/options/prompt (24h TTL) instead of
+    /// calling the backend. Only for deterministic prompts.
+    #[serde(default)]
+    pub cache: bool,
 }
 
 /// Input parameters for the roundtable tool.
@@ -85,7 +170,8 @@ pub struct RoundtableArgs {
     #[serde(rename = "TOPIC")]
     pub topic: String,
 
-    /// Working directory (repo root recommended)
+    /// Working directory (repo root recommended; defaults to the server's --default-cd)
+    #[serde(default)]
     pub cd: String,
 
     /// Participant list
@@ -102,12 +188,98 @@ pub struct RoundtableArgs {
     /// Optional main conversation/session identifier from the host CLI.
     #[serde(default)]
     pub conversation_id: Option<String>,
+
+    /// If true, return immediately with an `operation_id` and poll `roundtable-poll` for results.
+    #[serde(default)]
+    pub background: bool,
+
+    /// Max participants running at once; the rest queue. Unbounded if omitted.
+    #[serde(default)]
+    pub max_concurrency: Option<usize>,
+
+    /// Start participant N after N * stagger_ms milliseconds (default: 0, all at once)
+    #[serde(default)]
+    pub stagger_ms: u64,
+
+    /// Optional moderator that synthesizes the contributions into `synthesis` (not with `background`).
+    #[serde(default)]
+    pub moderator: Option<RoundtableModerator>,
+
+    /// `structured` (default) or `transcript`, which also returns every contribution joined into
+    /// one `transcript` string
+    #[serde(default)]
+    pub format: RoundtableFormat,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundtableFormat {
+    #[default]
+    Structured,
+    Transcript,
+}
+
+/// Input parameters for the roundtable-poll tool.
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct RoundtablePollArgs {
+    /// Operation id returned by a `background=true` roundtable call
+    pub operation_id: String,
+}
+
+/// Input parameters for the validate-patch tool.
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ValidatePatchArgs {
+    /// Working directory (repo root recommended; defaults to the server's --default-cd)
+    #[serde(default)]
+    pub cd: String,
+
+    /// Patch text to check with `git apply --check`
+    pub patch: String,
+}
+
+/// Input parameters for the validate-config tool.
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ValidateConfigArgs {
+    /// Working directory (repo root recommended; defaults to the server's --default-cd)
+    #[serde(default)]
+    pub cd: String,
+
+    /// Optional client identifier (e.g., claude|codex|opencode)
+    #[serde(default)]
+    pub client: Option<String>,
+}
+
+/// Input parameters for the resolved-config tool.
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ResolvedConfigArgs {
+    /// Working directory (repo root recommended; defaults to the server's --default-cd)
+    #[serde(default)]
+    pub cd: String,
+
+    /// Optional client identifier (e.g., claude|codex|opencode)
+    #[serde(default)]
+    pub client: Option<String>,
+}
+
+/// Input parameters for the health tool.
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct HealthArgs {
+    /// Working directory (repo root recommended; defaults to the server's --default-cd)
+    #[serde(default)]
+    pub cd: String,
+
+    /// Optional client identifier (e.g., claude|codex|opencode)
+    #[serde(default)]
+    pub client: Option<String>,
 }
 
 /// Input parameters for the batch tool.
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct BatchArgs {
-    /// Working directory (repo root recommended)
+    /// Working directory (repo root recommended; defaults to the server's --default-cd)
+    #[serde(default)]
     pub cd: String,
 
     /// Task list (fan-out)
@@ -124,31 +296,70 @@ pub struct BatchArgs {
     /// Optional main conversation/session identifier from the host CLI.
     #[serde(default)]
     pub conversation_id: Option<String>,
+
+    /// Max backend re-invocations (model fallbacks) shared by all tasks. Unlimited if omitted.
+    #[serde(default)]
+    pub retry_budget: Option<u32>,
+
+    /// Max tasks running at once; the rest queue. Unbounded if omitted.
+    #[serde(default)]
+    pub max_concurrency: Option<usize>,
+
+    /// Parallel mode only: start task N after N * stagger_ms milliseconds (default: 0, all at once)
+    #[serde(default)]
+    pub stagger_ms: u64,
+
+    /// Re-run only the failed tasks up to this many extra rounds. No batch-level retry if omitted.
+    #[serde(default)]
+    pub retry_failed: Option<u32>,
+
+    /// `parallel` (default) fans the tasks out; `sequential` runs them one at a time, in order.
+    #[serde(default)]
+    pub mode: BatchMode,
+
+    /// Sequential mode only: skip the remaining tasks once one fails.
+    #[serde(default)]
+    pub stop_on_error: bool,
+
+    /// Deadline in seconds for the whole batch (retries included). Tasks still running when it
+    /// passes are stopped and fail with "batch deadline exceeded". No limit if omitted.
+    #[serde(default)]
+    pub overall_timeout_secs: Option<u64>,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+    #[default]
+    Parallel,
+    Sequential,
 }
 
 #[derive(Debug, Clone, Deserialize, schemars::JsonSchema)]
 pub struct BatchTask {
-    /// Task instruction
-    #[serde(rename = "PROMPT")]
+    /// Task instruction (may be empty when prompt_file is set)
+    #[serde(rename = "PROMPT", default)]
     pub prompt: String,
 
     /// Optional task label
     #[serde(default)]
     pub name: Option<String>,
 
-    /// Role name (used in session key + optional config mapping)
+    /// Role name (used in session key + config mapping); defaults to the config's `default_role`
     #[serde(default)]
     pub role: Option<String>,
 
-    /// Backend override when not using config (codex|gemini)
+    /// Backend override; must match a configured role's backend, else runs an ad-hoc role on it
     #[serde(default)]
     pub backend: Option<String>,
 
-    /// Model override when not using config
+    /// Model override (replaces the role's model and length routing for this call)
     #[serde(default)]
     pub model: Option<String>,
 
-    /// Reasoning effort override for codex when not using config (low|medium|high|xhigh)
+    /// Reasoning effort override (`model_reasoning_effort` option: low|medium|high|xhigh)
     #[serde(default)]
     pub reasoning_effort: Option<String>,
 
@@ -164,6 +375,10 @@ pub struct BatchTask {
     #[serde(default)]
     pub session_key: Option<String>,
 
+    /// Tasks with the same session group share one session (see `roundtable`'s `session_group`).
+    #[serde(default)]
+    pub session_group: Option<String>,
+
     /// Backend timeout in seconds (default: 600)
     #[serde(default)]
     pub timeout_secs: Option<u64>,
@@ -175,17 +390,103 @@ pub struct BatchTask {
     /// If true, run `git apply --check` on extracted unified diff patches.
     #[serde(default)]
     pub validate_patch: bool,
+
+    /// If true, `patch_with_citations` also requires every patched file to be cited.
+    #[serde(default)]
+    pub strict_citations: bool,
+
+    /// Relay agent messages as log notifications while the task runs (json_stream parsers only)
+    #[serde(default)]
+    pub stream: bool,
+
+    /// Read the prompt from this file (relative to `cd`, must stay inside it) when PROMPT is empty
+    #[serde(default)]
+    pub prompt_file: Option<String>,
+
+    /// Reuse a cached answer for an identical call (deterministic prompts only)
+    #[serde(default)]
+    pub cache: bool,
 }
 
 /// Input parameters for the info tool.
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct InfoArgs {
-    /// Working directory (repo root recommended)
+    /// Working directory (repo root recommended; defaults to the server's --default-cd)
+    #[serde(default)]
+    pub cd: String,
+
+    /// Optional client identifier (e.g., claude|codex|opencode)
+    #[serde(default)]
+    pub client: Option<String>,
+}
+
+/// Input parameters for the list-roles tool.
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ListRolesArgs {
+    /// Working directory (repo root recommended; defaults to the server's --default-cd)
+    #[serde(default)]
+    pub cd: String,
+
+    /// Optional client identifier (e.g., claude|codex|opencode)
+    #[serde(default)]
+    pub client: Option<String>,
+}
+
+/// Input parameters for the list-sessions tool.
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ListSessionsArgs {
+    /// Working directory (repo root recommended; defaults to the server's --default-cd)
+    #[serde(default)]
+    pub cd: String,
+
+    /// Optional client identifier; when set (or with conversation_id), only sessions keyed for
+    /// this client/conversation scope are returned
+    #[serde(default)]
+    pub client: Option<String>,
+
+    /// Optional main conversation/session identifier from the host CLI
+    #[serde(default)]
+    pub conversation_id: Option<String>,
+
+    /// Only return sessions updated strictly after this unix timestamp (seconds)
+    #[serde(default)]
+    pub updated_after_unix_secs: Option<u64>,
+
+    /// Maximum number of sessions to return (newest first); must be at least 1
+    #[serde(default)]
+    pub limit: Option<usize>,
+
+    /// Number of matching sessions to skip before `limit` applies (default: 0)
+    #[serde(default)]
+    pub offset: Option<usize>,
+}
+
+/// Input parameters for the clear-session tool.
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ClearSessionArgs {
+    /// Working directory (repo root recommended; defaults to the server's --default-cd)
+    #[serde(default)]
     pub cd: String,
 
+    /// Remove exactly this session key
+    #[serde(default)]
+    pub session_key: Option<String>,
+
+    /// Remove the session auto-resume would reuse for this role (honors client/conversation_id)
+    #[serde(default)]
+    pub role: Option<String>,
+
+    /// Remove every stored session for this directory (scoped by client/conversation_id if set)
+    #[serde(default)]
+    pub all: bool,
+
     /// Optional client identifier (e.g., claude|codex|opencode)
     #[serde(default)]
     pub client: Option<String>,
+
+    /// Optional main conversation/session identifier from the host CLI
+    #[serde(default)]
+    pub conversation_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, schemars::JsonSchema)]
@@ -206,12 +507,64 @@ pub struct RoundtableParticipant {
 
     #[serde(default)]
     pub force_new_session: bool,
+
+    /// Participants with the same session group share one session instead of one per participant.
+    #[serde(default)]
+    pub session_group: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RoundtableModerator {
+    /// Role that writes the synthesis
+    pub role: String,
+
+    #[serde(default)]
+    pub backend: Option<String>,
+
+    #[serde(default)]
+    pub model: Option<String>,
+
+    #[serde(default)]
+    pub reasoning_effort: Option<String>,
+
+    #[serde(default)]
+    pub force_new_session: bool,
+
+    /// Extra instructions appended to the synthesis prompt
+    #[serde(default)]
+    pub instructions: Option<String>,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractOutput {
+    #[default]
+    Message,
+    Patch,
+    Citations,
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize, schemars::JsonSchema)]
+#[derive(Debug, Clone, Deserialize, Serialize, schemars::JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum OutputContract {
     PatchWithCitations,
+    /// Require the response to be written in `expected` (ISO 639-1 code, e.g. `en`, `zh`).
+    /// Mismatches are reported as warnings unless `enforce` is true.
+    Language {
+        expected: String,
+        #[serde(default)]
+        enforce: bool,
+    },
+    /// Require the response to contain a JSON object/array matching `schema` (JSON Schema).
+    /// The first JSON value in the response is validated; on success it is returned in
+    /// `contract_json`.
+    JsonSchema {
+        #[schemars(with = "serde_json::Map<String, serde_json::Value>")]
+        schema: serde_json::Value,
+    },
 }
 
 #[derive(Debug, Serialize)]
@@ -223,15 +576,77 @@ pub struct VibeOutput {
     pub model: Option<String>,
     pub session_key: String,
     pub resumed: bool,
+    /// Seconds since the resumed session record was last updated (only when `resumed`).
+    pub session_age_secs: Option<u64>,
+    /// Model recorded on the resumed session record, if any (only when `resumed`).
+    pub previous_model: Option<String>,
     pub backend_session_id: String,
     pub agent_messages: String,
+    /// Original response when `contract_output` replaced `agent_messages` with a projection.
+    pub raw_message: Option<String>,
+    /// `warning_list` joined with newlines (`None` when there are none).
     pub warnings: Option<String>,
+    /// Every warning as its own entry, in the order they were raised.
+    pub warning_list: Vec<String>,
     pub contract: Option<String>,
     pub contract_errors: Vec<String>,
+    /// File references parsed from the citations (`patch_with_citations` only).
+    pub citations: Vec<contract::Citation>,
+    /// The JSON value extracted from the response (`json_schema` only, when it matched the schema).
+    pub contract_json: Option<serde_json::Value>,
     pub patch_format: Option<String>,
     pub patch_apply_check_ok: Option<bool>,
     pub patch_apply_check_output: Option<String>,
+    /// `git apply --check` result for each diff in the response (`validate_patch` only);
+    /// `patch_apply_check_ok` is true only if all of them pass.
+    pub patch_checks: Vec<contract::PatchCheck>,
+    /// Files, insertions, and deletions of the extracted unified diffs (`patch_with_citations`
+    /// only, when the response has one and the contract check passes).
+    pub patch_stats: Option<contract::PatchStats>,
+    /// Fenced code blocks matching `extract_code_lang` (empty when not requested).
+    pub code_blocks: Vec<String>,
+    /// Every answer the backend produced; `agent_messages` is the first (before any projection).
+    pub candidates: Vec<String>,
+    /// Exact char count of the prompt sent to the backend (after persona injection/truncation).
+    pub prompt_chars: usize,
+    /// Exact char count of the backend's answer (before any `contract_output` projection).
+    pub response_chars: usize,
+    /// Heuristic token estimate (`prompt_chars / 4`, rounded up); not a tokenizer count.
+    pub prompt_tokens_estimate: usize,
+    /// Heuristic token estimate (`response_chars / 4`, rounded up); not a tokenizer count.
+    pub response_tokens_estimate: usize,
+    /// Args the backend would be invoked with (`dry_run` only).
+    pub rendered_args: Option<Vec<String>>,
+    /// Backend executable that would be spawned (`dry_run` only).
+    pub resolved_command: Option<String>,
+    /// How the prompt would be passed, `arg` or `stdin` (`dry_run` only).
+    pub prompt_transport: Option<String>,
+    /// Backend executable that actually ran (path or name only; never the prompt or args).
+    pub command_used: Option<String>,
+    /// Args the backend actually ran with (the fallback's, if one answered). `${VAR}` references
+    /// stay as written; the prompt is left out when it went over stdin.
+    pub invoked_args: Option<Vec<String>>,
+    /// How the prompt was actually passed, `arg` or `stdin`.
+    pub prompt_transport_used: Option<String>,
+    /// Whether the backend's answer was cut to the adapter's `max_output_chars`.
+    pub truncated: bool,
+    /// Char count of the answer before that cut (only when `truncated`).
+    pub original_len: Option<usize>,
     pub error: Option<String>,
+    /// Stable category of `error` (see `ErrorKind`); `None` on success.
+    pub error_kind: Option<String>,
+    /// The answer came from the response cache (`cache: true`); no backend ran and no session
+    /// record was written.
+    pub cached: bool,
+    /// Whether this call's session record was written (false for cache hits, dry runs, and a
+    /// read-only session store).
+    pub session_persisted: bool,
+    /// A fallback model answered instead of the primary one.
+    pub fallback_used: bool,
+    /// `backend/model@variant` of the fallback that answered (only when `fallback_used`).
+    pub fallback_model_ref: Option<String>,
+    /// Error from the previous candidate that moved the call on to that fallback.
+    pub fallback_trigger: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -239,9 +654,19 @@ struct BatchOutput {
     success: bool,
     cd: String,
     results: Vec<BatchResult>,
+    warnings: Vec<String>,
+    /// One entry per `retry_failed` round that re-ran at least one task.
+    retry_rounds: Vec<BatchRetryRound>,
     error: Option<String>,
 }
 
+#[derive(Debug, Serialize)]
+struct BatchRetryRound {
+    round: u32,
+    retried: usize,
+    recovered: usize,
+}
+
 #[derive(Debug, Serialize)]
 struct BatchResult {
     name: Option<String>,
@@ -249,22 +674,124 @@ struct BatchResult {
     backend: String,
     output: Option<VibeOutput>,
     error: Option<String>,
+    error_kind: Option<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct FanoutTaskSpec {
+    /// Position in the caller's request (participants / tasks); outputs are sorted by it.
+    order: usize,
     name: Option<String>,
     role: String,
     args: VibeArgs,
+    retry_budget: Option<Arc<RetryBudget>>,
+}
+
+/// Batch-wide cap on backend re-invocations, shared by every task in the fan-out.
+#[derive(Debug)]
+struct RetryBudget {
+    remaining: AtomicU32,
+    exhausted: AtomicBool,
+}
+
+impl RetryBudget {
+    fn new(limit: u32) -> Self {
+        Self {
+            remaining: AtomicU32::new(limit),
+            exhausted: AtomicBool::new(false),
+        }
+    }
+
+    /// Takes one unit from the budget; returns false (and marks it exhausted) when none is left.
+    fn try_consume(&self) -> bool {
+        let ok = self
+            .remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        if !ok {
+            self.exhausted.store(true, Ordering::SeqCst);
+        }
+        ok
+    }
 }
 
 #[derive(Debug)]
 struct FanoutResult {
+    /// Position of the task in the fan-out's input (`None` for a join error).
+    index: Option<usize>,
     name: Option<String>,
     role: String,
     result: std::result::Result<VibeOutput, McpError>,
 }
 
+fn batch_deadline_exceeded() -> McpError {
+    ErrorKind::Timeout.error("batch deadline exceeded".to_string())
+}
+
+/// Stable `error_kind` values reported next to the human-readable `error` text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorKind {
+    InvalidParams,
+    BackendFailed,
+    Timeout,
+    ContractViolation,
+    PatchValidationFailed,
+    ModelNotFound,
+    HookFailed,
+}
+
+impl ErrorKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::InvalidParams => "invalid_params",
+            ErrorKind::BackendFailed => "backend_failed",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::ContractViolation => "contract_violation",
+            ErrorKind::PatchValidationFailed => "patch_validation_failed",
+            ErrorKind::ModelNotFound => "model_not_found",
+            ErrorKind::HookFailed => "hook_failed",
+        }
+    }
+
+    fn of_backend_message(msg: &str) -> Self {
+        if msg.contains("timed out") {
+            ErrorKind::Timeout
+        } else if is_model_error_message(msg) {
+            ErrorKind::ModelNotFound
+        } else {
+            ErrorKind::BackendFailed
+        }
+    }
+
+    /// Internal error carrying this kind in its `data`, where `error_kind_of` finds it.
+    fn error(self, message: String) -> McpError {
+        McpError::internal_error(
+            message,
+            Some(serde_json::json!({ "error_kind": self.as_str() })),
+        )
+    }
+}
+
+/// `error_kind` for a failed call: the kind attached by `ErrorKind::error`, else
+/// `invalid_params` for parameter errors. Other internal errors have no kind.
+fn error_kind_of(e: &McpError) -> Option<String> {
+    if let Some(kind) = e
+        .data
+        .as_ref()
+        .and_then(|d| d.get("error_kind"))
+        .and_then(|k| k.as_str())
+    {
+        return Some(kind.to_string());
+    }
+    (e.code == ErrorCode::INVALID_PARAMS).then(|| ErrorKind::InvalidParams.as_str().to_string())
+}
+
+impl FanoutResult {
+    fn failed(&self) -> bool {
+        !matches!(&self.result, Ok(out) if out.error.is_none())
+    }
+}
+
 #[derive(Debug, Clone)]
 struct ModelCandidate {
     backend_id: String,
@@ -274,48 +801,400 @@ struct ModelCandidate {
     options: BTreeMap<String, OptionValue>,
     ref_str: String,
     fallback_error_patterns: Vec<String>,
+    primary: bool,
 }
 
-fn format_model_ref(backend_id: &str, model_id: &str, variant: Option<&str>) -> String {
-    match variant {
-        Some(v) if !v.trim().is_empty() => format!("{backend_id}/{model_id}@{v}"),
-        _ => format!("{backend_id}/{model_id}"),
-    }
-}
-
-fn compute_default_session_key(
-    repo_root: &PathBuf,
-    role: &str,
-    role_id: &str,
-    client_hint: Option<&str>,
-    conversation_id: Option<&str>,
-) -> String {
-    SessionStore::compute_key_with_scope(repo_root, role, role_id, client_hint, conversation_id)
+/// A role's model chain entry: attempted in order, or skipped with a reason.
+enum ChainEntry {
+    Candidate(Box<ModelCandidate>),
+    Filtered { ref_str: String, reason: String },
 }
 
-fn resolve_client_hint(explicit: Option<&str>) -> Result<Option<String>, McpError> {
-    let raw = explicit
-        .map(|s| s.to_string())
-        .or_else(|| std::env::var("ROUNDTABLE_CLIENT").ok());
-    let Some(raw) = raw else {
-        return Ok(None);
-    };
-    let trimmed = raw.trim();
-    if trimmed.is_empty() {
-        return Ok(None);
-    }
-    if !trimmed
-        .chars()
-        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
-    {
-        return Err(McpError::invalid_params(
-            "client must be alphanumeric/underscore/dash".to_string(),
+/// Primary model followed by the backend's fallback chain, with incompatible fallbacks filtered out.
+/// Shared by `run_vibe_internal` (to run the chain) and `info` (to report it).
+fn build_model_chain(cfg: &VibeConfig, profile: &RoleProfile) -> Result<Vec<ChainEntry>, McpError> {
+    let backend_cfg = cfg.backend.get(&profile.backend_id).ok_or_else(|| {
+        McpError::internal_error(
+            format!("missing backend config: {}", profile.backend_id),
             None,
-        ));
-    }
-    Ok(Some(trimmed.to_ascii_lowercase()))
-}
-
+        )
+    })?;
+    let mut chain: Vec<ChainEntry> = Vec::new();
+    let fallback_patterns = backend_cfg
+        .fallback
+        .as_ref()
+        .map(|f| f.patterns.clone())
+        .unwrap_or_default();
+    chain.push(ChainEntry::Candidate(Box::new(ModelCandidate {
+        backend_id: profile.backend_id.clone(),
+        backend: profile.backend,
+        adapter: profile.adapter.clone(),
+        model: profile.model.clone(),
+        options: profile.options.clone(),
+        ref_str: format_model_ref(&profile.backend_id, &profile.model, None),
+        fallback_error_patterns: fallback_patterns,
+        primary: true,
+    })));
+    if let Some(fallback) = backend_cfg.fallback.as_ref() {
+        for fallback_ref in fallback.chain() {
+            let (backend_id, model_id, variant) = parse_role_model_ref(fallback_ref)
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+            let fallback_backend = Backend::parse(&backend_id).ok_or_else(|| {
+                McpError::invalid_params(format!("unsupported backend key: {backend_id}"), None)
+            })?;
+            let fallback_cfg = cfg.backend.get(&backend_id).ok_or_else(|| {
+                McpError::invalid_params(format!("missing backend config: {backend_id}"), None)
+            })?;
+            let adapter = fallback_cfg.adapter.clone().ok_or_else(|| {
+                McpError::invalid_params(
+                    format!("missing adapter config for backend: {backend_id}"),
+                    None,
+                )
+            })?;
+            let compatible = adapter
+                .filesystem_capabilities
+                .as_ref()
+                .map(|allowed| allowed.contains(&profile.capabilities.filesystem))
+                .unwrap_or(true);
+            if compatible {
+                let options = if model_id == "default" {
+                    if variant.is_some() {
+                        return Err(McpError::invalid_params(
+                            "fallback model 'default' does not support variants".to_string(),
+                            None,
+                        ));
+                    }
+                    if let Some(model_cfg) = fallback_cfg.models.get("default") {
+                        resolve_model_options(model_cfg, None)
+                            .map_err(|e| McpError::invalid_params(e.to_string(), None))?
+                    } else {
+                        BTreeMap::new()
+                    }
+                } else {
+                    let model_cfg = fallback_cfg.models.get(&model_id).ok_or_else(|| {
+                        McpError::invalid_params(
+                            format!(
+                                "unknown fallback model '{model_id}' for backend '{backend_id}'"
+                            ),
+                            None,
+                        )
+                    })?;
+                    resolve_model_options(model_cfg, variant.as_deref())
+                        .map_err(|e| McpError::invalid_params(e.to_string(), None))?
+                };
+                let ref_str = format_model_ref(&backend_id, &model_id, variant.as_deref());
+                // Later chain entries need error patterns too, so a target backend without its
+                // own fallback borrows the patterns of the chain it belongs to.
+                let candidate_patterns = fallback_cfg
+                    .fallback
+                    .as_ref()
+                    .map(|f| f.patterns.clone())
+                    .unwrap_or_else(|| fallback.patterns.clone());
+                chain.push(ChainEntry::Candidate(Box::new(ModelCandidate {
+                    backend_id,
+                    backend: fallback_backend,
+                    adapter,
+                    model: model_id,
+                    options,
+                    ref_str,
+                    fallback_error_patterns: candidate_patterns,
+                    primary: false,
+                })));
+            } else {
+                chain.push(ChainEntry::Filtered {
+                    ref_str: format_model_ref(&backend_id, &model_id, variant.as_deref()),
+                    reason: format!(
+                        "unsupported filesystem capability {:?} for backend '{}'",
+                        profile.capabilities.filesystem, backend_id
+                    ),
+                });
+            }
+        }
+    }
+    Ok(chain)
+}
+
+fn format_model_ref(backend_id: &str, model_id: &str, variant: Option<&str>) -> String {
+    match variant {
+        Some(v) if !v.trim().is_empty() => format!("{backend_id}/{model_id}@{v}"),
+        _ => format!("{backend_id}/{model_id}"),
+    }
+}
+
+/// The legacy newline-joined `warnings` string for `list` (`None` when it is empty).
+fn join_warnings(list: &[String]) -> Option<String> {
+    (!list.is_empty()).then(|| list.join("\n"))
+}
+
+/// Joins the present prompt segments in `layout` order, separated by blank lines.
+fn assemble_prompt(
+    layout: &[PromptSegment],
+    persona: Option<&str>,
+    guardrail: Option<&str>,
+    user: &str,
+) -> String {
+    layout
+        .iter()
+        .filter_map(|segment| match segment {
+            PromptSegment::Persona => persona,
+            PromptSegment::Guardrail => guardrail,
+            PromptSegment::User => Some(user),
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Wraps a new-session prompt in the role's `prompt_prefix` / `prompt_suffix`, separated by blank
+/// lines. Blank prefix or suffix text is skipped.
+fn wrap_prompt(prefix: Option<&str>, prompt: String, suffix: Option<&str>) -> String {
+    let prefix = prefix.map(str::trim).filter(|p| !p.is_empty());
+    let suffix = suffix.map(str::trim).filter(|s| !s.is_empty());
+    if prefix.is_none() && suffix.is_none() {
+        return prompt;
+    }
+    prefix
+        .into_iter()
+        .chain(std::iter::once(prompt.as_str()))
+        .chain(suffix)
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Runs a role's `pre_command` / `post_command` (program, then args) in `workdir`. A spawn
+/// failure, timeout, or nonzero exit comes back as a message with the command's output.
+async fn run_role_command(
+    field: &str,
+    command: &[String],
+    workdir: &Path,
+    timeout_secs: u64,
+) -> Result<(), String> {
+    let Some((program, args)) = command.split_first() else {
+        return Ok(());
+    };
+    let run = tokio::process::Command::new(program)
+        .args(args)
+        .current_dir(workdir)
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = match tokio::time::timeout(std::time::Duration::from_secs(timeout_secs), run).await
+    {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(format!("{field} {program:?} failed to start: {e}")),
+        Err(_) => {
+            return Err(format!(
+                "{field} {program:?} timed out after {timeout_secs}s"
+            ))
+        }
+    };
+    if output.status.success() {
+        return Ok(());
+    }
+    let text = [&output.stdout, &output.stderr]
+        .iter()
+        .map(|bytes| String::from_utf8_lossy(bytes).trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    Err(format!(
+        "{field} {program:?} exited with {}: {text}",
+        output.status
+    ))
+}
+
+/// Persona block injected at the start of a new session.
+fn format_persona_block(role_id: &str, persona: &str) -> String {
+    format!("[ROUNDTABLE_PERSONA id={role_id}]\n{persona}\n[/ROUNDTABLE_PERSONA]")
+}
+
+/// Chars a new-session prompt carries besides the user's text, mirroring how
+/// `run_vibe_internal` and the backend assemble it.
+fn prompt_overhead_chars(
+    layout: Option<&[PromptSegment]>,
+    persona_block: Option<&str>,
+    guardrail: Option<&str>,
+    prefix: Option<&str>,
+    suffix: Option<&str>,
+) -> usize {
+    let wrapped = wrap_prompt(prefix, String::new(), suffix).chars().count();
+    let base = match layout {
+        Some(layout) => assemble_prompt(layout, persona_block, guardrail, ""),
+        None => persona_block
+            .map(|block| format!("{block}\n\n"))
+            .unwrap_or_default(),
+    };
+    let appended = match guardrail {
+        // Guardrails not placed by the layout are prepended before a newline.
+        Some(g) if !base.contains(g) => g.chars().count() + 1,
+        _ => 0,
+    };
+    base.chars().count() + appended + wrapped
+}
+
+/// Workdir used for a request: the canonical path, or (when `canonicalize` is false) the given
+/// path made absolute and lexically cleaned, without resolving symlinks.
+fn workdir_for(cd: &Path, canonical: PathBuf, canonicalize: bool) -> PathBuf {
+    if canonicalize {
+        return canonical;
+    }
+    let abs = if cd.is_absolute() {
+        cd.to_path_buf()
+    } else {
+        match std::env::current_dir() {
+            Ok(dir) => dir.join(cd),
+            Err(_) => return canonical,
+        }
+    };
+    let mut out = PathBuf::new();
+    for c in abs.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other.as_os_str()),
+        }
+    }
+    out
+}
+
+fn compute_default_session_key(
+    repo_root: &PathBuf,
+    role: &str,
+    role_id: &str,
+    client_hint: Option<&str>,
+    conversation_id: Option<&str>,
+) -> String {
+    SessionStore::compute_key_with_scope(repo_root, role, role_id, client_hint, conversation_id)
+}
+
+fn session_scope_role(role: &str, participant: Option<&str>) -> String {
+    match participant.map(str::trim) {
+        Some(name) if !name.is_empty() && name != role => format!("{role}#{name}"),
+        _ => role.to_string(),
+    }
+}
+
+/// Role and role id components of the default session key. A `session_group` replaces both, so
+/// every call in the group lands on the same key whatever its role or participant name.
+fn session_scope(
+    role: &str,
+    role_id: &str,
+    participant: Option<&str>,
+    session_group: Option<&str>,
+) -> (String, String) {
+    match session_group.map(str::trim).filter(|g| !g.is_empty()) {
+        Some(group) => {
+            let scope = format!("group:{group}");
+            (scope.clone(), scope)
+        }
+        None => (session_scope_role(role, participant), role_id.to_string()),
+    }
+}
+
+/// Resolves the profile `args` will run with, honoring its backend/model/reasoning_effort overrides.
+fn resolve_call_profile(cfg: &VibeConfig, args: &VibeArgs) -> anyhow::Result<ResolvedProfile> {
+    let mut cfg = cfg.clone();
+    let role = cfg.apply_call_overrides(
+        args.role.as_deref(),
+        args.backend.as_deref(),
+        args.model.as_deref(),
+        args.reasoning_effort.as_deref(),
+    )?;
+    cfg.resolve_profile_for_prompt(role.as_deref(), args.prompt.chars().count())
+}
+
+/// The `session_key` a fan-out task will lock in `run_vibe_internal`, or `None` when its role does
+/// not resolve (the task then fails on its own).
+fn fanout_session_key(
+    cfg: &VibeConfig,
+    repo_root: &Path,
+    args: &VibeArgs,
+    client: Option<&str>,
+    conversation_id: Option<&str>,
+) -> Option<String> {
+    if let Some(key) = args
+        .session_key
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+    {
+        return Some(key.to_string());
+    }
+    let resolved = resolve_call_profile(cfg, args).ok()?;
+    let task_root = workdir_for(
+        Path::new(&args.cd),
+        repo_root.to_path_buf(),
+        args.canonicalize_cd.or(cfg.canonicalize_cd).unwrap_or(true),
+    );
+    let (scope_role, scope_role_id) = session_scope(
+        &resolved.role_id,
+        &resolved.role_id,
+        args.participant.as_deref(),
+        args.session_group.as_deref(),
+    );
+    Some(compute_default_session_key(
+        &task_root,
+        &scope_role,
+        &scope_role_id,
+        args.client.as_deref().or(client),
+        args.conversation_id.as_deref().or(conversation_id),
+    ))
+}
+
+/// Rough token count for budgeting (~4 chars per token); not tied to any tokenizer.
+fn estimate_tokens(chars: usize) -> usize {
+    chars.div_ceil(4)
+}
+
+const PROMPT_TRUNCATION_MARKER: &str = "\n[... prompt truncated ...]\n";
+
+/// Trims `prompt` to at most `max_chars` chars, replacing the dropped part with a marker.
+fn truncate_prompt(prompt: &str, max_chars: usize, policy: OversizePrompt) -> String {
+    let marker_len = PROMPT_TRUNCATION_MARKER.chars().count();
+    let total = prompt.chars().count();
+    if max_chars <= marker_len {
+        return match policy {
+            OversizePrompt::TruncateHead => prompt.chars().skip(total - max_chars).collect(),
+            _ => prompt.chars().take(max_chars).collect(),
+        };
+    }
+    let keep = max_chars - marker_len;
+    match policy {
+        OversizePrompt::TruncateHead => {
+            let tail: String = prompt.chars().skip(total - keep).collect();
+            format!("{PROMPT_TRUNCATION_MARKER}{tail}")
+        }
+        _ => {
+            let head: String = prompt.chars().take(keep).collect();
+            format!("{head}{PROMPT_TRUNCATION_MARKER}")
+        }
+    }
+}
+
+fn resolve_client_hint(explicit: Option<&str>) -> Result<Option<String>, McpError> {
+    let raw = explicit
+        .map(|s| s.to_string())
+        .or_else(|| std::env::var("ROUNDTABLE_CLIENT").ok());
+    let Some(raw) = raw else {
+        return Ok(None);
+    };
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    if !trimmed
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(McpError::invalid_params(
+            "client must be alphanumeric/underscore/dash".to_string(),
+            None,
+        ));
+    }
+    Ok(Some(trimmed.to_ascii_lowercase()))
+}
+
 fn resolve_conversation_hint(explicit: Option<&str>) -> Result<Option<String>, McpError> {
     let raw = explicit
         .map(|s| s.to_string())
@@ -370,6 +1249,10 @@ struct RoundtableOutput {
     topic: String,
     cd: String,
     contributions: Vec<RoundtableContribution>,
+    /// Moderator's synthesis of the contributions (only when a `moderator` was given).
+    synthesis: Option<String>,
+    /// Contributions joined into one text (only with `format: "transcript"`).
+    transcript: Option<String>,
     error: Option<String>,
 }
 
@@ -383,6 +1266,124 @@ struct InfoOutput {
     error: Option<String>,
 }
 
+#[derive(Debug, Serialize)]
+struct ListRolesOutput {
+    success: bool,
+    cd: String,
+    roles: Vec<ListRolesEntry>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ListRolesEntry {
+    role: String,
+    enabled: bool,
+    /// Backend id from the role's model reference; `None` when it cannot be parsed.
+    backend: Option<String>,
+    /// Why the role's model reference is unusable; other problems are left to validate-config.
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ListSessionsOutput {
+    success: bool,
+    cd: String,
+    /// Number of matching sessions before `offset` / `limit` were applied.
+    total: usize,
+    sessions: Vec<SessionEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct SessionEntry {
+    session_key: String,
+    role: String,
+    role_id: String,
+    backend: String,
+    backend_session_id: String,
+    /// True when there is no backend session id to resume (e.g. kimi's "stateless").
+    stateless: bool,
+    model: Option<String>,
+    updated_at_unix_secs: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct ClearSessionOutput {
+    success: bool,
+    cd: String,
+    removed: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ValidatePatchOutput {
+    ok: bool,
+    output: String,
+    format: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ValidateConfigOutput {
+    success: bool,
+    cd: String,
+    config_sources: Vec<String>,
+    /// Config-level problems (backends, fallbacks, client overrides) not tied to one role.
+    errors: Vec<String>,
+    roles: Vec<ValidateConfigRole>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ValidateConfigRole {
+    role: String,
+    /// `ok`, `error`, or `disabled` (disabled roles are not resolved).
+    status: String,
+    errors: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ResolvedConfigOutput {
+    success: bool,
+    cd: String,
+    /// Loaded files in merge order (user, then project); later ones override earlier ones.
+    config_sources: Vec<String>,
+    /// The merged config with adapter catalog defaults and client overrides applied.
+    config: Option<VibeConfig>,
+    /// Fields whose `${VAR}` references are shown as written (expanded only at spawn time).
+    env_interpolated: Vec<String>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct HealthOutput {
+    success: bool,
+    cd: String,
+    /// One entry per backend used by an enabled role.
+    backends: Vec<HealthBackend>,
+    /// Roles that could not be resolved (their backend is not probed).
+    errors: Vec<String>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct HealthBackend {
+    backend: String,
+    /// Enabled roles that use this backend.
+    roles: Vec<String>,
+    command: String,
+    args: Vec<String>,
+    /// `ok` or `error`.
+    status: String,
+    output: Option<String>,
+    error: Option<String>,
+    /// Reused from an earlier probe (`limits.probe_cache_secs`) instead of run now.
+    cached: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct AdaptersOutput {
+    success: bool,
+    adapters: BTreeMap<String, AdapterConfig>,
+}
+
 #[derive(Debug, Serialize)]
 struct InfoRole {
     role: String,
@@ -393,34 +1394,339 @@ struct InfoRole {
     prompt_present: bool,
     prompt_len: Option<usize>,
     prompt_preview: Option<String>,
+    /// Chars added to every new-session prompt before the user's text (persona block, guardrail,
+    /// separators); `None` for disabled roles.
+    prompt_overhead_chars: Option<usize>,
+    /// Models `roundtable` would try, in order (empty for disabled roles).
+    candidate_chain: Vec<InfoCandidate>,
+    /// argv for a new session with a placeholder prompt, `${VAR}` references left unexpanded;
+    /// `None` for disabled roles.
+    rendered_args_example: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize)]
-struct RoundtableContribution {
-    name: String,
-    role: String,
-    backend: String,
-    role_id: String,
-    resumed: bool,
-    backend_session_id: String,
-    agent_messages: String,
+struct InfoCandidate {
+    #[serde(rename = "ref")]
+    ref_str: String,
+    /// Why this fallback would be skipped (`None` when it would be attempted).
+    filtered_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct RoundtableStartOutput {
+    success: bool,
+    operation_id: String,
+    topic: String,
+    cd: String,
+    participants: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct RoundtablePollOutput {
+    success: bool,
+    operation_id: String,
+    topic: String,
+    cd: String,
+    done: bool,
+    total: usize,
+    completed: usize,
+    contributions: Vec<RoundtableContribution>,
+    /// Contributions so far joined into one text (only with `format: "transcript"`).
+    transcript: Option<String>,
     error: Option<String>,
 }
 
-#[derive(Clone)]
-pub struct VibeServer {
-    tool_router: ToolRouter<VibeServer>,
-    config_loader: ConfigLoader,
-    store: SessionStore,
+/// In-flight (or finished but not yet polled) background roundtable.
+#[derive(Debug)]
+struct RoundtableOperation {
+    topic: String,
+    cd: String,
+    total: usize,
+    /// Contributions in completion order, each with its participant index.
+    contributions: Vec<(usize, RoundtableContribution)>,
+    done: bool,
+    error: Option<String>,
+    format: RoundtableFormat,
+    /// When the fan-out finished; unpolled operations are evicted `FINISHED_OPERATION_TTL` later.
+    finished_at: Option<std::time::Instant>,
 }
 
-impl VibeServer {
+/// How long a finished background roundtable waits for its final poll before it is dropped.
+const FINISHED_OPERATION_TTL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Drops finished operations nobody polled within `FINISHED_OPERATION_TTL`.
+fn evict_stale_operations(ops: &mut BTreeMap<String, RoundtableOperation>) {
+    ops.retain(|_, op| {
+        op.finished_at
+            .is_none_or(|at| at.elapsed() < FINISHED_OPERATION_TTL)
+    });
+}
+
+struct PreparedRoundtable {
+    topic: String,
+    repo_root: PathBuf,
+    tasks: Vec<FanoutTaskSpec>,
+    client_hint: Option<String>,
+    conversation_hint: Option<String>,
+    max_concurrency: Option<usize>,
+    stagger_ms: u64,
+    /// Moderator call; its prompt is filled in once the contributions are in.
+    moderator: Option<VibeArgs>,
+    format: RoundtableFormat,
+}
+
+fn next_operation_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let seq = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    format!("rt-{nanos:x}-{seq}")
+}
+
+fn roundtable_contribution(res: &FanoutResult) -> RoundtableContribution {
+    match &res.result {
+        Ok(out) => RoundtableContribution {
+            name: res.name.clone().unwrap_or_default(),
+            role: out.role.clone(),
+            backend: out.backend.clone(),
+            role_id: out.role_id.clone(),
+            resumed: out.resumed,
+            backend_session_id: out.backend_session_id.clone(),
+            agent_messages: out.agent_messages.clone(),
+            error: out.error.clone(),
+            error_kind: out.error_kind.clone(),
+        },
+        Err(e) => RoundtableContribution {
+            name: res.name.clone().unwrap_or_default(),
+            role: res.role.clone(),
+            backend: "error".to_string(),
+            role_id: "".to_string(),
+            resumed: false,
+            backend_session_id: "".to_string(),
+            agent_messages: "".to_string(),
+            error: Some(e.to_string()),
+            error_kind: error_kind_of(e),
+        },
+    }
+}
+
+/// `format: "transcript"` text: one `### name (role/backend)` section per contribution, with a
+/// failed participant's error in place of its answer.
+fn roundtable_transcript(contributions: &[RoundtableContribution]) -> String {
+    contributions
+        .iter()
+        .map(|c| {
+            let body = match &c.error {
+                Some(e) => format!("[error: {e}]"),
+                None => c.agent_messages.trim_end().to_string(),
+            };
+            format!("### {} ({}/{})\n{body}", c.name, c.role, c.backend)
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Prompt for the roundtable moderator: the topic, every contribution, then `instructions`.
+fn roundtable_synthesis_prompt(
+    topic: &str,
+    contributions: &[RoundtableContribution],
+    instructions: &str,
+) -> String {
+    let mut prompt = format!("TOPIC:\n{}\n\nTRANSCRIPT:\n", topic.trim());
+    for c in contributions {
+        prompt.push_str(&format!("\n--- {} (role: {}) ---\n", c.name, c.role));
+        match &c.error {
+            Some(e) => prompt.push_str(&format!("[no contribution: {e}]\n")),
+            None => {
+                prompt.push_str(c.agent_messages.trim_end());
+                prompt.push('\n');
+            }
+        }
+    }
+    prompt.push_str(
+        "\nYou are the roundtable moderator. Synthesize the contributions above into:\n\
+1) Points of agreement\n\
+2) Open disagreements\n\
+3) Final recommendation (actionable)\n",
+    );
+    if !instructions.trim().is_empty() {
+        prompt.push_str(&format!(
+            "\nAdditional instructions:\n{}\n",
+            instructions.trim()
+        ));
+    }
+    prompt
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct RoundtableContribution {
+    name: String,
+    role: String,
+    backend: String,
+    role_id: String,
+    resumed: bool,
+    backend_session_id: String,
+    agent_messages: String,
+    error: Option<String>,
+    error_kind: Option<String>,
+}
+
+/// A backend CLI probe (`backend::probe`), run now or reused from the probe cache.
+#[derive(Debug, Clone)]
+pub struct BackendProbe {
+    pub command: String,
+    pub args: Vec<String>,
+    /// What the probe printed, or why it failed.
+    pub outcome: Result<String, String>,
+    /// Reused from an earlier probe (`limits.probe_cache_secs`) instead of run now.
+    pub cached: bool,
+}
+
+/// A successful probe, reused for `limits.probe_cache_secs`.
+#[derive(Debug, Clone)]
+struct CachedProbe {
+    command: String,
+    args: Vec<String>,
+    output: String,
+    probed_at: std::time::Instant,
+}
+
+const WARMUP_PROMPT: &str = "Reply with OK.";
+const WARMUP_TIMEOUT_SECS: u64 = 120;
+
+/// Outcome of one `--warmup` call.
+#[derive(Debug)]
+pub struct WarmupResult {
+    pub backend: String,
+    pub role: String,
+    pub error: Option<String>,
+    /// Wall time of the warmup call, spawn included.
+    pub elapsed_ms: u64,
+}
+
+#[derive(Clone)]
+pub struct VibeServer {
+    tool_router: ToolRouter<VibeServer>,
+    config_loader: ConfigLoader,
+    store: SessionStore,
+    /// Successful probes by backend id; dropped when that backend fails to spawn.
+    probe_cache: Arc<Mutex<BTreeMap<String, CachedProbe>>>,
+    operations: Arc<Mutex<BTreeMap<String, RoundtableOperation>>>,
+    default_cd: Option<PathBuf>,
+    idle: IdleTracker,
+    metrics: Metrics,
+    inflight: Option<InflightLimit>,
+    pretty_output: bool,
+}
+
+/// Server-wide cap on concurrent tool calls (`--max-inflight`).
+#[derive(Clone)]
+struct InflightLimit {
+    slots: Arc<tokio::sync::Semaphore>,
+    max: usize,
+    nonblocking: bool,
+}
+
+impl VibeServer {
     pub fn new(config_loader: ConfigLoader, store: SessionStore) -> Self {
         Self {
             tool_router: Self::tool_router(),
             config_loader,
             store,
+            probe_cache: Arc::new(Mutex::new(BTreeMap::new())),
+            operations: Arc::new(Mutex::new(BTreeMap::new())),
+            default_cd: None,
+            idle: IdleTracker::default(),
+            metrics: Metrics::default(),
+            inflight: None,
+            pretty_output: false,
+        }
+    }
+
+    /// Per-backend call counters (exported with `--metrics-addr`).
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+
+    /// Activity tracker shared by all tool calls (used for `--idle-shutdown-secs`).
+    pub fn idle_tracker(&self) -> IdleTracker {
+        self.idle.clone()
+    }
+
+    /// Working directory used by all tools when a request omits `cd`.
+    pub fn with_default_cd(mut self, cd: PathBuf) -> Self {
+        self.default_cd = Some(cd);
+        self
+    }
+
+    /// At most `max` tool calls run at once. Extra calls queue, or fail as busy when
+    /// `nonblocking`. Fan-out children run inside their parent call and do not take a slot.
+    pub fn with_max_inflight(mut self, max: usize, nonblocking: bool) -> Self {
+        self.inflight = Some(InflightLimit {
+            slots: Arc::new(tokio::sync::Semaphore::new(max)),
+            max,
+            nonblocking,
+        });
+        self
+    }
+
+    /// Pretty-print tool output JSON (`--pretty-output`); compact by default.
+    pub fn with_pretty_output(mut self, pretty: bool) -> Self {
+        self.pretty_output = pretty;
+        self
+    }
+
+    fn to_json<T: Serialize>(&self, out: &T) -> Result<String, McpError> {
+        let json = if self.pretty_output {
+            serde_json::to_string_pretty(out)
+        } else {
+            serde_json::to_string(out)
+        };
+        json.map_err(|e| McpError::internal_error(format!("failed to serialize output: {e}"), None))
+    }
+
+    /// Takes a `--max-inflight` slot for one tool call; held until the returned permit drops.
+    async fn begin_inflight(&self) -> Result<Option<tokio::sync::OwnedSemaphorePermit>, McpError> {
+        let Some(limit) = self.inflight.as_ref() else {
+            return Ok(None);
+        };
+        if limit.nonblocking {
+            return limit
+                .slots
+                .clone()
+                .try_acquire_owned()
+                .map(Some)
+                .map_err(|_| {
+                    McpError::internal_error(
+                        format!(
+                            "server busy: {} tool calls already in flight (--max-inflight)",
+                            limit.max
+                        ),
+                        None,
+                    )
+                });
+        }
+        limit
+            .slots
+            .clone()
+            .acquire_owned()
+            .await
+            .map(Some)
+            .map_err(|_| McpError::internal_error("in-flight limiter closed", None))
+    }
+
+    fn effective_cd(&self, cd: &str) -> Result<PathBuf, McpError> {
+        if !cd.trim().is_empty() {
+            return Ok(PathBuf::from(cd));
         }
+        self.default_cd.clone().ok_or_else(|| {
+            McpError::invalid_params(
+                "cd is required and must be a non-empty string (or start the server with --default-cd)",
+                None,
+            )
+        })
     }
 }
 
@@ -436,43 +1742,199 @@ impl VibeServer {
         peer: Peer<RoleServer>,
         Parameters(args): Parameters<BatchArgs>,
     ) -> Result<CallToolResult, McpError> {
+        let _idle = self.idle.begin();
+        let _inflight = self.begin_inflight().await?;
         let out = self.run_batch_internal(Some(peer), args).await?;
-        let json = serde_json::to_string(&out).map_err(|e| {
-            McpError::internal_error(format!("failed to serialize output: {e}"), None)
-        })?;
+        let json = self.to_json(&out)?;
         Ok(CallToolResult::success(vec![Content::text(json)]))
     }
     /// Run a multi-role discussion on a topic and return participant contributions.
     #[tool(
         name = "roundtable",
-        description = "Fan-out a topic to multiple roles (conductor synthesizes externally)"
+        description = "Fan-out a topic to multiple roles (optional moderator synthesizes the contributions)"
     )]
     async fn roundtable(
         &self,
         peer: Peer<RoleServer>,
         Parameters(args): Parameters<RoundtableArgs>,
     ) -> Result<CallToolResult, McpError> {
-        if args.topic.trim().is_empty() {
-            return Err(McpError::invalid_params(
-                "TOPIC is required and must be a non-empty string",
-                None,
-            ));
-        }
-        if args.cd.trim().is_empty() {
-            return Err(McpError::invalid_params(
-                "cd is required and must be a non-empty string",
-                None,
-            ));
-        }
-        if args.participants.is_empty() {
-            return Err(McpError::invalid_params(
-                "participants must be a non-empty array",
-                None,
-            ));
-        }
+        let _idle = self.idle.begin();
+        let inflight = self.begin_inflight().await?;
+        let json = if args.background {
+            let out = self.start_roundtable_operation(Some(peer), args, inflight)?;
+            self.to_json(&out)?
+        } else {
+            let out = self.run_roundtable_internal(Some(peer), args).await?;
+            self.to_json(&out)?
+        };
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Poll a background roundtable for the contributions completed so far.
+    #[tool(
+        name = "roundtable-poll",
+        description = "Poll a background roundtable (background=true) for partial or final contributions"
+    )]
+    async fn roundtable_poll(
+        &self,
+        Parameters(args): Parameters<RoundtablePollArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let _idle = self.idle.begin();
+        let _inflight = self.begin_inflight().await?;
+        let out = self.poll_roundtable_internal(args)?;
+        let json = self.to_json(&out)?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Show effective config (roles -> models) without calling any LLM.
+    #[tool(
+        name = "info",
+        description = "Show effective roundtable role/model mapping for this directory"
+    )]
+    async fn info(
+        &self,
+        Parameters(args): Parameters<InfoArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let _idle = self.idle.begin();
+        let _inflight = self.begin_inflight().await?;
+        let out = self.info_internal(args)?;
+        let json = self.to_json(&out)?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// List configured role names only (no profile resolution, no LLM call).
+    #[tool(
+        name = "list-roles",
+        description = "List role names for this directory with enabled flag and backend (lightweight info)"
+    )]
+    async fn list_roles(
+        &self,
+        Parameters(args): Parameters<ListRolesArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let _idle = self.idle.begin();
+        let _inflight = self.begin_inflight().await?;
+        let out = self.list_roles_internal(args)?;
+        let json = self.to_json(&out)?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Show the embedded adapter catalog (how each backend CLI is invoked and parsed).
+    #[tool(
+        name = "adapters",
+        description = "Show the embedded adapter catalog (args templates, output parsers, capabilities)"
+    )]
+    async fn adapters(&self) -> Result<CallToolResult, McpError> {
+        let _idle = self.idle.begin();
+        let _inflight = self.begin_inflight().await?;
+        let out = AdaptersOutput {
+            success: true,
+            adapters: embedded_adapter_catalog().adapters,
+        };
+        let json = self.to_json(&out)?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// List stored sessions for this directory (no LLM call).
+    #[tool(
+        name = "list-sessions",
+        description = "List stored role sessions for this directory and whether each can resume"
+    )]
+    async fn list_sessions(
+        &self,
+        Parameters(args): Parameters<ListSessionsArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let _idle = self.idle.begin();
+        let _inflight = self.begin_inflight().await?;
+        let out = self.list_sessions_internal(args)?;
+        let json = self.to_json(&out)?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Drop stored sessions so the next call starts fresh (no LLM call).
+    #[tool(
+        name = "clear-session",
+        description = "Remove stored sessions by session_key, role, or all for this directory"
+    )]
+    async fn clear_session(
+        &self,
+        Parameters(args): Parameters<ClearSessionArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let _idle = self.idle.begin();
+        let _inflight = self.begin_inflight().await?;
+        let out = self.clear_session_internal(args).await?;
+        let json = self.to_json(&out)?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Check a patch against the repo with `git apply --check` (no LLM call).
+    #[tool(
+        name = "validate-patch",
+        description = "Run git apply --check on a user-supplied patch and report its format"
+    )]
+    async fn validate_patch(
+        &self,
+        Parameters(args): Parameters<ValidatePatchArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let _idle = self.idle.begin();
+        let _inflight = self.begin_inflight().await?;
+        let out = self.validate_patch_internal(args)?;
+        let json = self.to_json(&out)?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Check the effective config and report every problem at once (no LLM call).
+    #[tool(
+        name = "validate-config",
+        description = "Validate the effective roundtable config for this directory and list all errors per role"
+    )]
+    async fn validate_config(
+        &self,
+        Parameters(args): Parameters<ValidateConfigArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let _idle = self.idle.begin();
+        let _inflight = self.begin_inflight().await?;
+        let out = self.validate_config_internal(args)?;
+        let json = self.to_json(&out)?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Show the merged config this directory resolves to (no LLM call).
+    #[tool(
+        name = "resolved-config",
+        description = "Return the merged roundtable config for this directory (user + project, adapter defaults and client overrides applied) and the files it came from"
+    )]
+    async fn resolved_config(
+        &self,
+        Parameters(args): Parameters<ResolvedConfigArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let _idle = self.idle.begin();
+        let _inflight = self.begin_inflight().await?;
+        let out = self.resolved_config_internal(args)?;
+        let json = self.to_json(&out)?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Check that every backend CLI used by an enabled role starts (no prompt is sent).
+    #[tool(
+        name = "health",
+        description = "Probe each backend CLI used by an enabled role (e.g. --version) without sending a prompt"
+    )]
+    async fn health(
+        &self,
+        Parameters(args): Parameters<HealthArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let _idle = self.idle.begin();
+        let _inflight = self.begin_inflight().await?;
+        let out = self.health_internal(args).await?;
+        let json = self.to_json(&out)?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+}
+
+impl VibeServer {
+    fn info_internal(&self, args: InfoArgs) -> Result<InfoOutput, McpError> {
+        let cd = self.effective_cd(&args.cd)?;
 
-        // Canonicalize cd once to validate it's usable.
-        let cd = PathBuf::from(args.cd.as_str());
         let repo_root = cd.canonicalize().map_err(|e| {
             McpError::invalid_params(
                 format!(
@@ -494,228 +1956,51 @@ impl VibeServer {
         }
 
         let client_hint = resolve_client_hint(args.client.as_deref())?;
-        let conversation_hint = resolve_conversation_hint(args.conversation_id.as_deref())?;
-        let RoundtableArgs {
-            topic,
-            participants,
-            timeout_secs,
-            client: _client,
-            conversation_id: _conversation_id,
-            cd: _,
-        } = args;
+        let cfg_result = self
+            .config_loader
+            .load_for_repo_with_client(&repo_root, client_hint.as_deref())
+            .map_err(|e| McpError::internal_error(format!("failed to load config: {e}"), None))?;
+        let sources: Vec<String> = cfg_result
+            .sources
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect();
+        let cfg = cfg_result.config;
 
-        let topic_trimmed = topic.trim().to_string();
-        let repo_cd = repo_root.to_string_lossy().to_string();
-        let timeout_override = timeout_secs;
+        let Some(cfg) = cfg else {
+            let out = InfoOutput {
+                success: false,
+                cd: repo_root.to_string_lossy().to_string(),
+                config_sources: sources,
+                roles: Vec::new(),
+                warnings: Vec::new(),
+                error: Some("no config found (create ~/.config/roundtable/config.json)".to_string()),
+            };
+            return Ok(out);
+        };
 
-        let mut tasks: Vec<FanoutTaskSpec> = Vec::new();
-        for p in participants {
-            if p.name.trim().is_empty() {
-                return Err(McpError::invalid_params(
-                    "participant.name must be non-empty",
-                    None,
-                ));
-            }
+        let mut roles: Vec<InfoRole> = Vec::new();
+        let mut errors: Vec<String> = Vec::new();
 
-            let name = p.name.trim().to_string();
-            let role = p
-                .role
-                .clone()
-                .filter(|s| !s.trim().is_empty())
-                .unwrap_or_else(|| name.clone());
-
-            let prompt = format!(
-                "TOPIC:
-{}
-
-You are a roundtable participant named '{}' (role: {}).
-
-Reply with:
-1) Position (1-2 sentences)
-2) Arguments (bullets)
-3) Risks/edge cases (bullets)
-4) Recommendation (actionable)
-
-Constraints:
-- Do not claim to have run commands unless you actually did.
-- Prefer referencing repo paths when relevant.
-",
-                topic_trimmed, name, role
-            );
-
-            let args = VibeArgs {
-                prompt,
-                cd: repo_cd.clone(),
-                role: Some(role.clone()),
-                backend: p.backend,
-                model: p.model,
-                reasoning_effort: p.reasoning_effort,
-                session_id: None,
-                force_new_session: p.force_new_session,
-                session_key: None,
-                timeout_secs: timeout_override,
-                contract: None,
-                validate_patch: false,
-                client: client_hint.clone(),
-                conversation_id: conversation_hint.clone(),
-            };
-            tasks.push(FanoutTaskSpec {
-                name: Some(name),
-                role,
-                args,
-            });
-        }
-
-        let results = self
-            .run_fanout_internal(
-                Some(peer.clone()),
-                &repo_root,
-                tasks,
-                client_hint.clone(),
-                conversation_hint.clone(),
-                "roundtable",
-            )
-            .await?;
-
-        let mut contributions = Vec::new();
-        let mut any_error = false;
-        for res in results {
-            match res.result {
-                Ok(out) => {
-                    if out.error.is_some() {
-                        any_error = true;
-                    }
-                    contributions.push(RoundtableContribution {
-                        name: res.name.unwrap_or_default(),
-                        role: out.role.clone(),
-                        backend: out.backend.clone(),
-                        role_id: out.role_id.clone(),
-                        resumed: out.resumed,
-                        backend_session_id: out.backend_session_id.clone(),
-                        agent_messages: out.agent_messages.clone(),
-                        error: out.error.clone(),
-                    });
-                }
-                Err(e) => {
-                    any_error = true;
-                    contributions.push(RoundtableContribution {
-                        name: res.name.unwrap_or_default(),
-                        role: res.role,
-                        backend: "error".to_string(),
-                        role_id: "".to_string(),
-                        resumed: false,
-                        backend_session_id: "".to_string(),
-                        agent_messages: "".to_string(),
-                        error: Some(e.to_string()),
-                    });
-                }
-            }
-        }
-
-        let out = RoundtableOutput {
-            success: !any_error,
-            topic,
-            cd: repo_root.to_string_lossy().to_string(),
-            contributions,
-            error: if any_error {
-                Some("one or more participants returned an error".to_string())
-            } else {
-                None
-            },
-        };
-
-        let json = serde_json::to_string(&out).map_err(|e| {
-            McpError::internal_error(format!("failed to serialize output: {e}"), None)
-        })?;
-        Ok(CallToolResult::success(vec![Content::text(json)]))
-    }
-
-    /// Show effective config (roles -> models) without calling any LLM.
-    #[tool(
-        name = "info",
-        description = "Show effective roundtable role/model mapping for this directory"
-    )]
-    async fn info(
-        &self,
-        Parameters(args): Parameters<InfoArgs>,
-    ) -> Result<CallToolResult, McpError> {
-        if args.cd.trim().is_empty() {
-            return Err(McpError::invalid_params(
-                "cd is required and must be a non-empty string",
-                None,
-            ));
-        }
-
-        let cd = PathBuf::from(args.cd.as_str());
-        let repo_root = cd.canonicalize().map_err(|e| {
-            McpError::invalid_params(
-                format!(
-                    "working directory does not exist or is not accessible: {} ({})",
-                    cd.display(),
-                    e
-                ),
-                None,
-            )
-        })?;
-        if !repo_root.is_dir() {
-            return Err(McpError::invalid_params(
-                format!(
-                    "working directory is not a directory: {}",
-                    repo_root.display()
-                ),
-                None,
-            ));
-        }
-
-        let client_hint = resolve_client_hint(args.client.as_deref())?;
-        let cfg_result = self
-            .config_loader
-            .load_for_repo_with_client(&repo_root, client_hint.as_deref())
-            .map_err(|e| McpError::internal_error(format!("failed to load config: {e}"), None))?;
-        let sources: Vec<String> = cfg_result
-            .sources
-            .iter()
-            .map(|p| p.display().to_string())
-            .collect();
-        let cfg = cfg_result.config;
-
-        let Some(cfg) = cfg else {
-            let out = InfoOutput {
-                success: false,
-                cd: repo_root.to_string_lossy().to_string(),
-                config_sources: sources,
-                roles: Vec::new(),
-                warnings: Vec::new(),
-                error: Some("no config found (create ~/.config/roundtable/config.json)".to_string()),
-            };
-            let json = serde_json::to_string(&out).map_err(|e| {
-                McpError::internal_error(format!("failed to serialize output: {e}"), None)
-            })?;
-            return Ok(CallToolResult::success(vec![Content::text(json)]));
-        };
-
-        let mut roles: Vec<InfoRole> = Vec::new();
-        let mut errors: Vec<String> = Vec::new();
-
-        for (role_id, role_cfg) in &cfg.roles {
-            let persona = resolve_persona(role_id, role_cfg.personas.as_ref());
-            let description = persona
-                .as_ref()
-                .map(|p| p.description.clone())
-                .unwrap_or_default();
-            let prompt_raw = persona.as_ref().map(|p| p.prompt.trim()).unwrap_or("");
-            let (prompt_present, prompt_len, prompt_preview) = if prompt_raw.is_empty() {
-                (false, None, None)
-            } else {
-                let len = prompt_raw.len();
-                let preview_len = 120usize;
-                let preview = if len <= preview_len {
-                    prompt_raw.to_string()
-                } else {
-                    format!("{}...", &prompt_raw[..preview_len])
-                };
-                (true, Some(len), Some(preview))
-            };
+        for (role_id, role_cfg) in &cfg.roles {
+            let persona = resolve_persona(role_id, role_cfg.personas.as_ref());
+            let description = persona
+                .as_ref()
+                .map(|p| p.description.clone())
+                .unwrap_or_default();
+            let prompt_raw = persona.as_ref().map(|p| p.prompt.trim()).unwrap_or("");
+            let (prompt_present, prompt_len, prompt_preview) = if prompt_raw.is_empty() {
+                (false, None, None)
+            } else {
+                let len = prompt_raw.len();
+                let preview_len = 120usize;
+                let preview = if len <= preview_len {
+                    prompt_raw.to_string()
+                } else {
+                    format!("{}...", &prompt_raw[..preview_len])
+                };
+                (true, Some(len), Some(preview))
+            };
 
             let enabled = role_cfg.enabled;
             if !enabled {
@@ -735,6 +2020,9 @@ Constraints:
                     prompt_present,
                     prompt_len,
                     prompt_preview,
+                    prompt_overhead_chars: None,
+                    candidate_chain: Vec::new(),
+                    rendered_args_example: None,
                 });
                 continue;
             }
@@ -747,6 +2035,65 @@ Constraints:
                 }
             };
 
+            let block = prompt_present.then(|| format_persona_block(role_id, prompt_raw));
+            let overhead = prompt_overhead_chars(
+                resolved.profile.adapter.prompt_layout.as_deref(),
+                block.as_deref(),
+                backend::prompt_guardrail(
+                    &resolved.profile.backend_id,
+                    &resolved.profile.adapter,
+                    &resolved.profile.capabilities,
+                ),
+                role_cfg.prompt_prefix.as_deref(),
+                role_cfg.prompt_suffix.as_deref(),
+            );
+
+            let candidate_chain = match build_model_chain(&cfg, &resolved.profile) {
+                Ok(chain) => chain
+                    .into_iter()
+                    .map(|entry| match entry {
+                        ChainEntry::Candidate(c) => InfoCandidate {
+                            ref_str: c.ref_str,
+                            filtered_reason: None,
+                        },
+                        ChainEntry::Filtered { ref_str, reason } => InfoCandidate {
+                            ref_str,
+                            filtered_reason: Some(reason),
+                        },
+                    })
+                    .collect(),
+                Err(e) => {
+                    errors.push(format!("role '{role_id}' fallback invalid: {}", e.message));
+                    Vec::new()
+                }
+            };
+
+            let example_opts = backend::GenericOptions {
+                backend_id: resolved.profile.backend_id.clone(),
+                adapter: resolved.profile.adapter.clone(),
+                prompt: "<prompt>".to_string(),
+                workdir: repo_root.clone(),
+                session_id: None,
+                resume: false,
+                model: resolved.profile.model.clone(),
+                options: resolved.profile.options.clone(),
+                capabilities: resolved.profile.capabilities.clone(),
+                fallback_error_patterns: Vec::new(),
+                timeout_secs: resolved.profile.timeout_secs.unwrap_or_default(),
+                n: None,
+                client_id: cfg.client_id.clone(),
+                env: BTreeMap::new(),
+                progress: None,
+                include_directories: Vec::new(),
+            };
+            let rendered_args_example = match backend::render_args_redacted(&example_opts) {
+                Ok(args) => Some(args),
+                Err(e) => {
+                    errors.push(format!("role '{role_id}' args_template invalid: {e}"));
+                    None
+                }
+            };
+
             roles.push(InfoRole {
                 role: role_id.to_string(),
                 backend: resolved.profile.backend_id.clone(),
@@ -756,6 +2103,9 @@ Constraints:
                 prompt_present,
                 prompt_len,
                 prompt_preview,
+                prompt_overhead_chars: Some(overhead),
+                candidate_chain,
+                rendered_args_example,
             });
         }
 
@@ -765,10 +2115,15 @@ Constraints:
                 .filter(|r| r.enabled && r.backend == "kimi")
                 .map(|r| r.role.clone())
                 .collect();
+            let serialize = cfg
+                .backend
+                .get("kimi")
+                .is_some_and(|b| b.serialize_instead_of_reject == Some(true));
             if kimi_roles.len() > 1 {
                 vec![format!(
-                    "multiple enabled roles use backend 'kimi' ({}). Parallel resume (roundtable-batch/roundtable with force_new_session=false) will be rejected.",
-                    kimi_roles.join(", ")
+                    "multiple enabled roles use backend 'kimi' ({}). Parallel resume (roundtable-batch/roundtable with force_new_session=false) will be {}.",
+                    kimi_roles.join(", "),
+                    if serialize { "serialized" } else { "rejected" }
                 )]
             } else {
                 Vec::new()
@@ -788,33 +2143,86 @@ Constraints:
             },
         };
 
-        let json = serde_json::to_string(&out).map_err(|e| {
-            McpError::internal_error(format!("failed to serialize output: {e}"), None)
-        })?;
-        Ok(CallToolResult::success(vec![Content::text(json)]))
+        Ok(out)
     }
-}
 
-impl VibeServer {
-    pub async fn run_vibe_internal(
-        &self,
-        _peer: Option<Peer<RoleServer>>,
-        args: VibeArgs,
-    ) -> Result<VibeOutput, McpError> {
-        if args.prompt.trim().is_empty() {
-            return Err(McpError::invalid_params(
-                "PROMPT is required and must be a non-empty string",
-                None,
-            ));
+    /// Plain-text role summary for the `print-config` subcommand, built from the same
+    /// `info_internal` output as the `info` tool. Returns `(success, text)`.
+    pub fn info_text(&self, args: InfoArgs) -> Result<(bool, String), McpError> {
+        let out = self.info_internal(args)?;
+        let mut text = format!("cd: {}\n", out.cd);
+        for source in &out.config_sources {
+            text.push_str(&format!("config: {source}\n"));
         }
-        if args.cd.trim().is_empty() {
-            return Err(McpError::invalid_params(
-                "cd is required and must be a non-empty string",
-                None,
+        let width = out.roles.iter().map(|r| r.role.len()).max().unwrap_or(0);
+        for role in &out.roles {
+            let state = if role.enabled { "" } else { "  (disabled)" };
+            text.push_str(&format!(
+                "{:width$}  {}/{}{state}\n",
+                role.role, role.backend, role.model
             ));
         }
+        for warning in &out.warnings {
+            text.push_str(&format!("warning: {warning}\n"));
+        }
+        if let Some(error) = &out.error {
+            text.push_str(&format!("error: {error}\n"));
+        }
+        Ok((out.success, text))
+    }
+
+    /// Checks that `backend_id`'s CLI starts (`backend::probe` with `adapter`'s `probe_args`). A
+    /// successful result is reused for `cache_ttl` (`limits.probe_cache_secs`); failures are never
+    /// cached.
+    pub async fn probe_backend(
+        &self,
+        backend_id: &str,
+        adapter: &AdapterConfig,
+        workdir: &Path,
+        cache_ttl: std::time::Duration,
+    ) -> BackendProbe {
+        if let Some(hit) = self.cached_probe(backend_id, cache_ttl) {
+            return BackendProbe {
+                command: hit.command,
+                args: hit.args,
+                outcome: Ok(hit.output),
+                cached: true,
+            };
+        }
+        let probe = backend::probe(backend_id, adapter, workdir).await;
+        let outcome = probe.outcome.map_err(|e| format!("{e:#}"));
+        if let (Ok(output), false) = (outcome.as_ref(), cache_ttl.is_zero()) {
+            if let Ok(mut cache) = self.probe_cache.lock() {
+                cache.insert(
+                    backend_id.to_string(),
+                    CachedProbe {
+                        command: probe.command.clone(),
+                        args: probe.args.clone(),
+                        output: output.clone(),
+                        probed_at: std::time::Instant::now(),
+                    },
+                );
+            }
+        }
+        BackendProbe {
+            command: probe.command,
+            args: probe.args,
+            outcome,
+            cached: false,
+        }
+    }
+
+    /// `backend_id`'s cached probe, if one was stored within `ttl`.
+    fn cached_probe(&self, backend_id: &str, ttl: std::time::Duration) -> Option<CachedProbe> {
+        let cache = self.probe_cache.lock().ok()?;
+        cache
+            .get(backend_id)
+            .filter(|hit| hit.probed_at.elapsed() < ttl)
+            .cloned()
+    }
 
-        let cd = PathBuf::from(args.cd.as_str());
+    fn list_roles_internal(&self, args: ListRolesArgs) -> Result<ListRolesOutput, McpError> {
+        let cd = self.effective_cd(&args.cd)?;
         let repo_root = cd.canonicalize().map_err(|e| {
             McpError::invalid_params(
                 format!(
@@ -825,416 +2233,684 @@ impl VibeServer {
                 None,
             )
         })?;
-        if !repo_root.is_dir() {
+        let client_hint = resolve_client_hint(args.client.as_deref())?;
+        let mut out = ListRolesOutput {
+            success: false,
+            cd: repo_root.to_string_lossy().to_string(),
+            roles: Vec::new(),
+            error: None,
+        };
+        // Unvalidated, so one bad role does not hide the others.
+        let cfg = match self
+            .config_loader
+            .load_for_repo_unvalidated(&repo_root, client_hint.as_deref())
+        {
+            Ok(r) => r.config,
+            Err(e) => {
+                out.error = Some(format!("failed to load config: {e:#}"));
+                return Ok(out);
+            }
+        };
+        let Some(cfg) = cfg else {
+            out.error =
+                Some("no config found (create ~/.config/roundtable/config.json)".to_string());
+            return Ok(out);
+        };
+
+        out.roles = cfg
+            .roles
+            .iter()
+            .map(|(role_id, role_cfg)| {
+                let (backend, error) = match parse_role_model_ref(&role_cfg.model) {
+                    Ok((backend_id, _, _)) => {
+                        let error = (!cfg.backend.contains_key(&backend_id))
+                            .then(|| format!("missing backend config: {backend_id}"));
+                        (Some(backend_id), error)
+                    }
+                    Err(e) => (None, Some(e.to_string())),
+                };
+                ListRolesEntry {
+                    role: role_id.clone(),
+                    enabled: role_cfg.enabled,
+                    backend,
+                    error,
+                }
+            })
+            .collect();
+        out.success = true;
+        Ok(out)
+    }
+
+    fn list_sessions_internal(
+        &self,
+        args: ListSessionsArgs,
+    ) -> Result<ListSessionsOutput, McpError> {
+        if args.limit == Some(0) {
             return Err(McpError::invalid_params(
-                format!(
-                    "working directory is not a directory: {}",
-                    repo_root.display()
-                ),
+                "limit must be at least 1".to_string(),
                 None,
             ));
         }
-
-        let role = args.role.clone().unwrap_or_else(|| "default".to_string());
-        let client_hint = resolve_client_hint(args.client.as_deref())?;
-        let conversation_hint = resolve_conversation_hint(args.conversation_id.as_deref())?;
-
-        let cfg_for_repo = self
-            .config_loader
-            .load_for_repo_with_client(&repo_root, client_hint.as_deref())
-            .map_err(|e| McpError::internal_error(format!("failed to load config: {e}"), None))?;
-        let cfg = cfg_for_repo.config.ok_or_else(|| {
-            McpError::invalid_params("no config found (create ~/.config/roundtable/config.json)", None)
+        let cd = self.effective_cd(&args.cd)?;
+        let repo_root = cd.canonicalize().map_err(|e| {
+            McpError::invalid_params(
+                format!(
+                    "working directory does not exist or is not accessible: {} ({})",
+                    cd.display(),
+                    e
+                ),
+                None,
+            )
         })?;
+        let client = match args.client.as_deref() {
+            Some(c) => resolve_client_hint(Some(c))?,
+            None => None,
+        };
+        let conversation = match args.conversation_id.as_deref() {
+            Some(c) => resolve_conversation_hint(Some(c))?,
+            None => None,
+        };
+        let scoped = client.is_some() || conversation.is_some();
 
-        let rp = cfg
-            .resolve_profile(args.role.as_deref())
-            .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
-        let mut prompt_text = args.prompt.clone();
-
-        let session_key = args
-            .session_key
-            .as_ref()
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty())
-            .unwrap_or_else(|| {
-                compute_default_session_key(
-                    &repo_root,
-                    &role,
-                    &rp.role_id,
-                    client_hint.as_deref(),
-                    conversation_hint.as_deref(),
-                )
-            });
-        let _key_lock = self
+        let repo = repo_root.to_string_lossy().to_string();
+        let records = self
             .store
-            .acquire_key_lock(&session_key)
-            .map_err(|e| McpError::internal_error(e.to_string(), None))?;
+            .list_recent(&repo, args.updated_after_unix_secs)
+            .map_err(|e| McpError::internal_error(format!("failed to read sessions: {e}"), None))?;
+        let matching: Vec<_> = records
+            .into_iter()
+            .filter(|(key, rec)| {
+                !scoped
+                    || *key
+                        == compute_default_session_key(
+                            &repo_root,
+                            &rec.role,
+                            &rec.role_id,
+                            client.as_deref(),
+                            conversation.as_deref(),
+                        )
+            })
+            .collect();
+        let total = matching.len();
+        let sessions = matching
+            .into_iter()
+            .skip(args.offset.unwrap_or(0))
+            .take(args.limit.unwrap_or(usize::MAX))
+            .map(|(session_key, rec)| {
+                let sid = rec.backend_session_id.trim();
+                SessionEntry {
+                    stateless: sid.is_empty() || sid == "stateless",
+                    session_key,
+                    role: rec.role,
+                    role_id: rec.role_id,
+                    backend: rec.backend.as_str().to_string(),
+                    backend_session_id: rec.backend_session_id,
+                    model: rec.model,
+                    updated_at_unix_secs: rec.updated_at_unix_secs,
+                }
+            })
+            .collect();
 
-        let timeout_secs = args.timeout_secs.or(rp.profile.timeout_secs).unwrap_or(600);
+        Ok(ListSessionsOutput {
+            success: true,
+            cd: repo,
+            total,
+            sessions,
+        })
+    }
 
-        let explicit_session_id = args
-            .session_id
-            .as_ref()
-            .map(|s| s.trim().to_string())
+    async fn clear_session_internal(
+        &self,
+        args: ClearSessionArgs,
+    ) -> Result<ClearSessionOutput, McpError> {
+        let cd = self.effective_cd(&args.cd)?;
+        let repo_root = cd.canonicalize().map_err(|e| {
+            McpError::invalid_params(
+                format!(
+                    "working directory does not exist or is not accessible: {} ({})",
+                    cd.display(),
+                    e
+                ),
+                None,
+            )
+        })?;
+        let session_key = args
+            .session_key
+            .as_deref()
+            .map(str::trim)
             .filter(|s| !s.is_empty());
-        let mut warning_messages: Vec<String> = Vec::new();
+        let role = args
+            .role
+            .as_deref()
+            .map(str::trim)
+            .filter(|s| !s.is_empty());
+        let selectors = [session_key.is_some(), role.is_some(), args.all];
+        if selectors.iter().filter(|s| **s).count() != 1 {
+            return Err(McpError::invalid_params(
+                "exactly one of session_key, role, or all must be provided",
+                None,
+            ));
+        }
 
-        let prev_rec = self.store.get(&session_key).ok().flatten();
-        let supports_session = rp.profile.adapter.output_parser.supports_session();
-        let mut resumed = false;
-        let mut session_id_to_use = if args.force_new_session {
-            if let Some(sid) = explicit_session_id.as_ref() {
-                warning_messages.push(format!(
-                    "force_new_session=true ignored provided session_id '{sid}'"
-                ));
-            }
-            None
+        let repo = repo_root.to_string_lossy().to_string();
+        let keys: Vec<String> = if let Some(key) = session_key {
+            vec![key.to_string()]
+        } else if let Some(role) = role {
+            // Same scoping as `run_vibe_internal`, including the env fallbacks.
+            let client = resolve_client_hint(args.client.as_deref())?;
+            let conversation = resolve_conversation_hint(args.conversation_id.as_deref())?;
+            vec![compute_default_session_key(
+                &repo_root,
+                role,
+                role,
+                client.as_deref(),
+                conversation.as_deref(),
+            )]
         } else {
-            explicit_session_id.clone()
+            let client = match args.client.as_deref() {
+                Some(c) => resolve_client_hint(Some(c))?,
+                None => None,
+            };
+            let conversation = match args.conversation_id.as_deref() {
+                Some(c) => resolve_conversation_hint(Some(c))?,
+                None => None,
+            };
+            let scoped = client.is_some() || conversation.is_some();
+            self.store
+                .list()
+                .map_err(|e| {
+                    McpError::internal_error(format!("failed to read sessions: {e}"), None)
+                })?
+                .into_iter()
+                .filter(|(_, rec)| rec.repo_root == repo)
+                .filter(|(key, rec)| {
+                    !scoped
+                        || *key
+                            == compute_default_session_key(
+                                &repo_root,
+                                &rec.role,
+                                &rec.role_id,
+                                client.as_deref(),
+                                conversation.as_deref(),
+                            )
+                })
+                .map(|(key, _)| key)
+                .collect()
         };
-        let mut resume_without_session = false;
-        if !args.force_new_session
-            && args.session_key.is_none()
-            && explicit_session_id.is_none()
-            && conversation_hint.is_none()
-        {
-            warning_messages.push(
-                "conversation_id not provided; auto-resume may cross top-level chats in the same repo/role".to_string(),
-            );
-        }
 
-        if session_id_to_use.is_none() && !args.force_new_session {
-            if supports_session {
-                if let Some(rec) = prev_rec.as_ref() {
-                    if rec.backend == rp.profile.backend {
-                        let prev_id = rec.backend_session_id.trim();
-                        if !prev_id.is_empty() && prev_id != "stateless" {
-                            session_id_to_use = Some(rec.backend_session_id.clone());
-                            resumed = true;
-                        }
-                    }
-                }
-            } else if rp.profile.backend_id == "kimi" {
-                if let Some(rec) = prev_rec.as_ref() {
-                    if rec.backend == rp.profile.backend {
-                        resume_without_session = true;
-                        resumed = true;
-                    }
-                }
+        let mut removed = Vec::new();
+        for key in keys {
+            // Wait for any in-flight call on this key so we don't race its final `put`.
+            let _key_lock = self
+                .store
+                .acquire_key_lock(&key)
+                .await
+                .map_err(|e| McpError::internal_error(e.to_string(), None))?;
+            let existed = self.store.remove(&key).map_err(|e| {
+                McpError::internal_error(format!("failed to remove session: {e}"), None)
+            })?;
+            if existed {
+                removed.push(key);
             }
         }
 
-        let role_cfg = cfg.roles.get(&role).ok_or_else(|| {
-            McpError::internal_error(format!("role '{role}' missing after resolve"), None)
+        Ok(ClearSessionOutput {
+            success: true,
+            cd: repo,
+            removed,
+        })
+    }
+
+    fn validate_patch_internal(
+        &self,
+        args: ValidatePatchArgs,
+    ) -> Result<ValidatePatchOutput, McpError> {
+        let cd = self.effective_cd(&args.cd)?;
+        let repo_root = cd.canonicalize().map_err(|e| {
+            McpError::invalid_params(
+                format!(
+                    "working directory does not exist or is not accessible: {} ({})",
+                    cd.display(),
+                    e
+                ),
+                None,
+            )
         })?;
-        let persona = resolve_persona(&role, role_cfg.personas.as_ref());
+        if !repo_root.is_dir() {
+            return Err(McpError::invalid_params(
+                format!(
+                    "working directory is not a directory: {}",
+                    repo_root.display()
+                ),
+                None,
+            ));
+        }
 
-        let is_resuming = !args.force_new_session && (explicit_session_id.is_some() || resumed);
-        if !is_resuming && !prompt_text.contains("[ROUNDTABLE_PERSONA") {
-            let ptext = persona.as_ref().map(|p| p.prompt.trim()).unwrap_or("");
-            if !ptext.is_empty() {
-                let bid = rp.role_id.as_str();
-                prompt_text = format!(
-                    "[ROUNDTABLE_PERSONA id={bid}]
-{ptext}
-[/ROUNDTABLE_PERSONA]
-
-{prompt_text}"
-                );
+        let format = contract::detect_patch_format(&args.patch);
+        let (ok, output) = if format == contract::PatchFormat::UnifiedDiff {
+            match contract::validate_git_apply_check(&repo_root, &args.patch) {
+                Ok(apply) => (apply.ok, apply.output),
+                Err(e) => (false, e.to_string()),
             }
-        }
+        } else {
+            (false, "patch is not a unified diff".to_string())
+        };
+        Ok(ValidatePatchOutput {
+            ok,
+            output,
+            format: format!("{format:?}").to_ascii_lowercase(),
+        })
+    }
 
-        let backend_cfg = cfg.backend.get(&rp.profile.backend_id).ok_or_else(|| {
-            McpError::internal_error(
-                format!("missing backend config: {}", rp.profile.backend_id),
+    fn validate_config_internal(
+        &self,
+        args: ValidateConfigArgs,
+    ) -> Result<ValidateConfigOutput, McpError> {
+        let cd = self.effective_cd(&args.cd)?;
+        let repo_root = cd.canonicalize().map_err(|e| {
+            McpError::invalid_params(
+                format!(
+                    "working directory does not exist or is not accessible: {} ({})",
+                    cd.display(),
+                    e
+                ),
                 None,
             )
         })?;
-        let mut candidates: Vec<ModelCandidate> = Vec::new();
-        let fallback_patterns = backend_cfg
-            .fallback
-            .as_ref()
-            .map(|f| f.patterns.clone())
-            .unwrap_or_default();
-        candidates.push(ModelCandidate {
-            backend_id: rp.profile.backend_id.clone(),
-            backend: rp.profile.backend,
-            adapter: rp.profile.adapter.clone(),
-            model: rp.profile.model.clone(),
-            options: rp.profile.options.clone(),
-            ref_str: format_model_ref(&rp.profile.backend_id, &rp.profile.model, None),
-            fallback_error_patterns: fallback_patterns,
-        });
-        if let Some(fallback) = backend_cfg.fallback.as_ref() {
-            let (backend_id, model_id, variant) = parse_role_model_ref(&fallback.model)
-                .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
-            let fallback_backend = Backend::parse(&backend_id).ok_or_else(|| {
-                McpError::invalid_params(format!("unsupported backend key: {backend_id}"), None)
-            })?;
-            let fallback_cfg = cfg.backend.get(&backend_id).ok_or_else(|| {
-                McpError::invalid_params(format!("missing backend config: {backend_id}"), None)
-            })?;
-            let adapter = fallback_cfg.adapter.clone().ok_or_else(|| {
-                McpError::invalid_params(
-                    format!("missing adapter config for backend: {backend_id}"),
-                    None,
-                )
-            })?;
-            let compatible = adapter
-                .filesystem_capabilities
-                .as_ref()
-                .map(|allowed| allowed.contains(&rp.profile.capabilities.filesystem))
-                .unwrap_or(true);
-            if compatible {
-                let options = if model_id == "default" {
-                    if variant.is_some() {
-                        return Err(McpError::invalid_params(
-                            "fallback model 'default' does not support variants".to_string(),
-                            None,
-                        ));
-                    }
-                    if let Some(model_cfg) = fallback_cfg.models.get("default") {
-                        resolve_model_options(model_cfg, None)
-                            .map_err(|e| McpError::invalid_params(e.to_string(), None))?
-                    } else {
-                        BTreeMap::new()
-                    }
-                } else {
-                    let model_cfg = fallback_cfg.models.get(&model_id).ok_or_else(|| {
-                        McpError::invalid_params(
-                            format!(
-                                "unknown fallback model '{model_id}' for backend '{backend_id}'"
-                            ),
-                            None,
-                        )
-                    })?;
-                    resolve_model_options(model_cfg, variant.as_deref())
-                        .map_err(|e| McpError::invalid_params(e.to_string(), None))?
-                };
-                let ref_str = format_model_ref(&backend_id, &model_id, variant.as_deref());
-                let candidate_patterns = fallback_cfg
-                    .fallback
-                    .as_ref()
-                    .map(|f| f.patterns.clone())
-                    .unwrap_or_default();
-                candidates.push(ModelCandidate {
-                    backend_id,
-                    backend: fallback_backend,
-                    adapter,
-                    model: model_id,
-                    options,
-                    ref_str,
-                    fallback_error_patterns: candidate_patterns,
-                });
-            }
+        if !repo_root.is_dir() {
+            return Err(McpError::invalid_params(
+                format!(
+                    "working directory is not a directory: {}",
+                    repo_root.display()
+                ),
+                None,
+            ));
         }
 
-        let mut used_fallback: Option<String> = None;
-        let mut last_err: Option<String> = None;
-        let mut result: Option<backend::GenericResult> = None;
-        let mut used_backend_id = rp.profile.backend_id.clone();
-        let mut used_backend = rp.profile.backend;
-        let mut used_model = rp.profile.model.clone();
-        let total_candidates = candidates.len();
-        for (idx, candidate) in candidates.into_iter().enumerate() {
-            let same_backend = candidate.backend_id == rp.profile.backend_id;
-            let out = backend::run(backend::GenericOptions {
-                backend_id: candidate.backend_id.clone(),
-                adapter: candidate.adapter.clone(),
-                prompt: prompt_text.clone(),
-                workdir: repo_root.clone(),
-                session_id: if same_backend {
-                    session_id_to_use.clone()
-                } else {
-                    None
-                },
-                resume: if same_backend {
-                    resume_without_session
-                } else {
-                    false
-                },
-                model: candidate.model.clone(),
-                options: candidate.options,
-                capabilities: rp.profile.capabilities.clone(),
-                fallback_error_patterns: candidate.fallback_error_patterns.clone(),
-                timeout_secs,
-            })
-            .await;
-            match out {
-                Ok(r) => {
-                    if idx > 0 {
-                        used_fallback = Some(candidate.ref_str.clone());
+        let client_hint = resolve_client_hint(args.client.as_deref())?;
+        let mut out = ValidateConfigOutput {
+            success: false,
+            cd: repo_root.to_string_lossy().to_string(),
+            config_sources: Vec::new(),
+            errors: Vec::new(),
+            roles: Vec::new(),
+            error: None,
+        };
+        // Parse errors still stop here; everything after parsing is collected.
+        let cfg_result = match self
+            .config_loader
+            .load_for_repo_unvalidated(&repo_root, client_hint.as_deref())
+        {
+            Ok(r) => r,
+            Err(e) => {
+                out.error = Some(format!("failed to load config: {e:#}"));
+                return Ok(out);
+            }
+        };
+        out.config_sources = cfg_result
+            .sources
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect();
+        let Some(cfg) = cfg_result.config else {
+            out.error =
+                Some("no config found (create ~/.config/roundtable/config.json)".to_string());
+            return Ok(out);
+        };
+
+        out.errors = cfg.validation_errors();
+        for (role_id, role_cfg) in &cfg.roles {
+            if !role_cfg.enabled {
+                out.roles.push(ValidateConfigRole {
+                    role: role_id.clone(),
+                    status: "disabled".to_string(),
+                    errors: Vec::new(),
+                });
+                continue;
+            }
+            let mut errors: Vec<String> = Vec::new();
+            match cfg.resolve_profile_with_model(role_id, role_cfg, &role_cfg.model) {
+                Ok(rp) => {
+                    if let Err(e) = build_model_chain(&cfg, &rp.profile) {
+                        errors.push(format!("fallback: {}", e.message));
                     }
-                    used_backend_id = candidate.backend_id;
-                    used_backend = candidate.backend;
-                    used_model = candidate.model;
-                    result = Some(r);
-                    break;
                 }
-                Err(e) => {
-                    let msg = e.to_string();
-                    last_err = Some(msg.clone());
-                    if is_model_error_message(&msg) && idx + 1 < total_candidates {
-                        continue;
-                    }
-                    return Err(McpError::internal_error(
-                        format!("backend failed: {msg}"),
-                        None,
+                Err(e) => errors.push(e.to_string()),
+            }
+            for rule in &role_cfg.length_routing {
+                if let Err(e) = cfg.resolve_profile_with_model(role_id, role_cfg, &rule.model) {
+                    errors.push(format!(
+                        "length_routing (max_chars {}): {e}",
+                        rule.max_chars
                     ));
                 }
             }
+            out.roles.push(ValidateConfigRole {
+                role: role_id.clone(),
+                status: if errors.is_empty() { "ok" } else { "error" }.to_string(),
+                errors,
+            });
         }
+        out.success = out.errors.is_empty() && out.roles.iter().all(|r| r.errors.is_empty());
+        if !out.success {
+            out.error = Some("config has errors".to_string());
+        }
+        Ok(out)
+    }
 
-        let r = result.ok_or_else(|| {
-            McpError::internal_error(
+    fn resolved_config_internal(
+        &self,
+        args: ResolvedConfigArgs,
+    ) -> Result<ResolvedConfigOutput, McpError> {
+        let cd = self.effective_cd(&args.cd)?;
+        let repo_root = cd.canonicalize().map_err(|e| {
+            McpError::invalid_params(
                 format!(
-                    "backend failed: {}",
-                    last_err.unwrap_or_else(|| "unknown error".to_string())
+                    "working directory does not exist or is not accessible: {} ({})",
+                    cd.display(),
+                    e
                 ),
                 None,
             )
         })?;
-
-        let backend_session_id = r.session_id;
-        let agent_messages = r.agent_messages;
-        let fallback_warning = used_fallback
-            .as_ref()
-            .map(|m| format!("model fallback used: {m}"));
-        let warning_extra = if warning_messages.is_empty() {
-            None
-        } else {
-            Some(warning_messages.join("\n"))
+        if !repo_root.is_dir() {
+            return Err(McpError::invalid_params(
+                format!(
+                    "working directory is not a directory: {}",
+                    repo_root.display()
+                ),
+                None,
+            ));
+        }
+
+        let client_hint = resolve_client_hint(args.client.as_deref())?;
+        let mut out = ResolvedConfigOutput {
+            success: false,
+            cd: repo_root.to_string_lossy().to_string(),
+            config_sources: Vec::new(),
+            config: None,
+            env_interpolated: Vec::new(),
+            error: None,
         };
-        let warnings = match (r.warnings, warning_extra, fallback_warning) {
-            (Some(base), Some(extra), Some(fallback)) => {
-                Some(format!("{base}\n{extra}\n{fallback}"))
+        // Unvalidated so a config that `validate-config` rejects can still be inspected.
+        let cfg_result = match self
+            .config_loader
+            .load_for_repo_unvalidated(&repo_root, client_hint.as_deref())
+        {
+            Ok(r) => r,
+            Err(e) => {
+                out.error = Some(format!("failed to load config: {e:#}"));
+                return Ok(out);
             }
-            (Some(base), Some(extra), None) => Some(format!("{base}\n{extra}")),
-            (Some(base), None, Some(fallback)) => Some(format!("{base}\n{fallback}")),
-            (Some(base), None, None) => Some(base),
-            (None, Some(extra), Some(fallback)) => Some(format!("{extra}\n{fallback}")),
-            (None, Some(extra), None) => Some(extra),
-            (None, None, Some(fallback)) => Some(fallback),
-            (None, None, None) => None,
         };
+        out.config_sources = cfg_result
+            .sources
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect();
+        let Some(cfg) = cfg_result.config else {
+            out.error =
+                Some("no config found (create ~/.config/roundtable/config.json)".to_string());
+            return Ok(out);
+        };
+        out.env_interpolated = cfg.env_interpolated_fields();
+        out.config = Some(cfg);
+        out.success = true;
+        Ok(out)
+    }
 
-        self.store
-            .put(
-                &session_key,
-                SessionRecord {
-                    repo_root: repo_root.to_string_lossy().to_string(),
-                    role: role.clone(),
-                    role_id: rp.role_id.clone(),
-                    backend: used_backend,
-                    backend_session_id: backend_session_id.clone(),
-                    sampling_history: Vec::new(),
-                    updated_at_unix_secs: now_unix_secs(),
-                },
+    async fn health_internal(&self, args: HealthArgs) -> Result<HealthOutput, McpError> {
+        let cd = self.effective_cd(&args.cd)?;
+        let repo_root = cd.canonicalize().map_err(|e| {
+            McpError::invalid_params(
+                format!(
+                    "working directory does not exist or is not accessible: {} ({})",
+                    cd.display(),
+                    e
+                ),
+                None,
             )
-            .map_err(|e| {
-                McpError::internal_error(format!("failed to persist session: {e}"), None)
-            })?;
+        })?;
+        if !repo_root.is_dir() {
+            return Err(McpError::invalid_params(
+                format!(
+                    "working directory is not a directory: {}",
+                    repo_root.display()
+                ),
+                None,
+            ));
+        }
 
-        let mut contract_errors: Vec<String> = Vec::new();
-        let mut patch_format: Option<String> = None;
-        let mut patch_apply_check_ok: Option<bool> = None;
-        let mut patch_apply_check_output: Option<String> = None;
-        let mut error: Option<String> = None;
+        let client_hint = resolve_client_hint(args.client.as_deref())?;
+        let mut out = HealthOutput {
+            success: false,
+            cd: repo_root.to_string_lossy().to_string(),
+            backends: Vec::new(),
+            errors: Vec::new(),
+            error: None,
+        };
+        let cfg = match self
+            .config_loader
+            .load_for_repo_with_client(&repo_root, client_hint.as_deref())
+        {
+            Ok(r) => r.config,
+            Err(e) => {
+                out.error = Some(format!("failed to load config: {e}"));
+                return Ok(out);
+            }
+        };
+        let Some(cfg) = cfg else {
+            out.error =
+                Some("no config found (create ~/.config/roundtable/config.json)".to_string());
+            return Ok(out);
+        };
 
-        if let Some(OutputContract::PatchWithCitations) = args.contract {
-            let check = contract::check_patch_with_citations(&agent_messages);
-            contract_errors = check.errors.clone();
-            patch_format = Some(format!("{:?}", check.patch_format).to_ascii_lowercase());
+        // Probe each backend once, with the adapter of its first enabled role.
+        let mut targets: BTreeMap<String, (AdapterConfig, Vec<String>)> = BTreeMap::new();
+        for (role_id, role_cfg) in &cfg.roles {
+            if !role_cfg.enabled {
+                continue;
+            }
+            match cfg.resolve_profile(Some(role_id)) {
+                Ok(rp) => targets
+                    .entry(rp.profile.backend_id)
+                    .or_insert_with(|| (rp.profile.adapter, Vec::new()))
+                    .1
+                    .push(role_id.clone()),
+                Err(e) => out.errors.push(format!("role '{role_id}' invalid: {e}")),
+            }
+        }
 
-            if args.validate_patch {
-                match (check.patch_format, check.extracted_patch.as_deref()) {
-                    (contract::PatchFormat::UnifiedDiff, Some(patch)) => {
-                        match contract::validate_git_apply_check(&repo_root, patch) {
-                            Ok(apply) => {
-                                patch_apply_check_ok = Some(apply.ok);
-                                patch_apply_check_output = Some(apply.output);
-                            }
-                            Err(e) => {
-                                patch_apply_check_ok = Some(false);
-                                patch_apply_check_output = Some(e.to_string());
-                            }
-                        }
-                    }
-                    (contract::PatchFormat::UnifiedDiff, None) => {
-                        patch_apply_check_ok = Some(false);
-                        patch_apply_check_output = Some(
-                            "validate_patch=true but failed to extract unified diff patch"
-                                .to_string(),
-                        );
-                    }
-                    _ => {
-                        patch_apply_check_ok = Some(false);
-                        patch_apply_check_output =
-                            Some("validate_patch=true but patch is not a unified diff".to_string());
-                    }
-                }
+        let cache_ttl = cfg.limits.probe_cache_ttl();
+        let mut probes = tokio::task::JoinSet::new();
+        for (backend_id, (adapter, roles)) in targets {
+            let server = self.clone();
+            let workdir = repo_root.clone();
+            probes.spawn(async move {
+                let probe = server
+                    .probe_backend(&backend_id, &adapter, &workdir, cache_ttl)
+                    .await;
+                (backend_id, roles, probe)
+            });
+        }
+        while let Some(joined) = probes.join_next().await {
+            let Ok((backend_id, roles, probe)) = joined else {
+                continue;
+            };
+            let (status, output, error) = match probe.outcome {
+                Ok(output) => ("ok", Some(output), None),
+                Err(e) => ("error", None, Some(e)),
+            };
+            out.backends.push(HealthBackend {
+                backend: backend_id,
+                roles,
+                command: probe.command,
+                args: probe.args,
+                status: status.to_string(),
+                output,
+                error,
+                cached: probe.cached,
+            });
+        }
+        out.backends.sort_by(|a, b| a.backend.cmp(&b.backend));
+        out.success = out.errors.is_empty() && out.backends.iter().all(|b| b.error.is_none());
+        if !out.success {
+            out.error = Some("health check failed".to_string());
+        }
+        Ok(out)
+    }
+
+    /// Primes cold backends (`--warmup`): sends a trivial prompt through the first enabled role of
+    /// each backend. Warmup runs use their own session keys and store no session record, so real
+    /// sessions are left untouched.
+    pub async fn warmup(&self) -> Vec<WarmupResult> {
+        self.warmup_backends(false).await
+    }
+
+    /// Like `warmup`, but only for backends with `prewarm: true` in the config.
+    pub async fn prewarm(&self) -> Vec<WarmupResult> {
+        self.warmup_backends(true).await
+    }
+
+    async fn warmup_backends(&self, only_prewarm: bool) -> Vec<WarmupResult> {
+        let cd = match self
+            .default_cd
+            .clone()
+            .map(Ok)
+            .unwrap_or_else(std::env::current_dir)
+        {
+            Ok(cd) => cd,
+            // Without `--warmup`, the next tool call reports the problem instead.
+            Err(_) if only_prewarm => return Vec::new(),
+            Err(e) => {
+                return vec![WarmupResult {
+                    backend: String::new(),
+                    role: String::new(),
+                    error: Some(format!("failed to resolve working directory: {e}")),
+                    elapsed_ms: 0,
+                }]
+            }
+        };
+        let cfg = match self.config_loader.load_for_repo_with_client(&cd, None) {
+            Ok(loaded) => loaded.config,
+            Err(_) if only_prewarm => return Vec::new(),
+            Err(e) => {
+                return vec![WarmupResult {
+                    backend: String::new(),
+                    role: String::new(),
+                    error: Some(format!("failed to load config: {e}")),
+                    elapsed_ms: 0,
+                }]
             }
+        };
+        let Some(cfg) = cfg else {
+            return Vec::new();
+        };
 
-            if !contract_errors.is_empty() {
-                error = Some(format!(
-                    "output contract violation: {}",
-                    contract_errors.join(", ")
-                ));
+        let mut seen: BTreeSet<String> = BTreeSet::new();
+        let mut targets = Vec::new();
+        for (role_id, role_cfg) in &cfg.roles {
+            if !role_cfg.enabled {
+                continue;
             }
-            if args.validate_patch {
-                if patch_apply_check_ok != Some(true) {
-                    let msg = patch_apply_check_output
-                        .clone()
-                        .unwrap_or_else(|| "git apply --check failed".to_string());
-                    error = Some(format!("patch validation failed: {msg}"));
-                }
+            let Ok(rp) =
+                cfg.resolve_profile_for_prompt(Some(role_id), WARMUP_PROMPT.chars().count())
+            else {
+                continue;
+            };
+            if only_prewarm
+                && !cfg
+                    .backend
+                    .get(&rp.profile.backend_id)
+                    .is_some_and(|b| b.prewarm)
+            {
+                continue;
+            }
+            if seen.insert(rp.profile.backend_id.clone()) {
+                targets.push((role_id.clone(), rp.profile));
             }
         }
 
-        let out = VibeOutput {
-            success: error.is_none(),
-            backend: used_backend_id,
-            role,
-            role_id: rp.role_id,
-            model: Some(used_model),
-            session_key,
-            resumed,
-            backend_session_id,
-            agent_messages,
-            warnings,
-            contract: args.contract.map(|c| match c {
-                OutputContract::PatchWithCitations => "patch_with_citations".to_string(),
-            }),
-            contract_errors,
-            patch_format,
-            patch_apply_check_ok,
-            patch_apply_check_output,
-            error,
-        };
+        // A regular call on its own session key, kept out of the session store.
+        let cd_arg = cd.to_string_lossy().to_string();
+        let mut results = Vec::with_capacity(targets.len());
+        for (role, profile) in targets {
+            let args = VibeArgs {
+                prompt: WARMUP_PROMPT.to_string(),
+                cd: cd_arg.clone(),
+                role: Some(role.clone()),
+                backend: None,
+                model: None,
+                reasoning_effort: None,
+                session_id: None,
+                force_new_session: true,
+                session_key: Some(format!("warmup:{}", profile.backend_id)),
+                timeout_secs: Some(WARMUP_TIMEOUT_SECS),
+                contract: None,
+                validate_patch: false,
+                strict_citations: false,
+                contract_output: None,
+                output_parser: None,
+                canonicalize_cd: None,
+                extract_code_lang: None,
+                n: None,
+                client: None,
+                conversation_id: None,
+                participant: None,
+                session_group: None,
+                ephemeral: true,
+                dry_run: false,
+                persona_override: None,
+                stream: false,
+                prompt_file: None,
+                prompt_transport: None,
+                include_directories: None,
+                cache: false,
+            };
+            let started = std::time::Instant::now();
+            let error = match self.run_vibe_internal(None, args).await {
+                Ok(out) => out.error,
+                Err(e) => Some(e.message.to_string()),
+            };
+            results.push(WarmupResult {
+                backend: profile.backend_id,
+                role,
+                error,
+                elapsed_ms: started.elapsed().as_millis() as u64,
+            });
+        }
+        results
+    }
 
-        Ok(out)
+    pub async fn run_vibe_internal(
+        &self,
+        peer: Option<Peer<RoleServer>>,
+        args: VibeArgs,
+    ) -> Result<VibeOutput, McpError> {
+        self.run_vibe_with_budget(peer, args, None).await
     }
 
-    async fn run_batch_internal(
+    async fn run_vibe_with_budget(
         &self,
         peer: Option<Peer<RoleServer>>,
-        args: BatchArgs,
-    ) -> Result<BatchOutput, McpError> {
-        if args.cd.trim().is_empty() {
+        mut args: VibeArgs,
+        retry_budget: Option<&RetryBudget>,
+    ) -> Result<VibeOutput, McpError> {
+        let prompt_file = args
+            .prompt_file
+            .clone()
+            .filter(|p| !p.trim().is_empty() && args.prompt.trim().is_empty());
+        if args.prompt.trim().is_empty() && prompt_file.is_none() {
             return Err(McpError::invalid_params(
-                "cd is required and must be a non-empty string",
+                "PROMPT (or prompt_file) is required and must be a non-empty string",
                 None,
             ));
         }
-        if args.tasks.is_empty() {
-            return Err(McpError::invalid_params(
-                "tasks must be a non-empty array",
-                None,
-            ));
+        if args.n == Some(0) {
+            return Err(McpError::invalid_params("n must be at least 1", None));
         }
+        let cd = self.effective_cd(&args.cd)?;
 
-        let cd = PathBuf::from(args.cd.as_str());
         let repo_root = cd.canonicalize().map_err(|e| {
             McpError::invalid_params(
                 format!(
@@ -1254,302 +2930,2056 @@ impl VibeServer {
                 None,
             ));
         }
+        if let Some(path) = prompt_file.as_deref() {
+            args.prompt = read_prompt_file(&repo_root, path)?;
+        }
+        let include_directories =
+            resolve_include_directories(&repo_root, args.include_directories.as_deref())?;
 
-        let repo_cd = repo_root.to_string_lossy().to_string();
         let client_hint = resolve_client_hint(args.client.as_deref())?;
         let conversation_hint = resolve_conversation_hint(args.conversation_id.as_deref())?;
-        let mut tasks: Vec<FanoutTaskSpec> = Vec::with_capacity(args.tasks.len());
-        for task in args.tasks {
-            let role_opt = task
-                .role
-                .as_ref()
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty());
-            let role_label = role_opt
-                .clone()
-                .unwrap_or_else(|| "<missing role>".to_string());
-            let timeout_secs = task.timeout_secs.or(args.timeout_secs);
-            let args = VibeArgs {
-                prompt: task.prompt,
-                cd: repo_cd.clone(),
-                role: role_opt,
-                backend: task.backend,
-                model: task.model,
-                reasoning_effort: task.reasoning_effort,
-                session_id: task.session_id,
-                force_new_session: task.force_new_session,
-                session_key: task.session_key,
-                timeout_secs,
-                contract: task.contract,
-                validate_patch: task.validate_patch,
-                client: client_hint.clone(),
-                conversation_id: conversation_hint.clone(),
-            };
-            tasks.push(FanoutTaskSpec {
-                name: task.name,
-                role: role_label,
-                args,
-            });
-        }
 
-        let results = self
-            .run_fanout_internal(
-                peer,
-                &repo_root,
-                tasks,
-                client_hint.clone(),
-                conversation_hint.clone(),
-                "roundtable-batch",
-            )
-            .await?;
+        let cfg_for_repo = self
+            .config_loader
+            .load_for_repo_with_client(&repo_root, client_hint.as_deref())
+            .map_err(|e| McpError::internal_error(format!("failed to load config: {e}"), None))?;
+        let mut cfg = cfg_for_repo.config.ok_or_else(|| {
+            McpError::invalid_params("no config found (create ~/.config/roundtable/config.json)", None)
+        })?;
+        let repo_root = workdir_for(
+            &cd,
+            repo_root,
+            args.canonicalize_cd.or(cfg.canonicalize_cd).unwrap_or(true),
+        );
 
-        let mut any_error = false;
-        let mut outputs: Vec<BatchResult> = Vec::new();
-        for res in results {
-            match res.result {
-                Ok(out) => {
-                    if out.error.is_some() {
-                        any_error = true;
-                    }
-                    let err = out.error.clone();
-                    let backend = out.backend.clone();
-                    outputs.push(BatchResult {
-                        name: res.name,
-                        role: res.role,
-                        backend,
-                        output: Some(out),
-                        error: err,
-                    });
-                }
-                Err(e) => {
-                    any_error = true;
-                    outputs.push(BatchResult {
-                        name: res.name,
-                        role: res.role,
-                        backend: "error".to_string(),
-                        output: None,
-                        error: Some(e.to_string()),
-                    });
-                }
-            }
-        }
+        let call_role = cfg
+            .apply_call_overrides(
+                args.role.as_deref(),
+                args.backend.as_deref(),
+                args.model.as_deref(),
+                args.reasoning_effort.as_deref(),
+            )
+            .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+        // A first guess from the raw prompt; `length_routing` is settled below, once the prompt as
+        // sent (persona, prefix/suffix, layout and guardrail included) is known.
+        let mut rp = cfg
+            .resolve_profile_for_prompt(call_role.as_deref(), args.prompt.chars().count())
+            .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+        // An omitted role falls back to the config's default_role (or the ad-hoc override role).
+        let role = rp.role_id.clone();
 
-        Ok(BatchOutput {
-            success: !any_error,
-            cd: repo_root.to_string_lossy().to_string(),
-            results: outputs,
-            error: if any_error {
-                Some("one or more tasks returned an error".to_string())
-            } else {
-                None
-            },
-        })
-    }
+        let session_key = args
+            .session_key
+            .as_ref()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| {
+                let (scope_role, scope_role_id) = session_scope(
+                    &role,
+                    &rp.role_id,
+                    args.participant.as_deref(),
+                    args.session_group.as_deref(),
+                );
+                compute_default_session_key(
+                    &repo_root,
+                    &scope_role,
+                    &scope_role_id,
+                    client_hint.as_deref(),
+                    conversation_hint.as_deref(),
+                )
+            });
+        let _key_lock = self
+            .store
+            .acquire_key_lock(&session_key)
+            .await
+            .map_err(|e| McpError::internal_error(e.to_string(), None))?;
 
-    async fn run_fanout_internal(
-        &self,
-        peer: Option<Peer<RoleServer>>,
-        repo_root: &PathBuf,
-        tasks: Vec<FanoutTaskSpec>,
-        client: Option<String>,
-        conversation_id: Option<String>,
-        operation: &'static str,
-    ) -> Result<Vec<FanoutResult>, McpError> {
-        let cfg_for_repo = self
-            .config_loader
-            .load_for_repo_with_client(repo_root, client.as_deref())
-            .map_err(|e| McpError::internal_error(format!("failed to load config: {e}"), None))?;
-        let cfg = cfg_for_repo.config.ok_or_else(|| {
-            McpError::invalid_params("no config found (create ~/.config/roundtable/config.json)", None)
-        })?;
+        let explicit_session_id = args
+            .session_id
+            .as_ref()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty());
+        let mut warning_messages: Vec<String> = Vec::new();
+        if let Some(parser) = args.output_parser.as_ref() {
+            // Only the primary backend's adapter is overridden; fallbacks keep their own parser.
+            rp.profile.adapter.output_parser = parser.clone();
+            warning_messages.push(format!(
+                "output_parser override in use (debug): {}",
+                serde_json::to_string(parser).unwrap_or_default()
+            ));
+        }
 
-        let mut kimi_resume_roles: Vec<String> = Vec::new();
-        for task in &tasks {
-            if task.args.force_new_session {
-                continue;
-            }
-            let Some(role_id) = task.args.role.as_deref() else {
-                continue;
-            };
-            let resolved = match cfg.resolve_profile(Some(role_id)) {
-                Ok(r) => r,
-                Err(_) => continue,
-            };
-            if resolved.profile.backend_id != "kimi" {
-                continue;
+        let prev_rec = self.store.get(&session_key).ok().flatten();
+        let requested_session_id = if args.force_new_session {
+            if let Some(sid) = explicit_session_id.as_ref() {
+                warning_messages.push(format!(
+                    "force_new_session=true ignored provided session_id '{sid}'"
+                ));
             }
+            None
+        } else {
+            explicit_session_id.clone()
+        };
+        if !args.force_new_session
+            && args.session_key.is_none()
+            && explicit_session_id.is_none()
+            && conversation_hint.is_none()
+        {
+            warning_messages.push(
+                "conversation_id not provided; auto-resume may cross top-level chats in the same repo/role".to_string(),
+            );
+        }
 
-            let explicit_session_id = task
-                .args
-                .session_id
-                .as_ref()
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty());
-            if explicit_session_id.is_some() {
-                kimi_resume_roles.push(role_id.to_string());
-                continue;
+        let role_cfg = cfg.roles.get(&role).ok_or_else(|| {
+            McpError::internal_error(format!("role '{role}' missing after resolve"), None)
+        })?;
+        let persona = resolve_persona(&role, role_cfg.personas.as_ref());
+        let (pre_command, post_command) = if cfg.allow_role_commands {
+            (role_cfg.pre_command.clone(), role_cfg.post_command.clone())
+        } else {
+            if !role_cfg.pre_command.is_empty() || !role_cfg.post_command.is_empty() {
+                warning_messages.push(format!(
+                    "role '{role}' pre_command/post_command ignored; set allow_role_commands in the user config to run them"
+                ));
             }
+            (Vec::new(), Vec::new())
+        };
 
-            let effective_client = task.args.client.as_deref().or(client.as_deref());
-            let effective_conversation = task
-                .args
-                .conversation_id
-                .as_deref()
-                .or(conversation_id.as_deref());
-            let session_key = task
-                .args
-                .session_key
-                .as_ref()
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty())
-                .unwrap_or_else(|| {
-                    compute_default_session_key(
-                        repo_root,
-                        role_id,
-                        &resolved.role_id,
-                        effective_client,
-                        effective_conversation,
-                    )
-                });
-            let prev_rec = self.store.get(&session_key).ok().flatten();
-            if let Some(rec) = prev_rec {
-                if rec.backend == resolved.profile.backend {
-                    kimi_resume_roles.push(role_id.to_string());
+        // Assembled for `rp`'s backend; a length route to another backend assembles it once more,
+        // since resuming, the layout and the guardrail all depend on the backend.
+        let mut rerouted = false;
+        let (mut prompt_text, session_id_to_use, resume_without_session, resumed) = loop {
+            let supports_session = rp.profile.adapter.output_parser.supports_session();
+            let mut session_id_to_use = requested_session_id.clone();
+            let mut resume_without_session = false;
+            let mut resumed = false;
+            if session_id_to_use.is_none() && !args.force_new_session {
+                if supports_session {
+                    if let Some(rec) = prev_rec.as_ref() {
+                        if rec.backend == rp.profile.backend {
+                            let prev_id = rec.backend_session_id.trim();
+                            if !prev_id.is_empty() && prev_id != "stateless" {
+                                session_id_to_use = Some(rec.backend_session_id.clone());
+                                resumed = true;
+                            }
+                        }
+                    }
+                } else if rp.profile.backend_id == "kimi" {
+                    if let Some(rec) = prev_rec.as_ref() {
+                        if rec.backend == rp.profile.backend {
+                            resume_without_session = true;
+                            resumed = true;
+                        }
+                    }
                 }
             }
-        }
-
-        if kimi_resume_roles.len() > 1 {
-            return Err(McpError::invalid_params(
-                format!(
-                    "multiple kimi roles requested with force_new_session=false: {}. Kimi cannot resume multiple sessions in the same working directory.",
-                    kimi_resume_roles.join(", ")
-                ),
-                None,
-            ));
-        }
 
-        let logger = format!("roundtable.{operation}");
-        let total_tasks = tasks.len();
-        let mut joinset: tokio::task::JoinSet<FanoutResult> = tokio::task::JoinSet::new();
-        for (idx, task) in tasks.into_iter().enumerate() {
-            let task_label = task.name.clone().unwrap_or_else(|| task.role.clone());
-            notify_fanout_progress(
-                peer.as_ref(),
-                &logger,
-                LoggingLevel::Info,
-                format!(
-                    "[{operation}] started {task_label} ({}/{})",
-                    idx + 1,
-                    total_tasks
+            let mut prompt_text = args.prompt.clone();
+            let is_resuming = !args.force_new_session && (explicit_session_id.is_some() || resumed);
+            let mut persona_block: Option<String> = None;
+            if !is_resuming && !prompt_text.contains("[ROUNDTABLE_PERSONA") {
+                // A per-call override wins over the configured/built-in persona.
+                let ptext = args
+                    .persona_override
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|p| !p.is_empty())
+                    .or_else(|| persona.as_ref().map(|p| p.prompt.trim()))
+                    .unwrap_or("");
+                if !ptext.is_empty() {
+                    persona_block = Some(format_persona_block(&rp.role_id, ptext));
+                }
+            }
+            prompt_text = match rp.profile.adapter.prompt_layout.as_deref() {
+                Some(layout) => assemble_prompt(
+                    layout,
+                    persona_block.as_deref(),
+                    backend::prompt_guardrail(
+                        &rp.profile.backend_id,
+                        &rp.profile.adapter,
+                        &rp.profile.capabilities,
+                    ),
+                    &prompt_text,
                 ),
-            )
-            .await;
+                None => match persona_block {
+                    Some(block) => format!("{block}\n\n{prompt_text}"),
+                    None => prompt_text,
+                },
+            };
+            if !is_resuming {
+                prompt_text = wrap_prompt(
+                    role_cfg.prompt_prefix.as_deref(),
+                    prompt_text,
+                    role_cfg.prompt_suffix.as_deref(),
+                );
+            }
+            // Placed here rather than by the backend, so `max_prompt_chars` counts it.
+            prompt_text = backend::apply_prompt_guardrails(
+                &rp.profile.backend_id,
+                &rp.profile.adapter,
+                &rp.profile.capabilities,
+                &prompt_text,
+            );
 
-            let server = VibeServer::new(self.config_loader.clone(), self.store.clone());
-            let peer = peer.clone();
-            let FanoutTaskSpec { name, role, args } = task;
-            joinset.spawn(async move {
-                let out = server.run_vibe_internal(peer, args).await;
-                FanoutResult {
-                    name,
-                    role,
-                    result: out,
+            let mut routed = cfg
+                .resolve_profile_for_prompt(call_role.as_deref(), prompt_text.chars().count())
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+            if let Some(parser) = args.output_parser.as_ref() {
+                routed.profile.adapter.output_parser = parser.clone();
+            }
+            let same_backend = routed.profile.backend_id == rp.profile.backend_id;
+            if same_backend || rerouted {
+                if same_backend {
+                    rp = routed;
                 }
-            });
-        }
+                break (
+                    prompt_text,
+                    session_id_to_use,
+                    resume_without_session,
+                    resumed,
+                );
+            }
+            rp = routed;
+            rerouted = true;
+        };
 
-        let mut results: Vec<FanoutResult> = Vec::with_capacity(total_tasks);
-        let mut completed = 0usize;
-        while let Some(joined) = joinset.join_next().await {
-            completed += 1;
-            match joined {
-                Ok(res) => {
-                    let task_label = res
-                        .name
-                        .clone()
-                        .filter(|s| !s.trim().is_empty())
-                        .unwrap_or_else(|| {
-                            if res.role.trim().is_empty() {
-                                "<unknown>".to_string()
-                            } else {
-                                res.role.clone()
-                            }
-                        });
-                    let status = match &res.result {
-                        Ok(out) if out.error.is_none() => "ok",
-                        _ => "error",
-                    };
-                    notify_fanout_progress(
-                        peer.as_ref(),
-                        &logger,
-                        if status == "ok" {
-                            LoggingLevel::Info
-                        } else {
-                            LoggingLevel::Warning
-                        },
-                        format!(
-                            "[{operation}] completed {task_label} ({}/{}) status={status}",
-                            completed, total_tasks
-                        ),
-                    )
-                    .await;
-                    results.push(res);
-                }
-                Err(e) => {
-                    notify_fanout_progress(
-                        peer.as_ref(),
-                        &logger,
-                        LoggingLevel::Error,
+        let timeout_secs = args.timeout_secs.or(rp.profile.timeout_secs).unwrap_or(600);
+
+        if let Some(max_chars) = role_cfg.max_prompt_chars {
+            let prompt_chars = prompt_text.chars().count();
+            if prompt_chars > max_chars {
+                let policy = role_cfg.on_oversize_prompt;
+                if policy == OversizePrompt::Reject {
+                    return Err(McpError::invalid_params(
                         format!(
-                            "[{operation}] join error ({}/{}) {}",
-                            completed, total_tasks, e
+                            "prompt is {prompt_chars} chars, exceeding max_prompt_chars={max_chars} for role '{role}'"
                         ),
-                    )
-                    .await;
-                    results.push(FanoutResult {
-                        name: None,
-                        role: "".to_string(),
-                        result: Err(McpError::internal_error(format!("join error: {e}"), None)),
-                    });
+                        None,
+                    ));
                 }
+                prompt_text = truncate_prompt(&prompt_text, max_chars, policy);
+                warning_messages.push(format!(
+                    "prompt truncated from {prompt_chars} to {max_chars} chars ({})",
+                    policy.as_str()
+                ));
             }
         }
 
-        Ok(results)
-    }
-}
-
-fn is_model_error_message(msg: &str) -> bool {
-    msg.trim_start()
-        .to_ascii_lowercase()
-        .starts_with("model_not_found:")
-}
+        let backend_cfg = cfg.backend.get(&rp.profile.backend_id).ok_or_else(|| {
+            McpError::internal_error(
+                format!("missing backend config: {}", rp.profile.backend_id),
+                None,
+            )
+        })?;
+        let mut candidates: Vec<ModelCandidate> = build_model_chain(&cfg, &rp.profile)?
+            .into_iter()
+            .filter_map(|entry| match entry {
+                ChainEntry::Candidate(c) => Some(*c),
+                ChainEntry::Filtered { .. } => None,
+            })
+            .collect();
 
-#[tool_handler]
-impl ServerHandler for VibeServer {
-    fn get_info(&self) -> ServerInfo {
-        ServerInfo {
-            protocol_version: ProtocolVersion::V_2024_11_05,
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
-            server_info: Implementation::from_build_env(),
-            instructions: Some(
-                "This server provides 'roundtable' (core), 'roundtable-batch' (fan-out), and 'info' tools for multi-role orchestration."
-                    .to_string(),
-            ),
+        // Sticky fallback: if this session last succeeded on a fallback model, try that first.
+        // The primary stays in the list so it is still attempted if the fallback now fails.
+        if backend_cfg.sticky_fallback == Some(true) && !args.force_new_session {
+            if let Some(rec) = prev_rec.as_ref() {
+                let sticky_idx = candidates.iter().position(|c| {
+                    !c.primary
+                        && c.backend == rec.backend
+                        && rec.model.as_deref() == Some(c.model.as_str())
+                });
+                if let Some(idx) = sticky_idx {
+                    let sticky = candidates.remove(idx);
+                    candidates.insert(0, sticky);
+                }
+            }
         }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::config::Backend;
-    use std::io::Write;
-    use std::path::Path;
-    use std::process::Command;
 
-    // Note: tests call `run_vibe_internal` directly.
+        // `stream`: messages parsed while the backend runs are relayed as log notifications.
+        let streams = candidates.first().is_some_and(|c| {
+            matches!(
+                c.adapter.output_parser,
+                OutputParserConfig::JsonStream { .. }
+            )
+        });
+        if args.stream && !args.dry_run && !streams {
+            warning_messages.push(
+                "stream: ignored; only json_stream parsers emit incremental messages".to_string(),
+            );
+        }
+        let (stream_tx, stream_forwarder) = match peer.filter(|_| args.stream && !args.dry_run) {
+            Some(peer) => {
+                let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+                let label = role.clone();
+                let forwarder = tokio::spawn(async move {
+                    while let Some(message) = rx.recv().await {
+                        notify_fanout_progress(
+                            Some(&peer),
+                            "roundtable.stream",
+                            LoggingLevel::Info,
+                            format!("[{label}] {message}"),
+                        )
+                        .await;
+                    }
+                });
+                (Some(tx), Some(forwarder))
+            }
+            None => (None, None),
+        };
+
+        let options_for = |candidate: &ModelCandidate| {
+            let same_backend = candidate.backend_id == rp.profile.backend_id;
+            let mut adapter = candidate.adapter.clone();
+            if let Some(transport) = args.prompt_transport {
+                adapter.prompt_transport = Some(transport);
+            }
+            backend::GenericOptions {
+                backend_id: candidate.backend_id.clone(),
+                adapter,
+                prompt: prompt_text.clone(),
+                workdir: repo_root.clone(),
+                session_id: if same_backend {
+                    session_id_to_use.clone()
+                } else {
+                    None
+                },
+                resume: if same_backend {
+                    resume_without_session
+                } else {
+                    false
+                },
+                model: candidate.model.clone(),
+                options: candidate.options.clone(),
+                capabilities: rp.profile.capabilities.clone(),
+                fallback_error_patterns: candidate.fallback_error_patterns.clone(),
+                timeout_secs,
+                n: args.n,
+                client_id: cfg.client_id.clone(),
+                env: rp.profile.env.clone(),
+                progress: stream_tx.clone(),
+                include_directories: include_directories.clone(),
+            }
+        };
+
+        if args.dry_run {
+            let candidate = candidates
+                .first()
+                .ok_or_else(|| McpError::internal_error("empty model candidate chain", None))?;
+            let opts = options_for(candidate);
+            let preview = backend::dry_run(&opts)
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+            warning_messages.push("dry_run: backend not invoked; no session recorded".to_string());
+            let prompt_chars = prompt_text.chars().count();
+            return Ok(VibeOutput {
+                success: true,
+                backend: candidate.backend_id.clone(),
+                role,
+                role_id: rp.role_id,
+                model: Some(candidate.model.clone()),
+                session_key,
+                resumed,
+                session_age_secs: None,
+                previous_model: None,
+                backend_session_id: opts.session_id.unwrap_or_default(),
+                agent_messages: String::new(),
+                raw_message: None,
+                warnings: join_warnings(&warning_messages),
+                warning_list: warning_messages,
+                contract: None,
+                contract_errors: Vec::new(),
+                citations: Vec::new(),
+                contract_json: None,
+                patch_format: None,
+                patch_apply_check_ok: None,
+                patch_apply_check_output: None,
+                patch_checks: Vec::new(),
+                patch_stats: None,
+                code_blocks: Vec::new(),
+                candidates: Vec::new(),
+                prompt_chars,
+                response_chars: 0,
+                prompt_tokens_estimate: estimate_tokens(prompt_chars),
+                response_tokens_estimate: 0,
+                rendered_args: Some(preview.args),
+                resolved_command: Some(preview.command),
+                prompt_transport: Some(preview.prompt_transport.to_string()),
+                command_used: None,
+                invoked_args: None,
+                prompt_transport_used: None,
+                truncated: false,
+                original_len: None,
+                error: None,
+                error_kind: None,
+                cached: false,
+                session_persisted: false,
+                fallback_used: false,
+                fallback_model_ref: None,
+                fallback_trigger: None,
+            });
+        }
+
+        // `cache`: an unexpired answer for the same repo/role/backend/model/n/options/prompt
+        // replaces the backend call; contracts and projections below still run on it.
+        let cache_key = args.cache.then(|| {
+            SessionStore::compute_cache_key(
+                &repo_root,
+                &rp.role_id,
+                &rp.profile.backend_id,
+                &rp.profile.model,
+                args.n,
+                &prompt_text,
+                &rp.profile.options,
+            )
+        });
+        let cache_hit = match cache_key.as_deref() {
+            Some(key) => self.store.cached_response(key).unwrap_or_else(|e| {
+                warning_messages.push(format!("response cache unavailable: {e}"));
+                None
+            }),
+            None => None,
+        };
+        let cached = cache_hit.is_some();
+        let mut used_fallback: Option<String> = None;
+        let mut fallback_trigger: Option<String> = None;
+        let mut last_err: Option<String> = None;
+        let mut result: Option<backend::GenericResult> = None;
+        let mut used_backend_id = rp.profile.backend_id.clone();
+        let mut used_backend = rp.profile.backend;
+        let mut used_model = rp.profile.model.clone();
+        let total_candidates = candidates.len();
+        if let Some(hit) = cache_hit {
+            used_backend_id = hit.backend_id;
+            used_backend = hit.backend;
+            used_model = hit.model;
+            result = Some(backend::GenericResult {
+                command: String::new(),
+                args: Vec::new(),
+                prompt_transport: "",
+                session_id: String::new(),
+                rejected_session_id: None,
+                original_len: hit.agent_messages.chars().count(),
+                agent_messages: hit.agent_messages,
+                candidates: hit.candidates,
+                warnings: None,
+                truncated: false,
+            });
+        } else {
+            if let Err(msg) =
+                run_role_command("pre_command", &pre_command, &repo_root, timeout_secs).await
+            {
+                return Err(ErrorKind::HookFailed.error(msg));
+            }
+            self.metrics.inc(Counter::Calls, &rp.profile.backend_id);
+            // A backend failure still runs `post_command` before it is returned.
+            let mut failure: Option<McpError> = None;
+            for (idx, candidate) in candidates.into_iter().enumerate() {
+                let out = backend::run(options_for(&candidate)).await;
+                match out {
+                    Ok(r) => {
+                        self.metrics.inc(Counter::Successes, &candidate.backend_id);
+                        if !candidate.primary {
+                            self.metrics.inc(Counter::Fallbacks, &candidate.backend_id);
+                            used_fallback = Some(candidate.ref_str.clone());
+                            fallback_trigger = last_err.clone();
+                        }
+                        used_backend_id = candidate.backend_id;
+                        used_backend = candidate.backend;
+                        used_model = candidate.model;
+                        result = Some(r);
+                        break;
+                    }
+                    Err(e) => {
+                        if e.downcast_ref::<backend::SpawnFailed>().is_some() {
+                            // A cached probe for this CLI is no longer true.
+                            if let Ok(mut cache) = self.probe_cache.lock() {
+                                cache.remove(&candidate.backend_id);
+                            }
+                        }
+                        let msg = e.to_string();
+                        if msg.contains("timed out") {
+                            self.metrics.inc(Counter::Timeouts, &candidate.backend_id);
+                        }
+                        last_err = Some(msg.clone());
+                        if is_model_error_message(&msg) && idx + 1 < total_candidates {
+                            let allowed = match retry_budget {
+                                Some(budget) => budget.try_consume(),
+                                None => true,
+                            };
+                            if allowed {
+                                self.metrics.inc(Counter::Retries, &candidate.backend_id);
+                                continue;
+                            }
+                            self.metrics.inc(Counter::Failures, &rp.profile.backend_id);
+                            failure =
+                                Some(ErrorKind::of_backend_message(&msg).error(format!(
+                                    "backend failed: {msg} (retry budget exhausted)"
+                                )));
+                            break;
+                        }
+                        self.metrics.inc(Counter::Failures, &rp.profile.backend_id);
+                        failure = Some(
+                            ErrorKind::of_backend_message(&msg)
+                                .error(format!("backend failed: {msg}")),
+                        );
+                        break;
+                    }
+                }
+            }
+            if let Err(msg) =
+                run_role_command("post_command", &post_command, &repo_root, timeout_secs).await
+            {
+                warning_messages.push(msg);
+            }
+            if let Some(e) = failure {
+                return Err(e);
+            }
+        }
+
+        // Let every streamed message reach the client before the final response.
+        drop(stream_tx);
+        if let Some(forwarder) = stream_forwarder {
+            let _ = forwarder.await;
+        }
+
+        let r = result.ok_or_else(|| {
+            self.metrics.inc(Counter::Failures, &rp.profile.backend_id);
+            let msg = last_err.unwrap_or_else(|| "unknown error".to_string());
+            ErrorKind::of_backend_message(&msg).error(format!("backend failed: {msg}"))
+        })?;
+
+        // A cache hit ran nothing and leaves the stored session alone.
+        let resumed = resumed && !cached;
+        let command_used = r.command;
+        let invoked_args = r.args;
+        let prompt_transport_used = r.prompt_transport;
+        let output_truncated = r.truncated;
+        let original_len = r.original_len;
+        if output_truncated {
+            warning_messages.push(format!(
+                "backend answer truncated to max_output_chars (originally {original_len} chars)"
+            ));
+        }
+        let backend_session_id = r.session_id;
+        let agent_messages = r.agent_messages;
+        let answer_candidates = r.candidates;
+        let prompt_chars = prompt_text.chars().count();
+        let response_chars = agent_messages.chars().count();
+        if let Some(n) = args.n.filter(|n| *n as usize > answer_candidates.len()) {
+            warning_messages.push(format!(
+                "n={n} requested but backend '{used_backend_id}' returned {} candidate(s)",
+                answer_candidates.len()
+            ));
+        }
+        if let Some(rejected) = r.rejected_session_id.as_deref() {
+            warning_messages.push(format!(
+                "session id {rejected:?} from backend '{used_backend_id}' does not match session_id_pattern_validate; this session cannot be resumed"
+            ));
+        } else if backend_session_id.trim().is_empty() && !cached {
+            warning_messages.push(format!(
+                "no session id captured from backend '{used_backend_id}'; this session cannot be resumed"
+            ));
+        }
+        let (session_age_secs, previous_model) = match prev_rec.as_ref() {
+            Some(rec) if resumed => (
+                Some(now_unix_secs().saturating_sub(rec.updated_at_unix_secs)),
+                rec.model.clone(),
+            ),
+            _ => (None, None),
+        };
+        // Backend stderr first, then this call's own warnings, then the fallback notice.
+        let mut warning_list: Vec<String> = r.warnings.into_iter().collect();
+        warning_list.extend(warning_messages);
+        warning_list.extend(
+            used_fallback
+                .as_ref()
+                .map(|m| format!("model fallback used: {m}")),
+        );
+
+        let session_persisted = !cached && !args.ephemeral && !self.store.is_readonly();
+        if !cached && !args.ephemeral {
+            self.store
+                .put(
+                    &session_key,
+                    SessionRecord {
+                        repo_root: repo_root.to_string_lossy().to_string(),
+                        role: role.clone(),
+                        role_id: rp.role_id.clone(),
+                        backend: used_backend,
+                        backend_session_id: backend_session_id.clone(),
+                        model: Some(used_model.clone()),
+                        sampling_history: Vec::new(),
+                        updated_at_unix_secs: now_unix_secs(),
+                    },
+                )
+                .map_err(|e| {
+                    McpError::internal_error(format!("failed to persist session: {e}"), None)
+                })?;
+        }
+
+        let mut contract_errors: Vec<String> = Vec::new();
+        let mut citations: Vec<contract::Citation> = Vec::new();
+        let mut contract_json: Option<serde_json::Value> = None;
+        let mut patch_format: Option<String> = None;
+        let mut patch_apply_check_ok: Option<bool> = None;
+        let mut patch_apply_check_output: Option<String> = None;
+        let mut patch_checks: Vec<contract::PatchCheck> = Vec::new();
+        let mut patch_stats: Option<contract::PatchStats> = None;
+        let mut error: Option<String> = None;
+        let mut error_kind: Option<ErrorKind> = None;
+        let contract_output = args.contract_output.unwrap_or_default();
+        let mut projected: Option<String> = None;
+
+        if let Some(OutputContract::PatchWithCitations) = args.contract.as_ref() {
+            let check = if args.strict_citations {
+                contract::check_patch_with_citations_strict(&agent_messages)
+            } else {
+                contract::check_patch_with_citations(&agent_messages)
+            };
+            contract_errors = check.errors.clone();
+            citations = check.citations.clone();
+            patch_format = Some(format!("{:?}", check.patch_format).to_ascii_lowercase());
+            if check.errors.is_empty() {
+                patch_stats = contract::patch_stats(&check.extracted_patches);
+            }
+            projected = match contract_output {
+                ContractOutput::Message => None,
+                ContractOutput::Patch => check.extracted_patch.clone(),
+                ContractOutput::Citations => {
+                    Some(contract::citation_text(&agent_messages).trim().to_string())
+                        .filter(|c| !c.is_empty())
+                }
+            };
+
+            if args.validate_patch {
+                match (check.patch_format, check.extracted_patches.is_empty()) {
+                    (contract::PatchFormat::UnifiedDiff, false) => {
+                        patch_checks =
+                            contract::validate_patches(&repo_root, &check.extracted_patches);
+                        patch_apply_check_ok = Some(patch_checks.iter().all(|c| c.ok));
+                        patch_apply_check_output = Some(match patch_checks.as_slice() {
+                            [only] => only.output.clone(),
+                            all => all
+                                .iter()
+                                .filter(|c| !c.output.trim().is_empty())
+                                .map(|c| format!("patch {}: {}", c.index, c.output.trim()))
+                                .collect::<Vec<_>>()
+                                .join("\n"),
+                        });
+                    }
+                    (contract::PatchFormat::UnifiedDiff, true) => {
+                        patch_apply_check_ok = Some(false);
+                        patch_apply_check_output = Some(
+                            "validate_patch=true but failed to extract unified diff patch"
+                                .to_string(),
+                        );
+                    }
+                    _ => {
+                        patch_apply_check_ok = Some(false);
+                        patch_apply_check_output =
+                            Some("validate_patch=true but patch is not a unified diff".to_string());
+                    }
+                }
+            }
+
+            if !contract_errors.is_empty() {
+                error = Some(format!(
+                    "output contract violation: {}",
+                    contract_errors.join(", ")
+                ));
+                error_kind = Some(ErrorKind::ContractViolation);
+            }
+            if args.validate_patch {
+                if patch_apply_check_ok != Some(true) {
+                    let msg = patch_apply_check_output
+                        .clone()
+                        .unwrap_or_else(|| "git apply --check failed".to_string());
+                    error = Some(format!("patch validation failed: {msg}"));
+                    error_kind = Some(ErrorKind::PatchValidationFailed);
+                }
+            }
+        }
+
+        if let Some(OutputContract::Language { expected, enforce }) = args.contract.as_ref() {
+            let errors = contract::check_language(&agent_messages, expected);
+            if !errors.is_empty() {
+                if *enforce {
+                    error = Some(format!("output contract violation: {}", errors.join(", ")));
+                    error_kind = Some(ErrorKind::ContractViolation);
+                    contract_errors = errors;
+                } else {
+                    warning_list.push(format!("output contract warning: {}", errors.join(", ")));
+                }
+            }
+        }
+
+        if let Some(OutputContract::JsonSchema { schema }) = args.contract.as_ref() {
+            let check = contract::check_json_schema(&agent_messages, schema);
+            if check.errors.is_empty() {
+                contract_json = check.value;
+            } else {
+                error = Some(format!(
+                    "output contract violation: {}",
+                    check.errors.join(", ")
+                ));
+                error_kind = Some(ErrorKind::ContractViolation);
+                contract_errors = check.errors;
+            }
+        }
+
+        if let Some(key) = cache_key.as_deref().filter(|_| !cached && error.is_none()) {
+            let stored = self.store.put_cached_response(
+                key,
+                CachedResponse {
+                    backend_id: used_backend_id.clone(),
+                    backend: used_backend,
+                    model: used_model.clone(),
+                    agent_messages: agent_messages.clone(),
+                    candidates: answer_candidates.clone(),
+                    stored_at_unix_secs: now_unix_secs(),
+                },
+            );
+            if let Err(e) = stored {
+                warning_list.push(format!("failed to cache response: {e}"));
+            }
+        }
+
+        let code_blocks = args
+            .extract_code_lang
+            .as_deref()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| contract::extract_code_blocks(&agent_messages, l))
+            .unwrap_or_default();
+
+        if contract_output != ContractOutput::Message && projected.is_none() {
+            let msg = if matches!(args.contract, Some(OutputContract::PatchWithCitations)) {
+                "contract_output ignored: nothing to extract; returning the full message"
+            } else {
+                "contract_output ignored: requires contract=patch_with_citations"
+            };
+            warning_list.push(msg.to_string());
+        }
+        let (agent_messages, raw_message) = match projected {
+            Some(p) => (p, Some(agent_messages)),
+            None => (agent_messages, None),
+        };
+
+        let out = VibeOutput {
+            success: error.is_none(),
+            backend: used_backend_id,
+            role,
+            role_id: rp.role_id,
+            model: Some(used_model),
+            session_key,
+            resumed,
+            session_age_secs,
+            previous_model,
+            backend_session_id,
+            agent_messages,
+            raw_message,
+            warnings: join_warnings(&warning_list),
+            warning_list,
+            contract: args.contract.map(|c| match c {
+                OutputContract::PatchWithCitations => "patch_with_citations".to_string(),
+                OutputContract::Language { .. } => "language".to_string(),
+                OutputContract::JsonSchema { .. } => "json_schema".to_string(),
+            }),
+            contract_errors,
+            citations,
+            contract_json,
+            patch_format,
+            patch_apply_check_ok,
+            patch_apply_check_output,
+            patch_checks,
+            patch_stats,
+            code_blocks,
+            candidates: answer_candidates,
+            prompt_chars,
+            response_chars,
+            prompt_tokens_estimate: estimate_tokens(prompt_chars),
+            response_tokens_estimate: estimate_tokens(response_chars),
+            rendered_args: None,
+            resolved_command: None,
+            prompt_transport: None,
+            command_used: (!cached).then_some(command_used),
+            invoked_args: (!cached).then_some(invoked_args),
+            prompt_transport_used: (!cached).then(|| prompt_transport_used.to_string()),
+            truncated: output_truncated,
+            original_len: output_truncated.then_some(original_len),
+            error,
+            error_kind: error_kind.map(|k| k.as_str().to_string()),
+            cached,
+            session_persisted,
+            fallback_used: used_fallback.is_some(),
+            fallback_model_ref: used_fallback,
+            fallback_trigger,
+        };
+
+        Ok(out)
+    }
+
+    fn prepare_roundtable(&self, args: RoundtableArgs) -> Result<PreparedRoundtable, McpError> {
+        if args.topic.trim().is_empty() {
+            return Err(McpError::invalid_params(
+                "TOPIC is required and must be a non-empty string",
+                None,
+            ));
+        }
+        let cd = self.effective_cd(&args.cd)?;
+        if args.participants.is_empty() {
+            return Err(McpError::invalid_params(
+                "participants must be a non-empty array",
+                None,
+            ));
+        }
+
+        // Canonicalize cd once to validate it's usable.
+        let repo_root = cd.canonicalize().map_err(|e| {
+            McpError::invalid_params(
+                format!(
+                    "working directory does not exist or is not accessible: {} ({})",
+                    cd.display(),
+                    e
+                ),
+                None,
+            )
+        })?;
+        if !repo_root.is_dir() {
+            return Err(McpError::invalid_params(
+                format!(
+                    "working directory is not a directory: {}",
+                    repo_root.display()
+                ),
+                None,
+            ));
+        }
+
+        let client_hint = resolve_client_hint(args.client.as_deref())?;
+        let conversation_hint = resolve_conversation_hint(args.conversation_id.as_deref())?;
+        let RoundtableArgs {
+            topic,
+            participants,
+            timeout_secs,
+            client: _client,
+            conversation_id: _conversation_id,
+            background: _,
+            max_concurrency,
+            stagger_ms,
+            moderator,
+            format,
+            cd: _,
+        } = args;
+
+        let topic_trimmed = topic.trim().to_string();
+        // Tasks get the cd as given so each one applies `canonicalize_cd` itself.
+        let repo_cd = cd.to_string_lossy().to_string();
+        let timeout_override = timeout_secs;
+
+        let mut tasks: Vec<FanoutTaskSpec> = Vec::new();
+        for (order, p) in participants.into_iter().enumerate() {
+            if p.name.trim().is_empty() {
+                return Err(McpError::invalid_params(
+                    "participant.name must be non-empty",
+                    None,
+                ));
+            }
+
+            let name = p.name.trim().to_string();
+            let role = p
+                .role
+                .clone()
+                .filter(|s| !s.trim().is_empty())
+                .unwrap_or_else(|| name.clone());
+
+            let prompt = format!(
+                "TOPIC:
+{}
+
+You are a roundtable participant named '{}' (role: {}).
+
+Reply with:
+1) Position (1-2 sentences)
+2) Arguments (bullets)
+3) Risks/edge cases (bullets)
+4) Recommendation (actionable)
+
+Constraints:
+- Do not claim to have run commands unless you actually did.
+- Prefer referencing repo paths when relevant.
+",
+                topic_trimmed, name, role
+            );
+
+            let args = VibeArgs {
+                prompt,
+                cd: repo_cd.clone(),
+                role: Some(role.clone()),
+                backend: p.backend,
+                model: p.model,
+                reasoning_effort: p.reasoning_effort,
+                session_id: None,
+                force_new_session: p.force_new_session,
+                session_key: None,
+                timeout_secs: timeout_override,
+                contract: None,
+                validate_patch: false,
+                strict_citations: false,
+                contract_output: None,
+                output_parser: None,
+                canonicalize_cd: None,
+                extract_code_lang: None,
+                n: None,
+                client: client_hint.clone(),
+                conversation_id: conversation_hint.clone(),
+                participant: Some(name.clone()),
+                session_group: p.session_group,
+                ephemeral: false,
+                dry_run: false,
+                persona_override: None,
+                stream: false,
+                prompt_file: None,
+                prompt_transport: None,
+                include_directories: None,
+                cache: false,
+            };
+            tasks.push(FanoutTaskSpec {
+                order,
+                name: Some(name),
+                role,
+                args,
+                retry_budget: None,
+            });
+        }
+
+        let moderator = match moderator {
+            None => None,
+            Some(m) => {
+                if m.role.trim().is_empty() {
+                    return Err(McpError::invalid_params(
+                        "moderator.role must be non-empty",
+                        None,
+                    ));
+                }
+                Some(VibeArgs {
+                    prompt: m.instructions.unwrap_or_default(),
+                    cd: repo_cd.clone(),
+                    role: Some(m.role.trim().to_string()),
+                    backend: m.backend,
+                    model: m.model,
+                    reasoning_effort: m.reasoning_effort,
+                    session_id: None,
+                    force_new_session: m.force_new_session,
+                    session_key: None,
+                    timeout_secs: timeout_override,
+                    contract: None,
+                    validate_patch: false,
+                    strict_citations: false,
+                    contract_output: None,
+                    output_parser: None,
+                    canonicalize_cd: None,
+                    extract_code_lang: None,
+                    n: None,
+                    client: client_hint.clone(),
+                    conversation_id: conversation_hint.clone(),
+                    participant: Some("moderator".to_string()),
+                    session_group: None,
+                    ephemeral: false,
+                    dry_run: false,
+                    persona_override: None,
+                    stream: false,
+                    prompt_file: None,
+                    prompt_transport: None,
+                    include_directories: None,
+                    cache: false,
+                })
+            }
+        };
+
+        Ok(PreparedRoundtable {
+            topic,
+            repo_root,
+            tasks,
+            client_hint,
+            conversation_hint,
+            max_concurrency,
+            stagger_ms,
+            moderator,
+            format,
+        })
+    }
+
+    async fn run_roundtable_internal(
+        &self,
+        peer: Option<Peer<RoleServer>>,
+        args: RoundtableArgs,
+    ) -> Result<RoundtableOutput, McpError> {
+        let PreparedRoundtable {
+            topic,
+            repo_root,
+            tasks,
+            client_hint,
+            conversation_hint,
+            max_concurrency,
+            stagger_ms,
+            moderator,
+            format,
+        } = self.prepare_roundtable(args)?;
+
+        let results = self
+            .run_fanout_internal(
+                peer,
+                &repo_root,
+                tasks,
+                client_hint,
+                conversation_hint,
+                "roundtable",
+                max_concurrency,
+                stagger_ms,
+                None,
+                None,
+            )
+            .await?;
+
+        let contributions: Vec<RoundtableContribution> =
+            results.iter().map(roundtable_contribution).collect();
+        let mut error = if contributions.iter().any(|c| c.error.is_some()) {
+            Some("one or more participants returned an error".to_string())
+        } else {
+            None
+        };
+
+        let mut synthesis = None;
+        if let Some(mut args) = moderator {
+            args.prompt = roundtable_synthesis_prompt(&topic, &contributions, &args.prompt);
+            // A failed moderator keeps the contributions; only the synthesis is missing.
+            match self.run_vibe_internal(None, args).await {
+                Ok(out) if out.error.is_none() => synthesis = Some(out.agent_messages),
+                Ok(out) => {
+                    error.get_or_insert(format!(
+                        "moderator returned an error: {}",
+                        out.error.unwrap_or_default()
+                    ));
+                }
+                Err(e) => {
+                    error.get_or_insert(format!("moderator failed: {e}"));
+                }
+            }
+        }
+
+        let transcript =
+            (format == RoundtableFormat::Transcript).then(|| roundtable_transcript(&contributions));
+        Ok(RoundtableOutput {
+            success: error.is_none(),
+            topic,
+            cd: repo_root.to_string_lossy().to_string(),
+            contributions,
+            synthesis,
+            transcript,
+            error,
+        })
+    }
+
+    /// Validate a roundtable request, then run its fan-out in the background. Contributions are
+    /// recorded under the returned `operation_id` as they complete (see `roundtable-poll`).
+    /// `inflight` is the caller's `--max-inflight` slot, held until the fan-out finishes.
+    fn start_roundtable_operation(
+        &self,
+        peer: Option<Peer<RoleServer>>,
+        args: RoundtableArgs,
+        inflight: Option<tokio::sync::OwnedSemaphorePermit>,
+    ) -> Result<RoundtableStartOutput, McpError> {
+        let PreparedRoundtable {
+            topic,
+            repo_root,
+            tasks,
+            client_hint,
+            conversation_hint,
+            max_concurrency,
+            stagger_ms,
+            moderator,
+            format,
+        } = self.prepare_roundtable(args)?;
+        if moderator.is_some() {
+            return Err(McpError::invalid_params(
+                "moderator is not supported with background=true",
+                None,
+            ));
+        }
+
+        let operation_id = next_operation_id();
+        let total = tasks.len();
+        let cd = repo_root.to_string_lossy().to_string();
+        {
+            let mut ops = self
+                .operations
+                .lock()
+                .map_err(|_| McpError::internal_error("operation state poisoned", None))?;
+            evict_stale_operations(&mut ops);
+            ops.insert(
+                operation_id.clone(),
+                RoundtableOperation {
+                    topic: topic.clone(),
+                    cd: cd.clone(),
+                    total,
+                    contributions: Vec::new(),
+                    done: false,
+                    error: None,
+                    format,
+                    finished_at: None,
+                },
+            );
+        }
+
+        let server = self.clone();
+        let id = operation_id.clone();
+        // Keep the server busy until the background fan-out finishes.
+        let idle = self.idle.begin();
+        tokio::spawn(async move {
+            let _idle = idle;
+            let _inflight = inflight;
+            let operations = server.operations.clone();
+            let record_id = id.clone();
+            let on_result = move |res: &FanoutResult| {
+                if let Ok(mut ops) = operations.lock() {
+                    if let Some(op) = ops.get_mut(&record_id) {
+                        let index = res.index.unwrap_or(usize::MAX);
+                        op.contributions.push((index, roundtable_contribution(res)));
+                    }
+                }
+            };
+            let outcome = server
+                .run_fanout_internal(
+                    peer,
+                    &repo_root,
+                    tasks,
+                    client_hint,
+                    conversation_hint,
+                    "roundtable",
+                    max_concurrency,
+                    stagger_ms,
+                    Some(&on_result),
+                    None,
+                )
+                .await;
+            if let Ok(mut ops) = server.operations.lock() {
+                if let Some(op) = ops.get_mut(&id) {
+                    op.done = true;
+                    op.finished_at = Some(std::time::Instant::now());
+                    op.error = match outcome {
+                        Err(e) => Some(e.to_string()),
+                        Ok(_) if op.contributions.iter().any(|(_, c)| c.error.is_some()) => {
+                            Some("one or more participants returned an error".to_string())
+                        }
+                        Ok(_) => None,
+                    };
+                }
+            }
+        });
+
+        Ok(RoundtableStartOutput {
+            success: true,
+            operation_id,
+            topic,
+            cd,
+            participants: total,
+        })
+    }
+
+    /// Snapshot a background roundtable, contributions in participant order. Finished operations
+    /// are dropped after they are polled, or after `FINISHED_OPERATION_TTL` if never polled.
+    fn poll_roundtable_internal(
+        &self,
+        args: RoundtablePollArgs,
+    ) -> Result<RoundtablePollOutput, McpError> {
+        let operation_id = args.operation_id.trim().to_string();
+        let mut ops = self
+            .operations
+            .lock()
+            .map_err(|_| McpError::internal_error("operation state poisoned", None))?;
+        evict_stale_operations(&mut ops);
+        let op = ops.get(&operation_id).ok_or_else(|| {
+            McpError::invalid_params(format!("unknown operation_id: {operation_id}"), None)
+        })?;
+        let mut indexed = op.contributions.clone();
+        indexed.sort_by_key(|(index, _)| *index);
+        let contributions: Vec<RoundtableContribution> =
+            indexed.into_iter().map(|(_, c)| c).collect();
+        let out = RoundtablePollOutput {
+            success: op.done && op.error.is_none(),
+            operation_id: operation_id.clone(),
+            topic: op.topic.clone(),
+            cd: op.cd.clone(),
+            done: op.done,
+            total: op.total,
+            completed: contributions.len(),
+            transcript: (op.format == RoundtableFormat::Transcript)
+                .then(|| roundtable_transcript(&contributions)),
+            contributions,
+            error: op.error.clone(),
+        };
+        if out.done {
+            ops.remove(&operation_id);
+        }
+        Ok(out)
+    }
+
+    async fn run_batch_internal(
+        &self,
+        peer: Option<Peer<RoleServer>>,
+        args: BatchArgs,
+    ) -> Result<BatchOutput, McpError> {
+        let cd = self.effective_cd(&args.cd)?;
+        if args.tasks.is_empty() {
+            return Err(McpError::invalid_params(
+                "tasks must be a non-empty array",
+                None,
+            ));
+        }
+        if args.overall_timeout_secs == Some(0) {
+            return Err(McpError::invalid_params(
+                "overall_timeout_secs must be at least 1",
+                None,
+            ));
+        }
+        let deadline = args
+            .overall_timeout_secs
+            .map(|secs| tokio::time::Instant::now() + std::time::Duration::from_secs(secs));
+
+        let repo_root = cd.canonicalize().map_err(|e| {
+            McpError::invalid_params(
+                format!(
+                    "working directory does not exist or is not accessible: {} ({})",
+                    cd.display(),
+                    e
+                ),
+                None,
+            )
+        })?;
+        if !repo_root.is_dir() {
+            return Err(McpError::invalid_params(
+                format!(
+                    "working directory is not a directory: {}",
+                    repo_root.display()
+                ),
+                None,
+            ));
+        }
+
+        let repo_cd = cd.to_string_lossy().to_string();
+        let client_hint = resolve_client_hint(args.client.as_deref())?;
+        let conversation_hint = resolve_conversation_hint(args.conversation_id.as_deref())?;
+        let retry_budget = args.retry_budget.map(|n| Arc::new(RetryBudget::new(n)));
+        // Only used to pre-check roles; a missing config is reported by the fan-out itself.
+        let cfg = self
+            .config_loader
+            .load_for_repo_with_client(&repo_root, client_hint.as_deref())
+            .ok()
+            .and_then(|c| c.config);
+        let mut tasks: Vec<FanoutTaskSpec> = Vec::with_capacity(args.tasks.len());
+        let mut invalid: Vec<(usize, BatchResult)> = Vec::new();
+        // Number of valid tasks queued before the first invalid one.
+        let mut first_invalid: Option<usize> = None;
+        for (order, task) in args.tasks.into_iter().enumerate() {
+            let role_opt = task
+                .role
+                .as_ref()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .or_else(|| cfg.as_ref().and_then(|c| c.default_role.clone()));
+            let role_label = role_opt
+                .clone()
+                .or_else(|| task.backend.clone())
+                .unwrap_or_else(|| "<missing role>".to_string());
+            if let Err(e) = validate_batch_task(cfg.as_ref(), &task, role_opt.as_deref()) {
+                invalid.push((
+                    order,
+                    BatchResult {
+                        name: task.name,
+                        role: role_label,
+                        backend: "error".to_string(),
+                        output: None,
+                        error: Some(format!("invalid task: {e}")),
+                        error_kind: Some(ErrorKind::InvalidParams.as_str().to_string()),
+                    },
+                ));
+                first_invalid.get_or_insert(tasks.len());
+                continue;
+            }
+            let timeout_secs = task.timeout_secs.or(args.timeout_secs);
+            let args = VibeArgs {
+                prompt: task.prompt,
+                cd: repo_cd.clone(),
+                role: role_opt,
+                backend: task.backend,
+                model: task.model,
+                reasoning_effort: task.reasoning_effort,
+                session_id: task.session_id,
+                force_new_session: task.force_new_session,
+                session_key: task.session_key,
+                timeout_secs,
+                contract: task.contract,
+                validate_patch: task.validate_patch,
+                strict_citations: task.strict_citations,
+                contract_output: None,
+                output_parser: None,
+                canonicalize_cd: None,
+                extract_code_lang: None,
+                n: None,
+                client: client_hint.clone(),
+                conversation_id: conversation_hint.clone(),
+                participant: None,
+                session_group: task.session_group,
+                ephemeral: false,
+                dry_run: false,
+                persona_override: None,
+                stream: task.stream,
+                prompt_file: task.prompt_file,
+                prompt_transport: None,
+                include_directories: None,
+                cache: task.cache,
+            };
+            tasks.push(FanoutTaskSpec {
+                order,
+                name: task.name,
+                role: role_label,
+                args,
+                retry_budget: retry_budget.clone(),
+            });
+        }
+
+        let stop_on_error = args.mode == BatchMode::Sequential && args.stop_on_error;
+        // With stop_on_error, an invalid task ends the pipeline just like a failed one.
+        let runnable = match first_invalid {
+            Some(n) if stop_on_error => n,
+            _ => tasks.len(),
+        };
+        let mut results = if runnable == 0 {
+            Vec::new()
+        } else {
+            self.run_batch_round(
+                peer.clone(),
+                &repo_root,
+                tasks[..runnable].to_vec(),
+                client_hint.clone(),
+                conversation_hint.clone(),
+                args.mode,
+                args.max_concurrency,
+                args.stagger_ms,
+                stop_on_error,
+                deadline,
+            )
+            .await?
+        };
+        let skipped = tasks.len() - results.len();
+
+        // Batch-level retry: fan out again with only the tasks that failed, then splice the new
+        // results over the old ones. Join errors carry no task index and are not retried.
+        let mut retry_rounds = Vec::new();
+        for round in 1..=args.retry_failed.unwrap_or(0) {
+            if deadline.is_some_and(|d| tokio::time::Instant::now() >= d) {
+                break;
+            }
+            let failed: Vec<usize> = results
+                .iter()
+                .enumerate()
+                .filter(|(_, r)| r.index.is_some() && r.failed())
+                .map(|(pos, _)| pos)
+                .collect();
+            if failed.is_empty() {
+                break;
+            }
+            notify_fanout_progress(
+                peer.as_ref(),
+                "roundtable.roundtable-batch",
+                LoggingLevel::Info,
+                format!(
+                    "[roundtable-batch] retry round {round}/{}: re-running {} failed task(s)",
+                    args.retry_failed.unwrap_or(0),
+                    failed.len()
+                ),
+            )
+            .await;
+            let retry_tasks: Vec<FanoutTaskSpec> = failed
+                .iter()
+                .filter_map(|&pos| results[pos].index.map(|i| tasks[i].clone()))
+                .collect();
+            let retried = self
+                .run_batch_round(
+                    peer.clone(),
+                    &repo_root,
+                    retry_tasks,
+                    client_hint.clone(),
+                    conversation_hint.clone(),
+                    args.mode,
+                    args.max_concurrency,
+                    args.stagger_ms,
+                    stop_on_error,
+                    deadline,
+                )
+                .await?;
+            let mut recovered = 0;
+            for res in retried {
+                let Some(i) = res.index else {
+                    continue;
+                };
+                if !res.failed() {
+                    recovered += 1;
+                }
+                let pos = failed[i];
+                results[pos] = FanoutResult {
+                    index: results[pos].index,
+                    ..res
+                };
+            }
+            retry_rounds.push(BatchRetryRound {
+                round,
+                retried: failed.len(),
+                recovered,
+            });
+        }
+
+        let mut any_error = !invalid.is_empty();
+        let mut outputs: Vec<(usize, BatchResult)> = invalid;
+        for res in results {
+            // Join errors lost their task; they go last.
+            let order = res.index.map_or(usize::MAX, |i| tasks[i].order);
+            match res.result {
+                Ok(out) => {
+                    if out.error.is_some() {
+                        any_error = true;
+                    }
+                    let err = out.error.clone();
+                    let error_kind = out.error_kind.clone();
+                    let backend = out.backend.clone();
+                    outputs.push((
+                        order,
+                        BatchResult {
+                            name: res.name,
+                            role: res.role,
+                            backend,
+                            output: Some(out),
+                            error: err,
+                            error_kind,
+                        },
+                    ));
+                }
+                Err(e) => {
+                    any_error = true;
+                    outputs.push((
+                        order,
+                        BatchResult {
+                            name: res.name,
+                            role: res.role,
+                            backend: "error".to_string(),
+                            output: None,
+                            error: Some(e.to_string()),
+                            error_kind: error_kind_of(&e),
+                        },
+                    ));
+                }
+            }
+        }
+
+        let mut warnings = Vec::new();
+        if skipped > 0 {
+            warnings.push(format!(
+                "stop_on_error: {skipped} task(s) after the first failure were not run"
+            ));
+        }
+        if let Some(budget) = retry_budget.as_ref() {
+            if budget.exhausted.load(Ordering::SeqCst) {
+                warnings.push(format!(
+                    "retry budget exhausted ({} re-invocations); later failing tasks were not retried",
+                    args.retry_budget.unwrap_or(0)
+                ));
+            }
+        }
+        // Report tasks in request order, whatever order they finished (or were rejected) in.
+        outputs.sort_by_key(|(order, _)| *order);
+
+        Ok(BatchOutput {
+            success: !any_error,
+            cd: repo_root.to_string_lossy().to_string(),
+            results: outputs.into_iter().map(|(_, res)| res).collect(),
+            warnings,
+            retry_rounds,
+            error: if any_error {
+                Some("one or more tasks returned an error".to_string())
+            } else {
+                None
+            },
+        })
+    }
+
+    /// Runs one round of batch tasks in `mode`.
+    #[allow(clippy::too_many_arguments)]
+    async fn run_batch_round(
+        &self,
+        peer: Option<Peer<RoleServer>>,
+        repo_root: &Path,
+        tasks: Vec<FanoutTaskSpec>,
+        client: Option<String>,
+        conversation_id: Option<String>,
+        mode: BatchMode,
+        max_concurrency: Option<usize>,
+        stagger_ms: u64,
+        stop_on_error: bool,
+        deadline: Option<tokio::time::Instant>,
+    ) -> Result<Vec<FanoutResult>, McpError> {
+        match mode {
+            BatchMode::Parallel => {
+                self.run_fanout_internal(
+                    peer,
+                    repo_root,
+                    tasks,
+                    client,
+                    conversation_id,
+                    "roundtable-batch",
+                    max_concurrency,
+                    stagger_ms,
+                    None,
+                    deadline,
+                )
+                .await
+            }
+            BatchMode::Sequential => Ok(self
+                .run_sequential_internal(peer, tasks, stop_on_error, deadline)
+                .await),
+        }
+    }
+
+    /// Runs tasks one at a time, in order, so each one sees the sessions left by the previous
+    /// ones. Only one task runs at a time, so neither the session-key collision warnings nor the
+    /// kimi multi-resume check apply. With `stop_on_error`, the first failed task ends the run and
+    /// the results cover only the tasks that ran. Once `deadline` passes, the running task is
+    /// stopped and it and every later task fail with "batch deadline exceeded".
+    async fn run_sequential_internal(
+        &self,
+        peer: Option<Peer<RoleServer>>,
+        tasks: Vec<FanoutTaskSpec>,
+        stop_on_error: bool,
+        deadline: Option<tokio::time::Instant>,
+    ) -> Vec<FanoutResult> {
+        let logger = "roundtable.roundtable-batch";
+        let total_tasks = tasks.len();
+        let mut results: Vec<FanoutResult> = Vec::with_capacity(total_tasks);
+        for (idx, task) in tasks.into_iter().enumerate() {
+            let FanoutTaskSpec {
+                order: _,
+                name,
+                role,
+                args,
+                retry_budget,
+            } = task;
+            let task_label = name.clone().unwrap_or_else(|| role.clone());
+            notify_fanout_progress(
+                peer.as_ref(),
+                logger,
+                LoggingLevel::Info,
+                format!(
+                    "[roundtable-batch] started {task_label} ({}/{})",
+                    idx + 1,
+                    total_tasks
+                ),
+            )
+            .await;
+            let run = self.run_vibe_with_budget(peer.clone(), args, retry_budget.as_deref());
+            let result = match deadline {
+                Some(d) if tokio::time::Instant::now() >= d => Err(batch_deadline_exceeded()),
+                Some(d) => tokio::time::timeout_at(d, run)
+                    .await
+                    .unwrap_or_else(|_| Err(batch_deadline_exceeded())),
+                None => run.await,
+            };
+            let res = FanoutResult {
+                index: Some(idx),
+                name,
+                role,
+                result,
+            };
+            let failed = res.failed();
+            notify_fanout_progress(
+                peer.as_ref(),
+                logger,
+                if failed {
+                    LoggingLevel::Warning
+                } else {
+                    LoggingLevel::Info
+                },
+                format!(
+                    "[roundtable-batch] completed {task_label} ({}/{}) status={}",
+                    idx + 1,
+                    total_tasks,
+                    if failed { "error" } else { "ok" }
+                ),
+            )
+            .await;
+            results.push(res);
+            if failed && stop_on_error {
+                break;
+            }
+        }
+        results
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn run_fanout_internal(
+        &self,
+        peer: Option<Peer<RoleServer>>,
+        repo_root: &Path,
+        tasks: Vec<FanoutTaskSpec>,
+        client: Option<String>,
+        conversation_id: Option<String>,
+        operation: &'static str,
+        max_concurrency: Option<usize>,
+        stagger_ms: u64,
+        on_result: Option<&(dyn Fn(&FanoutResult) + Send + Sync)>,
+        deadline: Option<tokio::time::Instant>,
+    ) -> Result<Vec<FanoutResult>, McpError> {
+        if max_concurrency == Some(0) {
+            return Err(McpError::invalid_params(
+                "max_concurrency must be at least 1",
+                None,
+            ));
+        }
+
+        let cfg_for_repo = self
+            .config_loader
+            .load_for_repo_with_client(repo_root, client.as_deref())
+            .map_err(|e| McpError::internal_error(format!("failed to load config: {e}"), None))?;
+        let cfg = cfg_for_repo.config.ok_or_else(|| {
+            McpError::invalid_params("no config found (create ~/.config/roundtable/config.json)", None)
+        })?;
+
+        let task_keys: Vec<Option<String>> = tasks
+            .iter()
+            .map(|task| {
+                fanout_session_key(
+                    &cfg,
+                    repo_root,
+                    &task.args,
+                    client.as_deref(),
+                    conversation_id.as_deref(),
+                )
+            })
+            .collect();
+        // Tasks sharing a key wait on the same session lock, so they quietly run one at a time.
+        let collision_warnings: Vec<Option<String>> = task_keys
+            .iter()
+            .enumerate()
+            .map(|(idx, key)| {
+                let key = key.as_deref()?;
+                let group = tasks[idx].args.session_group.as_deref();
+                let others: Vec<String> = task_keys
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, k)| *other != idx && k.as_deref() == Some(key))
+                    // Sharing is the point of a session group, so don't warn about it.
+                    .filter(|(other, _)| {
+                        group.is_none() || tasks[*other].args.session_group.as_deref() != group
+                    })
+                    .map(|(other, _)| {
+                        let task = &tasks[other];
+                        task.name.clone().unwrap_or_else(|| task.role.clone())
+                    })
+                    .collect();
+                (!others.is_empty()).then(|| {
+                    format!(
+                        "session_key shared with {}: these tasks run one at a time on the same session; give each a distinct role, participant, or session_key",
+                        others.join(", ")
+                    )
+                })
+            })
+            .collect();
+
+        let mut kimi_resume_roles: Vec<String> = Vec::new();
+        let mut kimi_resume_tasks: BTreeSet<usize> = BTreeSet::new();
+        // Tasks on the same session key (e.g. one session group) resume a single session between
+        // them, one at a time, so they count once.
+        let mut kimi_resume_keys: BTreeSet<String> = BTreeSet::new();
+        for (idx, task) in tasks.iter().enumerate() {
+            if task.args.force_new_session {
+                continue;
+            }
+            let resolved = match resolve_call_profile(&cfg, &task.args) {
+                Ok(r) => r,
+                Err(_) => continue,
+            };
+            let role_id = resolved.role_id.as_str();
+            if resolved.profile.backend_id != "kimi" {
+                continue;
+            }
+
+            let explicit_session_id = task
+                .args
+                .session_id
+                .as_ref()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty());
+            if explicit_session_id.is_some() {
+                kimi_resume_roles.push(role_id.to_string());
+                kimi_resume_tasks.insert(idx);
+                continue;
+            }
+
+            let Some(session_key) = task_keys[idx].as_deref() else {
+                continue;
+            };
+            let prev_rec = self.store.get(session_key).ok().flatten();
+            if let Some(rec) = prev_rec {
+                if rec.backend == resolved.profile.backend {
+                    if kimi_resume_keys.insert(session_key.to_string()) {
+                        kimi_resume_roles.push(role_id.to_string());
+                    }
+                    kimi_resume_tasks.insert(idx);
+                }
+            }
+        }
+
+        let serialize_kimi = cfg
+            .backend
+            .get("kimi")
+            .is_some_and(|b| b.serialize_instead_of_reject == Some(true));
+        let kimi_lock = if kimi_resume_roles.len() > 1 && serialize_kimi {
+            Some(Arc::new(tokio::sync::Mutex::new(())))
+        } else {
+            None
+        };
+        if kimi_resume_roles.len() > 1 && kimi_lock.is_none() {
+            return Err(McpError::invalid_params(
+                format!(
+                    "multiple kimi roles requested with force_new_session=false: {}. Kimi cannot resume multiple sessions in the same working directory.",
+                    kimi_resume_roles.join(", ")
+                ),
+                None,
+            ));
+        }
+
+        // Per-backend limits apply to the task's resolved primary backend, on top of the global cap.
+        let mut backend_slots: BTreeMap<String, Arc<tokio::sync::Semaphore>> = BTreeMap::new();
+        let task_backend_slots: Vec<Option<Arc<tokio::sync::Semaphore>>> = tasks
+            .iter()
+            .map(|task| {
+                let resolved = resolve_call_profile(&cfg, &task.args).ok()?;
+                let backend_id = resolved.profile.backend_id;
+                let limit = cfg.backend.get(&backend_id)?.max_concurrency?;
+                Some(
+                    backend_slots
+                        .entry(backend_id)
+                        .or_insert_with(|| Arc::new(tokio::sync::Semaphore::new(limit)))
+                        .clone(),
+                )
+            })
+            .collect();
+
+        let logger = format!("roundtable.{operation}");
+        let total_tasks = tasks.len();
+        let slots = max_concurrency.map(|n| Arc::new(tokio::sync::Semaphore::new(n)));
+        let labels: Vec<(Option<String>, String)> = tasks
+            .iter()
+            .map(|task| (task.name.clone(), task.role.clone()))
+            .collect();
+        let mut joinset: tokio::task::JoinSet<FanoutResult> = tokio::task::JoinSet::new();
+        for (idx, task) in tasks.into_iter().enumerate() {
+            let task_label = task.name.clone().unwrap_or_else(|| task.role.clone());
+            notify_fanout_progress(
+                peer.as_ref(),
+                &logger,
+                LoggingLevel::Info,
+                format!(
+                    "[{operation}] started {task_label} ({}/{})",
+                    idx + 1,
+                    total_tasks
+                ),
+            )
+            .await;
+
+            let server = self.clone();
+            let peer = peer.clone();
+            let FanoutTaskSpec {
+                order: _,
+                name,
+                role,
+                args,
+                retry_budget,
+            } = task;
+            let lock = kimi_lock
+                .as_ref()
+                .filter(|_| kimi_resume_tasks.contains(&idx))
+                .cloned();
+            let slots = slots.clone();
+            let backend_slot = task_backend_slots[idx].clone();
+            let collision = collision_warnings[idx].clone();
+            // Spread out the starts so tasks sharing a backend login don't all hit it at once.
+            let stagger = std::time::Duration::from_millis(stagger_ms.saturating_mul(idx as u64));
+            joinset.spawn(async move {
+                if !stagger.is_zero() {
+                    tokio::time::sleep(stagger).await;
+                }
+                // Queued tasks wait here until a running one finishes.
+                let _permit = match slots {
+                    Some(s) => s.acquire_owned().await.ok(),
+                    None => None,
+                };
+                let _backend_permit = match backend_slot {
+                    Some(s) => s.acquire_owned().await.ok(),
+                    None => None,
+                };
+                let _guard = match lock.as_ref() {
+                    Some(l) => Some(l.lock().await),
+                    None => None,
+                };
+                let out = server
+                    .run_vibe_with_budget(peer, args, retry_budget.as_deref())
+                    .await
+                    .map(|mut out| {
+                        if let Some(msg) = collision {
+                            out.warning_list.push(msg);
+                            out.warnings = join_warnings(&out.warning_list);
+                        }
+                        out
+                    });
+                FanoutResult {
+                    index: Some(idx),
+                    name,
+                    role,
+                    result: out,
+                }
+            });
+        }
+
+        let mut results: Vec<FanoutResult> = Vec::with_capacity(total_tasks);
+        let mut completed = 0usize;
+        let mut expired = false;
+        loop {
+            let next = match deadline {
+                Some(d) => match tokio::time::timeout_at(d, joinset.join_next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        expired = true;
+                        break;
+                    }
+                },
+                None => joinset.join_next().await,
+            };
+            let Some(joined) = next else {
+                break;
+            };
+            completed += 1;
+            match joined {
+                Ok(res) => {
+                    let task_label = res
+                        .name
+                        .clone()
+                        .filter(|s| !s.trim().is_empty())
+                        .unwrap_or_else(|| {
+                            if res.role.trim().is_empty() {
+                                "<unknown>".to_string()
+                            } else {
+                                res.role.clone()
+                            }
+                        });
+                    let status = match &res.result {
+                        Ok(out) if out.error.is_none() => "ok",
+                        _ => "error",
+                    };
+                    notify_fanout_progress(
+                        peer.as_ref(),
+                        &logger,
+                        if status == "ok" {
+                            LoggingLevel::Info
+                        } else {
+                            LoggingLevel::Warning
+                        },
+                        format!(
+                            "[{operation}] completed {task_label} ({}/{}) status={status}",
+                            completed, total_tasks
+                        ),
+                    )
+                    .await;
+                    if let Some(cb) = on_result {
+                        cb(&res);
+                    }
+                    results.push(res);
+                }
+                Err(e) => {
+                    notify_fanout_progress(
+                        peer.as_ref(),
+                        &logger,
+                        LoggingLevel::Error,
+                        format!(
+                            "[{operation}] join error ({}/{}) {}",
+                            completed, total_tasks, e
+                        ),
+                    )
+                    .await;
+                    let res = FanoutResult {
+                        index: None,
+                        name: None,
+                        role: "".to_string(),
+                        result: Err(McpError::internal_error(format!("join error: {e}"), None)),
+                    };
+                    if let Some(cb) = on_result {
+                        cb(&res);
+                    }
+                    results.push(res);
+                }
+            }
+        }
+
+        if expired {
+            // Aborting drops each running backend call, which kills its process group.
+            joinset.abort_all();
+            while let Some(joined) = joinset.join_next().await {
+                // A task may have finished between the deadline and the abort.
+                if let Ok(res) = joined {
+                    if let Some(cb) = on_result {
+                        cb(&res);
+                    }
+                    results.push(res);
+                }
+            }
+            let finished: BTreeSet<usize> = results.iter().filter_map(|r| r.index).collect();
+            let mut stopped = 0usize;
+            for (idx, (name, role)) in labels.into_iter().enumerate() {
+                if finished.contains(&idx) {
+                    continue;
+                }
+                stopped += 1;
+                let res = FanoutResult {
+                    index: Some(idx),
+                    name,
+                    role,
+                    result: Err(batch_deadline_exceeded()),
+                };
+                if let Some(cb) = on_result {
+                    cb(&res);
+                }
+                results.push(res);
+            }
+            notify_fanout_progress(
+                peer.as_ref(),
+                &logger,
+                LoggingLevel::Warning,
+                format!("[{operation}] batch deadline exceeded; stopped {stopped}/{total_tasks} task(s)"),
+            )
+            .await;
+        }
+
+        // Completion order depends on timing; return results in task order instead.
+        results.sort_by_key(|res| res.index.unwrap_or(usize::MAX));
+        Ok(results)
+    }
+}
+
+/// Canonicalizes explicit `include_directories` (relative entries resolve against `repo_root`).
+fn resolve_include_directories(
+    repo_root: &Path,
+    dirs: Option<&[String]>,
+) -> Result<Vec<String>, McpError> {
+    let mut out = Vec::new();
+    for dir in dirs.unwrap_or_default() {
+        let resolved = repo_root
+            .join(dir.trim())
+            .canonicalize()
+            .ok()
+            .filter(|p| p.is_dir())
+            .ok_or_else(|| {
+                McpError::invalid_params(
+                    format!("include_directories entry {dir} does not exist or is not a directory"),
+                    None,
+                )
+            })?;
+        out.push(resolved.to_string_lossy().into_owned());
+    }
+    Ok(out)
+}
+
+/// Reads `prompt_file` relative to `repo_root`; the resolved path must stay inside it.
+fn read_prompt_file(repo_root: &Path, path: &str) -> Result<String, McpError> {
+    let resolved = repo_root.join(path.trim()).canonicalize().map_err(|e| {
+        McpError::invalid_params(format!("prompt_file {path} cannot be read: {e}"), None)
+    })?;
+    if !resolved.starts_with(repo_root) {
+        return Err(McpError::invalid_params(
+            format!("prompt_file {path} resolves outside the working directory"),
+            None,
+        ));
+    }
+    let prompt = std::fs::read_to_string(&resolved).map_err(|e| {
+        McpError::invalid_params(format!("prompt_file {path} cannot be read: {e}"), None)
+    })?;
+    if prompt.trim().is_empty() {
+        return Err(McpError::invalid_params(
+            format!("prompt_file {path} is empty"),
+            None,
+        ));
+    }
+    Ok(prompt)
+}
+
+/// Pre-flight checks for one batch task, so a bad task is reported on its own instead of
+/// surfacing as an opaque backend error.
+fn validate_batch_task(
+    cfg: Option<&VibeConfig>,
+    task: &BatchTask,
+    role: Option<&str>,
+) -> std::result::Result<(), String> {
+    let has_file = task
+        .prompt_file
+        .as_deref()
+        .is_some_and(|p| !p.trim().is_empty());
+    if task.prompt.trim().is_empty() && !has_file {
+        return Err("PROMPT is empty or whitespace-only and no prompt_file is set".to_string());
+    }
+    if role.is_none() && task.backend.is_none() {
+        return Err("role is required".to_string());
+    }
+    if let Some(cfg) = cfg {
+        let mut cfg = cfg.clone();
+        let role = cfg
+            .apply_call_overrides(
+                role,
+                task.backend.as_deref(),
+                task.model.as_deref(),
+                task.reasoning_effort.as_deref(),
+            )
+            .map_err(|e| e.to_string())?;
+        cfg.resolve_profile_for_prompt(role.as_deref(), task.prompt.chars().count())
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn is_model_error_message(msg: &str) -> bool {
+    msg.trim_start()
+        .to_ascii_lowercase()
+        .starts_with("model_not_found:")
+}
+
+#[tool_handler]
+impl ServerHandler for VibeServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            protocol_version: ProtocolVersion::V_2024_11_05,
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            server_info: Implementation::from_build_env(),
+            instructions: Some(
+                "This server provides 'roundtable' (core), 'roundtable-batch' (fan-out), 'roundtable-poll', 'info', 'list-roles', 'adapters', 'list-sessions', 'clear-session', 'validate-patch', 'validate-config', 'resolved-config', and 'health' tools for multi-role orchestration."
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Backend, OutputPick};
+    use std::io::Write;
+    use std::path::Path;
+    use std::process::Command;
+
+    // Note: tests call `run_vibe_internal` directly.
 
     fn write_fake_cli(bin: &Path, log: &Path, session_id: &str, agent_text: &str) {
         let agent_text_json = agent_text
@@ -1557,390 +4987,4656 @@ mod tests {
             .replace('"', "\\\"")
             .replace('\n', "\\n");
         let script = format!(
-            "#!/bin/sh\nset -e\n( printf 'ARGS:'; printf ' %s' \"$@\"; printf '\\n' ) > \"{}\"\n\nprintf '%s\\n' '{{\"type\":\"thread.started\",\"thread_id\":\"{}\"}}'\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"{}\"}}}}'\n",
-            log.display(),
-            session_id,
+            "#!/bin/sh\nset -e\n( printf 'ARGS:'; printf ' %s' \"$@\"; printf '\\n' ) > \"{}\"\n\nprintf '%s\\n' '{{\"type\":\"thread.started\",\"thread_id\":\"{}\"}}'\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"{}\"}}}}'\n",
+            log.display(),
+            session_id,
+            agent_text_json
+        );
+        {
+            let mut f = std::fs::File::create(bin).unwrap();
+            f.write_all(script.as_bytes()).unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(bin).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(bin, perms).unwrap();
+        }
+    }
+
+    fn read_log(path: &Path) -> String {
+        std::fs::read_to_string(path).unwrap_or_default()
+    }
+
+    /// One entry per call logged by the appending fake CLIs; the args themselves (e.g. a persona
+    /// block in the prompt) may span several lines.
+    fn logged_calls(path: &Path) -> Vec<String> {
+        read_log(path)
+            .lines()
+            .filter(|l| l.starts_with("ARGS:"))
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn read_log_args(path: &Path) -> Vec<String> {
+        let raw = std::fs::read(path).unwrap_or_default();
+        raw.split(|b| *b == 0)
+            .filter(|chunk| !chunk.is_empty())
+            .map(|chunk| String::from_utf8_lossy(chunk).to_string())
+            .collect()
+    }
+
+    /// `prompt` as sent to a read-only backend with the default guardrail prepended.
+    fn guarded(prompt: &str) -> String {
+        format!("{}\n{prompt}", backend::DEFAULT_READONLY_GUARDRAIL)
+    }
+
+    fn write_codex_test_config(path: &Path) {
+        let cfg = r#"{
+  "backend": {
+    "codex": {
+      "models": {
+        "gpt-5.2-codex": {
+          "options": { "model_reasoning_effort": "high" },
+          "variants": { "xhigh": { "model_reasoning_effort": "xhigh" } }
+        }
+      }
+    }
+  },
+  "roles": {
+    "oracle": {
+      "model": "codex/gpt-5.2-codex@xhigh",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
+    }
+  }
+}"#;
+        std::fs::write(path, cfg).unwrap();
+    }
+
+    fn write_codex_test_config_with_model(path: &Path, model_id: &str) {
+        let cfg = format!(
+            r#"{{
+  "backend": {{
+    "codex": {{
+      "models": {{
+        "{model_id}": {{ "options": {{}} }}
+      }}
+    }}
+  }},
+  "roles": {{
+    "oracle": {{
+      "model": "codex/{model_id}",
+      "personas": {{ "description": "d", "prompt": "p" }},
+      "capabilities": {{ "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }}
+    }}
+  }}
+}}"#,
+            model_id = model_id
+        );
+        std::fs::write(path, cfg).unwrap();
+    }
+
+    fn write_batch_codex_config(path: &Path) {
+        let cfg = r#"{
+  "backend": {
+    "codex": {
+      "models": {
+        "gpt-5.2-codex": { "options": {} }
+      }
+    }
+  },
+  "roles": {
+    "oracle": {
+      "model": "codex/gpt-5.2-codex",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
+    },
+    "builder": {
+      "model": "codex/gpt-5.2-codex",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
+    }
+  }
+}"#;
+        std::fs::write(path, cfg).unwrap();
+    }
+
+    fn write_batch_kimi_config(path: &Path) {
+        let cfg = r#"{
+  "backend": {
+    "kimi": { "models": {} }
+  },
+  "roles": {
+    "kimi_a": {
+      "model": "kimi/default",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-write", "shell": "deny", "network": "deny", "tools": ["read"] }
+    },
+    "kimi_b": {
+      "model": "kimi/default",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-write", "shell": "deny", "network": "deny", "tools": ["read"] }
+    }
+  }
+}"#;
+        std::fs::write(path, cfg).unwrap();
+    }
+
+    fn write_codex_prompt_transport_config(path: &Path, max_chars: usize) {
+        let cfg = format!(
+            r#"{{
+  "backend": {{
+    "codex": {{
+      "adapter": {{
+        "args_template": [
+          "exec",
+          "{{% if not session_id and model != 'default' %}}--model{{% endif %}}",
+          "{{% if not session_id and model != 'default' %}}{{{{ model }}}}{{% endif %}}",
+          "--skip-git-repo-check",
+          "{{% if not session_id %}}-C{{% endif %}}",
+          "{{% if not session_id %}}{{{{ workdir }}}}{{% endif %}}",
+          "--json",
+          "{{% if session_id %}}resume{{% endif %}}",
+          "{{% if session_id %}}{{{{ session_id }}}}{{% endif %}}",
+          "{{% if prompt %}}{{{{ prompt }}}}{{% endif %}}"
+        ],
+        "output_parser": {{
+          "type": "json_stream",
+          "session_id_path": "thread_id",
+          "message_path": "item.text",
+          "pick": "last"
+        }},
+        "prompt_transport": "auto",
+        "prompt_max_chars": {max_chars}
+      }},
+      "models": {{
+        "gpt-5.2-codex": {{}}
+      }}
+    }}
+  }},
+  "roles": {{
+    "oracle": {{
+      "model": "codex/gpt-5.2-codex",
+      "personas": {{ "description": "d", "prompt": "p" }},
+      "capabilities": {{ "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }}
+    }}
+  }}
+}}"#,
+            max_chars = max_chars
+        );
+        std::fs::write(path, cfg).unwrap();
+    }
+
+    fn write_codex_fallback_config(path: &Path) {
+        let cfg = r#"{
+  "backend": {
+    "codex": {
+      "fallback": {
+        "model": "codex/gpt-5.2",
+        "patterns": ["model_not_found"]
+      },
+      "models": {
+        "gpt-5.2-codex": { "options": {} },
+        "gpt-5.2": { "options": {} }
+      }
+    }
+  },
+  "roles": {
+    "oracle": {
+      "model": "codex/gpt-5.2-codex",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
+    }
+  }
+}"#;
+        std::fs::write(path, cfg).unwrap();
+    }
+
+    fn write_codex_fallback_pattern_config(path: &Path) {
+        let cfg = r#"{
+  "backend": {
+    "codex": {
+      "fallback": {
+        "model": "codex/gpt-5.2",
+        "patterns": ["custom_not_found"]
+      },
+      "models": {
+        "gpt-5.2-codex": { "options": {} },
+        "gpt-5.2": { "options": {} }
+      }
+    }
+  },
+  "roles": {
+    "oracle": {
+      "model": "codex/gpt-5.2-codex",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
+    }
+  }
+}"#;
+        std::fs::write(path, cfg).unwrap();
+    }
+
+    fn write_fake_cli_with_arg_log(bin: &Path, log: &Path, session_id: &str) {
+        let script = format!(
+            "#!/bin/sh\nset -e\n\nprintf '%s\\0' \"$@\" > \"{}\"\n\nprintf '%s\\n' '{{\"type\":\"thread.started\",\"thread_id\":\"{}\"}}'\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"ok\"}}}}'\n",
+            log.display(),
+            session_id
+        );
+        {
+            let mut f = std::fs::File::create(bin).unwrap();
+            f.write_all(script.as_bytes()).unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(bin).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(bin, perms).unwrap();
+        }
+    }
+
+    fn write_fake_cli_with_arg_and_stdin_log(
+        bin: &Path,
+        args_log: &Path,
+        stdin_log: &Path,
+        session_id: &str,
+    ) {
+        let script = format!(
+            "#!/bin/sh\nset -e\n\ncat - > \"{}\"\nprintf '%s\\0' \"$@\" > \"{}\"\n\nprintf '%s\\n' '{{\"type\":\"thread.started\",\"thread_id\":\"{}\"}}'\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"ok\"}}}}'\n",
+            stdin_log.display(),
+            args_log.display(),
+            session_id
+        );
+        {
+            let mut f = std::fs::File::create(bin).unwrap();
+            f.write_all(script.as_bytes()).unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(bin).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(bin, perms).unwrap();
+        }
+    }
+
+    fn write_fake_cli_with_model_error_once(bin: &Path, log: &Path, bad_model: &str) {
+        let script = format!(
+            "#!/bin/sh\nset -e\n\nprintf 'ARGS: ' >> \"{}\"\nprintf '%s ' \"$@\" >> \"{}\"\nprintf '\\n' >> \"{}\"\n\nif echo \"$@\" | grep -q '{}'; then\n  printf '%s\\n' '{{\"type\":\"error\",\"message\":\"model_not_found\"}}'\n  exit 0\nfi\n\nprintf '%s\\n' '{{\"type\":\"thread.started\",\"thread_id\":\"sess-1\"}}'\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"ok\"}}}}'\n",
+            log.display(),
+            log.display(),
+            log.display(),
+            bad_model
+        );
+        {
+            let mut f = std::fs::File::create(bin).unwrap();
+            f.write_all(script.as_bytes()).unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(bin).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(bin, perms).unwrap();
+        }
+    }
+
+    fn write_fake_cli_with_custom_model_error_once(
+        bin: &Path,
+        log: &Path,
+        bad_model: &str,
+        message: &str,
+    ) {
+        let msg = message
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        let script = format!(
+            "#!/bin/sh\nset -e\n\nprintf 'ARGS: ' >> \"{}\"\nprintf '%s ' \"$@\" >> \"{}\"\nprintf '\\n' >> \"{}\"\n\nif echo \"$@\" | grep -q '{}'; then\n  printf '%s\\n' '{{\"type\":\"error\",\"message\":\"{}\"}}'\n  exit 0\nfi\n\nprintf '%s\\n' '{{\"type\":\"thread.started\",\"thread_id\":\"sess-1\"}}'\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"ok\"}}}}'\n",
+            log.display(),
+            log.display(),
+            log.display(),
+            bad_model,
+            msg
+        );
+        {
+            let mut f = std::fs::File::create(bin).unwrap();
+            f.write_all(script.as_bytes()).unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(bin).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(bin, perms).unwrap();
+        }
+    }
+
+    fn codex_loader(cfg_path: &Path) -> ConfigLoader {
+        ConfigLoader::new(Some(cfg_path.to_path_buf()))
+    }
+
+    fn test_args(repo: &Path, role: &str, prompt: &str) -> VibeArgs {
+        VibeArgs {
+            prompt: prompt.to_string(),
+            cd: repo.to_string_lossy().to_string(),
+            role: Some(role.to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            session_id: None,
+            force_new_session: true,
+            session_key: None,
+            timeout_secs: Some(5),
+            contract: None,
+            validate_patch: false,
+            strict_citations: false,
+            contract_output: None,
+            output_parser: None,
+            canonicalize_cd: None,
+            extract_code_lang: None,
+            n: None,
+            client: None,
+            conversation_id: None,
+            participant: None,
+            session_group: None,
+            ephemeral: false,
+            dry_run: false,
+            persona_override: None,
+            stream: false,
+            prompt_file: None,
+            prompt_transport: None,
+            include_directories: None,
+            cache: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn client_config_prefers_client_specific_file() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store = SessionStore::new(td.path().join("sessions.json"));
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config_with_model(&cfg_path, "gpt-5.2-codex");
+        let cfg_client = td.path().join("config-claude.json");
+        write_codex_test_config_with_model(&cfg_client, "gpt-5.3-codex");
+
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        write_fake_cli(&fake, &log, "sess-cfg-1", "pong");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    prompt: "ping".to_string(),
+                    cd: repo.to_string_lossy().to_string(),
+                    role: Some("oracle".to_string()),
+                    backend: None,
+                    model: None,
+                    reasoning_effort: None,
+                    session_id: None,
+                    force_new_session: true,
+                    session_key: None,
+                    timeout_secs: Some(5),
+                    contract: None,
+                    validate_patch: false,
+                    strict_citations: false,
+                    contract_output: None,
+                    output_parser: None,
+                    canonicalize_cd: None,
+                    extract_code_lang: None,
+                    n: None,
+                    client: Some("claude".to_string()),
+
+                    conversation_id: None,
+                    participant: None,
+                    session_group: None,
+                    ephemeral: false,
+                    dry_run: false,
+                    persona_override: None,
+                    stream: false,
+                    prompt_file: None,
+                    prompt_transport: None,
+                    include_directories: None,
+                    cache: false,
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(out.model.as_deref(), Some("gpt-5.3-codex"));
+    }
+
+    #[tokio::test]
+    async fn session_reuse_uses_stored_backend_session_id() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store_path = td.path().join("sessions.json");
+        let store = SessionStore::new(store_path);
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let server = VibeServer::new(codex_loader(&cfg_path), store.clone());
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        let script = format!(
+            "#!/bin/sh\nset -e\n\n# append args each invocation\necho \"ARGS: $@\" >> \"{}\"\n\nif echo \"$@\" | grep -q 'resume sess-1'; then\n  sid='sess-2'\nelse\n  sid='sess-1'\nfi\n\necho '{{\"type\":\"thread.started\",\"thread_id\":\"'\"$sid\"'\"}}'\necho '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"ok\"}}}}'\n",
+            log.display()
+        );
+        {
+            let mut f = std::fs::File::create(&fake).unwrap();
+            f.write_all(script.as_bytes()).unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(&fake).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(&fake, perms).unwrap();
+        }
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let args1 = VibeArgs {
+            prompt: "first".to_string(),
+            cd: repo.to_string_lossy().to_string(),
+            role: Some("oracle".to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            session_id: None,
+            force_new_session: false,
+            session_key: None,
+            timeout_secs: Some(5),
+            contract: None,
+            validate_patch: false,
+            strict_citations: false,
+            contract_output: None,
+            output_parser: None,
+            canonicalize_cd: None,
+            extract_code_lang: None,
+            n: None,
+            client: None,
+
+            conversation_id: None,
+            participant: None,
+            session_group: None,
+            ephemeral: false,
+            dry_run: false,
+            persona_override: None,
+            stream: false,
+            prompt_file: None,
+            prompt_transport: None,
+            include_directories: None,
+            cache: false,
+        };
+        let out1 = server.run_vibe_internal(None, args1).await.unwrap();
+        assert_eq!(out1.success, true);
+        assert_eq!(out1.resumed, false);
+        assert_eq!(out1.backend_session_id, "sess-1");
+
+        let args2 = VibeArgs {
+            prompt: "second".to_string(),
+            cd: repo.to_string_lossy().to_string(),
+            role: Some("oracle".to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            session_id: None,
+            force_new_session: false,
+            session_key: None,
+            timeout_secs: Some(5),
+            contract: None,
+            validate_patch: false,
+            strict_citations: false,
+            contract_output: None,
+            output_parser: None,
+            canonicalize_cd: None,
+            extract_code_lang: None,
+            n: None,
+            client: None,
+
+            conversation_id: None,
+            participant: None,
+            session_group: None,
+            ephemeral: false,
+            dry_run: false,
+            persona_override: None,
+            stream: false,
+            prompt_file: None,
+            prompt_transport: None,
+            include_directories: None,
+            cache: false,
+        };
+        let out2 = server.run_vibe_internal(None, args2).await.unwrap();
+        assert_eq!(out2.success, true);
+        assert_eq!(out2.resumed, true);
+        assert_eq!(out2.backend_session_id, "sess-2");
+
+        let log_txt = std::fs::read_to_string(&log).unwrap();
+        assert!(log_txt.lines().any(|l| l.contains("resume sess-1")));
+
+        let role_id = "oracle";
+        let key = SessionStore::compute_key(&repo.canonicalize().unwrap(), role_id, role_id);
+        let rec = store.get(&key).unwrap().unwrap();
+        assert_eq!(rec.backend_session_id, "sess-2");
+    }
+
+    #[tokio::test]
+    async fn persona_override_replaces_config_persona() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex-args.log");
+        write_fake_cli_with_arg_log(&fake, &log, "sess-1");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    persona_override: Some("OVERRIDE-PERSONA".to_string()),
+                    stream: false,
+                    prompt_file: None,
+                    prompt_transport: None,
+                    include_directories: None,
+                    cache: false,
+                    ..test_args(&repo, "oracle", "ping")
+                },
+            )
+            .await
+            .unwrap();
+
+        let args = read_log_args(&log);
+        let prompt_arg = args.last().cloned().unwrap_or_default();
+        assert!(
+            prompt_arg.starts_with(&guarded(&format_persona_block(
+                "oracle",
+                "OVERRIDE-PERSONA"
+            ))),
+            "prompt={prompt_arg}"
+        );
+        assert!(!prompt_arg.contains(&format_persona_block("oracle", "p")));
+        assert!(prompt_arg.ends_with("ping"));
+    }
+
+    #[tokio::test]
+    async fn backend_overrides_are_honored_or_rejected_on_conflict() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex-args.log");
+        write_fake_cli_with_arg_log(&fake, &log, "sess-1");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    role: None,
+                    backend: Some("codex".to_string()),
+                    model: Some("gpt-5.3-codex".to_string()),
+                    reasoning_effort: Some("high".to_string()),
+                    ..test_args(&repo, "oracle", "ping")
+                },
+            )
+            .await
+            .unwrap();
+        assert!(out.success, "{:?}", out.error);
+        assert_eq!(out.role, "codex");
+        let args = read_log_args(&log);
+        assert!(args.iter().any(|a| a == "gpt-5.3-codex"), "args={args:?}");
+        assert!(
+            args.iter().any(|a| a == "model_reasoning_effort=high"),
+            "args={args:?}"
+        );
+
+        let err = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    backend: Some("gemini".to_string()),
+                    ..test_args(&repo, "oracle", "ping")
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            err.message
+                .contains("backend override 'gemini' conflicts with role 'oracle'"),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn persona_override_is_skipped_on_resume_and_existing_persona_block() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex-args.log");
+        write_fake_cli_with_arg_log(&fake, &log, "sess-1");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        // Store a session to resume.
+        server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "hello"))
+            .await
+            .unwrap();
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    force_new_session: false,
+                    persona_override: Some("OVERRIDE-PERSONA".to_string()),
+                    ..test_args(&repo, "oracle", "ping")
+                },
+            )
+            .await
+            .unwrap();
+        assert!(out.resumed);
+        let args = read_log_args(&log);
+        assert!(args.iter().any(|v| v == "resume"), "args={args:?}");
+        assert_eq!(args.last(), Some(&guarded("ping")), "args={args:?}");
+
+        let own_block = format_persona_block("oracle", "CALLER-PERSONA");
+        server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    force_new_session: true,
+                    persona_override: Some("OVERRIDE-PERSONA".to_string()),
+                    ..test_args(&repo, "oracle", &format!("{own_block}\n\nping"))
+                },
+            )
+            .await
+            .unwrap();
+        let args = read_log_args(&log);
+        let prompt_arg = args.last().cloned().unwrap_or_default();
+        assert!(
+            prompt_arg.starts_with(&guarded(&own_block)),
+            "prompt={prompt_arg}"
+        );
+        assert!(
+            !prompt_arg.contains("OVERRIDE-PERSONA"),
+            "prompt={prompt_arg}"
+        );
+    }
+
+    #[tokio::test]
+    async fn role_prompt_prefix_and_suffix_wrap_new_sessions_only() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let mut cfg: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&cfg_path).unwrap()).unwrap();
+        cfg["roles"]["oracle"]["prompt_prefix"] = serde_json::json!("HOUSE RULES");
+        cfg["roles"]["oracle"]["prompt_suffix"] = serde_json::json!("SIGN OFF");
+        std::fs::write(&cfg_path, cfg.to_string()).unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex-args.log");
+        write_fake_cli_with_arg_log(&fake, &log, "sess-1");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "ping"))
+            .await
+            .unwrap();
+        let args = read_log_args(&log);
+        assert_eq!(
+            args.last().cloned().unwrap_or_default(),
+            guarded(&format!(
+                "HOUSE RULES\n\n{}\n\nping\n\nSIGN OFF",
+                format_persona_block("oracle", "p")
+            ))
+        );
+
+        server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    session_id: Some("sess-1".to_string()),
+                    force_new_session: false,
+                    ..test_args(&repo, "oracle", "pong")
+                },
+            )
+            .await
+            .unwrap();
+        let args = read_log_args(&log);
+        assert_eq!(args.last(), Some(&guarded("pong")), "args={args:?}");
+    }
+
+    #[tokio::test]
+    async fn role_commands_run_around_the_backend_only_when_allowed() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let mut cfg: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&cfg_path).unwrap()).unwrap();
+        cfg["roles"]["oracle"]["pre_command"] =
+            serde_json::json!(["sh", "-c", "echo pre >> hooks.log"]);
+        cfg["roles"]["oracle"]["post_command"] = serde_json::json!([
+            "sh",
+            "-c",
+            "echo post >> hooks.log; echo cleanup failed >&2; exit 3"
+        ]);
+        std::fs::write(&cfg_path, cfg.to_string()).unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex-args.log");
+        write_fake_cli_with_arg_log(&fake, &log, "sess-1");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+        let hooks_log = repo.join("hooks.log");
+
+        // Without the opt-in, the hooks are skipped with a warning.
+        let out = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "ping"))
+            .await
+            .unwrap();
+        assert!(!hooks_log.exists());
+        assert!(out
+            .warning_list
+            .iter()
+            .any(|w| w.contains("allow_role_commands")));
+
+        cfg["allow_role_commands"] = serde_json::json!(true);
+        std::fs::write(&cfg_path, cfg.to_string()).unwrap();
+        let out = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "ping"))
+            .await
+            .unwrap();
+        assert!(out.success);
+        assert_eq!(std::fs::read_to_string(&hooks_log).unwrap(), "pre\npost\n");
+        let warn = out.warnings.unwrap_or_default();
+        assert!(warn.contains("post_command"), "warn={warn}");
+        assert!(warn.contains("cleanup failed"), "warn={warn}");
+
+        // A failing pre_command aborts before the backend runs.
+        std::fs::remove_file(&log).unwrap();
+        cfg["roles"]["oracle"]["pre_command"] = serde_json::json!(["false"]);
+        std::fs::write(&cfg_path, cfg.to_string()).unwrap();
+        let err = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "ping"))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("pre_command"), "err={}", err.message);
+        assert_eq!(error_kind_of(&err).as_deref(), Some("hook_failed"));
+        assert!(!log.exists());
+    }
+
+    #[tokio::test]
+    async fn cached_response_skips_backend_and_session_store() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let store_path = td.path().join("sessions.json");
+        let server = VibeServer::new(
+            codex_loader(&cfg_path),
+            SessionStore::new(store_path.clone()),
+        );
+        let store = SessionStore::new(store_path);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex-runs.log");
+        let script = format!(
+            "#!/bin/sh\necho run >> \"{}\"\necho '{{\"type\":\"thread.started\",\"thread_id\":\"sess-c\"}}'\necho '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"answer\"}}}}'\n",
+            log.display()
+        );
+        {
+            let mut f = std::fs::File::create(&fake).unwrap();
+            f.write_all(script.as_bytes()).unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(&fake).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(&fake, perms).unwrap();
+        }
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let cached_args = |prompt: &str| VibeArgs {
+            cache: true,
+            force_new_session: true,
+            ..test_args(&repo, "oracle", prompt)
+        };
+
+        let first = server
+            .run_vibe_internal(None, cached_args("list the modules"))
+            .await
+            .unwrap();
+        assert!(!first.cached);
+        assert_eq!(read_log(&log).lines().count(), 1);
+        assert!(store.remove(&first.session_key).unwrap());
+
+        let hit = server
+            .run_vibe_internal(None, cached_args("list the modules"))
+            .await
+            .unwrap();
+        assert!(hit.cached, "{hit:?}");
+        assert!(hit.success);
+        assert_eq!(hit.agent_messages, "answer");
+        assert_eq!(hit.command_used, None);
+        assert_eq!(read_log(&log).lines().count(), 1);
+        assert!(store.get(&hit.session_key).unwrap().is_none());
+
+        let miss = server
+            .run_vibe_internal(None, cached_args("list the tests"))
+            .await
+            .unwrap();
+        assert!(!miss.cached);
+        assert_eq!(read_log(&log).lines().count(), 2);
+
+        let uncached = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    force_new_session: true,
+                    ..test_args(&repo, "oracle", "list the modules")
+                },
+            )
+            .await
+            .unwrap();
+        assert!(!uncached.cached);
+        assert_eq!(read_log(&log).lines().count(), 3);
+    }
+
+    #[tokio::test]
+    async fn readonly_store_resumes_but_leaves_file_unchanged() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let store_path = td.path().join("sessions.json");
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex-args.log");
+        write_fake_cli(&fake, &log, "sess-1", "ok");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let writer = VibeServer::new(
+            codex_loader(&cfg_path),
+            SessionStore::new(store_path.clone()),
+        );
+        let first = writer
+            .run_vibe_internal(None, test_args(&repo, "oracle", "ping"))
+            .await
+            .unwrap();
+        assert!(first.session_persisted);
+        let before = std::fs::read(&store_path).unwrap();
+
+        write_fake_cli(&fake, &log, "sess-2", "ok");
+        let readonly = VibeServer::new(
+            codex_loader(&cfg_path),
+            SessionStore::new(store_path.clone()).with_readonly(true),
+        );
+        let out = readonly
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    force_new_session: false,
+                    ..test_args(&repo, "oracle", "ping")
+                },
+            )
+            .await
+            .unwrap();
+        assert!(out.success);
+        assert!(out.resumed);
+        assert!(read_log(&log).contains("resume sess-1"));
+        assert!(!out.session_persisted);
+        assert_eq!(std::fs::read(&store_path).unwrap(), before);
+    }
+
+    #[tokio::test]
+    async fn prompt_file_is_read_inside_cd_only() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(repo.join("prompts")).unwrap();
+        std::fs::write(repo.join("prompts/task.md"), "review the parser").unwrap();
+        std::fs::write(td.path().join("secret.txt"), "outside").unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex-args.log");
+        write_fake_cli_with_arg_log(&fake, &log, "sess-1");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let with_file = |path: &str| VibeArgs {
+            prompt_file: Some(path.to_string()),
+            prompt_transport: None,
+            include_directories: None,
+            cache: false,
+            force_new_session: true,
+            ..test_args(&repo, "oracle", "")
+        };
+
+        server
+            .run_vibe_internal(None, with_file("prompts/task.md"))
+            .await
+            .unwrap();
+        let args = read_log_args(&log);
+        let prompt_arg = args.last().cloned().unwrap_or_default();
+        assert!(
+            prompt_arg.ends_with("review the parser"),
+            "prompt={prompt_arg}"
+        );
+
+        let err = server
+            .run_vibe_internal(None, with_file("prompts/../../secret.txt"))
+            .await
+            .unwrap_err();
+        assert!(
+            err.message.contains("outside the working directory"),
+            "{err:?}"
+        );
+
+        let err = server
+            .run_vibe_internal(None, with_file("prompts/missing.md"))
+            .await
+            .unwrap_err();
+        assert!(
+            err.message.contains("prompt_file prompts/missing.md"),
+            "{err:?}"
+        );
+
+        let err = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", " "))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("prompt_file"), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn dry_run_renders_args_without_spawning_or_recording() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store.clone());
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        write_fake_cli(&fake, &log, "sess-1", "ok");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    dry_run: true,
+                    persona_override: None,
+                    stream: false,
+                    prompt_file: None,
+                    prompt_transport: None,
+                    include_directories: None,
+                    cache: false,
+                    ..test_args(&repo, "oracle", "ping")
+                },
+            )
+            .await
+            .unwrap();
+
+        assert!(out.success);
+        assert_eq!(
+            out.resolved_command.as_deref(),
+            Some(fake.to_string_lossy().as_ref())
+        );
+        assert_eq!(out.prompt_transport.as_deref(), Some("arg"));
+        let args = out.rendered_args.unwrap();
+        assert!(args.iter().any(|a| a == "gpt-5.2-codex"), "args={args:?}");
+        let prompt_arg = args.iter().find(|a| a.contains("ping")).unwrap();
+        assert!(prompt_arg.contains("[ROUNDTABLE_PERSONA id=oracle]"));
+        assert!(out.agent_messages.is_empty());
+        assert!(out.warning_list.iter().any(|w| w.starts_with("dry_run:")));
+        assert!(out.warnings.unwrap_or_default().contains("dry_run"));
+
+        assert!(!log.exists(), "backend should not be spawned");
+        assert!(store.get(&out.session_key).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn prompt_transport_override_wins_over_adapter() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let args_log = td.path().join("codex-args.log");
+        let stdin_log = td.path().join("codex-stdin.log");
+        write_fake_cli_with_arg_and_stdin_log(&fake, &args_log, &stdin_log, "sess-1");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        // The catalog codex adapter is `auto`, which sends a short prompt as an arg.
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    prompt_transport: Some(PromptTransport::Stdin),
+                    include_directories: None,
+                    cache: false,
+                    ..test_args(&repo, "oracle", "ping")
+                },
+            )
+            .await
+            .unwrap();
+        assert!(out.success, "{:?}", out.error);
+        assert_eq!(out.prompt_transport_used.as_deref(), Some("stdin"));
+        let args = read_log_args(&args_log);
+        assert!(!args.iter().any(|a| a.contains("ping")), "args={args:?}");
+        assert!(read_log(&stdin_log).contains("ping"));
+    }
+
+    #[tokio::test]
+    async fn omitted_role_uses_config_default_role() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_batch_codex_config(&cfg_path);
+        let cfg = std::fs::read_to_string(&cfg_path).unwrap().replacen(
+            "{",
+            "{\n  \"default_role\": \"builder\",",
+            1,
+        );
+        std::fs::write(&cfg_path, cfg).unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store.clone());
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        write_fake_cli(&fake, &log, "sess-1", "ok");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    role: None,
+                    ..test_args(&repo, "oracle", "ping")
+                },
+            )
+            .await
+            .unwrap();
+        assert!(out.success, "{:?}", out.error);
+        assert_eq!(out.role, "builder");
+        let rec = store.get(&out.session_key).unwrap().unwrap();
+        assert_eq!(
+            (rec.role.as_str(), rec.role_id.as_str()),
+            ("builder", "builder")
+        );
+    }
+
+    #[tokio::test]
+    async fn output_reports_prompt_and_response_sizes() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        write_fake_cli(&fake, &log, "sess-1", "héllo wörld");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "size me"))
+            .await
+            .unwrap();
+        let prompt = guarded(&format!(
+            "{}\n\nsize me",
+            format_persona_block("oracle", "p")
+        ));
+        assert_eq!(out.prompt_chars, prompt.chars().count());
+        assert_eq!(out.response_chars, 11);
+        assert_eq!(out.prompt_tokens_estimate, out.prompt_chars.div_ceil(4));
+        assert_eq!(out.response_tokens_estimate, 3);
+    }
+
+    #[tokio::test]
+    async fn output_reports_command_used_without_args() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        write_fake_cli(&fake, &log, "sess-1", "ok");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "secret prompt"))
+            .await
+            .unwrap();
+        assert!(out.success);
+        let used = out.command_used.expect("command_used should be set");
+        assert_eq!(used, fake.to_string_lossy());
+        assert!(!used.contains("secret prompt"));
+        assert_eq!(out.prompt_transport_used.as_deref(), Some("arg"));
+        let invoked = out.invoked_args.expect("invoked_args should be set");
+        assert_eq!(invoked.first().map(String::as_str), Some("exec"));
+        assert!(invoked.iter().any(|a| a.contains("secret prompt")));
+    }
+
+    #[tokio::test]
+    async fn role_env_reaches_backend_but_not_output() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let cfg_path = td.path().join("config.json");
+        let cfg = r#"{
+  "backend": {
+    "codex": { "models": { "gpt-5.2-codex": { "options": {} } } }
+  },
+  "roles": {
+    "oracle": {
+      "model": "codex/gpt-5.2-codex",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] },
+      "env": { "THREE_TEST_API_KEY": "s3cret-value", "THREE_TEST_PATH_COPY": "${PATH}" }
+    }
+  }
+}"#;
+        std::fs::write(&cfg_path, cfg).unwrap();
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("env.log");
+        let script = format!(
+            "#!/bin/sh\nprintf '%s\\n%s\\n' \"$THREE_TEST_API_KEY\" \"$THREE_TEST_PATH_COPY\" > \"{}\"\nprintf '%s\\n' '{{\"type\":\"thread.started\",\"thread_id\":\"sess-1\"}}'\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"ok\"}}}}'\n",
+            log.display()
+        );
+        std::fs::write(&fake, script).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "ping"))
+            .await
+            .unwrap();
+        assert!(out.success, "{:?}", out.error);
+        let seen = read_log(&log);
+        let mut lines = seen.lines();
+        assert_eq!(lines.next(), Some("s3cret-value"));
+        assert_eq!(lines.next(), std::env::var("PATH").ok().as_deref());
+        let json = serde_json::to_string(&out).unwrap();
+        assert!(!json.contains("s3cret-value"));
+    }
+
+    #[tokio::test]
+    async fn max_inflight_caps_simultaneous_tool_calls() {
+        let td = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(ConfigLoader::new(None), store).with_max_inflight(1, false);
+
+        // While one call holds the only slot, a second top-level call queues.
+        let held = server.begin_inflight().await.unwrap();
+        let queued = {
+            let server = server.clone();
+            tokio::spawn(async move { server.adapters().await })
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(!queued.is_finished());
+        drop(held);
+        let out = tokio::time::timeout(std::time::Duration::from_secs(5), queued)
+            .await
+            .expect("queued call should run once the slot frees up")
+            .unwrap();
+        assert!(out.is_ok());
+
+        // Non-blocking mode rejects instead of queueing.
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(ConfigLoader::new(None), store).with_max_inflight(1, true);
+        let held = server.begin_inflight().await.unwrap();
+        let err = server.adapters().await.unwrap_err();
+        assert!(err.message.contains("server busy"), "err={err:?}");
+        drop(held);
+        assert!(server.adapters().await.is_ok());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn metrics_endpoint_counts_backend_calls() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        write_fake_cli(&fake, &log, "sess-1", "ok");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        for _ in 0..3 {
+            server
+                .run_vibe_internal(None, test_args(&repo, "oracle", "hi"))
+                .await
+                .unwrap();
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(crate::metrics::serve(server.metrics(), listener));
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.contains("roundtable_calls_total{backend=\"codex\"} 3\n"));
+        assert!(response.contains("roundtable_successes_total{backend=\"codex\"} 3\n"));
+        assert!(!response.contains("roundtable_failures_total{"));
+    }
+
+    #[tokio::test]
+    async fn resumed_output_reports_session_age_and_previous_model() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let server = VibeServer::new(codex_loader(&cfg_path), store.clone());
+
+        let repo_root = repo.canonicalize().unwrap();
+        let key = SessionStore::compute_key(&repo_root, "oracle", "oracle");
+        store
+            .put(
+                &key,
+                SessionRecord {
+                    repo_root: repo_root.to_string_lossy().to_string(),
+                    role: "oracle".to_string(),
+                    role_id: "oracle".to_string(),
+                    backend: Backend::Codex,
+                    backend_session_id: "sess-old".to_string(),
+                    model: Some("gpt-5.2".to_string()),
+                    sampling_history: Vec::new(),
+                    updated_at_unix_secs: now_unix_secs() - 3600,
+                },
+            )
+            .unwrap();
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        write_fake_cli(&fake, &log, "sess-old", "ok");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    force_new_session: false,
+                    ..test_args(&repo, "oracle", "again")
+                },
+            )
+            .await
+            .unwrap();
+        assert!(out.resumed);
+        let age = out.session_age_secs.unwrap();
+        assert!((3600..3700).contains(&age), "age={age}");
+        assert_eq!(out.previous_model.as_deref(), Some("gpt-5.2"));
+
+        let fresh = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "new"))
+            .await
+            .unwrap();
+        assert!(!fresh.resumed);
+        assert_eq!(fresh.session_age_secs, None);
+        assert_eq!(fresh.previous_model, None);
+    }
+
+    #[tokio::test]
+    async fn default_cd_fills_missing_cd() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        let other = td.path().join("other");
+        std::fs::create_dir_all(&repo).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let repo_root = repo.canonicalize().unwrap();
+        let server =
+            VibeServer::new(codex_loader(&cfg_path), store).with_default_cd(repo_root.clone());
+
+        let info_cd = |out: CallToolResult| {
+            let content = out
+                .content
+                .first()
+                .and_then(|c| c.as_text())
+                .map(|t| t.text.clone())
+                .unwrap_or_default();
+            let v: serde_json::Value = serde_json::from_str(&content).unwrap();
+            v["cd"].as_str().unwrap().to_string()
+        };
+
+        let out = server
+            .info(Parameters(InfoArgs {
+                cd: String::new(),
+                client: None,
+            }))
+            .await
+            .unwrap();
+        assert_eq!(info_cd(out), repo_root.to_string_lossy());
+
+        let out = server
+            .info(Parameters(InfoArgs {
+                cd: other.to_string_lossy().to_string(),
+                client: None,
+            }))
+            .await
+            .unwrap();
+        assert_eq!(
+            info_cd(out),
+            other.canonicalize().unwrap().to_string_lossy()
+        );
+
+        // Backend calls without `cd` run in the default directory too.
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex-args.log");
+        write_fake_cli_with_arg_log(&fake, &log, "sess-1");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    cd: String::new(),
+                    ..test_args(&repo, "oracle", "ping")
+                },
+            )
+            .await
+            .unwrap();
+        assert!(out.success);
+        let args = read_log_args(&log);
+        let workdir = args.iter().position(|a| a == "-C").map(|i| &args[i + 1]);
+        assert_eq!(
+            workdir.map(String::as_str),
+            Some(repo_root.to_string_lossy().as_ref()),
+            "args={args:?}"
+        );
+
+        let bare = VibeServer::new(
+            codex_loader(&cfg_path),
+            SessionStore::new(td.path().join("sessions2.json")),
+        );
+        let err = bare
+            .info(Parameters(InfoArgs {
+                cd: "  ".to_string(),
+                client: None,
+            }))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("--default-cd"));
+    }
+
+    #[tokio::test]
+    async fn extract_code_lang_returns_only_matching_blocks() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        let text = "first:\n```python\nprint(1)\n```\nthen:\n```bash\necho hi\n```\nlast:\n```Python\nx = 2\ny = 3\n```\n";
+        write_fake_cli(&fake, &log, "sess-1", text);
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    extract_code_lang: Some("python".to_string()),
+                    n: None,
+                    ..test_args(&repo, "oracle", "write code")
+                },
+            )
+            .await
+            .unwrap();
+        assert!(out.success);
+        assert_eq!(out.code_blocks, vec!["print(1)", "x = 2\ny = 3"]);
+
+        let out = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "write code"))
+            .await
+            .unwrap();
+        assert!(out.code_blocks.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn canonicalize_cd_false_keeps_symlinked_workdir() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        let link = td.path().join("link");
+        std::os::unix::fs::symlink(&repo, &link).unwrap();
+        let canonical = repo.canonicalize().unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store.clone());
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        write_fake_cli(&fake, &log, "sess-1", "ok");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let link_cd = format!("{}/./", link.display());
+        server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    cd: link_cd.clone(),
+                    canonicalize_cd: Some(false),
+                    ..test_args(&repo, "oracle", "hi")
+                },
+            )
+            .await
+            .unwrap();
+        let args = read_log(&log);
+        assert!(
+            args.contains(&format!(" {} ", link.display())),
+            "args={args}"
+        );
+        assert!(
+            !args.contains(&canonical.to_string_lossy().to_string()),
+            "args={args}"
+        );
+        let key = SessionStore::compute_key(&link, "oracle", "oracle");
+        assert!(store.get(&key).unwrap().is_some());
+
+        server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    cd: link_cd,
+                    ..test_args(&repo, "oracle", "hi")
+                },
+            )
+            .await
+            .unwrap();
+        let args = read_log(&log);
+        assert!(
+            args.contains(&canonical.to_string_lossy().to_string()),
+            "args={args}"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn health_probes_each_backend_without_a_prompt() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg = serde_json::json!({
+            "limits": { "probe_cache_secs": 60 },
+            "backend": {
+                "codex": { "models": { "gpt-5.2": {} } },
+                "kimi": { "models": {} }
+            },
+            "roles": {
+                "oracle": { "model": "codex/gpt-5.2", "capabilities": { "filesystem": "read-only" } },
+                "reviewer": { "model": "codex/gpt-5.2", "capabilities": { "filesystem": "read-only" } },
+                "kimi": { "model": "kimi/default", "capabilities": { "filesystem": "read-write" } },
+                "off": { "model": "kimi/default", "enabled": false }
+            }
+        });
+        let cfg_path = td.path().join("config.json");
+        std::fs::write(&cfg_path, cfg.to_string()).unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        std::fs::write(
+            &fake,
+            format!(
+                "#!/bin/sh\nprintf '%s\\n' \"$*\" >> \"{}\"\necho 'codex-cli 9.9.9'\n",
+                log.display()
+            ),
+        )
+        .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let _codex = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+        let missing = td.path().join("no-such-kimi");
+        let _kimi = crate::test_utils::scoped_kimi_bin(missing.to_string_lossy().as_ref());
+
+        let out = server
+            .health_internal(HealthArgs {
+                cd: repo.to_string_lossy().to_string(),
+                client: None,
+            })
+            .await
+            .unwrap();
+
+        assert!(!out.success);
+        assert_eq!(out.backends.len(), 2);
+        let codex = &out.backends[0];
+        assert_eq!(codex.backend, "codex");
+        assert_eq!(codex.status, "ok");
+        assert_eq!(codex.roles, vec!["oracle", "reviewer"]);
+        assert_eq!(codex.output.as_deref(), Some("codex-cli 9.9.9"));
+        let kimi = &out.backends[1];
+        assert_eq!(kimi.status, "error");
+        assert_eq!(kimi.roles, vec!["kimi"]);
+        assert!(
+            kimi.error
+                .as_deref()
+                .unwrap_or_default()
+                .contains("command not found"),
+            "{:?}",
+            kimi.error
+        );
+        // One probe per backend, and only the probe args.
+        assert_eq!(read_log(&log), "--version\n");
+        assert!(!codex.cached);
+
+        // Within `probe_cache_secs` the successful probe is reused; the failed one is re-run.
+        let again = server
+            .health_internal(HealthArgs {
+                cd: repo.to_string_lossy().to_string(),
+                client: None,
+            })
+            .await
+            .unwrap();
+        assert!(again.backends[0].cached);
+        assert_eq!(again.backends[0].output.as_deref(), Some("codex-cli 9.9.9"));
+        assert!(!again.backends[1].cached);
+        assert_eq!(again.backends[1].status, "error");
+        assert_eq!(read_log(&log), "--version\n");
+    }
+
+    #[test]
+    fn instructions_name_every_tool() {
+        let td = tempfile::tempdir().unwrap();
+        let server = VibeServer::new(
+            ConfigLoader::new(None),
+            SessionStore::new(td.path().join("sessions.json")),
+        );
+        let instructions = server.get_info().instructions.unwrap();
+        for tool in server.tool_router.list_all() {
+            assert!(
+                instructions.contains(&format!("'{}'", tool.name)),
+                "instructions miss '{}'",
+                tool.name
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn warmup_invokes_each_enabled_backend_once() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        std::fs::write(
+            &cfg_path,
+            r#"{
+  "backend": {
+    "codex": { "models": { "gpt-5.2": { "options": {} } } },
+    "kimi": { "models": {} }
+  },
+  "roles": {
+    "oracle": {
+      "model": "codex/gpt-5.2",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
+    },
+    "reader": {
+      "model": "codex/gpt-5.2",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
+    },
+    "coder": {
+      "model": "kimi/default",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-write", "shell": "deny", "network": "deny", "tools": ["read"] }
+    },
+    "off": {
+      "model": "kimi/default",
+      "enabled": false,
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-write", "shell": "deny", "network": "deny", "tools": ["read"] }
+    }
+  }
+}"#,
+        )
+        .unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store.clone())
+            .with_default_cd(repo.canonicalize().unwrap());
+
+        let codex_log = td.path().join("codex.log");
+        let kimi_log = td.path().join("kimi.log");
+        let codex_bin = td.path().join("fake-codex.sh");
+        let kimi_bin = td.path().join("fake-kimi.sh");
+        std::fs::write(
+            &codex_bin,
+            format!(
+                "#!/bin/sh\necho run >> \"{}\"\nprintf '%s\\n' '{{\"type\":\"thread.started\",\"thread_id\":\"w1\"}}'\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"OK\"}}}}'\n",
+                codex_log.display()
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            &kimi_bin,
+            format!(
+                "#!/bin/sh\necho run >> \"{}\"\necho OK\n",
+                kimi_log.display()
+            ),
+        )
+        .unwrap();
+        for bin in [&codex_bin, &kimi_bin] {
+            let mut perms = std::fs::metadata(bin).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(bin, perms).unwrap();
+        }
+        let _codex = crate::test_utils::scoped_codex_bin(codex_bin.to_string_lossy().as_ref());
+        let _kimi = crate::test_utils::scoped_kimi_bin(kimi_bin.to_string_lossy().as_ref());
+
+        let results = server.warmup().await;
+        let mut backends: Vec<&str> = results.iter().map(|r| r.backend.as_str()).collect();
+        backends.sort();
+        assert_eq!(backends, vec!["codex", "kimi"]);
+        assert!(results.iter().all(|r| r.error.is_none()), "{results:?}");
+        assert_eq!(read_log(&codex_log).lines().count(), 1);
+        assert_eq!(read_log(&kimi_log).lines().count(), 1);
+        assert!(store.list().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn prewarm_primes_flagged_backends_and_times_cold_vs_warm() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        std::fs::write(
+            &cfg_path,
+            r#"{
+  "backend": {
+    "codex": { "prewarm": true, "models": { "gpt-5.2": { "options": {} } } },
+    "kimi": { "models": {} }
+  },
+  "roles": {
+    "oracle": {
+      "model": "codex/gpt-5.2",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
+    },
+    "coder": {
+      "model": "kimi/default",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-write", "shell": "deny", "network": "deny", "tools": ["read"] }
+    }
+  }
+}"#,
+        )
+        .unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store)
+            .with_default_cd(repo.canonicalize().unwrap());
+
+        let codex_log = td.path().join("codex.log");
+        let kimi_log = td.path().join("kimi.log");
+        let codex_bin = td.path().join("fake-codex.sh");
+        let kimi_bin = td.path().join("fake-kimi.sh");
+        // A slow spawn, so the reported time is measurably above zero.
+        std::fs::write(
+            &codex_bin,
+            format!(
+                "#!/bin/sh\nsleep 0.2\necho run >> \"{}\"\nprintf '%s\\n' '{{\"type\":\"thread.started\",\"thread_id\":\"w1\"}}'\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"OK\"}}}}'\n",
+                codex_log.display()
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            &kimi_bin,
+            format!(
+                "#!/bin/sh\necho run >> \"{}\"\necho OK\n",
+                kimi_log.display()
+            ),
+        )
+        .unwrap();
+        for bin in [&codex_bin, &kimi_bin] {
+            let mut perms = std::fs::metadata(bin).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(bin, perms).unwrap();
+        }
+        let _codex = crate::test_utils::scoped_codex_bin(codex_bin.to_string_lossy().as_ref());
+        let _kimi = crate::test_utils::scoped_kimi_bin(kimi_bin.to_string_lossy().as_ref());
+
+        let started = std::time::Instant::now();
+        let results = server.prewarm().await;
+        let wall_ms = started.elapsed().as_millis() as u64;
+        assert_eq!(results.len(), 1, "{results:?}");
+        assert_eq!(results[0].backend, "codex");
+        assert!(results[0].error.is_none(), "{results:?}");
+        // The reported time covers the whole spawn and nothing outside the call.
+        assert!(results[0].elapsed_ms >= 200, "{results:?}");
+        assert!(
+            results[0].elapsed_ms <= wall_ms,
+            "{results:?} wall={wall_ms}"
+        );
+        assert_eq!(read_log(&codex_log).lines().count(), 1);
+        assert!(read_log(&kimi_log).is_empty());
+
+        // The first real call after the prewarm, timed the same way: the cold vs warm delta.
+        let started = std::time::Instant::now();
+        let out = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "ping"))
+            .await
+            .unwrap();
+        let warm_ms = started.elapsed().as_millis() as i128;
+        assert!(out.success, "{out:?}");
+        assert_eq!(read_log(&codex_log).lines().count(), 2);
+        let cold_ms = results[0].elapsed_ms as i128;
+        eprintln!(
+            "codex spawn: cold {cold_ms} ms, warm {warm_ms} ms, delta {} ms",
+            cold_ms - warm_ms
+        );
+    }
+
+    #[tokio::test]
+    async fn contract_output_projects_patch_or_citations() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        let text = "Here is the fix.\nPATCH\n```diff\ndiff --git a/a.rs b/a.rs\n--- a/a.rs\n+++ b/a.rs\n@@\n-1\n+2\n```\nCITATIONS:\n- a.rs:1\n";
+        write_fake_cli(&fake, &log, "sess-1", text);
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    contract: Some(OutputContract::PatchWithCitations),
+                    contract_output: Some(ContractOutput::Patch),
+                    output_parser: None,
+                    ..test_args(&repo, "oracle", "fix it")
+                },
+            )
+            .await
+            .unwrap();
+        assert!(out.success, "error={:?}", out.error);
+        assert_eq!(
+            out.agent_messages,
+            "diff --git a/a.rs b/a.rs\n--- a/a.rs\n+++ b/a.rs\n@@\n-1\n+2"
+        );
+        assert_eq!(
+            out.patch_stats.map(|s| s.files_changed),
+            Some(vec!["a.rs".to_string()])
+        );
+        let raw = out.raw_message.unwrap();
+        assert!(raw.contains("Here is the fix.") && raw.contains("CITATIONS:"));
+
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    contract: Some(OutputContract::PatchWithCitations),
+                    contract_output: Some(ContractOutput::Citations),
+                    output_parser: None,
+                    ..test_args(&repo, "oracle", "fix it")
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(out.agent_messages, "- a.rs:1");
+
+        let out = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "fix it"))
+            .await
+            .unwrap();
+        assert!(out.raw_message.is_none());
+        assert!(out.agent_messages.contains("Here is the fix."));
+
+        // A diff without citations violates the contract, so no stats are reported.
+        let text =
+            "PATCH\n```diff\ndiff --git a/a.rs b/a.rs\n--- a/a.rs\n+++ b/a.rs\n@@\n-1\n+2\n```\n";
+        write_fake_cli(&fake, &log, "sess-1", text);
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    contract: Some(OutputContract::PatchWithCitations),
+                    ..test_args(&repo, "oracle", "fix it")
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(out.error_kind.as_deref(), Some("contract_violation"));
+        assert_eq!(out.patch_stats, None);
+    }
+
+    #[tokio::test]
+    async fn output_parser_override_changes_extraction_and_warns() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        write_fake_cli(&fake, &log, "sess-1", "hello");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "hi"))
+            .await
+            .unwrap();
+        assert_eq!(out.agent_messages, "hello");
+
+        let parser: OutputParserConfig = serde_json::from_value(serde_json::json!({
+            "type": "json_stream",
+            "session_id_path": "thread_id",
+            "message_path": "item.type"
+        }))
+        .unwrap();
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    output_parser: Some(parser),
+                    ..test_args(&repo, "oracle", "hi")
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(out.agent_messages, "agent_message");
+        assert_eq!(out.backend_session_id, "sess-1");
+        assert!(out
+            .warnings
+            .unwrap_or_default()
+            .contains("output_parser override in use"));
+    }
+
+    #[tokio::test]
+    async fn missing_session_id_is_reported_and_not_resumed() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        let script = format!(
+            "#!/bin/sh\n( printf 'ARGS:'; printf ' %s' \"$@\"; printf '\\n' ) > \"{}\"\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"ok\"}}}}'\n",
+            log.display()
+        );
+        std::fs::write(&fake, script).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(&fake).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(&fake, perms).unwrap();
+        }
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "first"))
+            .await
+            .unwrap();
+        assert!(out.success);
+        assert_eq!(out.agent_messages, "ok");
+        assert_eq!(out.backend_session_id, "");
+        assert!(out
+            .warnings
+            .unwrap_or_default()
+            .contains("no session id captured from backend 'codex'"));
+
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    force_new_session: false,
+                    ..test_args(&repo, "oracle", "second")
+                },
+            )
+            .await
+            .unwrap();
+        assert!(!out.resumed);
+        assert!(!read_log(&log).contains("resume"));
+    }
+
+    #[tokio::test]
+    async fn session_id_failing_validate_pattern_is_not_stored() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let mut adapter = embedded_adapter_catalog().adapters["codex"].clone();
+        adapter.session_id_pattern_validate = Some("^[0-9a-f-]{36}$".to_string());
+        let cfg = serde_json::json!({
+            "backend": { "codex": { "adapter": adapter, "models": { "gpt-5.2": {} } } },
+            "roles": { "oracle": { "model": "codex/gpt-5.2" } }
+        });
+        let cfg_path = td.path().join("config.json");
+        std::fs::write(&cfg_path, cfg.to_string()).unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store.clone());
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        write_fake_cli(&fake, &log, "not-a-uuid", "ok");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "first"))
+            .await
+            .unwrap();
+        assert!(out.success);
+        assert_eq!(out.backend_session_id, "");
+        assert!(
+            out.warning_list.iter().any(|w| w.contains(
+                "session id \"not-a-uuid\" from backend 'codex' does not match session_id_pattern_validate"
+            )),
+            "{:?}",
+            out.warning_list
+        );
+        assert!(!out
+            .warning_list
+            .iter()
+            .any(|w| w.contains("no session id captured")));
+        let rec = store.get(&out.session_key).unwrap().unwrap();
+        assert_eq!(rec.backend_session_id, "");
+
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    force_new_session: false,
+                    ..test_args(&repo, "oracle", "second")
+                },
+            )
+            .await
+            .unwrap();
+        assert!(!out.resumed);
+        assert!(!read_log(&log).contains("not-a-uuid"));
+    }
+
+    #[tokio::test]
+    async fn n_returns_all_candidates_with_pick_all() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let mut adapter = embedded_adapter_catalog().adapters["codex"].clone();
+        adapter
+            .args_template
+            .push("{% if n %}--n={{ n }}{% endif %}".to_string());
+        adapter.output_parser = OutputParserConfig::JsonStream {
+            session_id_path: "thread_id".to_string(),
+            message_path: "item.text".to_string(),
+            pick: Some(OutputPick::All),
+            fallback: None,
+        };
+        let cfg = serde_json::json!({
+            "backend": { "codex": { "adapter": adapter, "models": { "gpt-5.2": {} } } },
+            "roles": {
+                "oracle": {
+                    "model": "codex/gpt-5.2",
+                    "capabilities": { "filesystem": "read-only" }
+                }
+            }
+        });
+        let cfg_path = td.path().join("config.json");
+        std::fs::write(&cfg_path, cfg.to_string()).unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        let script = format!(
+            r#"#!/bin/sh
+printf '%s\0' "$@" > "{log}"
+echo '{{"type":"thread.started","thread_id":"sess-n"}}'
+echo '{{"type":"item.completed","item":{{"type":"agent_message","text":"first"}}}}'
+echo '{{"type":"item.completed","item":{{"type":"agent_message","text":"second"}}}}'
+echo '{{"type":"item.completed","item":{{"type":"agent_message","text":"third"}}}}'
+"#,
+            log = log.display()
+        );
+        std::fs::write(&fake, script).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(&fake).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(&fake, perms).unwrap();
+        }
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    n: Some(3),
+                    ..test_args(&repo, "oracle", "vote")
+                },
+            )
+            .await
+            .unwrap();
+        assert!(out.success, "error={:?}", out.error);
+        assert_eq!(out.candidates, vec!["first", "second", "third"]);
+        assert_eq!(out.agent_messages, "first");
+        assert!(read_log_args(&log).contains(&"--n=3".to_string()));
+    }
+
+    #[tokio::test]
+    async fn client_id_reaches_adapter_args_and_child_env() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let mut adapter = embedded_adapter_catalog().adapters["codex"].clone();
+        adapter.args_template.push(
+            "{% if three_client_id %}--client-id={{ three_client_id }}{% endif %}".to_string(),
+        );
+        let cfg = serde_json::json!({
+            "client_id": "three-test",
+            "backend": { "codex": { "adapter": adapter, "models": { "gpt-5.2": {} } } },
+            "roles": {
+                "oracle": {
+                    "model": "codex/gpt-5.2",
+                    "capabilities": { "filesystem": "read-only" }
+                }
+            }
+        });
+        let cfg_path = td.path().join("config.json");
+        std::fs::write(&cfg_path, cfg.to_string()).unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        let env_log = td.path().join("env.log");
+        let script = format!(
+            r#"#!/bin/sh
+printf '%s\0' "$@" > "{log}"
+printf '%s' "$THREE_CLIENT_ID" > "{env_log}"
+echo '{{"type":"thread.started","thread_id":"sess-1"}}'
+echo '{{"type":"item.completed","item":{{"type":"agent_message","text":"ok"}}}}'
+"#,
+            log = log.display(),
+            env_log = env_log.display()
+        );
+        std::fs::write(&fake, script).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(&fake).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(&fake, perms).unwrap();
+        }
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "hi"))
+            .await
+            .unwrap();
+        assert!(out.success, "error={:?}", out.error);
+        assert!(read_log_args(&log).contains(&"--client-id=three-test".to_string()));
+        assert_eq!(std::fs::read_to_string(&env_log).unwrap(), "three-test");
+    }
+
+    #[tokio::test]
+    async fn prompt_layout_orders_segments() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        // The kimi adapter with read-only allowed, so its read-only guardrail is in play.
+        let mut adapter = embedded_adapter_catalog().adapters["kimi"].clone();
+        adapter.filesystem_capabilities = Some(vec![
+            crate::config::FilesystemCapability::ReadOnly,
+            crate::config::FilesystemCapability::ReadWrite,
+        ]);
+        adapter.prompt_layout = Some(vec![
+            PromptSegment::Guardrail,
+            PromptSegment::User,
+            PromptSegment::Persona,
+        ]);
+        let cfg = serde_json::json!({
+            "backend": { "kimi": { "adapter": adapter, "models": {} } },
+            "roles": {
+                "reader": {
+                    "model": "kimi/default",
+                    "personas": { "description": "d", "prompt": "PERSONA-TEXT" },
+                    "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
+                }
+            }
+        });
+        let cfg_path = td.path().join("config.json");
+        std::fs::write(&cfg_path, cfg.to_string()).unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store);
+
+        let fake = td.path().join("fake-kimi.sh");
+        let log = td.path().join("kimi.log");
+        write_fake_cli_with_arg_log(&fake, &log, "unused");
+        let _env = crate::test_utils::scoped_kimi_bin(fake.to_string_lossy().as_ref());
+
+        server
+            .run_vibe_internal(None, test_args(&repo, "reader", "USER-TEXT"))
+            .await
+            .unwrap();
+        let args = read_log_args(&log);
+        let idx = args.iter().position(|a| a == "--prompt").unwrap();
+        let prompt = &args[idx + 1];
+        let guardrail = prompt.find("不允许写文件").unwrap();
+        let user = prompt.find("USER-TEXT").unwrap();
+        let persona = prompt.find("PERSONA-TEXT").unwrap();
+        assert!(guardrail < user && user < persona, "prompt={prompt}");
+        assert_eq!(prompt.matches("不允许写文件").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn session_resume_skips_persona_injection() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store_path = td.path().join("sessions.json");
+        let store = SessionStore::new(store_path);
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let server = VibeServer::new(codex_loader(&cfg_path), store.clone());
+
+        let fake_first = td.path().join("fake-codex-first.sh");
+        let log_first = td.path().join("codex-first.log");
+        write_fake_cli_with_arg_log(&fake_first, &log_first, "sess-1");
+        {
+            let _env = crate::test_utils::scoped_codex_bin(fake_first.to_string_lossy().as_ref());
+            let out1 = server
+                .run_vibe_internal(
+                    None,
+                    VibeArgs {
+                        prompt: "first".to_string(),
+                        cd: repo.to_string_lossy().to_string(),
+                        role: Some("oracle".to_string()),
+                        backend: None,
+                        model: None,
+                        reasoning_effort: None,
+                        session_id: None,
+                        force_new_session: false,
+                        session_key: None,
+                        timeout_secs: Some(5),
+                        contract: None,
+                        validate_patch: false,
+                        strict_citations: false,
+                        contract_output: None,
+                        output_parser: None,
+                        canonicalize_cd: None,
+                        extract_code_lang: None,
+                        n: None,
+                        client: None,
+
+                        conversation_id: None,
+                        participant: None,
+                        session_group: None,
+                        ephemeral: false,
+                        dry_run: false,
+                        persona_override: None,
+                        stream: false,
+                        prompt_file: None,
+                        prompt_transport: None,
+                        include_directories: None,
+                        cache: false,
+                    },
+                )
+                .await
+                .unwrap();
+            assert!(!out1.resumed);
+        }
+
+        let fake_second = td.path().join("fake-codex-second.sh");
+        let log_second = td.path().join("codex-second.log");
+        write_fake_cli_with_arg_log(&fake_second, &log_second, "sess-2");
+        {
+            let _env = crate::test_utils::scoped_codex_bin(fake_second.to_string_lossy().as_ref());
+            let out2 = server
+                .run_vibe_internal(
+                    None,
+                    VibeArgs {
+                        prompt: "second".to_string(),
+                        cd: repo.to_string_lossy().to_string(),
+                        role: Some("oracle".to_string()),
+                        backend: None,
+                        model: None,
+                        reasoning_effort: None,
+                        session_id: None,
+                        force_new_session: false,
+                        session_key: None,
+                        timeout_secs: Some(5),
+                        contract: None,
+                        validate_patch: false,
+                        strict_citations: false,
+                        contract_output: None,
+                        output_parser: None,
+                        canonicalize_cd: None,
+                        extract_code_lang: None,
+                        n: None,
+                        client: None,
+
+                        conversation_id: None,
+                        participant: None,
+                        session_group: None,
+                        ephemeral: false,
+                        dry_run: false,
+                        persona_override: None,
+                        stream: false,
+                        prompt_file: None,
+                        prompt_transport: None,
+                        include_directories: None,
+                        cache: false,
+                    },
+                )
+                .await
+                .unwrap();
+            assert!(out2.resumed);
+        }
+
+        let args = read_log_args(&log_second);
+        let prompt_arg = args.last().cloned().unwrap_or_default();
+        assert!(prompt_arg.contains("second"));
+        assert!(
+            !prompt_arg.contains("[ROUNDTABLE_PERSONA"),
+            "prompt should not include persona on resume: {prompt_arg}"
+        );
+    }
+
+    #[tokio::test]
+    async fn force_new_session_ignores_session_id_and_warns() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store_path = td.path().join("sessions.json");
+        let store = SessionStore::new(store_path);
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        write_fake_cli_with_arg_log(&fake, &log, "sess-new");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    prompt: "fresh".to_string(),
+                    cd: repo.to_string_lossy().to_string(),
+                    role: Some("oracle".to_string()),
+                    backend: None,
+                    model: None,
+                    reasoning_effort: None,
+                    session_id: Some("sess-123".to_string()),
+                    force_new_session: true,
+                    session_key: None,
+                    timeout_secs: Some(5),
+                    contract: None,
+                    validate_patch: false,
+                    strict_citations: false,
+                    contract_output: None,
+                    output_parser: None,
+                    canonicalize_cd: None,
+                    extract_code_lang: None,
+                    n: None,
+                    client: None,
+
+                    conversation_id: None,
+                    participant: None,
+                    session_group: None,
+                    ephemeral: false,
+                    dry_run: false,
+                    persona_override: None,
+                    stream: false,
+                    prompt_file: None,
+                    prompt_transport: None,
+                    include_directories: None,
+                    cache: false,
+                },
+            )
+            .await
+            .unwrap();
+
+        assert!(!out.resumed);
+        let warn = out.warnings.unwrap_or_default();
+        assert!(warn.contains("force_new_session=true"));
+        assert!(warn.contains("sess-123"));
+
+        let args = read_log_args(&log);
+        assert!(!args.iter().any(|v| v == "resume"), "args={args:?}");
+    }
+
+    #[tokio::test]
+    async fn codex_auto_prompt_uses_stdin_for_long_prompt() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_prompt_transport_config(&cfg_path, 4);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let args_log = td.path().join("codex-args.log");
+        let stdin_log = td.path().join("codex-stdin.log");
+        write_fake_cli_with_arg_and_stdin_log(&fake, &args_log, &stdin_log, "sess-1");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let long_prompt = "12345".to_string();
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    prompt: long_prompt.clone(),
+                    cd: repo.to_string_lossy().to_string(),
+                    role: Some("oracle".to_string()),
+                    backend: None,
+                    model: None,
+                    reasoning_effort: None,
+                    session_id: None,
+                    force_new_session: true,
+                    session_key: None,
+                    timeout_secs: Some(5),
+                    contract: None,
+                    validate_patch: false,
+                    strict_citations: false,
+                    contract_output: None,
+                    output_parser: None,
+                    canonicalize_cd: None,
+                    extract_code_lang: None,
+                    n: None,
+                    client: None,
+
+                    conversation_id: None,
+                    participant: None,
+                    session_group: None,
+                    ephemeral: false,
+                    dry_run: false,
+                    persona_override: None,
+                    stream: false,
+                    prompt_file: None,
+                    prompt_transport: None,
+                    include_directories: None,
+                    cache: false,
+                },
+            )
+            .await
+            .unwrap();
+
+        let args = read_log_args(&args_log);
+        assert!(
+            !args.iter().any(|v| v.contains(&long_prompt)),
+            "prompt should not be passed as argv when auto uses stdin: {args:?}"
+        );
+        let stdin_text = read_log(&stdin_log);
+        assert!(
+            stdin_text.contains(&long_prompt),
+            "prompt should be passed via stdin when auto triggers"
+        );
+        assert_eq!(out.prompt_transport_used.as_deref(), Some("stdin"));
+        assert_eq!(out.invoked_args.as_ref(), Some(&args));
+    }
+
+    #[tokio::test]
+    async fn model_fallback_uses_next_model_on_error() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_fallback_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex-model.log");
+        write_fake_cli_with_model_error_once(&fake, &log, "gpt-5.2-codex");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    prompt: "ping".to_string(),
+                    cd: repo.to_string_lossy().to_string(),
+                    role: Some("oracle".to_string()),
+                    backend: None,
+                    model: None,
+                    reasoning_effort: None,
+                    session_id: None,
+                    force_new_session: true,
+                    session_key: None,
+                    timeout_secs: Some(5),
+                    contract: None,
+                    validate_patch: false,
+                    strict_citations: false,
+                    contract_output: None,
+                    output_parser: None,
+                    canonicalize_cd: None,
+                    extract_code_lang: None,
+                    n: None,
+                    client: None,
+
+                    conversation_id: None,
+                    participant: None,
+                    session_group: None,
+                    ephemeral: false,
+                    dry_run: false,
+                    persona_override: None,
+                    stream: false,
+                    prompt_file: None,
+                    prompt_transport: None,
+                    include_directories: None,
+                    cache: false,
+                },
+            )
+            .await
+            .unwrap();
+
+        let warn = out.warnings.unwrap_or_default();
+        assert!(warn.contains("model fallback used"));
+        assert!(warn.contains("gpt-5.2"));
+
+        let log_text = read_log(&log);
+        assert!(log_text.contains("gpt-5.2-codex"));
+        assert!(log_text.contains("gpt-5.2"));
+    }
+
+    #[tokio::test]
+    async fn model_fallback_walks_chain_until_a_model_answers() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        let cfg = r#"{
+  "backend": {
+    "codex": {
+      "fallback": {
+        "model": "codex/gpt-5.2",
+        "models": ["codex/gpt-5.1"],
+        "patterns": ["model_not_found"]
+      },
+      "models": {
+        "gpt-5.2-codex": { "options": {} },
+        "gpt-5.2": { "options": {} },
+        "gpt-5.1": { "options": {} }
+      }
+    }
+  },
+  "roles": {
+    "oracle": {
+      "model": "codex/gpt-5.2-codex",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
+    }
+  }
+}"#;
+        std::fs::write(&cfg_path, cfg).unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        // Both the primary (gpt-5.2-codex) and the first fallback (gpt-5.2) match and fail.
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex-model.log");
+        write_fake_cli_with_model_error_once(&fake, &log, "gpt-5.2");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "ping"))
+            .await
+            .unwrap();
+
+        assert!(out.success);
+        assert_eq!(out.model.as_deref(), Some("gpt-5.1"));
+        assert_eq!(
+            out.warning_list.last().map(String::as_str),
+            Some("model fallback used: codex/gpt-5.1")
+        );
+        let warn = out.warnings.unwrap_or_default();
+        assert!(
+            warn.contains("model fallback used: codex/gpt-5.1"),
+            "warn={warn}"
+        );
+        assert!(out.fallback_used);
+        assert_eq!(out.fallback_model_ref.as_deref(), Some("codex/gpt-5.1"));
+        let trigger = out.fallback_trigger.unwrap_or_default();
+        assert!(trigger.contains("model_not_found"), "trigger={trigger}");
+
+        let calls = logged_calls(&log);
+        assert_eq!(calls.len(), 3, "calls={calls:?}");
+        assert!(calls[2].contains("gpt-5.1"));
+    }
+
+    #[tokio::test]
+    async fn sticky_fallback_tries_previous_fallback_model_first_on_resume() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        std::fs::write(
+            &cfg_path,
+            r#"{
+  "backend": {
+    "codex": {
+      "sticky_fallback": true,
+      "fallback": {
+        "model": "codex/gpt-5.2",
+        "patterns": ["model_not_found"]
+      },
+      "models": {
+        "gpt-5.2-codex": { "options": {} },
+        "gpt-5.2": { "options": {} }
+      }
+    }
+  },
+  "roles": {
+    "oracle": {
+      "model": "codex/gpt-5.2-codex",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
+    }
+  }
+}"#,
+        )
+        .unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex-model.log");
+        write_fake_cli_with_model_error_once(&fake, &log, "gpt-5.2-codex");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let first = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "ping"))
+            .await
+            .unwrap();
+        assert_eq!(first.model.as_deref(), Some("gpt-5.2"));
+        assert_eq!(logged_calls(&log).len(), 2);
+
+        let second = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    force_new_session: false,
+                    ..test_args(&repo, "oracle", "again")
+                },
+            )
+            .await
+            .unwrap();
+        assert!(second.resumed);
+        assert_eq!(second.model.as_deref(), Some("gpt-5.2"));
+        assert!(second
+            .warnings
+            .unwrap_or_default()
+            .contains("model fallback used"));
+
+        let lines = logged_calls(&log);
+        assert_eq!(lines.len(), 3, "primary should not be retried: {lines:?}");
+        assert!(lines[2].contains("model=gpt-5.2"));
+        assert!(!lines[2].contains("gpt-5.2-codex"));
+    }
+
+    #[tokio::test]
+    async fn model_fallback_uses_configured_error_patterns() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_fallback_pattern_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex-model.log");
+        write_fake_cli_with_custom_model_error_once(
+            &fake,
+            &log,
+            "gpt-5.2-codex",
+            "custom_not_found",
+        );
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    prompt: "ping".to_string(),
+                    cd: repo.to_string_lossy().to_string(),
+                    role: Some("oracle".to_string()),
+                    backend: None,
+                    model: None,
+                    reasoning_effort: None,
+                    session_id: None,
+                    force_new_session: true,
+                    session_key: None,
+                    timeout_secs: Some(5),
+                    contract: None,
+                    validate_patch: false,
+                    strict_citations: false,
+                    contract_output: None,
+                    output_parser: None,
+                    canonicalize_cd: None,
+                    extract_code_lang: None,
+                    n: None,
+                    client: None,
+
+                    conversation_id: None,
+                    participant: None,
+                    session_group: None,
+                    ephemeral: false,
+                    dry_run: false,
+                    persona_override: None,
+                    stream: false,
+                    prompt_file: None,
+                    prompt_transport: None,
+                    include_directories: None,
+                    cache: false,
+                },
+            )
+            .await
+            .unwrap();
+
+        let warn = out.warnings.unwrap_or_default();
+        assert!(warn.contains("model fallback used"));
+        assert!(warn.contains("codex/gpt-5.2"));
+    }
+
+    #[tokio::test]
+    async fn length_routing_selects_model_by_prompt_length() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        // The persona block and guardrail alone are about 100 chars, so only the effective prompt
+        // decides whether a call fits the 128-char rule.
+        let cfg_path = td.path().join("config.json");
+        std::fs::write(
+            &cfg_path,
+            r#"{
+  "backend": {
+    "codex": {
+      "models": { "gpt-5.2-codex": {}, "gpt-5.2-mini": {} }
+    }
+  },
+  "roles": {
+    "oracle": {
+      "model": "codex/gpt-5.2-codex",
+      "length_routing": [{ "max_chars": 128, "model": "codex/gpt-5.2-mini" }],
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-only" }
+    },
+    "house": {
+      "model": "codex/gpt-5.2-codex",
+      "length_routing": [{ "max_chars": 128, "model": "codex/gpt-5.2-mini" }],
+      "personas": { "description": "d", "prompt": "p" },
+      "prompt_prefix": "Follow the house rules in CONTRIBUTING.md.",
+      "capabilities": { "filesystem": "read-only" }
+    }
+  }
+}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            repo.join("task.md"),
+            "Summarize the open issues in this repo.",
+        )
+        .unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        write_fake_cli(&fake, &log, "sess-1", "pong");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let model_for = |args: VibeArgs| {
+            let server = server.clone();
+            async move {
+                let out = server.run_vibe_internal(None, args).await.unwrap();
+                (out.model.unwrap_or_default(), out.prompt_chars)
+            }
+        };
+
+        let (model, chars) = model_for(test_args(&repo, "oracle", "ping")).await;
+        assert_eq!(model, "gpt-5.2-mini");
+        assert!(chars <= 128, "{chars}");
+        assert!(read_log(&log).contains("--model gpt-5.2-mini"));
+
+        // Short raw prompts whose assembled prompt is over the limit.
+        let (model, chars) = model_for(test_args(&repo, "oracle", &"x".repeat(64))).await;
+        assert_eq!(model, "gpt-5.2-codex");
+        assert!(chars > 128, "{chars}");
+        assert!(read_log(&log).contains("--model gpt-5.2-codex"));
+
+        let (model, chars) = model_for(test_args(&repo, "house", "ping")).await;
+        assert_eq!(model, "gpt-5.2-codex");
+        assert!(chars > 128, "{chars}");
+
+        let (model, chars) = model_for(VibeArgs {
+            prompt_file: Some("task.md".to_string()),
+            ..test_args(&repo, "oracle", "")
+        })
+        .await;
+        assert_eq!(model, "gpt-5.2-codex");
+        assert!(chars > 128, "{chars}");
+    }
+
+    fn write_codex_oversize_config(path: &Path, policy: &str) {
+        let cfg = format!(
+            r#"{{
+  "backend": {{
+    "codex": {{
+      "models": {{ "gpt-5.2-codex": {{}} }}
+    }}
+  }},
+  "roles": {{
+    "oracle": {{
+      "model": "codex/gpt-5.2-codex",
+      "max_prompt_chars": 100,
+      "on_oversize_prompt": "{policy}",
+      "personas": {{ "description": "d", "prompt": "p" }},
+      "capabilities": {{ "filesystem": "read-only" }}
+    }}
+  }}
+}}"#
+        );
+        std::fs::write(path, cfg).unwrap();
+    }
+
+    #[tokio::test]
+    async fn oversize_prompt_truncates_or_rejects_by_policy() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let fake = td.path().join("fake-codex.sh");
+        let args_log = td.path().join("codex-args.log");
+        write_fake_cli_with_arg_log(&fake, &args_log, "sess-1");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let long_prompt = format!("START{}END", "x".repeat(500));
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_oversize_config(&cfg_path, "truncate_tail");
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+        let out = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", &long_prompt))
+            .await
+            .unwrap();
+        assert!(out
+            .warnings
+            .unwrap_or_default()
+            .contains("prompt truncated from"));
+        let args = read_log_args(&args_log);
+        let sent = args.last().cloned().unwrap_or_default();
+        assert!(sent.chars().count() <= 100, "prompt={sent}");
+        assert!(sent.contains("prompt truncated"));
+        assert!(!sent.contains("END"));
+
+        let cfg_reject = td.path().join("config-reject.json");
+        write_codex_oversize_config(&cfg_reject, "reject");
+        let store = SessionStore::new(td.path().join("sessions-reject.json"));
+        let server = VibeServer::new(codex_loader(&cfg_reject), store);
+        let err = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", &long_prompt))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("max_prompt_chars"), "err={err}");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn probe_backend_reuses_cached_probes_until_a_spawn_fails() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        std::fs::write(
+            &cfg_path,
+            r#"{
+  "limits": { "probe_cache_secs": 60 },
+  "backend": { "codex": { "models": { "gpt-5.2": {} } } },
+  "roles": {
+    "oracle": { "model": "codex/gpt-5.2", "capabilities": { "filesystem": "read-only" } }
+  }
+}"#,
+        )
+        .unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+        let cfg = server.config_loader.load_for_repo(&repo).unwrap().unwrap();
+        let ttl = cfg.limits.probe_cache_ttl();
+        let adapter = cfg.resolve_profile(Some("oracle")).unwrap().profile.adapter;
+
+        // Every run of the CLI, probe or not, adds a line to the counter file.
+        let fake = td.path().join("fake-codex.sh");
+        let counter = td.path().join("runs.log");
+        std::fs::write(
+            &fake,
+            format!(
+                "#!/bin/sh\necho run >> \"{}\"\necho 'codex-cli 9.9.9'\n",
+                counter.display()
+            ),
+        )
+        .unwrap();
+        let set_mode = |mode: u32| {
+            std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(mode)).unwrap();
+        };
+        set_mode(0o755);
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let first = server.probe_backend("codex", &adapter, &repo, ttl).await;
+        assert_eq!(first.outcome.as_deref(), Ok("codex-cli 9.9.9"));
+        assert!(!first.cached);
+        let second = server.probe_backend("codex", &adapter, &repo, ttl).await;
+        assert!(second.cached);
+        assert_eq!(second.outcome.as_deref(), Ok("codex-cli 9.9.9"));
+        assert_eq!(read_log(&counter).lines().count(), 1);
+
+        // A call whose CLI cannot be started drops the cached probe.
+        set_mode(0o644);
+        let err = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", "ping"))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("failed to spawn backend"), "{err:?}");
+        set_mode(0o755);
+
+        let third = server.probe_backend("codex", &adapter, &repo, ttl).await;
+        assert!(!third.cached);
+        assert_eq!(read_log(&counter).lines().count(), 2);
+
+        // Without a TTL nothing is reused.
+        let uncached = server
+            .probe_backend("codex", &adapter, &repo, std::time::Duration::ZERO)
+            .await;
+        assert!(!uncached.cached);
+        assert_eq!(read_log(&counter).lines().count(), 3);
+    }
+
+    #[tokio::test]
+    async fn info_includes_enabled_flag() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        std::fs::write(
+            &cfg_path,
+            r#"{
+  "backend": {
+    "codex": {
+      "models": { "gpt-5.2": {} }
+    }
+  },
+  "roles": {
+    "oracle": {
+      "model": "codex/gpt-5.2",
+      "enabled": false,
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-only" }
+    },
+    "reader": {
+      "model": "codex/gpt-5.2",
+      "personas": { "description": "d2", "prompt": "p2" },
+      "capabilities": { "filesystem": "read-only" }
+    }
+  }
+}"#,
+        )
+        .unwrap();
+
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store);
+
+        let out = server
+            .info(Parameters(InfoArgs {
+                cd: repo.to_string_lossy().to_string(),
+                client: None,
+            }))
+            .await
+            .unwrap();
+
+        let content = out
+            .content
+            .first()
+            .and_then(|c| c.as_text())
+            .map(|t| t.text.clone())
+            .unwrap_or_default();
+        let v: serde_json::Value = serde_json::from_str(&content).unwrap();
+        let roles = v["roles"].as_array().unwrap();
+        let mut found = false;
+        for role in roles {
+            if role["role"] == "oracle" {
+                found = true;
+                assert_eq!(role["enabled"], false);
+            }
+        }
+        assert!(found, "role list should include disabled roles");
+    }
+
+    #[tokio::test]
+    async fn info_reports_rendered_args_with_env_references_redacted() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg = serde_json::json!({
+            "backend": {
+                "codex": {
+                    "adapter": {
+                        "args_template": [
+                            "exec",
+                            "--token=${PATH}",
+                            "{% if not session_id %}--model{% endif %}",
+                            "{% if not session_id %}{{ model }}{% endif %}",
+                            "{{ prompt }}"
+                        ],
+                        "output_parser": {
+                            "type": "json_stream",
+                            "session_id_path": "thread_id",
+                            "message_path": "item.text",
+                            "pick": "last"
+                        }
+                    },
+                    "models": { "gpt-5.2": {} }
+                }
+            },
+            "roles": {
+                "oracle": {
+                    "model": "codex/gpt-5.2",
+                    "capabilities": { "filesystem": "read-only" }
+                }
+            }
+        });
+        let cfg_path = td.path().join("config.json");
+        std::fs::write(&cfg_path, cfg.to_string()).unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store);
+
+        let out = server
+            .info(Parameters(InfoArgs {
+                cd: repo.to_string_lossy().to_string(),
+                client: None,
+            }))
+            .await
+            .unwrap();
+        let content = out
+            .content
+            .first()
+            .and_then(|c| c.as_text())
+            .map(|t| t.text.clone())
+            .unwrap_or_default();
+        let v: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(
+            v["roles"][0]["rendered_args_example"],
+            serde_json::json!([
+                "exec",
+                "--token=${PATH}",
+                "--model",
+                "gpt-5.2",
+                format!("{}\n<prompt>", backend::DEFAULT_READONLY_GUARDRAIL)
+            ])
+        );
+        assert!(!content.contains(&std::env::var("PATH").unwrap()));
+    }
+
+    #[tokio::test]
+    async fn info_reports_prompt_overhead_for_long_persona() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let persona = "x".repeat(5000);
+        let cfg = serde_json::json!({
+            "backend": { "codex": { "models": { "gpt-5.2": {} } } },
+            "roles": {
+                "oracle": {
+                    "model": "codex/gpt-5.2",
+                    "personas": { "description": "d", "prompt": persona },
+                    "capabilities": { "filesystem": "read-only" }
+                }
+            }
+        });
+        let cfg_path = td.path().join("config.json");
+        std::fs::write(&cfg_path, cfg.to_string()).unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store);
+
+        let out = server
+            .info(Parameters(InfoArgs {
+                cd: repo.to_string_lossy().to_string(),
+                client: None,
+            }))
+            .await
+            .unwrap();
+        let content = out
+            .content
+            .first()
+            .and_then(|c| c.as_text())
+            .map(|t| t.text.clone())
+            .unwrap_or_default();
+        let v: serde_json::Value = serde_json::from_str(&content).unwrap();
+        let role = &v["roles"][0];
+        assert_eq!(role["prompt_len"], 5000);
+        // The persona block and its blank line, then the default guardrail and its newline.
+        let expected = format!(
+            "{}\n{}\n\n",
+            backend::DEFAULT_READONLY_GUARDRAIL,
+            format_persona_block("oracle", &persona)
+        )
+        .chars()
+        .count();
+        assert_eq!(role["prompt_overhead_chars"], expected);
+    }
+
+    #[test]
+    fn info_text_lists_roles_and_fails_without_config() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        let cfg_path = td.path().join("config.json");
+        std::fs::write(
+            &cfg_path,
+            r#"{
+  "backend": { "codex": { "models": { "gpt-5.2": { "options": {} } } } },
+  "roles": {
+    "oracle": { "model": "codex/gpt-5.2", "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] } },
+    "off": { "model": "codex/gpt-5.2", "enabled": false }
+  }
+}"#,
+        )
+        .unwrap();
+        let args = || InfoArgs {
+            cd: repo.to_string_lossy().to_string(),
+            client: None,
+        };
+
+        let server = VibeServer::new(
+            ConfigLoader::new(Some(cfg_path.clone())),
+            SessionStore::new(td.path().join("sessions.json")),
+        );
+        let (ok, text) = server.info_text(args()).unwrap();
+        assert!(ok, "{text}");
+        assert!(text.contains(&format!("config: {}\n", cfg_path.display())));
+        assert!(text.contains("oracle  codex/gpt-5.2\n"), "{text}");
+        assert!(
+            text.contains("off     codex/gpt-5.2  (disabled)\n"),
+            "{text}"
+        );
+
+        let missing = VibeServer::new(
+            ConfigLoader::new(Some(td.path().join("missing.json"))),
+            SessionStore::new(td.path().join("sessions.json")),
+        );
+        let (ok, text) = missing.info_text(args()).unwrap();
+        assert!(!ok);
+        assert!(text.contains("error: no config found"), "{text}");
+    }
+
+    #[test]
+    fn resolved_config_shows_project_overriding_user() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(repo.join(".roundtable")).unwrap();
+        let user_path = td.path().join("config.json");
+        std::fs::write(
+            &user_path,
+            r#"{
+  "backend": { "codex": { "timeout_secs": 100, "models": { "a": { "options": {} }, "b": { "options": {} } } } },
+  "roles": {
+    "oracle": { "model": "codex/a", "env": { "TOKEN": "${MY_TOKEN}" } },
+    "builder": { "model": "codex/a" }
+  }
+}"#,
+        )
+        .unwrap();
+        let project_path = repo.join(".roundtable").join("config.json");
+        std::fs::write(
+            &project_path,
+            r#"{ "backend": { "codex": { "timeout_secs": 200, "models": {} } }, "roles": { "builder": { "model": "codex/b" } } }"#,
+        )
+        .unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(ConfigLoader::new(Some(user_path.clone())), store);
+
+        let out = server
+            .resolved_config_internal(ResolvedConfigArgs {
+                cd: repo.to_string_lossy().to_string(),
+                client: None,
+            })
+            .unwrap();
+
+        assert!(out.success, "{:?}", out.error);
+        assert_eq!(out.config_sources.len(), 2);
+        assert_eq!(out.config_sources[0], user_path.display().to_string());
+        let cfg = serde_json::to_value(out.config.as_ref().unwrap()).unwrap();
+        assert_eq!(cfg["roles"]["builder"]["model"], "codex/b");
+        assert_eq!(cfg["roles"]["oracle"]["model"], "codex/a");
+        assert_eq!(cfg["roles"]["oracle"]["env"]["TOKEN"], "${MY_TOKEN}");
+        assert_eq!(cfg["backend"]["codex"]["timeout_secs"], 200);
+        assert!(cfg["backend"]["codex"]["models"]["a"].is_object());
+        assert!(cfg["backend"]["codex"]["adapter"]["args_template"].is_array());
+        assert_eq!(
+            out.env_interpolated,
+            vec!["roles.oracle.env.TOKEN".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn validate_config_reports_every_error_per_role() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        let cfg_path = td.path().join("config.json");
+        let ro = r#""capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }"#;
+        std::fs::write(
+            &cfg_path,
+            format!(
+                r#"{{
+  "backend": {{
+    "codex": {{
+      "fallback": {{ "model": "codex/gpt-9", "patterns": ["model_not_found"] }},
+      "models": {{ "gpt-5.2": {{ "options": {{}} }} }}
+    }},
+    "opencode": {{ "models": {{}} }}
+  }},
+  "roles": {{
+    "good": {{ "model": "codex/gpt-5.2", {ro} }},
+    "bad_model": {{ "model": "codex/nope", {ro} }},
+    "bad_variant": {{ "model": "codex/gpt-5.2@turbo", {ro} }},
+    "no_backend": {{ "model": "gemini/flash", {ro} }},
+    "readonly_opencode": {{ "model": "opencode/default", {ro} }},
+    "off": {{ "model": "codex/nope", "enabled": false, {ro} }}
+  }}
+}}"#
+            ),
+        )
+        .unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store);
+
+        let out = server
+            .validate_config_internal(ValidateConfigArgs {
+                cd: repo.to_string_lossy().to_string(),
+                client: None,
+            })
+            .unwrap();
+
+        assert!(!out.success);
+        assert!(
+            out.errors
+                .iter()
+                .any(|e| e.contains("fallback model references unknown model 'gpt-9'")),
+            "errors={:?}",
+            out.errors
+        );
+        assert!(out
+            .errors
+            .iter()
+            .any(|e| e.contains("role no_backend references missing backend: gemini")));
+        let role = |id: &str| out.roles.iter().find(|r| r.role == id).unwrap();
+        let has = |id: &str, needle: &str| {
+            let r = role(id);
+            assert_eq!(r.status, "error", "role {id}: {:?}", r.errors);
+            assert!(
+                r.errors.iter().any(|e| e.contains(needle)),
+                "role {id}: {:?}",
+                r.errors
+            );
+        };
+        has("bad_model", "unknown model 'nope'");
+        has("bad_variant", "unknown variant: turbo");
+        has("no_backend", "missing backend config: gemini");
+        has("readonly_opencode", "unsupported filesystem capability");
+        // The backend-level fallback error also surfaces on every role that would use it.
+        has("good", "fallback: unknown fallback model 'gpt-9'");
+        assert_eq!(role("off").status, "disabled");
+        assert!(role("off").errors.is_empty());
+    }
+
+    #[test]
+    fn list_roles_marks_bad_roles_without_failing() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        let cfg = serde_json::json!({
+            "backend": { "codex": { "models": { "gpt-5.2": {} } } },
+            "roles": {
+                "good": { "model": "codex/gpt-5.2" },
+                "off": { "model": "codex/gpt-5.2", "enabled": false },
+                "no_backend": { "model": "gemini/flash" },
+                "bad_ref": { "model": "gpt-5.2" }
+            }
+        });
+        let cfg_path = td.path().join("config.json");
+        std::fs::write(&cfg_path, cfg.to_string()).unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store);
+
+        let out = server
+            .list_roles_internal(ListRolesArgs {
+                cd: repo.to_string_lossy().to_string(),
+                client: None,
+            })
+            .unwrap();
+        assert!(out.success, "{:?}", out.error);
+        let names: Vec<&str> = out.roles.iter().map(|r| r.role.as_str()).collect();
+        assert_eq!(names, vec!["bad_ref", "good", "no_backend", "off"]);
+        let role = |id: &str| out.roles.iter().find(|r| r.role == id).unwrap();
+        assert_eq!(role("good").backend.as_deref(), Some("codex"));
+        assert!(role("good").enabled && role("good").error.is_none());
+        assert!(!role("off").enabled);
+        assert_eq!(role("no_backend").backend.as_deref(), Some("gemini"));
+        assert_eq!(
+            role("no_backend").error.as_deref(),
+            Some("missing backend config: gemini")
+        );
+        assert_eq!(role("bad_ref").backend, None);
+        assert!(role("bad_ref").error.is_some());
+    }
+
+    #[tokio::test]
+    async fn info_lists_candidate_chain_with_filtered_fallback() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg = serde_json::json!({
+            "backend": {
+                "codex": {
+                    "models": { "gpt-5.2": {} },
+                    "fallback": { "model": "opencode/default", "patterns": ["model_not_found"] }
+                },
+                "opencode": { "models": {} }
+            },
+            "roles": {
+                "oracle": {
+                    "model": "codex/gpt-5.2",
+                    "capabilities": { "filesystem": "read-only" }
+                }
+            }
+        });
+        let cfg_path = td.path().join("config.json");
+        std::fs::write(&cfg_path, cfg.to_string()).unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store);
+
+        let out = server
+            .info(Parameters(InfoArgs {
+                cd: repo.to_string_lossy().to_string(),
+                client: None,
+            }))
+            .await
+            .unwrap();
+        let content = out
+            .content
+            .first()
+            .and_then(|c| c.as_text())
+            .map(|t| t.text.clone())
+            .unwrap_or_default();
+        let v: serde_json::Value = serde_json::from_str(&content).unwrap();
+        let chain = v["roles"][0]["candidate_chain"].as_array().unwrap();
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain[0]["ref"], "codex/gpt-5.2");
+        assert!(chain[0]["filtered_reason"].is_null());
+        assert_eq!(chain[1]["ref"], "opencode/default");
+        let reason = chain[1]["filtered_reason"].as_str().unwrap();
+        assert!(reason.contains("filesystem"), "reason={reason}");
+    }
+
+    #[tokio::test]
+    async fn adapters_tool_returns_embedded_catalog() {
+        let td = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(ConfigLoader::new(None), store);
+
+        let out = server.adapters().await.unwrap();
+        let content = out
+            .content
+            .first()
+            .and_then(|c| c.as_text())
+            .map(|t| t.text.clone())
+            .unwrap_or_default();
+        let v: serde_json::Value = serde_json::from_str(&content).unwrap();
+        let codex = &v["adapters"]["codex"];
+        assert_eq!(codex["output_parser"]["type"], "json_stream");
+        assert_eq!(codex["output_parser"]["session_id_path"], "thread_id");
+        assert_eq!(codex["prompt_transport"], "auto");
+        assert!(codex["args_template"].as_array().unwrap().len() > 1);
+        assert!(v["adapters"]["kimi"]["filesystem_capabilities"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("read-write")));
+    }
+
+    #[tokio::test]
+    async fn pretty_output_indents_json_without_changing_content() {
+        let td = tempfile::tempdir().unwrap();
+        let text_of = |out: CallToolResult| {
+            out.content
+                .first()
+                .and_then(|c| c.as_text())
+                .map(|t| t.text.clone())
+                .unwrap_or_default()
+        };
+
+        let compact = VibeServer::new(
+            ConfigLoader::new(None),
+            SessionStore::new(td.path().join("sessions.json")),
+        );
+        let compact = text_of(compact.adapters().await.unwrap());
+        let pretty = VibeServer::new(
+            ConfigLoader::new(None),
+            SessionStore::new(td.path().join("sessions.json")),
+        )
+        .with_pretty_output(true);
+        let pretty = text_of(pretty.adapters().await.unwrap());
+
+        assert!(!compact.contains('\n'));
+        assert!(pretty.lines().count() > 1);
+        assert!(pretty.contains("\n  \"adapters\": {"), "pretty={pretty}");
+        let a: serde_json::Value = serde_json::from_str(&compact).unwrap();
+        let b: serde_json::Value = serde_json::from_str(&pretty).unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[tokio::test]
+    async fn adapter_renders_options_and_capabilities() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store_path = td.path().join("sessions.json");
+        let store = SessionStore::new(store_path);
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        write_fake_cli(&fake, &log, "sess-cfg-1", "pong");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    prompt: "ping".to_string(),
+                    cd: repo.to_string_lossy().to_string(),
+                    role: Some("oracle".to_string()),
+                    backend: None,
+                    model: None,
+                    reasoning_effort: None,
+                    session_id: None,
+                    force_new_session: true,
+                    session_key: None,
+                    timeout_secs: Some(5),
+                    contract: None,
+                    validate_patch: false,
+                    strict_citations: false,
+                    contract_output: None,
+                    output_parser: None,
+                    canonicalize_cd: None,
+                    extract_code_lang: None,
+                    n: None,
+                    client: None,
+
+                    conversation_id: None,
+                    participant: None,
+                    session_group: None,
+                    ephemeral: false,
+                    dry_run: false,
+                    persona_override: None,
+                    stream: false,
+                    prompt_file: None,
+                    prompt_transport: None,
+                    include_directories: None,
+                    cache: false,
+                },
+            )
+            .await
+            .unwrap();
+
+        assert!(out.success, "error={:?}", out.error);
+        assert_eq!(out.backend, "codex");
+        assert!(out.agent_messages.contains("pong"));
+
+        let log_txt = read_log(&log);
+        assert!(log_txt.contains("--model gpt-5.2-codex"));
+        assert!(log_txt.contains("model_reasoning_effort=xhigh"));
+        assert!(!log_txt.contains("--dangerously-bypass-approvals-and-sandbox"));
+    }
+
+    #[tokio::test]
+    async fn contract_patch_with_citations_fails_when_missing() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store_path = td.path().join("sessions.json");
+        let store = SessionStore::new(store_path);
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let script = "#!/bin/sh\nset -e\necho '{\"type\":\"thread.started\",\"thread_id\":\"sess-x\"}'\necho '{\"type\":\"item.completed\",\"item\":{\"type\":\"agent_message\",\"text\":\"no patch here\"}}'\n";
+        {
+            let mut f = std::fs::File::create(&fake).unwrap();
+            f.write_all(script.as_bytes()).unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(&fake).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(&fake, perms).unwrap();
+        }
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    prompt: "do".to_string(),
+                    cd: repo.to_string_lossy().to_string(),
+                    role: Some("oracle".to_string()),
+                    backend: None,
+                    model: None,
+                    reasoning_effort: None,
+                    session_id: None,
+                    force_new_session: true,
+                    session_key: None,
+                    timeout_secs: Some(5),
+                    contract: Some(OutputContract::PatchWithCitations),
+                    validate_patch: false,
+                    strict_citations: false,
+                    contract_output: None,
+                    output_parser: None,
+                    canonicalize_cd: None,
+                    extract_code_lang: None,
+                    n: None,
+                    client: None,
+
+                    conversation_id: None,
+                    participant: None,
+                    session_group: None,
+                    ephemeral: false,
+                    dry_run: false,
+                    persona_override: None,
+                    stream: false,
+                    prompt_file: None,
+                    prompt_transport: None,
+                    include_directories: None,
+                    cache: false,
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(out.success, false);
+        assert!(out
+            .error
+            .as_deref()
+            .unwrap_or("")
+            .contains("output contract violation"));
+        assert_eq!(out.error_kind.as_deref(), Some("contract_violation"));
+    }
+
+    #[tokio::test]
+    async fn backend_timeout_reports_timeout_error_kind() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        {
+            let mut f = std::fs::File::create(&fake).unwrap();
+            f.write_all(b"#!/bin/sh\nexec sleep 30\n").unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(&fake).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(&fake, perms).unwrap();
+        }
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let err = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    timeout_secs: Some(1),
+                    force_new_session: true,
+                    ..test_args(&repo, "oracle", "ping")
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("timed out"), "{err:?}");
+        assert_eq!(error_kind_of(&err).as_deref(), Some("timeout"));
+
+        let err = server
+            .run_vibe_internal(None, test_args(&repo, "oracle", " "))
+            .await
+            .unwrap_err();
+        assert_eq!(error_kind_of(&err).as_deref(), Some("invalid_params"));
+    }
+
+    #[tokio::test]
+    async fn contract_json_schema_returns_value_or_errors() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store_path = td.path().join("sessions.json");
+        let store = SessionStore::new(store_path);
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let script = "#!/bin/sh\nset -e\necho '{\"type\":\"thread.started\",\"thread_id\":\"sess-x\"}'\necho '{\"type\":\"item.completed\",\"item\":{\"type\":\"agent_message\",\"text\":\"Scores: [3, 5] done\"}}'\n";
+        {
+            let mut f = std::fs::File::create(&fake).unwrap();
+            f.write_all(script.as_bytes()).unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(&fake).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(&fake, perms).unwrap();
+        }
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        for item_type in ["integer", "string"] {
+            let schema = serde_json::json!({"type": "array", "items": {"type": item_type}});
+            let out = server
+                .run_vibe_internal(
+                    None,
+                    VibeArgs {
+                        prompt: "score".to_string(),
+                        cd: repo.to_string_lossy().to_string(),
+                        role: Some("oracle".to_string()),
+                        backend: None,
+                        model: None,
+                        reasoning_effort: None,
+                        session_id: None,
+                        force_new_session: true,
+                        session_key: None,
+                        timeout_secs: Some(5),
+                        contract: Some(OutputContract::JsonSchema { schema }),
+                        validate_patch: false,
+                        strict_citations: false,
+                        contract_output: None,
+                        output_parser: None,
+                        canonicalize_cd: None,
+                        extract_code_lang: None,
+                        n: None,
+                        client: None,
+                        conversation_id: None,
+                        participant: None,
+                        session_group: None,
+                        ephemeral: false,
+                        dry_run: false,
+                        persona_override: None,
+                        stream: false,
+                        prompt_file: None,
+                        prompt_transport: None,
+                        include_directories: None,
+                        cache: false,
+                    },
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(out.contract.as_deref(), Some("json_schema"));
+            if item_type == "integer" {
+                assert!(out.success, "{:?}", out.error);
+                assert_eq!(out.contract_json, Some(serde_json::json!([3, 5])));
+                assert!(out.contract_errors.is_empty());
+            } else {
+                assert!(!out.success);
+                assert!(out.contract_json.is_none());
+                assert_eq!(out.contract_errors.len(), 2, "{:?}", out.contract_errors);
+                assert!(out
+                    .error
+                    .as_deref()
+                    .unwrap_or("")
+                    .starts_with("output contract violation: /0: "));
+            }
+        }
+    }
+
+    #[test]
+    fn list_sessions_filters_by_repo_and_scope() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        let other = td.path().join("other");
+        std::fs::create_dir_all(&repo).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+        let repo_root = repo.canonicalize().unwrap();
+        let other_root = other.canonicalize().unwrap();
+
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let record = |root: &Path, role: &str, backend: Backend, sid: &str| SessionRecord {
+            repo_root: root.to_string_lossy().to_string(),
+            role: role.to_string(),
+            role_id: role.to_string(),
+            backend,
+            backend_session_id: sid.to_string(),
+            model: None,
+            sampling_history: Vec::new(),
+            updated_at_unix_secs: 7,
+        };
+        let claude_key = SessionStore::compute_key_with_scope(
+            &repo_root,
+            "oracle",
+            "oracle",
+            Some("claude"),
+            None,
+        );
+        store
+            .put(
+                &SessionStore::compute_key(&repo_root, "oracle", "oracle"),
+                record(&repo_root, "oracle", Backend::Codex, "sess-1"),
+            )
+            .unwrap();
+        store
+            .put(
+                &claude_key,
+                record(&repo_root, "oracle", Backend::Codex, "sess-2"),
+            )
+            .unwrap();
+        store
+            .put(
+                &SessionStore::compute_key(&repo_root, "coder", "coder"),
+                record(&repo_root, "coder", Backend::Kimi, "stateless"),
+            )
+            .unwrap();
+        store
+            .put(
+                &SessionStore::compute_key(&other_root, "oracle", "oracle"),
+                record(&other_root, "oracle", Backend::Codex, "sess-3"),
+            )
+            .unwrap();
+
+        let server = VibeServer::new(ConfigLoader::new(None), store);
+        let out = server
+            .list_sessions_internal(ListSessionsArgs {
+                cd: repo.to_string_lossy().to_string(),
+                client: None,
+                conversation_id: None,
+                updated_after_unix_secs: None,
+                limit: None,
+                offset: None,
+            })
+            .unwrap();
+        assert_eq!(out.sessions.len(), 3);
+        let kimi = out.sessions.iter().find(|s| s.role == "coder").unwrap();
+        assert!(kimi.stateless);
+        assert_eq!(kimi.backend, "kimi");
+        assert!(out
+            .sessions
+            .iter()
+            .filter(|s| s.role == "oracle")
+            .all(|s| !s.stateless));
+
+        let out = server
+            .list_sessions_internal(ListSessionsArgs {
+                cd: repo.to_string_lossy().to_string(),
+                client: Some("claude".to_string()),
+                conversation_id: None,
+                updated_after_unix_secs: None,
+                limit: None,
+                offset: None,
+            })
+            .unwrap();
+        assert_eq!(out.sessions.len(), 1);
+        assert_eq!(out.sessions[0].session_key, claude_key);
+        assert_eq!(out.sessions[0].backend_session_id, "sess-2");
+    }
+
+    #[test]
+    fn list_sessions_pages_newest_first_after_timestamp() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        let repo_root = repo.canonicalize().unwrap();
+
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        for (role, updated) in [("a", 10), ("b", 30), ("c", 20), ("d", 5)] {
+            store
+                .put(
+                    &SessionStore::compute_key(&repo_root, role, role),
+                    SessionRecord {
+                        repo_root: repo_root.to_string_lossy().to_string(),
+                        role: role.to_string(),
+                        role_id: role.to_string(),
+                        backend: Backend::Codex,
+                        backend_session_id: format!("sess-{role}"),
+                        model: None,
+                        sampling_history: Vec::new(),
+                        updated_at_unix_secs: updated,
+                    },
+                )
+                .unwrap();
+        }
+
+        let server = VibeServer::new(ConfigLoader::new(None), store);
+        let args = |updated_after, limit, offset| ListSessionsArgs {
+            cd: repo.to_string_lossy().to_string(),
+            client: None,
+            conversation_id: None,
+            updated_after_unix_secs: updated_after,
+            limit,
+            offset,
+        };
+        let roles = |out: &ListSessionsOutput| {
+            out.sessions
+                .iter()
+                .map(|s| s.role.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let out = server
+            .list_sessions_internal(args(None, None, None))
+            .unwrap();
+        assert_eq!(out.total, 4);
+        assert_eq!(roles(&out), vec!["b", "c", "a", "d"]);
+
+        let out = server
+            .list_sessions_internal(args(Some(5), Some(2), Some(1)))
+            .unwrap();
+        assert_eq!(out.total, 3);
+        assert_eq!(roles(&out), vec!["c", "a"]);
+
+        let out = server
+            .list_sessions_internal(args(None, Some(2), Some(10)))
+            .unwrap();
+        assert_eq!(out.total, 4);
+        assert!(out.sessions.is_empty());
+
+        let err = server
+            .list_sessions_internal(args(None, Some(0), None))
+            .unwrap_err();
+        assert!(err.message.contains("limit"));
+    }
+
+    #[tokio::test]
+    async fn clear_session_removes_by_key_role_and_all() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        let other = td.path().join("other");
+        std::fs::create_dir_all(&repo).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+        let repo_root = repo.canonicalize().unwrap();
+        let other_root = other.canonicalize().unwrap();
+
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let record = |root: &Path, role: &str| SessionRecord {
+            repo_root: root.to_string_lossy().to_string(),
+            role: role.to_string(),
+            role_id: role.to_string(),
+            backend: Backend::Codex,
+            backend_session_id: "sess".to_string(),
+            model: None,
+            sampling_history: Vec::new(),
+            updated_at_unix_secs: 7,
+        };
+        let oracle_claude = SessionStore::compute_key_with_scope(
+            &repo_root,
+            "oracle",
+            "oracle",
+            Some("claude"),
+            None,
+        );
+        let coder = SessionStore::compute_key(&repo_root, "coder", "coder");
+        let reviewer = SessionStore::compute_key(&repo_root, "reviewer", "reviewer");
+        let other_key = SessionStore::compute_key(&other_root, "oracle", "oracle");
+        store
+            .put(&oracle_claude, record(&repo_root, "oracle"))
+            .unwrap();
+        store.put(&coder, record(&repo_root, "coder")).unwrap();
+        store
+            .put(&reviewer, record(&repo_root, "reviewer"))
+            .unwrap();
+        store
+            .put(&other_key, record(&other_root, "oracle"))
+            .unwrap();
+
+        let server = VibeServer::new(ConfigLoader::new(None), store.clone());
+        let args = |session_key: Option<&str>, role: Option<&str>, all: bool| ClearSessionArgs {
+            cd: repo.to_string_lossy().to_string(),
+            session_key: session_key.map(str::to_string),
+            role: role.map(str::to_string),
+            all,
+            client: Some("claude".to_string()),
+            conversation_id: None,
+        };
+
+        assert!(server
+            .clear_session_internal(args(Some(&coder), Some("coder"), false))
+            .await
+            .is_err());
+
+        let out = server
+            .clear_session_internal(args(None, Some("oracle"), false))
+            .await
+            .unwrap();
+        assert_eq!(out.removed, vec![oracle_claude.clone()]);
+        assert!(store.get(&oracle_claude).unwrap().is_none());
+
+        let out = server
+            .clear_session_internal(args(Some(&coder), None, false))
+            .await
+            .unwrap();
+        assert_eq!(out.removed, vec![coder.clone()]);
+        let out = server
+            .clear_session_internal(args(Some(&coder), None, false))
+            .await
+            .unwrap();
+        assert!(out.removed.is_empty());
+
+        let mut all = args(None, None, true);
+        all.client = None;
+        let out = server.clear_session_internal(all).await.unwrap();
+        assert_eq!(out.removed, vec![reviewer]);
+        assert!(store.get(&other_key).unwrap().is_some());
+    }
+
+    #[test]
+    fn validate_patch_tool_checks_applicable_and_inapplicable_patches() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        let run = |args: &[&str]| {
+            let out = Command::new("git")
+                .args(args)
+                .current_dir(&repo)
+                .output()
+                .unwrap();
+            assert!(out.status.success(), "git {args:?} failed");
+        };
+        run(&["init"]);
+        std::fs::write(repo.join("hello.txt"), "hi\n").unwrap();
+        run(&["add", "hello.txt"]);
+        run(&[
+            "-c",
+            "user.email=test@example.com",
+            "-c",
+            "user.name=test",
+            "-c",
+            "commit.gpgsign=false",
+            "commit",
+            "-m",
+            "init",
+        ]);
+
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(ConfigLoader::new(None), store);
+        let check = |patch: &str| {
+            server
+                .validate_patch_internal(ValidatePatchArgs {
+                    cd: repo.to_string_lossy().to_string(),
+                    patch: patch.to_string(),
+                })
+                .unwrap()
+        };
+
+        let good = check(
+            "diff --git a/hello.txt b/hello.txt\n--- a/hello.txt\n+++ b/hello.txt\n@@ -1 +1 @@\n-hi\n+hello\n",
+        );
+        assert!(good.ok, "output={}", good.output);
+        assert_eq!(good.format, "unifieddiff");
+
+        let bad = check(
+            "diff --git a/hello.txt b/hello.txt\n--- a/hello.txt\n+++ b/hello.txt\n@@ -1 +1 @@\n-bye\n+hello\n",
+        );
+        assert!(!bad.ok);
+        assert!(bad.output.contains("hello.txt"), "output={}", bad.output);
+
+        let not_diff = check("just some text");
+        assert!(!not_diff.ok);
+        assert_eq!(not_diff.format, "unknown");
+    }
+
+    #[tokio::test]
+    async fn contract_patch_validation_runs_git_apply_check() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let run = |args: &[&str]| {
+            let out = Command::new("git")
+                .args(args)
+                .current_dir(&repo)
+                .output()
+                .unwrap();
+            assert!(
+                out.status.success(),
+                "git {:?} failed: {}{}",
+                args,
+                String::from_utf8_lossy(&out.stdout),
+                String::from_utf8_lossy(&out.stderr)
+            );
+            out
+        };
+
+        run(&["init"]);
+        std::fs::write(repo.join("hello.txt"), "hi\n").unwrap();
+        run(&["add", "hello.txt"]);
+        run(&[
+            "-c",
+            "user.email=test@example.com",
+            "-c",
+            "user.name=test",
+            "-c",
+            "commit.gpgsign=false",
+            "commit",
+            "-m",
+            "init",
+        ]);
+
+        let store_path = td.path().join("sessions.json");
+        let store = SessionStore::new(store_path);
+        let cfg_path = td.path().join("config.json");
+        write_codex_test_config(&cfg_path);
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+
+        let agent_text = "CITATIONS:\n- hello.txt:1\n\nPATCH:\n```diff\ndiff --git a/hello.txt b/hello.txt\n--- a/hello.txt\n+++ b/hello.txt\n@@ -1 +1 @@\n-hi\n+hello\n```\n";
+        let agent_text_json = agent_text
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+
+        let script = format!(
+            "#!/bin/sh\nset -e\nprintf '%s\\n' '{{\"type\":\"thread.started\",\"thread_id\":\"sess-p\"}}'\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"{}\"}}}}'\n",
             agent_text_json
         );
         {
-            let mut f = std::fs::File::create(bin).unwrap();
+            let mut f = std::fs::File::create(&fake).unwrap();
             f.write_all(script.as_bytes()).unwrap();
         }
         #[cfg(unix)]
         {
             use std::os::unix::fs::PermissionsExt;
-            let mut perms = std::fs::metadata(bin).unwrap().permissions();
+            let mut perms = std::fs::metadata(&fake).unwrap().permissions();
             perms.set_mode(0o755);
-            std::fs::set_permissions(bin, perms).unwrap();
-        }
-    }
-
-    fn read_log(path: &Path) -> String {
-        std::fs::read_to_string(path).unwrap_or_default()
-    }
-
-    fn read_log_args(path: &Path) -> Vec<String> {
-        let raw = std::fs::read(path).unwrap_or_default();
-        raw.split(|b| *b == 0)
-            .filter(|chunk| !chunk.is_empty())
-            .map(|chunk| String::from_utf8_lossy(chunk).to_string())
-            .collect()
-    }
-
-    fn write_codex_test_config(path: &Path) {
-        let cfg = r#"{
-  "backend": {
-    "codex": {
-      "models": {
-        "gpt-5.2-codex": {
-          "options": { "model_reasoning_effort": "high" },
-          "variants": { "xhigh": { "model_reasoning_effort": "xhigh" } }
+            std::fs::set_permissions(&fake, perms).unwrap();
         }
-      }
-    }
-  },
-  "roles": {
-    "oracle": {
-      "model": "codex/gpt-5.2-codex@xhigh",
-      "personas": { "description": "d", "prompt": "p" },
-      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
-    }
-  }
-}"#;
-        std::fs::write(path, cfg).unwrap();
-    }
-
-    fn write_codex_test_config_with_model(path: &Path, model_id: &str) {
-        let cfg = format!(
-            r#"{{
-  "backend": {{
-    "codex": {{
-      "models": {{
-        "{model_id}": {{ "options": {{}} }}
-      }}
-    }}
-  }},
-  "roles": {{
-    "oracle": {{
-      "model": "codex/{model_id}",
-      "personas": {{ "description": "d", "prompt": "p" }},
-      "capabilities": {{ "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }}
-    }}
-  }}
-}}"#,
-            model_id = model_id
-        );
-        std::fs::write(path, cfg).unwrap();
-    }
-
-    fn write_batch_codex_config(path: &Path) {
-        let cfg = r#"{
-  "backend": {
-    "codex": {
-      "models": {
-        "gpt-5.2-codex": { "options": {} }
-      }
-    }
-  },
-  "roles": {
-    "oracle": {
-      "model": "codex/gpt-5.2-codex",
-      "personas": { "description": "d", "prompt": "p" },
-      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
-    },
-    "builder": {
-      "model": "codex/gpt-5.2-codex",
-      "personas": { "description": "d", "prompt": "p" },
-      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
-    }
-  }
-}"#;
-        std::fs::write(path, cfg).unwrap();
-    }
-
-    fn write_batch_kimi_config(path: &Path) {
-        let cfg = r#"{
-  "backend": {
-    "kimi": { "models": {} }
-  },
-  "roles": {
-    "kimi_a": {
-      "model": "kimi/default",
-      "personas": { "description": "d", "prompt": "p" },
-      "capabilities": { "filesystem": "read-write", "shell": "deny", "network": "deny", "tools": ["read"] }
-    },
-    "kimi_b": {
-      "model": "kimi/default",
-      "personas": { "description": "d", "prompt": "p" },
-      "capabilities": { "filesystem": "read-write", "shell": "deny", "network": "deny", "tools": ["read"] }
-    }
-  }
-}"#;
-        std::fs::write(path, cfg).unwrap();
-    }
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
 
-    fn write_codex_prompt_transport_config(path: &Path, max_chars: usize) {
-        let cfg = format!(
-            r#"{{
-  "backend": {{
-    "codex": {{
-      "adapter": {{
-        "args_template": [
-          "exec",
-          "{{% if not session_id and model != 'default' %}}--model{{% endif %}}",
-          "{{% if not session_id and model != 'default' %}}{{{{ model }}}}{{% endif %}}",
-          "--skip-git-repo-check",
-          "{{% if not session_id %}}-C{{% endif %}}",
-          "{{% if not session_id %}}{{{{ workdir }}}}{{% endif %}}",
-          "--json",
-          "{{% if session_id %}}resume{{% endif %}}",
-          "{{% if session_id %}}{{{{ session_id }}}}{{% endif %}}",
-          "{{% if prompt %}}{{{{ prompt }}}}{{% endif %}}"
-        ],
-        "output_parser": {{
-          "type": "json_stream",
-          "session_id_path": "thread_id",
-          "message_path": "item.text",
-          "pick": "last"
-        }},
-        "prompt_transport": "auto",
-        "prompt_max_chars": {max_chars}
-      }},
-      "models": {{
-        "gpt-5.2-codex": {{}}
-      }}
-    }}
-  }},
-  "roles": {{
-    "oracle": {{
-      "model": "codex/gpt-5.2-codex",
-      "personas": {{ "description": "d", "prompt": "p" }},
-      "capabilities": {{ "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }}
-    }}
-  }}
-}}"#,
-            max_chars = max_chars
-        );
-        std::fs::write(path, cfg).unwrap();
-    }
+        let out = server
+            .run_vibe_internal(
+                None,
+                VibeArgs {
+                    prompt: "do".to_string(),
+                    cd: repo.to_string_lossy().to_string(),
+                    role: Some("oracle".to_string()),
+                    backend: None,
+                    model: None,
+                    reasoning_effort: None,
+                    session_id: None,
+                    force_new_session: true,
+                    session_key: None,
+                    timeout_secs: Some(5),
+                    contract: Some(OutputContract::PatchWithCitations),
+                    validate_patch: true,
+                    strict_citations: false,
+                    contract_output: None,
+                    output_parser: None,
+                    canonicalize_cd: None,
+                    extract_code_lang: None,
+                    n: None,
+                    client: None,
 
-    fn write_codex_fallback_config(path: &Path) {
-        let cfg = r#"{
-  "backend": {
-    "codex": {
-      "fallback": {
-        "model": "codex/gpt-5.2",
-        "patterns": ["model_not_found"]
-      },
-      "models": {
-        "gpt-5.2-codex": { "options": {} },
-        "gpt-5.2": { "options": {} }
-      }
-    }
-  },
-  "roles": {
-    "oracle": {
-      "model": "codex/gpt-5.2-codex",
-      "personas": { "description": "d", "prompt": "p" },
-      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
-    }
-  }
-}"#;
-        std::fs::write(path, cfg).unwrap();
-    }
+                    conversation_id: None,
+                    participant: None,
+                    session_group: None,
+                    ephemeral: false,
+                    dry_run: false,
+                    persona_override: None,
+                    stream: false,
+                    prompt_file: None,
+                    prompt_transport: None,
+                    include_directories: None,
+                    cache: false,
+                },
+            )
+            .await
+            .unwrap();
 
-    fn write_codex_fallback_pattern_config(path: &Path) {
-        let cfg = r#"{
-  "backend": {
-    "codex": {
-      "fallback": {
-        "model": "codex/gpt-5.2",
-        "patterns": ["custom_not_found"]
-      },
-      "models": {
-        "gpt-5.2-codex": { "options": {} },
-        "gpt-5.2": { "options": {} }
-      }
-    }
-  },
-  "roles": {
-    "oracle": {
-      "model": "codex/gpt-5.2-codex",
-      "personas": { "description": "d", "prompt": "p" },
-      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
-    }
-  }
-}"#;
-        std::fs::write(path, cfg).unwrap();
+        assert_eq!(out.success, true, "error={:?}", out.error);
+        assert_eq!(out.patch_apply_check_ok, Some(true));
     }
 
-    fn write_fake_cli_with_arg_log(bin: &Path, log: &Path, session_id: &str) {
-        let script = format!(
-            "#!/bin/sh\nset -e\n\nprintf '%s\\0' \"$@\" > \"{}\"\n\nprintf '%s\\n' '{{\"type\":\"thread.started\",\"thread_id\":\"{}\"}}'\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"ok\"}}}}'\n",
-            log.display(),
-            session_id
-        );
-        {
-            let mut f = std::fs::File::create(bin).unwrap();
-            f.write_all(script.as_bytes()).unwrap();
-        }
+    #[tokio::test]
+    async fn batch_returns_partial_results() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store_path = td.path().join("sessions.json");
+        let store = SessionStore::new(store_path);
+
+        let cfg_path = td.path().join("config.json");
+        write_batch_codex_config(&cfg_path);
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let script = r#"#!/bin/sh
+set -e
+
+if echo "$@" | grep -q 'FAIL'; then
+  echo "boom" 1>&2
+  exit 1
+fi
+
+echo '{"type":"thread.started","thread_id":"sess-1"}'
+echo '{"type":"item.completed","item":{"type":"agent_message","text":"ok"}}'
+"#;
+        std::fs::write(&fake, script).unwrap();
         #[cfg(unix)]
         {
             use std::os::unix::fs::PermissionsExt;
-            let mut perms = std::fs::metadata(bin).unwrap().permissions();
+            let mut perms = std::fs::metadata(&fake).unwrap().permissions();
             perms.set_mode(0o755);
-            std::fs::set_permissions(bin, perms).unwrap();
+            std::fs::set_permissions(&fake, perms).unwrap();
         }
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let out = server
+            .run_batch_internal(
+                None,
+                BatchArgs {
+                    cd: repo.to_string_lossy().to_string(),
+                    timeout_secs: Some(5),
+                    client: None,
+                    tasks: vec![
+                        BatchTask {
+                            prompt: "ok".to_string(),
+                            role: Some("oracle".to_string()),
+                            backend: None,
+                            model: None,
+                            reasoning_effort: None,
+                            session_id: None,
+                            force_new_session: false,
+                            session_key: None,
+                            session_group: None,
+                            timeout_secs: None,
+                            contract: None,
+                            validate_patch: false,
+                            strict_citations: false,
+                            stream: false,
+                            prompt_file: None,
+                            cache: false,
+                            name: Some("one".to_string()),
+                        },
+                        BatchTask {
+                            prompt: "FAIL".to_string(),
+                            role: Some("builder".to_string()),
+                            backend: None,
+                            model: None,
+                            reasoning_effort: None,
+                            session_id: None,
+                            force_new_session: false,
+                            session_key: None,
+                            session_group: None,
+                            timeout_secs: None,
+                            contract: None,
+                            validate_patch: false,
+                            strict_citations: false,
+                            stream: false,
+                            prompt_file: None,
+                            cache: false,
+                            name: Some("two".to_string()),
+                        },
+                    ],
+
+                    conversation_id: None,
+                    retry_budget: None,
+                    max_concurrency: None,
+                    stagger_ms: 0,
+                    retry_failed: None,
+                    mode: BatchMode::Parallel,
+                    stop_on_error: false,
+                    overall_timeout_secs: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        assert!(!out.success);
+        assert_eq!(out.results.len(), 2);
+        assert!(out
+            .results
+            .iter()
+            .any(|r| r.output.as_ref().map(|o| o.success).unwrap_or(false)));
+        assert!(out.results.iter().any(|r| {
+            r.output.as_ref().map(|o| !o.success).unwrap_or(false) || r.error.is_some()
+        }));
     }
 
-    fn write_fake_cli_with_arg_and_stdin_log(
-        bin: &Path,
-        args_log: &Path,
-        stdin_log: &Path,
-        session_id: &str,
-    ) {
-        let script = format!(
-            "#!/bin/sh\nset -e\n\ncat - > \"{}\"\nprintf '%s\\0' \"$@\" > \"{}\"\n\nprintf '%s\\n' '{{\"type\":\"thread.started\",\"thread_id\":\"{}\"}}'\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"ok\"}}}}'\n",
-            stdin_log.display(),
-            args_log.display(),
-            session_id
-        );
-        {
-            let mut f = std::fs::File::create(bin).unwrap();
-            f.write_all(script.as_bytes()).unwrap();
-        }
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let mut perms = std::fs::metadata(bin).unwrap().permissions();
-            perms.set_mode(0o755);
-            std::fs::set_permissions(bin, perms).unwrap();
-        }
+    #[tokio::test]
+    async fn batch_reports_invalid_tasks_and_runs_valid_ones() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_batch_codex_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        // Appends one ARGS line per call; no task uses the failing model.
+        write_fake_cli_with_model_error_once(&fake, &log, "no-such-model");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let task = |name: &str, prompt: &str, role: &str| BatchTask {
+            prompt: prompt.to_string(),
+            role: Some(role.to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            session_id: None,
+            force_new_session: true,
+            session_key: None,
+            session_group: None,
+            timeout_secs: None,
+            contract: None,
+            validate_patch: false,
+            strict_citations: false,
+            stream: false,
+            prompt_file: None,
+            cache: false,
+            name: Some(name.to_string()),
+        };
+        let out = server
+            .run_batch_internal(
+                None,
+                BatchArgs {
+                    cd: repo.to_string_lossy().to_string(),
+                    timeout_secs: Some(5),
+                    client: None,
+                    tasks: vec![
+                        task("blank", "  \n\t ", "oracle"),
+                        task("valid", "ping", "oracle"),
+                        task("unknown", "ping", "nobody"),
+                    ],
+                    conversation_id: None,
+                    retry_budget: None,
+                    max_concurrency: None,
+                    stagger_ms: 0,
+                    retry_failed: None,
+                    mode: BatchMode::Parallel,
+                    stop_on_error: false,
+                    overall_timeout_secs: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        assert!(!out.success);
+        let names: Vec<_> = out.results.iter().map(|r| r.name.as_deref()).collect();
+        assert_eq!(names, [Some("blank"), Some("valid"), Some("unknown")]);
+        let by_name = |name: &str| {
+            out.results
+                .iter()
+                .find(|r| r.name.as_deref() == Some(name))
+                .unwrap()
+        };
+        let blank = by_name("blank");
+        assert!(blank.output.is_none());
+        assert!(blank
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("invalid task: PROMPT is empty"));
+        let unknown = by_name("unknown");
+        assert!(unknown
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("unknown role profile: nobody"));
+        let valid = by_name("valid");
+        assert!(valid.error.is_none());
+        assert!(valid.output.as_ref().unwrap().success);
+        assert_eq!(logged_calls(&log).len(), 1);
     }
 
-    fn write_fake_cli_with_model_error_once(bin: &Path, log: &Path, bad_model: &str) {
-        let script = format!(
-            "#!/bin/sh\nset -e\n\nprintf '%s ' \"$@\" >> \"{}\"\nprintf '\\n' >> \"{}\"\n\nif echo \"$@\" | grep -q '{}'; then\n  printf '%s\\n' '{{\"type\":\"error\",\"message\":\"model_not_found\"}}'\n  exit 0\nfi\n\nprintf '%s\\n' '{{\"type\":\"thread.started\",\"thread_id\":\"sess-1\"}}'\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"ok\"}}}}'\n",
-            log.display(),
-            log.display(),
-            bad_model
-        );
+    #[tokio::test]
+    async fn batch_overall_timeout_stops_running_tasks() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_batch_codex_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let script = "#!/bin/sh\nset -e\ncase \"$*\" in *slow*) sleep 30 ;; esac\necho '{\"type\":\"thread.started\",\"thread_id\":\"sess-1\"}'\necho '{\"type\":\"item.completed\",\"item\":{\"type\":\"agent_message\",\"text\":\"ok\"}}'\n";
         {
-            let mut f = std::fs::File::create(bin).unwrap();
+            let mut f = std::fs::File::create(&fake).unwrap();
             f.write_all(script.as_bytes()).unwrap();
         }
         #[cfg(unix)]
         {
             use std::os::unix::fs::PermissionsExt;
-            let mut perms = std::fs::metadata(bin).unwrap().permissions();
+            let mut perms = std::fs::metadata(&fake).unwrap().permissions();
             perms.set_mode(0o755);
-            std::fs::set_permissions(bin, perms).unwrap();
+            std::fs::set_permissions(&fake, perms).unwrap();
+        }
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let task = |name: &str, prompt: &str| BatchTask {
+            prompt: prompt.to_string(),
+            role: Some("oracle".to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            session_id: None,
+            force_new_session: true,
+            session_key: Some(name.to_string()),
+            session_group: None,
+            timeout_secs: None,
+            contract: None,
+            validate_patch: false,
+            strict_citations: false,
+            stream: false,
+            prompt_file: None,
+            cache: false,
+            name: Some(name.to_string()),
+        };
+        for mode in [BatchMode::Parallel, BatchMode::Sequential] {
+            let started = std::time::Instant::now();
+            let out = server
+                .run_batch_internal(
+                    None,
+                    BatchArgs {
+                        cd: repo.to_string_lossy().to_string(),
+                        timeout_secs: Some(60),
+                        client: None,
+                        tasks: vec![
+                            task("fast", "ping"),
+                            task("slow", "slow ping"),
+                            task("after", "ping"),
+                        ],
+                        conversation_id: None,
+                        retry_budget: None,
+                        max_concurrency: Some(2),
+                        stagger_ms: 0,
+                        retry_failed: Some(1),
+                        mode,
+                        stop_on_error: false,
+                        overall_timeout_secs: Some(1),
+                    },
+                )
+                .await
+                .unwrap();
+            assert!(started.elapsed() < std::time::Duration::from_secs(10));
+            assert!(!out.success);
+            assert!(out.retry_rounds.is_empty());
+            assert!(out.results[0].error.is_none(), "{mode:?}");
+            // In parallel mode `after` takes the slot `fast` frees and finishes in time; in
+            // sequential mode it is still queued behind `slow` when the deadline hits.
+            let stopped = match mode {
+                BatchMode::Parallel => &out.results[1..2],
+                BatchMode::Sequential => &out.results[1..],
+            };
+            for r in stopped {
+                assert!(
+                    r.error
+                        .as_deref()
+                        .unwrap_or_default()
+                        .contains("batch deadline exceeded"),
+                    "{mode:?}: {:?}",
+                    r.error
+                );
+            }
+            if mode == BatchMode::Parallel {
+                assert!(out.results[2].error.is_none(), "{:?}", out.results[2].error);
+            }
         }
     }
 
-    fn write_fake_cli_with_custom_model_error_once(
-        bin: &Path,
-        log: &Path,
-        bad_model: &str,
-        message: &str,
-    ) {
-        let msg = message
-            .replace('\\', "\\\\")
-            .replace('"', "\\\"")
-            .replace('\n', "\\n");
+    #[tokio::test]
+    async fn batch_retry_failed_reruns_only_failed_tasks() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let cfg_path = td.path().join("config.json");
+        write_batch_codex_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        // The "flaky" task fails until its attempt marker exists; the other task always succeeds.
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        let marker = td.path().join("flaky.attempted");
         let script = format!(
-            "#!/bin/sh\nset -e\n\nprintf '%s ' \"$@\" >> \"{}\"\nprintf '\\n' >> \"{}\"\n\nif echo \"$@\" | grep -q '{}'; then\n  printf '%s\\n' '{{\"type\":\"error\",\"message\":\"{}\"}}'\n  exit 0\nfi\n\nprintf '%s\\n' '{{\"type\":\"thread.started\",\"thread_id\":\"sess-1\"}}'\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"ok\"}}}}'\n",
-            log.display(),
-            log.display(),
-            bad_model,
-            msg
+            "#!/bin/sh\nprintf '%s\\n' \"$*\" >> \"{log}\"\ncase \"$*\" in\n  *flaky*)\n    if [ ! -f \"{marker}\" ]; then\n      touch \"{marker}\"\n      echo 'transient failure' >&2\n      exit 1\n    fi\n    ;;\nesac\nprintf '%s\\n' '{{\"type\":\"thread.started\",\"thread_id\":\"sess-1\"}}'\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"ok\"}}}}'\n",
+            log = log.display(),
+            marker = marker.display(),
         );
-        {
-            let mut f = std::fs::File::create(bin).unwrap();
-            f.write_all(script.as_bytes()).unwrap();
-        }
+        std::fs::write(&fake, script).unwrap();
         #[cfg(unix)]
         {
             use std::os::unix::fs::PermissionsExt;
-            let mut perms = std::fs::metadata(bin).unwrap().permissions();
-            perms.set_mode(0o755);
-            std::fs::set_permissions(bin, perms).unwrap();
+            std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
         }
-    }
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let task = |name: &str, prompt: &str| BatchTask {
+            prompt: prompt.to_string(),
+            role: Some("oracle".to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            session_id: None,
+            force_new_session: true,
+            session_key: Some(name.to_string()),
+            session_group: None,
+            timeout_secs: None,
+            contract: None,
+            validate_patch: false,
+            strict_citations: false,
+            stream: false,
+            prompt_file: None,
+            cache: false,
+            name: Some(name.to_string()),
+        };
+        let out = server
+            .run_batch_internal(
+                None,
+                BatchArgs {
+                    cd: repo.to_string_lossy().to_string(),
+                    timeout_secs: Some(5),
+                    client: None,
+                    tasks: vec![task("steady", "steady ping"), task("flaky", "flaky ping")],
+                    conversation_id: None,
+                    retry_budget: None,
+                    max_concurrency: None,
+                    stagger_ms: 0,
+                    retry_failed: Some(2),
+                    mode: BatchMode::Parallel,
+                    stop_on_error: false,
+                    overall_timeout_secs: None,
+                },
+            )
+            .await
+            .unwrap();
 
-    fn codex_loader(cfg_path: &Path) -> ConfigLoader {
-        ConfigLoader::new(Some(cfg_path.to_path_buf()))
+        assert!(out.success, "{:?}", out.results);
+        assert!(out.error.is_none());
+        assert_eq!(out.results.len(), 2);
+        assert!(out.results.iter().all(|r| r.error.is_none()));
+        // One round was enough, and it re-ran only the flaky task.
+        assert_eq!(out.retry_rounds.len(), 1);
+        assert_eq!(out.retry_rounds[0].round, 1);
+        assert_eq!(out.retry_rounds[0].retried, 1);
+        assert_eq!(out.retry_rounds[0].recovered, 1);
+        let calls = read_log(&log);
+        assert_eq!(calls.lines().filter(|l| l.contains("steady")).count(), 1);
+        assert_eq!(calls.lines().filter(|l| l.contains("flaky")).count(), 2);
     }
 
     #[tokio::test]
-    async fn client_config_prefers_client_specific_file() {
+    async fn batch_warns_tasks_sharing_a_session_key() {
         let td = tempfile::tempdir().unwrap();
         let repo = td.path().join("repo");
         std::fs::create_dir_all(&repo).unwrap();
 
-        let store = SessionStore::new(td.path().join("sessions.json"));
-
         let cfg_path = td.path().join("config.json");
-        write_codex_test_config_with_model(&cfg_path, "gpt-5.2-codex");
-        let cfg_client = td.path().join("config-claude.json");
-        write_codex_test_config_with_model(&cfg_client, "gpt-5.3-codex");
-
+        write_batch_codex_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
         let server = VibeServer::new(codex_loader(&cfg_path), store);
 
         let fake = td.path().join("fake-codex.sh");
         let log = td.path().join("codex.log");
-        write_fake_cli(&fake, &log, "sess-cfg-1", "pong");
+        write_fake_cli(&fake, &log, "sess-1", "ok");
         let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
 
+        let task = |name: &str, session_key: Option<&str>| BatchTask {
+            prompt: "ping".to_string(),
+            role: Some("oracle".to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            session_id: None,
+            force_new_session: true,
+            session_key: session_key.map(str::to_string),
+            session_group: None,
+            timeout_secs: None,
+            contract: None,
+            validate_patch: false,
+            strict_citations: false,
+            stream: false,
+            prompt_file: None,
+            cache: false,
+            name: Some(name.to_string()),
+        };
         let out = server
-            .run_vibe_internal(
+            .run_batch_internal(
                 None,
-                VibeArgs {
-                    prompt: "ping".to_string(),
+                BatchArgs {
                     cd: repo.to_string_lossy().to_string(),
-                    role: Some("oracle".to_string()),
-                    backend: None,
-                    model: None,
-                    reasoning_effort: None,
-                    session_id: None,
-                    force_new_session: true,
-                    session_key: None,
                     timeout_secs: Some(5),
-                    contract: None,
-                    validate_patch: false,
-                    client: Some("claude".to_string()),
-
-                    conversation_id: None,
+                    client: None,
+                    tasks: vec![
+                        task("first", None),
+                        task("second", None),
+                        task("own", Some("own-key")),
+                    ],
+                    conversation_id: Some("chat-1".to_string()),
+                    retry_budget: None,
+                    // One task at a time: the warning is computed up front, and the shared key's
+                    // lock is then never contended.
+                    max_concurrency: Some(1),
+                    stagger_ms: 0,
+                    retry_failed: None,
+                    mode: BatchMode::Parallel,
+                    stop_on_error: false,
+                    overall_timeout_secs: None,
                 },
             )
             .await
             .unwrap();
 
-        assert_eq!(out.model.as_deref(), Some("gpt-5.3-codex"));
+        assert!(out.success, "{:?}", out.results);
+        let warnings_of = |name: &str| {
+            out.results
+                .iter()
+                .find(|r| r.name.as_deref() == Some(name))
+                .and_then(|r| r.output.as_ref())
+                .and_then(|o| o.warnings.clone())
+                .unwrap_or_default()
+        };
+        assert!(
+            warnings_of("first").contains("session_key shared with second"),
+            "{}",
+            warnings_of("first")
+        );
+        assert!(warnings_of("second").contains("session_key shared with first"));
+        assert!(!warnings_of("own").contains("session_key shared"));
     }
 
     #[tokio::test]
-    async fn session_reuse_uses_stored_backend_session_id() {
+    async fn batch_sequential_runs_in_order_and_stops_on_error() {
         let td = tempfile::tempdir().unwrap();
         let repo = td.path().join("repo");
         std::fs::create_dir_all(&repo).unwrap();
 
-        let store_path = td.path().join("sessions.json");
-        let store = SessionStore::new(store_path);
         let cfg_path = td.path().join("config.json");
-        write_codex_test_config(&cfg_path);
-        let server = VibeServer::new(codex_loader(&cfg_path), store.clone());
+        write_batch_codex_config(&cfg_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
 
         let fake = td.path().join("fake-codex.sh");
         let log = td.path().join("codex.log");
         let script = format!(
-            "#!/bin/sh\nset -e\n\n# append args each invocation\necho \"ARGS: $@\" >> \"{}\"\n\nif echo \"$@\" | grep -q 'resume sess-1'; then\n  sid='sess-2'\nelse\n  sid='sess-1'\nfi\n\necho '{{\"type\":\"thread.started\",\"thread_id\":\"'\"$sid\"'\"}}'\necho '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"ok\"}}}}'\n",
+            r#"#!/bin/sh
+set -e
+echo "$@" | grep -o 'step-[a-z]*' | head -n 1 >> "{}"
+if echo "$@" | grep -q 'step-fail'; then
+  echo "boom" 1>&2
+  exit 1
+fi
+echo '{{"type":"thread.started","thread_id":"sess-1"}}'
+echo '{{"type":"item.completed","item":{{"type":"agent_message","text":"ok"}}}}'
+"#,
             log.display()
         );
-        {
-            let mut f = std::fs::File::create(&fake).unwrap();
-            f.write_all(script.as_bytes()).unwrap();
-        }
+        std::fs::write(&fake, script).unwrap();
         #[cfg(unix)]
         {
             use std::os::unix::fs::PermissionsExt;
@@ -1950,471 +9646,875 @@ mod tests {
         }
         let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
 
-        let args1 = VibeArgs {
-            prompt: "first".to_string(),
-            cd: repo.to_string_lossy().to_string(),
+        let task = |prompt: &str| BatchTask {
+            prompt: prompt.to_string(),
             role: Some("oracle".to_string()),
             backend: None,
             model: None,
             reasoning_effort: None,
             session_id: None,
-            force_new_session: false,
+            force_new_session: true,
             session_key: None,
-            timeout_secs: Some(5),
+            session_group: None,
+            timeout_secs: None,
             contract: None,
             validate_patch: false,
-            client: None,
-
-            conversation_id: None,
+            strict_citations: false,
+            stream: false,
+            prompt_file: None,
+            cache: false,
+            name: Some(prompt.to_string()),
         };
-        let out1 = server.run_vibe_internal(None, args1).await.unwrap();
-        assert_eq!(out1.success, true);
-        assert_eq!(out1.resumed, false);
-        assert_eq!(out1.backend_session_id, "sess-1");
+        for stop_on_error in [true, false] {
+            let _ = std::fs::remove_file(&log);
+            let out = server
+                .run_batch_internal(
+                    None,
+                    BatchArgs {
+                        cd: repo.to_string_lossy().to_string(),
+                        timeout_secs: Some(5),
+                        client: None,
+                        tasks: vec![task("step-one"), task("step-fail"), task("step-three")],
+                        conversation_id: None,
+                        retry_budget: None,
+                        max_concurrency: None,
+                        stagger_ms: 0,
+                        retry_failed: None,
+                        mode: BatchMode::Sequential,
+                        stop_on_error,
+                        overall_timeout_secs: None,
+                    },
+                )
+                .await
+                .unwrap();
 
-        let args2 = VibeArgs {
-            prompt: "second".to_string(),
-            cd: repo.to_string_lossy().to_string(),
-            role: Some("oracle".to_string()),
+            assert!(!out.success);
+            let names: Vec<&str> = out
+                .results
+                .iter()
+                .filter_map(|r| r.name.as_deref())
+                .collect();
+            if stop_on_error {
+                assert_eq!(names, vec!["step-one", "step-fail"]);
+                assert_eq!(read_log(&log), "step-one\nstep-fail\n");
+                assert!(
+                    out.warnings.iter().any(|w| w.contains("1 task(s)")),
+                    "{:?}",
+                    out.warnings
+                );
+            } else {
+                assert_eq!(names, vec!["step-one", "step-fail", "step-three"]);
+                assert_eq!(read_log(&log), "step-one\nstep-fail\nstep-three\n");
+                assert!(out.warnings.is_empty(), "{:?}", out.warnings);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_rejects_multiple_kimi_resume_tasks() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store_path = td.path().join("sessions.json");
+        let store = SessionStore::new(store_path);
+
+        let cfg_path = td.path().join("config.json");
+        write_batch_kimi_config(&cfg_path);
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store.clone());
+
+        let key_a = SessionStore::compute_key(&repo.canonicalize().unwrap(), "kimi_a", "kimi_a");
+        let key_b = SessionStore::compute_key(&repo.canonicalize().unwrap(), "kimi_b", "kimi_b");
+        store
+            .put(
+                &key_a,
+                SessionRecord {
+                    repo_root: repo.to_string_lossy().to_string(),
+                    role: "kimi_a".to_string(),
+                    role_id: "kimi_a".to_string(),
+                    backend: Backend::Kimi,
+                    backend_session_id: "stateless".to_string(),
+                    model: None,
+                    sampling_history: Vec::new(),
+                    updated_at_unix_secs: now_unix_secs(),
+                },
+            )
+            .unwrap();
+        store
+            .put(
+                &key_b,
+                SessionRecord {
+                    repo_root: repo.to_string_lossy().to_string(),
+                    role: "kimi_b".to_string(),
+                    role_id: "kimi_b".to_string(),
+                    backend: Backend::Kimi,
+                    backend_session_id: "stateless".to_string(),
+                    model: None,
+                    sampling_history: Vec::new(),
+                    updated_at_unix_secs: now_unix_secs(),
+                },
+            )
+            .unwrap();
+
+        let err = server
+            .run_batch_internal(
+                None,
+                BatchArgs {
+                    cd: repo.to_string_lossy().to_string(),
+                    timeout_secs: Some(5),
+                    client: None,
+                    tasks: vec![
+                        BatchTask {
+                            prompt: "a".to_string(),
+                            role: Some("kimi_a".to_string()),
+                            backend: None,
+                            model: None,
+                            reasoning_effort: None,
+                            session_id: None,
+                            force_new_session: false,
+                            session_key: None,
+                            session_group: None,
+                            timeout_secs: None,
+                            contract: None,
+                            validate_patch: false,
+                            strict_citations: false,
+                            stream: false,
+                            prompt_file: None,
+                            cache: false,
+                            name: None,
+                        },
+                        BatchTask {
+                            prompt: "b".to_string(),
+                            role: Some("kimi_b".to_string()),
+                            backend: None,
+                            model: None,
+                            reasoning_effort: None,
+                            session_id: None,
+                            force_new_session: false,
+                            session_key: None,
+                            session_group: None,
+                            timeout_secs: None,
+                            contract: None,
+                            validate_patch: false,
+                            strict_citations: false,
+                            stream: false,
+                            prompt_file: None,
+                            cache: false,
+                            name: None,
+                        },
+                    ],
+
+                    conversation_id: None,
+                    retry_budget: None,
+                    max_concurrency: None,
+                    stagger_ms: 0,
+                    retry_failed: None,
+                    mode: BatchMode::Parallel,
+                    stop_on_error: false,
+                    overall_timeout_secs: None,
+                },
+            )
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("kimi"));
+    }
+
+    #[tokio::test]
+    async fn batch_ignores_expired_kimi_records_when_ttl_set() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store = SessionStore::new(td.path().join("sessions.json")).with_ttl_secs(3600);
+        let cfg_path = td.path().join("config.json");
+        write_batch_kimi_config(&cfg_path);
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store.clone());
+
+        let repo_root = repo.canonicalize().unwrap();
+        for role in ["kimi_a", "kimi_b"] {
+            store
+                .put(
+                    &SessionStore::compute_key(&repo_root, role, role),
+                    SessionRecord {
+                        repo_root: repo_root.to_string_lossy().to_string(),
+                        role: role.to_string(),
+                        role_id: role.to_string(),
+                        backend: Backend::Kimi,
+                        backend_session_id: "stateless".to_string(),
+                        model: None,
+                        sampling_history: Vec::new(),
+                        updated_at_unix_secs: now_unix_secs() - 7200,
+                    },
+                )
+                .unwrap();
+        }
+
+        let fake = td.path().join("fake-kimi.sh");
+        let log = td.path().join("kimi.log");
+        write_fake_cli_with_arg_log(&fake, &log, "unused");
+        let _env = crate::test_utils::scoped_kimi_bin(fake.to_string_lossy().as_ref());
+
+        let task = |role: &str| BatchTask {
+            prompt: "go".to_string(),
+            role: Some(role.to_string()),
             backend: None,
             model: None,
             reasoning_effort: None,
             session_id: None,
             force_new_session: false,
             session_key: None,
-            timeout_secs: Some(5),
+            session_group: None,
+            timeout_secs: None,
             contract: None,
             validate_patch: false,
-            client: None,
-
-            conversation_id: None,
+            strict_citations: false,
+            stream: false,
+            prompt_file: None,
+            cache: false,
+            name: None,
         };
-        let out2 = server.run_vibe_internal(None, args2).await.unwrap();
-        assert_eq!(out2.success, true);
-        assert_eq!(out2.resumed, true);
-        assert_eq!(out2.backend_session_id, "sess-2");
-
-        let log_txt = std::fs::read_to_string(&log).unwrap();
-        assert!(log_txt.lines().any(|l| l.contains("resume sess-1")));
+        let out = server
+            .run_batch_internal(
+                None,
+                BatchArgs {
+                    cd: repo.to_string_lossy().to_string(),
+                    timeout_secs: Some(5),
+                    client: None,
+                    tasks: vec![task("kimi_a"), task("kimi_b")],
+                    conversation_id: None,
+                    retry_budget: None,
+                    max_concurrency: None,
+                    stagger_ms: 0,
+                    retry_failed: None,
+                    mode: BatchMode::Parallel,
+                    stop_on_error: false,
+                    overall_timeout_secs: None,
+                },
+            )
+            .await
+            .unwrap();
 
-        let role_id = "oracle";
-        let key = SessionStore::compute_key(&repo.canonicalize().unwrap(), role_id, role_id);
-        let rec = store.get(&key).unwrap().unwrap();
-        assert_eq!(rec.backend_session_id, "sess-2");
+        assert!(out.success, "error={:?}", out.error);
+        assert!(out
+            .results
+            .iter()
+            .all(|r| r.output.as_ref().is_some_and(|o| !o.resumed)));
+        assert!(!read_log_args(&log).contains(&"--continue".to_string()));
     }
 
     #[tokio::test]
-    async fn session_resume_skips_persona_injection() {
+    async fn batch_serializes_multiple_kimi_resume_tasks_when_configured() {
         let td = tempfile::tempdir().unwrap();
         let repo = td.path().join("repo");
         std::fs::create_dir_all(&repo).unwrap();
 
-        let store_path = td.path().join("sessions.json");
-        let store = SessionStore::new(store_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
         let cfg_path = td.path().join("config.json");
-        write_codex_test_config(&cfg_path);
-        let server = VibeServer::new(codex_loader(&cfg_path), store.clone());
+        write_batch_kimi_config(&cfg_path);
+        let cfg = std::fs::read_to_string(&cfg_path).unwrap().replace(
+            r#""kimi": { "models": {} }"#,
+            r#""kimi": { "models": {}, "serialize_instead_of_reject": true }"#,
+        );
+        std::fs::write(&cfg_path, cfg).unwrap();
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store.clone());
 
-        let fake_first = td.path().join("fake-codex-first.sh");
-        let log_first = td.path().join("codex-first.log");
-        write_fake_cli_with_arg_log(&fake_first, &log_first, "sess-1");
-        {
-            let _env = crate::test_utils::scoped_codex_bin(fake_first.to_string_lossy().as_ref());
-            let out1 = server
-                .run_vibe_internal(
-                    None,
-                    VibeArgs {
-                        prompt: "first".to_string(),
-                        cd: repo.to_string_lossy().to_string(),
-                        role: Some("oracle".to_string()),
-                        backend: None,
+        let repo_root = repo.canonicalize().unwrap();
+        for role in ["kimi_a", "kimi_b"] {
+            store
+                .put(
+                    &SessionStore::compute_key(&repo_root, role, role),
+                    SessionRecord {
+                        repo_root: repo_root.to_string_lossy().to_string(),
+                        role: role.to_string(),
+                        role_id: role.to_string(),
+                        backend: Backend::Kimi,
+                        backend_session_id: "stateless".to_string(),
                         model: None,
-                        reasoning_effort: None,
-                        session_id: None,
-                        force_new_session: false,
-                        session_key: None,
-                        timeout_secs: Some(5),
-                        contract: None,
-                        validate_patch: false,
-                        client: None,
-
-                        conversation_id: None,
+                        sampling_history: Vec::new(),
+                        updated_at_unix_secs: now_unix_secs(),
                     },
                 )
-                .await
                 .unwrap();
-            assert!(!out1.resumed);
         }
 
-        let fake_second = td.path().join("fake-codex-second.sh");
-        let log_second = td.path().join("codex-second.log");
-        write_fake_cli_with_arg_log(&fake_second, &log_second, "sess-2");
+        let fake = td.path().join("fake-kimi.sh");
+        let log = td.path().join("kimi.log");
+        let script = format!(
+            r#"#!/bin/sh
+echo "start" >> "{log}"
+sleep 0.3
+echo "end" >> "{log}"
+echo "done"
+"#,
+            log = log.display()
+        );
+        std::fs::write(&fake, script).unwrap();
+        #[cfg(unix)]
         {
-            let _env = crate::test_utils::scoped_codex_bin(fake_second.to_string_lossy().as_ref());
-            let out2 = server
-                .run_vibe_internal(
-                    None,
-                    VibeArgs {
-                        prompt: "second".to_string(),
-                        cd: repo.to_string_lossy().to_string(),
-                        role: Some("oracle".to_string()),
-                        backend: None,
-                        model: None,
-                        reasoning_effort: None,
-                        session_id: None,
-                        force_new_session: false,
-                        session_key: None,
-                        timeout_secs: Some(5),
-                        contract: None,
-                        validate_patch: false,
-                        client: None,
-
-                        conversation_id: None,
-                    },
-                )
-                .await
-                .unwrap();
-            assert!(out2.resumed);
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(&fake).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(&fake, perms).unwrap();
         }
+        let _env = crate::test_utils::scoped_kimi_bin(fake.to_string_lossy().as_ref());
 
-        let args = read_log_args(&log_second);
-        let prompt_arg = args.last().cloned().unwrap_or_default();
-        assert!(prompt_arg.contains("second"));
-        assert!(
-            !prompt_arg.contains("[ROUNDTABLE_PERSONA"),
-            "prompt should not include persona on resume: {prompt_arg}"
-        );
+        let task = |role: &str| BatchTask {
+            prompt: "go".to_string(),
+            role: Some(role.to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            session_id: None,
+            force_new_session: false,
+            session_key: None,
+            session_group: None,
+            timeout_secs: None,
+            contract: None,
+            validate_patch: false,
+            strict_citations: false,
+            stream: false,
+            prompt_file: None,
+            cache: false,
+            name: None,
+        };
+        let out = server
+            .run_batch_internal(
+                None,
+                BatchArgs {
+                    cd: repo.to_string_lossy().to_string(),
+                    timeout_secs: Some(5),
+                    client: None,
+                    tasks: vec![task("kimi_a"), task("kimi_b")],
+                    conversation_id: None,
+                    retry_budget: None,
+                    max_concurrency: None,
+                    stagger_ms: 0,
+                    retry_failed: None,
+                    mode: BatchMode::Parallel,
+                    stop_on_error: false,
+                    overall_timeout_secs: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        assert!(out.success, "error={:?}", out.error);
+        assert_eq!(out.results.len(), 2);
+        assert!(out
+            .results
+            .iter()
+            .all(|r| r.error.is_none() && r.output.as_ref().is_some_and(|o| o.success)));
+        let events: Vec<String> = std::fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        assert_eq!(events, vec!["start", "end", "start", "end"]);
     }
 
     #[tokio::test]
-    async fn force_new_session_ignores_session_id_and_warns() {
+    async fn batch_max_concurrency_queues_extra_tasks() {
         let td = tempfile::tempdir().unwrap();
         let repo = td.path().join("repo");
         std::fs::create_dir_all(&repo).unwrap();
 
-        let store_path = td.path().join("sessions.json");
-        let store = SessionStore::new(store_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
         let cfg_path = td.path().join("config.json");
-        write_codex_test_config(&cfg_path);
-        let server = VibeServer::new(codex_loader(&cfg_path), store);
+        write_batch_kimi_config(&cfg_path);
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store);
 
-        let fake = td.path().join("fake-codex.sh");
-        let log = td.path().join("codex.log");
-        write_fake_cli_with_arg_log(&fake, &log, "sess-new");
-        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+        let fake = td.path().join("fake-kimi.sh");
+        let log = td.path().join("kimi.log");
+        let script = format!(
+            r#"#!/bin/sh
+echo "start" >> "{log}"
+sleep 0.2
+echo "end" >> "{log}"
+echo "done"
+"#,
+            log = log.display()
+        );
+        std::fs::write(&fake, script).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(&fake).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(&fake, perms).unwrap();
+        }
+        let _env = crate::test_utils::scoped_kimi_bin(fake.to_string_lossy().as_ref());
 
+        let task = |role: &str| BatchTask {
+            prompt: "go".to_string(),
+            role: Some(role.to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            session_id: None,
+            force_new_session: true,
+            session_key: None,
+            session_group: None,
+            timeout_secs: None,
+            contract: None,
+            validate_patch: false,
+            strict_citations: false,
+            stream: false,
+            prompt_file: None,
+            cache: false,
+            name: None,
+        };
         let out = server
-            .run_vibe_internal(
+            .run_batch_internal(
                 None,
-                VibeArgs {
-                    prompt: "fresh".to_string(),
+                BatchArgs {
                     cd: repo.to_string_lossy().to_string(),
-                    role: Some("oracle".to_string()),
-                    backend: None,
-                    model: None,
-                    reasoning_effort: None,
-                    session_id: Some("sess-123".to_string()),
-                    force_new_session: true,
-                    session_key: None,
                     timeout_secs: Some(5),
-                    contract: None,
-                    validate_patch: false,
                     client: None,
-
+                    tasks: vec![task("kimi_a"), task("kimi_b"), task("kimi_a")],
                     conversation_id: None,
+                    retry_budget: None,
+                    max_concurrency: Some(1),
+                    stagger_ms: 0,
+                    retry_failed: None,
+                    mode: BatchMode::Parallel,
+                    stop_on_error: false,
+                    overall_timeout_secs: None,
                 },
             )
             .await
             .unwrap();
 
-        assert!(!out.resumed);
-        let warn = out.warnings.unwrap_or_default();
-        assert!(warn.contains("force_new_session=true"));
-        assert!(warn.contains("sess-123"));
-
-        let args = read_log_args(&log);
-        assert!(!args.iter().any(|v| v == "resume"), "args={args:?}");
+        assert!(out.success, "error={:?}", out.error);
+        assert_eq!(out.results.len(), 3);
+        let events: Vec<String> = std::fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        assert_eq!(events, vec!["start", "end", "start", "end", "start", "end"]);
     }
 
     #[tokio::test]
-    async fn codex_auto_prompt_uses_stdin_for_long_prompt() {
+    async fn batch_stagger_ms_delays_each_task_start() {
         let td = tempfile::tempdir().unwrap();
         let repo = td.path().join("repo");
         std::fs::create_dir_all(&repo).unwrap();
 
-        let cfg_path = td.path().join("config.json");
-        write_codex_prompt_transport_config(&cfg_path, 4);
         let store = SessionStore::new(td.path().join("sessions.json"));
-        let server = VibeServer::new(codex_loader(&cfg_path), store);
+        let cfg_path = td.path().join("config.json");
+        write_batch_kimi_config(&cfg_path);
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store);
 
-        let fake = td.path().join("fake-codex.sh");
-        let args_log = td.path().join("codex-args.log");
-        let stdin_log = td.path().join("codex-stdin.log");
-        write_fake_cli_with_arg_and_stdin_log(&fake, &args_log, &stdin_log, "sess-1");
-        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+        let fake = td.path().join("fake-kimi.sh");
+        std::fs::write(&fake, "#!/bin/sh\necho \"done\"\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = std::fs::metadata(&fake).unwrap().permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(&fake, perms).unwrap();
+        }
+        let _env = crate::test_utils::scoped_kimi_bin(fake.to_string_lossy().as_ref());
 
-        let long_prompt = "12345".to_string();
-        server
-            .run_vibe_internal(
+        let task = |role: &str| BatchTask {
+            prompt: "go".to_string(),
+            role: Some(role.to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            session_id: None,
+            force_new_session: true,
+            session_key: None,
+            session_group: None,
+            timeout_secs: None,
+            contract: None,
+            validate_patch: false,
+            strict_citations: false,
+            stream: false,
+            prompt_file: None,
+            cache: false,
+            name: None,
+        };
+        let started = std::time::Instant::now();
+        let out = server
+            .run_batch_internal(
                 None,
-                VibeArgs {
-                    prompt: long_prompt.clone(),
+                BatchArgs {
                     cd: repo.to_string_lossy().to_string(),
-                    role: Some("oracle".to_string()),
-                    backend: None,
-                    model: None,
-                    reasoning_effort: None,
-                    session_id: None,
-                    force_new_session: true,
-                    session_key: None,
                     timeout_secs: Some(5),
-                    contract: None,
-                    validate_patch: false,
                     client: None,
-
+                    tasks: vec![task("kimi_a"), task("kimi_b"), task("kimi_a")],
                     conversation_id: None,
+                    retry_budget: None,
+                    max_concurrency: None,
+                    stagger_ms: 300,
+                    retry_failed: None,
+                    mode: BatchMode::Parallel,
+                    stop_on_error: false,
+                    overall_timeout_secs: None,
                 },
             )
             .await
             .unwrap();
 
-        let args = read_log_args(&args_log);
-        assert!(
-            !args.iter().any(|v| v.contains(&long_prompt)),
-            "prompt should not be passed as argv when auto uses stdin: {args:?}"
-        );
-        let stdin_text = read_log(&stdin_log);
-        assert!(
-            stdin_text.contains(&long_prompt),
-            "prompt should be passed via stdin when auto triggers"
-        );
+        assert!(out.success, "error={:?}", out.error);
+        assert_eq!(out.results.len(), 3);
+        // The last task waits 2 * 300 ms before it starts.
+        assert!(started.elapsed() >= std::time::Duration::from_millis(600));
     }
 
     #[tokio::test]
-    async fn model_fallback_uses_next_model_on_error() {
+    async fn backend_max_concurrency_serializes_only_that_backend() {
         let td = tempfile::tempdir().unwrap();
         let repo = td.path().join("repo");
         std::fs::create_dir_all(&repo).unwrap();
 
         let cfg_path = td.path().join("config.json");
-        write_codex_fallback_config(&cfg_path);
+        let cfg = r#"{
+  "backend": {
+    "kimi": { "models": {}, "max_concurrency": 1 },
+    "codex": { "models": { "gpt-5.2-codex": { "options": {} } } }
+  },
+  "roles": {
+    "kimi_a": {
+      "model": "kimi/default",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-write", "shell": "deny", "network": "deny", "tools": ["read"] }
+    },
+    "oracle": {
+      "model": "codex/gpt-5.2-codex",
+      "personas": { "description": "d", "prompt": "p" },
+      "capabilities": { "filesystem": "read-only", "shell": "deny", "network": "deny", "tools": ["read"] }
+    }
+  }
+}"#;
+        std::fs::write(&cfg_path, cfg).unwrap();
         let store = SessionStore::new(td.path().join("sessions.json"));
-        let server = VibeServer::new(codex_loader(&cfg_path), store);
+        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store);
 
-        let fake = td.path().join("fake-codex.sh");
-        let log = td.path().join("codex-model.log");
-        write_fake_cli_with_model_error_once(&fake, &log, "gpt-5.2-codex");
-        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+        let log = td.path().join("events.log");
+        let fake_kimi = td.path().join("fake-kimi.sh");
+        let fake_codex = td.path().join("fake-codex.sh");
+        // Kimi holds its slot briefly so an uncapped second task would overlap it; the codex
+        // tasks only finish once both have started, which fails them if they were serialized.
+        let kimi_script = format!(
+            r#"#!/bin/sh
+echo "kimi start" >> "{log}"
+sleep 0.3
+echo "kimi end" >> "{log}"
+echo "done"
+"#,
+            log = log.display()
+        );
+        let codex_script = format!(
+            r#"#!/bin/sh
+echo "codex start" >> "{log}"
+i=0
+until [ "$(grep -c "codex start" "{log}")" -ge 2 ]; do
+  i=$((i + 1))
+  [ "$i" -gt 200 ] && exit 1
+  sleep 0.05
+done
+echo "codex end" >> "{log}"
+echo '{{"type":"thread.started","thread_id":"sess-1"}}'
+echo '{{"type":"item.completed","item":{{"type":"agent_message","text":"ok"}}}}'
+"#,
+            log = log.display()
+        );
+        for (bin, script) in [(&fake_kimi, kimi_script), (&fake_codex, codex_script)] {
+            std::fs::write(bin, script).unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mut perms = std::fs::metadata(bin).unwrap().permissions();
+                perms.set_mode(0o755);
+                std::fs::set_permissions(bin, perms).unwrap();
+            }
+        }
+        let _codex = crate::test_utils::scoped_codex_bin(fake_codex.to_string_lossy().as_ref());
+        let _kimi = crate::test_utils::scoped_kimi_bin(fake_kimi.to_string_lossy().as_ref());
 
+        // Distinct session keys, so only the backend cap can serialize the kimi tasks.
+        let task = |role: &str, key: &str| BatchTask {
+            prompt: "go".to_string(),
+            role: Some(role.to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            session_id: None,
+            force_new_session: true,
+            session_key: Some(key.to_string()),
+            session_group: None,
+            timeout_secs: None,
+            contract: None,
+            validate_patch: false,
+            strict_citations: false,
+            stream: false,
+            prompt_file: None,
+            cache: false,
+            name: None,
+        };
         let out = server
-            .run_vibe_internal(
+            .run_batch_internal(
                 None,
-                VibeArgs {
-                    prompt: "ping".to_string(),
+                BatchArgs {
                     cd: repo.to_string_lossy().to_string(),
-                    role: Some("oracle".to_string()),
-                    backend: None,
-                    model: None,
-                    reasoning_effort: None,
-                    session_id: None,
-                    force_new_session: true,
-                    session_key: None,
                     timeout_secs: Some(5),
-                    contract: None,
-                    validate_patch: false,
                     client: None,
-
+                    tasks: vec![
+                        task("kimi_a", "k1"),
+                        task("kimi_a", "k2"),
+                        task("oracle", "o1"),
+                        task("oracle", "o2"),
+                    ],
                     conversation_id: None,
+                    retry_budget: None,
+                    max_concurrency: None,
+                    stagger_ms: 0,
+                    retry_failed: None,
+                    mode: BatchMode::Parallel,
+                    stop_on_error: false,
+                    overall_timeout_secs: None,
                 },
             )
             .await
             .unwrap();
 
-        let warn = out.warnings.unwrap_or_default();
-        assert!(warn.contains("model fallback used"));
-        assert!(warn.contains("gpt-5.2"));
-
-        let log_text = read_log(&log);
-        assert!(log_text.contains("gpt-5.2-codex"));
-        assert!(log_text.contains("gpt-5.2"));
+        assert!(out.success, "error={:?}", out.error);
+        let events: Vec<String> = std::fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        let only = |prefix: &str| -> Vec<String> {
+            events
+                .iter()
+                .filter(|e| e.starts_with(prefix))
+                .cloned()
+                .collect()
+        };
+        assert_eq!(
+            only("kimi"),
+            vec!["kimi start", "kimi end", "kimi start", "kimi end"]
+        );
+        assert_eq!(
+            only("codex"),
+            vec!["codex start", "codex start", "codex end", "codex end"]
+        );
     }
 
     #[tokio::test]
-    async fn model_fallback_uses_configured_error_patterns() {
+    async fn batch_retry_budget_bounds_fallback_invocations() {
         let td = tempfile::tempdir().unwrap();
         let repo = td.path().join("repo");
         std::fs::create_dir_all(&repo).unwrap();
 
         let cfg_path = td.path().join("config.json");
-        write_codex_fallback_pattern_config(&cfg_path);
+        write_codex_fallback_config(&cfg_path);
         let store = SessionStore::new(td.path().join("sessions.json"));
         let server = VibeServer::new(codex_loader(&cfg_path), store);
 
+        // Both the primary and the fallback model fail, so every task wants one fallback.
         let fake = td.path().join("fake-codex.sh");
         let log = td.path().join("codex-model.log");
-        write_fake_cli_with_custom_model_error_once(
-            &fake,
-            &log,
-            "gpt-5.2-codex",
-            "custom_not_found",
-        );
+        write_fake_cli_with_model_error_once(&fake, &log, "gpt-5.2");
         let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
 
+        let task = |name: &str| BatchTask {
+            prompt: "ping".to_string(),
+            name: Some(name.to_string()),
+            role: Some("oracle".to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            session_id: None,
+            force_new_session: true,
+            session_key: Some(name.to_string()),
+            session_group: None,
+            timeout_secs: None,
+            contract: None,
+            validate_patch: false,
+            strict_citations: false,
+            stream: false,
+            prompt_file: None,
+            cache: false,
+        };
         let out = server
-            .run_vibe_internal(
+            .run_batch_internal(
                 None,
-                VibeArgs {
-                    prompt: "ping".to_string(),
+                BatchArgs {
                     cd: repo.to_string_lossy().to_string(),
-                    role: Some("oracle".to_string()),
-                    backend: None,
-                    model: None,
-                    reasoning_effort: None,
-                    session_id: None,
-                    force_new_session: true,
-                    session_key: None,
+                    tasks: vec![task("a"), task("b"), task("c")],
                     timeout_secs: Some(5),
-                    contract: None,
-                    validate_patch: false,
                     client: None,
-
                     conversation_id: None,
+                    retry_budget: Some(1),
+                    max_concurrency: None,
+                    stagger_ms: 0,
+                    retry_failed: None,
+                    mode: BatchMode::Parallel,
+                    stop_on_error: false,
+                    overall_timeout_secs: None,
                 },
             )
             .await
             .unwrap();
 
-        let warn = out.warnings.unwrap_or_default();
-        assert!(warn.contains("model fallback used"));
-        assert!(warn.contains("codex/gpt-5.2"));
+        assert!(!out.success);
+        // Three primary attempts plus a single budgeted fallback.
+        assert_eq!(logged_calls(&log).len(), 4);
+        assert!(
+            out.warnings
+                .iter()
+                .any(|w| w.contains("retry budget exhausted")),
+            "warnings={:?}",
+            out.warnings
+        );
+        let exhausted = out
+            .results
+            .iter()
+            .filter(|r| {
+                r.error
+                    .as_deref()
+                    .unwrap_or("")
+                    .contains("retry budget exhausted")
+            })
+            .count();
+        assert_eq!(exhausted, 2);
     }
 
     #[tokio::test]
-    async fn info_includes_enabled_flag() {
+    async fn roundtable_participants_sharing_role_get_distinct_sessions() {
         let td = tempfile::tempdir().unwrap();
         let repo = td.path().join("repo");
         std::fs::create_dir_all(&repo).unwrap();
 
+        let store = SessionStore::new(td.path().join("sessions.json"));
         let cfg_path = td.path().join("config.json");
-        std::fs::write(
-            &cfg_path,
-            r#"{
-  "backend": {
-    "codex": {
-      "models": { "gpt-5.2": {} }
-    }
-  },
-  "roles": {
-    "oracle": {
-      "model": "codex/gpt-5.2",
-      "enabled": false,
-      "personas": { "description": "d", "prompt": "p" },
-      "capabilities": { "filesystem": "read-only" }
-    },
-    "reader": {
-      "model": "codex/gpt-5.2",
-      "personas": { "description": "d2", "prompt": "p2" },
-      "capabilities": { "filesystem": "read-only" }
-    }
-  }
-}"#,
-        )
-        .unwrap();
+        write_batch_codex_config(&cfg_path);
+        let server = VibeServer::new(codex_loader(&cfg_path), store.clone());
 
-        let store = SessionStore::new(td.path().join("sessions.json"));
-        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store);
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        write_fake_cli(&fake, &log, "sess-rt", "pong");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
 
+        let participant = |name: &str| RoundtableParticipant {
+            name: name.to_string(),
+            role: Some("oracle".to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            force_new_session: true,
+            session_group: None,
+        };
         let out = server
-            .info(Parameters(InfoArgs {
-                cd: repo.to_string_lossy().to_string(),
-                client: None,
-            }))
+            .run_roundtable_internal(
+                None,
+                RoundtableArgs {
+                    topic: "t".to_string(),
+                    cd: repo.to_string_lossy().to_string(),
+                    participants: vec![participant("alice"), participant("bob")],
+                    timeout_secs: Some(5),
+                    client: None,
+                    conversation_id: None,
+                    background: false,
+                    max_concurrency: None,
+                    stagger_ms: 0,
+                    moderator: None,
+                    format: RoundtableFormat::Structured,
+                },
+            )
             .await
             .unwrap();
+        assert!(out.success, "error={:?}", out.error);
 
-        let content = out
-            .content
-            .first()
-            .and_then(|c| c.as_text())
-            .map(|t| t.text.clone())
-            .unwrap_or_default();
-        let v: serde_json::Value = serde_json::from_str(&content).unwrap();
-        let roles = v["roles"].as_array().unwrap();
-        let mut found = false;
-        for role in roles {
-            if role["role"] == "oracle" {
-                found = true;
-                assert_eq!(role["enabled"], false);
-            }
-        }
-        assert!(found, "role list should include disabled roles");
+        let repo_root = repo.canonicalize().unwrap();
+        let key_for = |scope: &str| {
+            SessionStore::compute_key_with_scope(&repo_root, scope, "oracle", None, None)
+        };
+        assert_ne!(key_for("oracle#alice"), key_for("oracle#bob"));
+        assert!(store.get(&key_for("oracle#alice")).unwrap().is_some());
+        assert!(store.get(&key_for("oracle#bob")).unwrap().is_some());
+        assert!(store.get(&key_for("oracle")).unwrap().is_none());
     }
 
     #[tokio::test]
-    async fn adapter_renders_options_and_capabilities() {
+    async fn roundtable_transcript_joins_contributions_with_inline_errors() {
         let td = tempfile::tempdir().unwrap();
         let repo = td.path().join("repo");
         std::fs::create_dir_all(&repo).unwrap();
 
-        let store_path = td.path().join("sessions.json");
-        let store = SessionStore::new(store_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
         let cfg_path = td.path().join("config.json");
-        write_codex_test_config(&cfg_path);
+        write_batch_codex_config(&cfg_path);
         let server = VibeServer::new(codex_loader(&cfg_path), store);
 
+        // Bob's call fails with a backend error event; alice gets the canned "ok".
         let fake = td.path().join("fake-codex.sh");
         let log = td.path().join("codex.log");
-        write_fake_cli(&fake, &log, "sess-cfg-1", "pong");
+        write_fake_cli_with_model_error_once(&fake, &log, "bob");
         let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
 
-        let out = server
-            .run_vibe_internal(
-                None,
-                VibeArgs {
-                    prompt: "ping".to_string(),
-                    cd: repo.to_string_lossy().to_string(),
-                    role: Some("oracle".to_string()),
-                    backend: None,
-                    model: None,
-                    reasoning_effort: None,
-                    session_id: None,
-                    force_new_session: true,
-                    session_key: None,
-                    timeout_secs: Some(5),
-                    contract: None,
-                    validate_patch: false,
-                    client: None,
+        let participant = |name: &str, role: &str| RoundtableParticipant {
+            name: name.to_string(),
+            role: Some(role.to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            force_new_session: true,
+            session_group: None,
+        };
+        let args = |format| RoundtableArgs {
+            topic: "t".to_string(),
+            cd: repo.to_string_lossy().to_string(),
+            participants: vec![
+                participant("alice", "oracle"),
+                participant("bob", "builder"),
+            ],
+            timeout_secs: Some(5),
+            client: None,
+            conversation_id: None,
+            background: false,
+            max_concurrency: None,
+            stagger_ms: 0,
+            moderator: None,
+            format,
+        };
 
-                    conversation_id: None,
-                },
-            )
+        let out = server
+            .run_roundtable_internal(None, args(RoundtableFormat::Transcript))
             .await
             .unwrap();
+        let transcript = out.transcript.expect("transcript");
+        let (alice, bob) = transcript.split_once("\n\n").unwrap();
+        assert_eq!(alice, "### alice (oracle/codex)\nok");
+        assert!(bob.starts_with("### bob (builder/"), "{bob}");
+        assert!(bob.contains(")\n[error: "), "{bob}");
+        assert!(bob.contains("backend failed"), "{bob}");
 
-        assert!(out.success, "error={:?}", out.error);
-        assert_eq!(out.backend, "codex");
-        assert!(out.agent_messages.contains("pong"));
-
-        let log_txt = read_log(&log);
-        assert!(log_txt.contains("--model gpt-5.2-codex"));
-        assert!(log_txt.contains("model_reasoning_effort=xhigh"));
-        assert!(!log_txt.contains("--dangerously-bypass-approvals-and-sandbox"));
+        let out = server
+            .run_roundtable_internal(None, args(RoundtableFormat::Structured))
+            .await
+            .unwrap();
+        assert!(out.transcript.is_none());
     }
 
     #[tokio::test]
-    async fn contract_patch_with_citations_fails_when_missing() {
+    async fn roundtable_contributions_follow_participant_order() {
         let td = tempfile::tempdir().unwrap();
         let repo = td.path().join("repo");
         std::fs::create_dir_all(&repo).unwrap();
 
-        let store_path = td.path().join("sessions.json");
-        let store = SessionStore::new(store_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
         let cfg_path = td.path().join("config.json");
-        write_codex_test_config(&cfg_path);
+        write_batch_codex_config(&cfg_path);
         let server = VibeServer::new(codex_loader(&cfg_path), store);
 
+        // The first participant finishes last.
         let fake = td.path().join("fake-codex.sh");
-        let script = "#!/bin/sh\nset -e\necho '{\"type\":\"thread.started\",\"thread_id\":\"sess-x\"}'\necho '{\"type\":\"item.completed\",\"item\":{\"type\":\"agent_message\",\"text\":\"no patch here\"}}'\n";
+        let script = "#!/bin/sh\nset -e\ncase \"$*\" in *alice*) sleep 1 ;; esac\necho '{\"type\":\"thread.started\",\"thread_id\":\"sess-rt\"}'\necho '{\"type\":\"item.completed\",\"item\":{\"type\":\"agent_message\",\"text\":\"ok\"}}'\n";
         {
             let mut f = std::fs::File::create(&fake).unwrap();
             f.write_all(script.as_bytes()).unwrap();
@@ -2428,92 +10528,74 @@ mod tests {
         }
         let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
 
+        let participant = |name: &str| RoundtableParticipant {
+            name: name.to_string(),
+            role: Some("oracle".to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            force_new_session: true,
+            session_group: None,
+        };
         let out = server
-            .run_vibe_internal(
+            .run_roundtable_internal(
                 None,
-                VibeArgs {
-                    prompt: "do".to_string(),
+                RoundtableArgs {
+                    topic: "t".to_string(),
                     cd: repo.to_string_lossy().to_string(),
-                    role: Some("oracle".to_string()),
-                    backend: None,
-                    model: None,
-                    reasoning_effort: None,
-                    session_id: None,
-                    force_new_session: true,
-                    session_key: None,
+                    participants: vec![participant("alice"), participant("bob")],
                     timeout_secs: Some(5),
-                    contract: Some(OutputContract::PatchWithCitations),
-                    validate_patch: false,
                     client: None,
-
                     conversation_id: None,
+                    background: false,
+                    max_concurrency: None,
+                    stagger_ms: 0,
+                    moderator: None,
+                    format: RoundtableFormat::Structured,
                 },
             )
             .await
             .unwrap();
-
-        assert_eq!(out.success, false);
-        assert!(out
-            .error
-            .as_deref()
-            .unwrap_or("")
-            .contains("output contract violation"));
+        assert!(out.success, "error={:?}", out.error);
+        let names: Vec<&str> = out.contributions.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["alice", "bob"]);
     }
 
     #[tokio::test]
-    async fn contract_patch_validation_runs_git_apply_check() {
+    async fn roundtable_session_group_shares_one_session() {
         let td = tempfile::tempdir().unwrap();
         let repo = td.path().join("repo");
         std::fs::create_dir_all(&repo).unwrap();
 
-        let run = |args: &[&str]| {
-            let out = Command::new("git")
-                .args(args)
-                .current_dir(&repo)
-                .output()
-                .unwrap();
-            assert!(
-                out.status.success(),
-                "git {:?} failed: {}{}",
-                args,
-                String::from_utf8_lossy(&out.stdout),
-                String::from_utf8_lossy(&out.stderr)
-            );
-            out
-        };
-
-        run(&["init"]);
-        std::fs::write(repo.join("hello.txt"), "hi\n").unwrap();
-        run(&["add", "hello.txt"]);
-        run(&[
-            "-c",
-            "user.email=test@example.com",
-            "-c",
-            "user.name=test",
-            "-c",
-            "commit.gpgsign=false",
-            "commit",
-            "-m",
-            "init",
-        ]);
-
-        let store_path = td.path().join("sessions.json");
-        let store = SessionStore::new(store_path);
+        let store = SessionStore::new(td.path().join("sessions.json"));
         let cfg_path = td.path().join("config.json");
-        write_codex_test_config(&cfg_path);
-        let server = VibeServer::new(codex_loader(&cfg_path), store);
+        write_batch_codex_config(&cfg_path);
+        let server = VibeServer::new(codex_loader(&cfg_path), store.clone());
+
+        let repo_root = repo.canonicalize().unwrap();
+        let group_key =
+            SessionStore::compute_key_with_scope(&repo_root, "group:g", "group:g", None, None);
+        store
+            .put(
+                &group_key,
+                SessionRecord {
+                    repo_root: repo_root.to_string_lossy().to_string(),
+                    role: "oracle".to_string(),
+                    role_id: "oracle".to_string(),
+                    backend: Backend::Codex,
+                    backend_session_id: "sess-shared".to_string(),
+                    model: None,
+                    sampling_history: Vec::new(),
+                    updated_at_unix_secs: now_unix_secs(),
+                },
+            )
+            .unwrap();
 
         let fake = td.path().join("fake-codex.sh");
-
-        let agent_text = "CITATIONS:\n- hello.txt:1\n\nPATCH:\n```diff\ndiff --git a/hello.txt b/hello.txt\n--- a/hello.txt\n+++ b/hello.txt\n@@ -1 +1 @@\n-hi\n+hello\n```\n";
-        let agent_text_json = agent_text
-            .replace('\\', "\\\\")
-            .replace('"', "\\\"")
-            .replace('\n', "\\n");
-
+        let log = td.path().join("codex.log");
         let script = format!(
-            "#!/bin/sh\nset -e\nprintf '%s\\n' '{{\"type\":\"thread.started\",\"thread_id\":\"sess-p\"}}'\nprintf '%s\\n' '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"{}\"}}}}'\n",
-            agent_text_json
+            "#!/bin/sh\nset -e\necho \"$@\" | head -n 1 >> \"{}\"\necho '{{\"type\":\"thread.started\",\"thread_id\":\"sess-shared\"}}'\necho '{{\"type\":\"item.completed\",\"item\":{{\"type\":\"agent_message\",\"text\":\"ok\"}}}}'\n",
+            log.display()
         );
         {
             let mut f = std::fs::File::create(&fake).unwrap();
@@ -2528,60 +10610,78 @@ mod tests {
         }
         let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
 
+        let participant = |name: &str, role: &str| RoundtableParticipant {
+            name: name.to_string(),
+            role: Some(role.to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            force_new_session: false,
+            session_group: Some("g".to_string()),
+        };
         let out = server
-            .run_vibe_internal(
+            .run_roundtable_internal(
                 None,
-                VibeArgs {
-                    prompt: "do".to_string(),
+                RoundtableArgs {
+                    topic: "t".to_string(),
                     cd: repo.to_string_lossy().to_string(),
-                    role: Some("oracle".to_string()),
-                    backend: None,
-                    model: None,
-                    reasoning_effort: None,
-                    session_id: None,
-                    force_new_session: true,
-                    session_key: None,
+                    participants: vec![
+                        participant("alice", "oracle"),
+                        participant("bob", "builder"),
+                    ],
                     timeout_secs: Some(5),
-                    contract: Some(OutputContract::PatchWithCitations),
-                    validate_patch: true,
                     client: None,
-
                     conversation_id: None,
+                    background: false,
+                    // One participant at a time, so the shared session's lock is never contended.
+                    max_concurrency: Some(1),
+                    stagger_ms: 0,
+                    moderator: None,
+                    format: RoundtableFormat::Structured,
                 },
             )
             .await
             .unwrap();
+        assert!(out.success, "error={:?}", out.error);
 
-        assert_eq!(out.success, true, "error={:?}", out.error);
-        assert_eq!(out.patch_apply_check_ok, Some(true));
+        let calls = read_log(&log);
+        let calls: Vec<&str> = calls.lines().collect();
+        assert_eq!(calls.len(), 2, "{calls:?}");
+        assert!(
+            calls.iter().all(|c| c.contains("resume sess-shared")),
+            "{calls:?}"
+        );
+        let key_for = |scope: &str, role: &str| {
+            SessionStore::compute_key_with_scope(&repo_root, scope, role, None, None)
+        };
+        assert!(store
+            .get(&key_for("oracle#alice", "oracle"))
+            .unwrap()
+            .is_none());
+        assert!(store
+            .get(&key_for("builder#bob", "builder"))
+            .unwrap()
+            .is_none());
     }
 
     #[tokio::test]
-    async fn batch_returns_partial_results() {
+    async fn background_roundtable_poll_reports_partial_then_complete() {
         let td = tempfile::tempdir().unwrap();
         let repo = td.path().join("repo");
         std::fs::create_dir_all(&repo).unwrap();
 
-        let store_path = td.path().join("sessions.json");
-        let store = SessionStore::new(store_path);
-
+        let store = SessionStore::new(td.path().join("sessions.json"));
         let cfg_path = td.path().join("config.json");
         write_batch_codex_config(&cfg_path);
         let server = VibeServer::new(codex_loader(&cfg_path), store);
 
+        // The participant named 'slow' sleeps so the first poll can observe a partial result.
         let fake = td.path().join("fake-codex.sh");
-        let script = r#"#!/bin/sh
-set -e
-
-if echo "$@" | grep -q 'FAIL'; then
-  echo "boom" 1>&2
-  exit 1
-fi
-
-echo '{"type":"thread.started","thread_id":"sess-1"}'
-echo '{"type":"item.completed","item":{"type":"agent_message","text":"ok"}}'
-"#;
-        std::fs::write(&fake, script).unwrap();
+        let script = "#!/bin/sh\nset -e\ncase \"$*\" in *\"named 'slow'\"*) sleep 2 ;; esac\necho '{\"type\":\"thread.started\",\"thread_id\":\"sess-bg\"}'\necho '{\"type\":\"item.completed\",\"item\":{\"type\":\"agent_message\",\"text\":\"ok\"}}'\n";
+        {
+            let mut f = std::fs::File::create(&fake).unwrap();
+            f.write_all(script.as_bytes()).unwrap();
+        }
         #[cfg(unix)]
         {
             use std::os::unix::fs::PermissionsExt;
@@ -2591,154 +10691,132 @@ echo '{"type":"item.completed","item":{"type":"agent_message","text":"ok"}}'
         }
         let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
 
-        let out = server
-            .run_batch_internal(
+        let participant = |name: &str, role: &str| RoundtableParticipant {
+            name: name.to_string(),
+            role: Some(role.to_string()),
+            backend: None,
+            model: None,
+            reasoning_effort: None,
+            force_new_session: true,
+            session_group: None,
+        };
+        let started = server
+            .start_roundtable_operation(
                 None,
-                BatchArgs {
+                RoundtableArgs {
+                    topic: "t".to_string(),
                     cd: repo.to_string_lossy().to_string(),
-                    timeout_secs: Some(5),
-                    client: None,
-                    tasks: vec![
-                        BatchTask {
-                            prompt: "ok".to_string(),
-                            role: Some("oracle".to_string()),
-                            backend: None,
-                            model: None,
-                            reasoning_effort: None,
-                            session_id: None,
-                            force_new_session: false,
-                            session_key: None,
-                            timeout_secs: None,
-                            contract: None,
-                            validate_patch: false,
-                            name: Some("one".to_string()),
-                        },
-                        BatchTask {
-                            prompt: "FAIL".to_string(),
-                            role: Some("builder".to_string()),
-                            backend: None,
-                            model: None,
-                            reasoning_effort: None,
-                            session_id: None,
-                            force_new_session: false,
-                            session_key: None,
-                            timeout_secs: None,
-                            contract: None,
-                            validate_patch: false,
-                            name: Some("two".to_string()),
-                        },
+                    participants: vec![
+                        participant("slow", "builder"),
+                        participant("fast", "oracle"),
                     ],
-
+                    timeout_secs: Some(10),
+                    client: None,
                     conversation_id: None,
+                    background: true,
+                    max_concurrency: None,
+                    stagger_ms: 0,
+                    moderator: None,
+                    format: RoundtableFormat::Structured,
                 },
+                None,
             )
-            .await
             .unwrap();
+        assert_eq!(started.participants, 2);
+
+        let poll = || {
+            server
+                .poll_roundtable_internal(RoundtablePollArgs {
+                    operation_id: started.operation_id.clone(),
+                })
+                .unwrap()
+        };
 
-        assert!(!out.success);
-        assert_eq!(out.results.len(), 2);
-        assert!(out
-            .results
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        let partial = loop {
+            let out = poll();
+            if out.completed >= 1 {
+                break out;
+            }
+            assert!(
+                std::time::Instant::now() < deadline,
+                "no contribution completed"
+            );
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        };
+        assert!(!partial.done);
+        assert_eq!(partial.completed, 1);
+        assert_eq!(partial.contributions[0].name, "fast");
+
+        let complete = loop {
+            let out = poll();
+            if out.done {
+                break out;
+            }
+            assert!(
+                std::time::Instant::now() < deadline,
+                "roundtable did not finish"
+            );
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        };
+        assert!(complete.success, "error={:?}", complete.error);
+        assert_eq!(complete.completed, 2);
+        assert_eq!(complete.total, 2);
+        // Participant order, not completion order.
+        let names: Vec<&str> = complete
+            .contributions
             .iter()
-            .any(|r| r.output.as_ref().map(|o| o.success).unwrap_or(false)));
-        assert!(out.results.iter().any(|r| {
-            r.output.as_ref().map(|o| !o.success).unwrap_or(false) || r.error.is_some()
-        }));
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["slow", "fast"]);
+
+        // Finished operations are released once polled.
+        assert!(server
+            .poll_roundtable_internal(RoundtablePollArgs {
+                operation_id: started.operation_id.clone(),
+            })
+            .is_err());
     }
 
-    #[tokio::test]
-    async fn batch_rejects_multiple_kimi_resume_tasks() {
+    #[test]
+    fn unpolled_finished_operations_expire() {
         let td = tempfile::tempdir().unwrap();
-        let repo = td.path().join("repo");
-        std::fs::create_dir_all(&repo).unwrap();
-
-        let store_path = td.path().join("sessions.json");
-        let store = SessionStore::new(store_path);
-
         let cfg_path = td.path().join("config.json");
-        write_batch_kimi_config(&cfg_path);
-        let server = VibeServer::new(ConfigLoader::new(Some(cfg_path)), store.clone());
-
-        let key_a = SessionStore::compute_key(&repo.canonicalize().unwrap(), "kimi_a", "kimi_a");
-        let key_b = SessionStore::compute_key(&repo.canonicalize().unwrap(), "kimi_b", "kimi_b");
-        store
-            .put(
-                &key_a,
-                SessionRecord {
-                    repo_root: repo.to_string_lossy().to_string(),
-                    role: "kimi_a".to_string(),
-                    role_id: "kimi_a".to_string(),
-                    backend: Backend::Kimi,
-                    backend_session_id: "stateless".to_string(),
-                    sampling_history: Vec::new(),
-                    updated_at_unix_secs: now_unix_secs(),
-                },
-            )
-            .unwrap();
-        store
-            .put(
-                &key_b,
-                SessionRecord {
-                    repo_root: repo.to_string_lossy().to_string(),
-                    role: "kimi_b".to_string(),
-                    role_id: "kimi_b".to_string(),
-                    backend: Backend::Kimi,
-                    backend_session_id: "stateless".to_string(),
-                    sampling_history: Vec::new(),
-                    updated_at_unix_secs: now_unix_secs(),
-                },
-            )
-            .unwrap();
-
-        let err = server
-            .run_batch_internal(
-                None,
-                BatchArgs {
-                    cd: repo.to_string_lossy().to_string(),
-                    timeout_secs: Some(5),
-                    client: None,
-                    tasks: vec![
-                        BatchTask {
-                            prompt: "a".to_string(),
-                            role: Some("kimi_a".to_string()),
-                            backend: None,
-                            model: None,
-                            reasoning_effort: None,
-                            session_id: None,
-                            force_new_session: false,
-                            session_key: None,
-                            timeout_secs: None,
-                            contract: None,
-                            validate_patch: false,
-                            name: None,
-                        },
-                        BatchTask {
-                            prompt: "b".to_string(),
-                            role: Some("kimi_b".to_string()),
-                            backend: None,
-                            model: None,
-                            reasoning_effort: None,
-                            session_id: None,
-                            force_new_session: false,
-                            session_key: None,
-                            timeout_secs: None,
-                            contract: None,
-                            validate_patch: false,
-                            name: None,
-                        },
-                    ],
-
-                    conversation_id: None,
-                },
-            )
-            .await
-            .unwrap_err();
+        write_batch_codex_config(&cfg_path);
+        let server = VibeServer::new(
+            codex_loader(&cfg_path),
+            SessionStore::new(td.path().join("sessions.json")),
+        );
+        let operation = |finished_at: Option<std::time::Instant>| RoundtableOperation {
+            topic: "t".to_string(),
+            cd: ".".to_string(),
+            total: 0,
+            contributions: Vec::new(),
+            done: finished_at.is_some(),
+            error: None,
+            format: RoundtableFormat::Structured,
+            finished_at,
+        };
+        let stale = std::time::Instant::now()
+            .checked_sub(FINISHED_OPERATION_TTL + std::time::Duration::from_secs(1));
+        {
+            let mut ops = server.operations.lock().unwrap();
+            ops.insert("stale".to_string(), operation(stale));
+            ops.insert("running".to_string(), operation(None));
+        }
+        let poll = |id: &str| {
+            server.poll_roundtable_internal(RoundtablePollArgs {
+                operation_id: id.to_string(),
+            })
+        };
 
-        assert!(err.to_string().contains("kimi"));
+        let err = poll("stale").unwrap_err();
+        assert!(err.message.contains("unknown operation_id"), "{err:?}");
+        assert!(!poll("running").unwrap().done);
     }
 
     #[test]
-    fn roundtable_args_rejects_moderator_field() {
+    fn roundtable_args_accepts_moderator_but_rejects_unknown_fields() {
         let raw = r#"{
   "TOPIC": "test",
   "cd": ".",
@@ -2747,14 +10825,95 @@ echo '{"type":"item.completed","item":{"type":"agent_message","text":"ok"}}'
   ],
   "moderator": {"role": "oracle"}
 }"#;
+        let args = serde_json::from_str::<RoundtableArgs>(raw).unwrap();
+        assert_eq!(args.moderator.unwrap().role, "oracle");
 
+        let raw = r#"{
+  "TOPIC": "test",
+  "participants": [],
+  "moderator": {"role": "oracle"},
+  "judge": {"role": "oracle"}
+}"#;
         let err = serde_json::from_str::<RoundtableArgs>(raw).unwrap_err();
         assert!(
-            err.to_string().contains("unknown field") && err.to_string().contains("moderator"),
+            err.to_string().contains("unknown field") && err.to_string().contains("judge"),
             "unexpected error: {err}"
         );
     }
 
+    #[tokio::test]
+    async fn roundtable_moderator_synthesizes_contributions() {
+        let td = tempfile::tempdir().unwrap();
+        let repo = td.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        let cfg_path = td.path().join("config.json");
+        write_batch_codex_config(&cfg_path);
+        let server = VibeServer::new(codex_loader(&cfg_path), store);
+
+        let fake = td.path().join("fake-codex.sh");
+        let log = td.path().join("codex.log");
+        write_fake_cli(&fake, &log, "sess-rt", "pong");
+        let _env = crate::test_utils::scoped_codex_bin(fake.to_string_lossy().as_ref());
+
+        let args = |moderator_role: &str| RoundtableArgs {
+            topic: "t".to_string(),
+            cd: repo.to_string_lossy().to_string(),
+            participants: vec![RoundtableParticipant {
+                name: "alice".to_string(),
+                role: Some("oracle".to_string()),
+                backend: None,
+                model: None,
+                reasoning_effort: None,
+                force_new_session: true,
+                session_group: None,
+            }],
+            timeout_secs: Some(5),
+            client: None,
+            conversation_id: None,
+            background: false,
+            max_concurrency: None,
+            format: RoundtableFormat::Structured,
+            stagger_ms: 0,
+            moderator: Some(RoundtableModerator {
+                role: moderator_role.to_string(),
+                backend: None,
+                model: None,
+                reasoning_effort: None,
+                force_new_session: true,
+                instructions: Some("Be brief.".to_string()),
+            }),
+        };
+
+        let out = server
+            .run_roundtable_internal(None, args("builder"))
+            .await
+            .unwrap();
+        assert!(out.success, "error={:?}", out.error);
+        assert_eq!(out.synthesis.as_deref(), Some("pong"));
+        let calls = read_log(&log);
+        assert!(calls.contains("--- alice (role: oracle) ---"), "{calls}");
+        assert!(calls.contains("Be brief."), "{calls}");
+
+        let out = server
+            .run_roundtable_internal(None, args("no-such-role"))
+            .await
+            .unwrap();
+        assert!(!out.success);
+        assert!(out.synthesis.is_none());
+        assert!(
+            out.error
+                .as_deref()
+                .unwrap_or_default()
+                .contains("moderator"),
+            "error={:?}",
+            out.error
+        );
+        assert_eq!(out.contributions.len(), 1);
+        assert!(out.contributions[0].error.is_none());
+    }
+
     #[tokio::test]
     async fn session_reuse_isolated_by_client() {
         let td = tempfile::tempdir().unwrap();
@@ -2788,6 +10947,7 @@ echo '{"type":"item.completed","item":{"type":"agent_message","text":"ok"}}'
                     role_id: "oracle".to_string(),
                     backend: Backend::Codex,
                     backend_session_id: "sess-prev".to_string(),
+                    model: None,
                     sampling_history: Vec::new(),
                     updated_at_unix_secs: now_unix_secs(),
                 },
@@ -2810,8 +10970,24 @@ echo '{"type":"item.completed","item":{"type":"agent_message","text":"ok"}}'
                     timeout_secs: Some(5),
                     contract: None,
                     validate_patch: false,
+                    strict_citations: false,
+                    contract_output: None,
+                    output_parser: None,
+                    canonicalize_cd: None,
+                    extract_code_lang: None,
+                    n: None,
                     client: Some("codex".to_string()),
                     conversation_id: None,
+                    participant: None,
+                    session_group: None,
+                    ephemeral: false,
+                    dry_run: false,
+                    persona_override: None,
+                    stream: false,
+                    prompt_file: None,
+                    prompt_transport: None,
+                    include_directories: None,
+                    cache: false,
                 },
             )
             .await
@@ -2856,6 +11032,7 @@ echo '{"type":"item.completed","item":{"type":"agent_message","text":"ok"}}'
                     role_id: "oracle".to_string(),
                     backend: Backend::Codex,
                     backend_session_id: "sess-prev".to_string(),
+                    model: None,
                     sampling_history: Vec::new(),
                     updated_at_unix_secs: now_unix_secs(),
                 },
@@ -2878,8 +11055,24 @@ echo '{"type":"item.completed","item":{"type":"agent_message","text":"ok"}}'
                     timeout_secs: Some(5),
                     contract: None,
                     validate_patch: false,
+                    strict_citations: false,
+                    contract_output: None,
+                    output_parser: None,
+                    canonicalize_cd: None,
+                    extract_code_lang: None,
+                    n: None,
                     client: Some("claude".to_string()),
                     conversation_id: Some("conv-b".to_string()),
+                    participant: None,
+                    session_group: None,
+                    ephemeral: false,
+                    dry_run: false,
+                    persona_override: None,
+                    stream: false,
+                    prompt_file: None,
+                    prompt_transport: None,
+                    include_directories: None,
+                    cache: false,
                 },
             )
             .await
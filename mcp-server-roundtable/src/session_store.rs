@@ -1,12 +1,14 @@
-use crate::config::Backend;
-use anyhow::{Context, Result};
+use crate::config::{Backend, OptionValue};
+use anyhow::{anyhow, Context, Result};
 use fs2::FileExt;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,6 +18,9 @@ pub struct SessionRecord {
     pub role_id: String,
     pub backend: Backend,
     pub backend_session_id: String,
+    /// Model id that produced the last successful response (may be a fallback model).
+    #[serde(default)]
+    pub model: Option<String>,
     /// For MCP sampling-based backends (e.g. Claude), we persist a short conversation history
     /// to approximate "session" reuse.
     #[serde(default)]
@@ -29,6 +34,25 @@ pub struct SamplingHistoryMessage {
     pub content: String,
 }
 
+/// How often a call waiting for a session key lock retries it.
+const KEY_LOCK_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(25);
+/// Responses kept for `cache: true` calls; the oldest are evicted beyond this.
+pub const RESPONSE_CACHE_MAX_ENTRIES: usize = 256;
+/// Cached responses older than this are not reused.
+pub const RESPONSE_CACHE_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// A backend answer stored for `cache: true` calls, keyed by `compute_cache_key`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub backend_id: String,
+    pub backend: Backend,
+    pub model: String,
+    pub agent_messages: String,
+    #[serde(default)]
+    pub candidates: Vec<String>,
+    pub stored_at_unix_secs: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct SessionFile {
     version: u32,
@@ -44,10 +68,43 @@ impl Default for SessionFile {
     }
 }
 
+/// The JSON store's response cache, kept next to `sessions.json` (see `cache_path`) so session
+/// reads and writes never load or rewrite cached answers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: BTreeMap<String, CachedResponse>,
+}
+
+impl Default for CacheFile {
+    fn default() -> Self {
+        Self {
+            version: 1,
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StoreFormat {
+    /// Whole-file `sessions.json`, rewritten under a file lock on every change.
+    Json,
+    /// SQLite database with one row per session key.
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
 #[derive(Debug, Clone)]
 pub struct SessionStore {
     path: PathBuf,
     locks_dir: PathBuf,
+    ttl_secs: Option<u64>,
+    format: StoreFormat,
+    readonly: bool,
+    /// Set once the first skipped write has been reported, so the warning is not repeated.
+    readonly_warned: Arc<AtomicBool>,
+    /// Keys locked by calls in this process while the store is read-only.
+    held_keys: Arc<Mutex<BTreeSet<String>>>,
 }
 
 impl SessionStore {
@@ -56,7 +113,54 @@ impl SessionStore {
             .parent()
             .unwrap_or_else(|| Path::new("."))
             .join("locks");
-        Self { path, locks_dir }
+        Self {
+            path,
+            locks_dir,
+            ttl_secs: None,
+            format: StoreFormat::Json,
+            readonly: false,
+            readonly_warned: Arc::new(AtomicBool::new(false)),
+            held_keys: Arc::new(Mutex::new(BTreeSet::new())),
+        }
+    }
+
+    /// Store backed by a SQLite database at `path`. Each `put`/`remove` only touches that key's
+    /// row, so several processes can share it without losing each other's updates.
+    #[cfg(feature = "sqlite")]
+    pub fn sqlite(path: PathBuf) -> Self {
+        Self {
+            format: StoreFormat::Sqlite,
+            ..Self::new(path)
+        }
+    }
+
+    /// Treat records not updated within `ttl_secs` as absent in `get`, so auto-resume starts fresh.
+    pub fn with_ttl_secs(mut self, ttl_secs: u64) -> Self {
+        self.ttl_secs = Some(ttl_secs);
+        self
+    }
+
+    /// Never write the store or create files next to it: `put` and response caching become no-ops
+    /// (warning once), `get` and listing keep working, and key locks only serialize calls in this
+    /// process. `remove` fails instead of pretending to succeed.
+    pub fn with_readonly(mut self, readonly: bool) -> Self {
+        self.readonly = readonly;
+        self
+    }
+
+    pub fn is_readonly(&self) -> bool {
+        self.readonly
+    }
+
+    /// Reports whether a write should be skipped, warning on the first skipped write.
+    fn skip_write(&self) -> bool {
+        if self.readonly && !self.readonly_warned.swap(true, Ordering::Relaxed) {
+            eprintln!(
+                "warning: session store {} is read-only; session updates are not saved",
+                self.path.display()
+            );
+        }
+        self.readonly
     }
 
     pub fn default_path() -> PathBuf {
@@ -118,7 +222,52 @@ impl SessionStore {
         hex::encode(h.finalize())
     }
 
-    pub fn acquire_key_lock(&self, key: &str) -> Result<KeyLock> {
+    /// Response cache key: the same repo, role, backend, model, candidate count, options and final
+    /// prompt text.
+    pub fn compute_cache_key(
+        repo_root: &Path,
+        role_id: &str,
+        backend_id: &str,
+        model: &str,
+        n: Option<u32>,
+        prompt: &str,
+        options: &BTreeMap<String, OptionValue>,
+    ) -> String {
+        let options_json = serde_json::to_string(options).unwrap_or_default();
+        let n = n.map_or_else(|| "-".to_string(), |n| n.to_string());
+        let mut h = Sha256::new();
+        h.update(repo_root.to_string_lossy().as_bytes());
+        h.update(b"\n");
+        h.update(role_id.as_bytes());
+        h.update(b"\n");
+        h.update(backend_id.as_bytes());
+        h.update(b"\n");
+        h.update(model.as_bytes());
+        h.update(b"\n");
+        h.update(n.as_bytes());
+        h.update(b"\n");
+        h.update(options_json.as_bytes());
+        h.update(b"\n");
+        h.update(prompt.as_bytes());
+        hex::encode(h.finalize())
+    }
+
+    /// Waits until no other call (in this or another process) holds `key`. The lock is polled
+    /// rather than blocked on, so waiting never ties up a runtime worker and the wait can be
+    /// cancelled by a timeout or abort.
+    pub async fn acquire_key_lock(&self, key: &str) -> Result<KeyLock> {
+        if self.readonly {
+            // No lock files: only calls in this process are serialized.
+            loop {
+                if self.held_keys.lock().unwrap().insert(key.to_string()) {
+                    return Ok(KeyLock::InProcess {
+                        held_keys: self.held_keys.clone(),
+                        key: key.to_string(),
+                    });
+                }
+                tokio::time::sleep(KEY_LOCK_POLL_INTERVAL).await;
+            }
+        }
         std::fs::create_dir_all(&self.locks_dir)
             .with_context(|| format!("failed to create locks dir: {}", self.locks_dir.display()))?;
         let lock_path = self.locks_dir.join(format!("{}.lock", key));
@@ -128,29 +277,196 @@ impl SessionStore {
             .create(true)
             .open(&lock_path)
             .with_context(|| format!("failed to open lock file: {}", lock_path.display()))?;
-        file.lock_exclusive()
-            .with_context(|| format!("failed to lock: {}", lock_path.display()))?;
-        Ok(KeyLock { file })
+        let contended = fs2::lock_contended_error().raw_os_error();
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(KeyLock::File(file)),
+                Err(err) if err.raw_os_error() == contended => {
+                    tokio::time::sleep(KEY_LOCK_POLL_INTERVAL).await;
+                }
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("failed to lock: {}", lock_path.display()));
+                }
+            }
+        }
     }
 
     pub fn get(&self, key: &str) -> Result<Option<SessionRecord>> {
-        self.with_store(|sf| Ok(sf.records.get(key).cloned()))
+        let now = now_unix_secs();
+        let rec = match self.format {
+            StoreFormat::Json => self.with_store(|sf| Ok(sf.records.get(key).cloned()))?,
+            #[cfg(feature = "sqlite")]
+            StoreFormat::Sqlite => self.sqlite_get(key)?,
+        };
+        Ok(rec.filter(|rec| !self.is_past_ttl(rec, now)))
+    }
+
+    /// All stored records, keyed by session key. Records past the TTL are left out, as in `get`.
+    pub fn list(&self) -> Result<BTreeMap<String, SessionRecord>> {
+        let now = now_unix_secs();
+        let mut records = match self.format {
+            StoreFormat::Json => self.with_store(|sf| Ok(sf.records.clone()))?,
+            #[cfg(feature = "sqlite")]
+            StoreFormat::Sqlite => self.sqlite_list()?,
+        };
+        records.retain(|_, rec| !self.is_past_ttl(rec, now));
+        Ok(records)
+    }
+
+    fn is_past_ttl(&self, rec: &SessionRecord, now: u64) -> bool {
+        self.ttl_secs.is_some_and(|ttl| is_expired(rec, ttl, now))
+    }
+
+    /// Records for `repo_root` updated after `updated_after` (if given) and not past the TTL, most
+    /// recently updated first. Only matching records are cloned out of the store.
+    pub fn list_recent(
+        &self,
+        repo_root: &str,
+        updated_after: Option<u64>,
+    ) -> Result<Vec<(String, SessionRecord)>> {
+        let now = now_unix_secs();
+        let mut records: Vec<(String, SessionRecord)> = match self.format {
+            StoreFormat::Json => self.with_store(|sf| {
+                Ok(sf
+                    .records
+                    .iter()
+                    .filter(|(_, rec)| rec.repo_root == repo_root && !self.is_past_ttl(rec, now))
+                    .filter(|(_, rec)| {
+                        updated_after.is_none_or(|after| rec.updated_at_unix_secs > after)
+                    })
+                    .map(|(key, rec)| (key.clone(), rec.clone()))
+                    .collect())
+            })?,
+            #[cfg(feature = "sqlite")]
+            StoreFormat::Sqlite => self
+                .sqlite_list_recent(updated_after)?
+                .into_iter()
+                .filter(|(_, rec)| rec.repo_root == repo_root && !self.is_past_ttl(rec, now))
+                .collect(),
+        };
+        // Newest first; ties keep key order so pages are stable.
+        records.sort_by(|(ka, a), (kb, b)| {
+            b.updated_at_unix_secs
+                .cmp(&a.updated_at_unix_secs)
+                .then_with(|| ka.cmp(kb))
+        });
+        Ok(records)
+    }
+
+    /// Removes the record stored under `key`; returns false if there was none.
+    pub fn remove(&self, key: &str) -> Result<bool> {
+        if self.readonly {
+            return Err(anyhow!(
+                "session store {} is read-only",
+                self.path.display()
+            ));
+        }
+        match self.format {
+            StoreFormat::Json => self.with_store(|sf| Ok(sf.records.remove(key).is_some())),
+            #[cfg(feature = "sqlite")]
+            StoreFormat::Sqlite => self.sqlite_remove(key),
+        }
+    }
+
+    /// Removes every record not updated within `ttl_secs`; returns how many were removed.
+    pub fn prune_expired(&self, ttl_secs: u64) -> Result<usize> {
+        if self.skip_write() {
+            return Ok(0);
+        }
+        let now = now_unix_secs();
+        match self.format {
+            StoreFormat::Json => self.with_store(|sf| {
+                let before = sf.records.len();
+                sf.records.retain(|_, rec| !is_expired(rec, ttl_secs, now));
+                Ok(before - sf.records.len())
+            }),
+            #[cfg(feature = "sqlite")]
+            StoreFormat::Sqlite => self.sqlite_prune(now.saturating_sub(ttl_secs)),
+        }
     }
 
     pub fn put(&self, key: &str, record: SessionRecord) -> Result<()> {
-        self.with_store(|sf| {
-            sf.records.insert(key.to_string(), record);
-            Ok(())
-        })
+        if self.skip_write() {
+            return Ok(());
+        }
+        match self.format {
+            StoreFormat::Json => self.with_store(|sf| {
+                sf.records.insert(key.to_string(), record);
+                Ok(())
+            }),
+            #[cfg(feature = "sqlite")]
+            StoreFormat::Sqlite => self.sqlite_put(key, &record),
+        }
+    }
+
+    /// The response cached under `key`, unless it is older than `RESPONSE_CACHE_TTL_SECS`.
+    pub fn cached_response(&self, key: &str) -> Result<Option<CachedResponse>> {
+        let now = now_unix_secs();
+        let cached = match self.format {
+            StoreFormat::Json => self.with_cache(|cf| Ok(cf.entries.get(key).cloned()))?,
+            #[cfg(feature = "sqlite")]
+            StoreFormat::Sqlite => self.sqlite_cache_get(key)?,
+        };
+        Ok(cached.filter(|c| now.saturating_sub(c.stored_at_unix_secs) <= RESPONSE_CACHE_TTL_SECS))
+    }
+
+    /// Stores `response` under `key`, dropping expired entries and the oldest ones beyond
+    /// `RESPONSE_CACHE_MAX_ENTRIES`.
+    pub fn put_cached_response(&self, key: &str, response: CachedResponse) -> Result<()> {
+        if self.skip_write() {
+            return Ok(());
+        }
+        let cutoff = now_unix_secs().saturating_sub(RESPONSE_CACHE_TTL_SECS);
+        match self.format {
+            StoreFormat::Json => self.with_cache(|cf| {
+                cf.entries.insert(key.to_string(), response);
+                cf.entries.retain(|_, c| c.stored_at_unix_secs >= cutoff);
+                while cf.entries.len() > RESPONSE_CACHE_MAX_ENTRIES {
+                    let oldest = cf
+                        .entries
+                        .iter()
+                        .min_by_key(|(_, c)| c.stored_at_unix_secs)
+                        .map(|(k, _)| k.clone());
+                    match oldest {
+                        Some(k) => cf.entries.remove(&k),
+                        None => break,
+                    };
+                }
+                Ok(())
+            }),
+            #[cfg(feature = "sqlite")]
+            StoreFormat::Sqlite => self.sqlite_cache_put(key, &response, cutoff),
+        }
     }
 
     fn with_store<T>(&self, f: impl FnOnce(&mut SessionFile) -> Result<T>) -> Result<T> {
-        if let Some(parent) = self.path.parent() {
+        self.with_json_file(&self.path, f)
+    }
+
+    fn with_cache<T>(&self, f: impl FnOnce(&mut CacheFile) -> Result<T>) -> Result<T> {
+        self.with_json_file(&self.cache_path(), f)
+    }
+
+    /// The JSON response cache file: `sessions.json` -> `sessions.cache.json`.
+    fn cache_path(&self) -> PathBuf {
+        self.path.with_extension("cache.json")
+    }
+
+    /// Loads the JSON file at `path` under its lock, runs `f`, and writes the result back.
+    fn with_json_file<F, T>(&self, path: &Path, f: impl FnOnce(&mut F) -> Result<T>) -> Result<T>
+    where
+        F: Default + Serialize + DeserializeOwned,
+    {
+        if self.readonly {
+            return f(&mut read_json_file_readonly(path)?);
+        }
+        if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)
                 .with_context(|| format!("failed to create store dir: {}", parent.display()))?;
         }
 
-        let lock_path = self.path.with_extension("lock");
+        let lock_path = path.with_extension("lock");
         let lock_file = OpenOptions::new()
             .read(true)
             .write(true)
@@ -159,9 +475,9 @@ impl SessionStore {
             .with_context(|| format!("failed to open session lock: {}", lock_path.display()))?;
         lock_file
             .lock_exclusive()
-            .with_context(|| format!("failed to lock session store: {}", self.path.display()))?;
+            .with_context(|| format!("failed to lock session store: {}", path.display()))?;
 
-        let raw = match std::fs::read_to_string(&self.path) {
+        let raw = match std::fs::read_to_string(path) {
             Ok(contents) => contents,
             Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
             Err(err) => {
@@ -170,17 +486,17 @@ impl SessionStore {
             }
         };
 
-        let mut sf: SessionFile = if raw.trim().is_empty() {
-            SessionFile::default()
+        let mut sf: F = if raw.trim().is_empty() {
+            F::default()
         } else {
             match serde_json::from_str(&raw) {
                 Ok(parsed) => parsed,
                 Err(err) => {
-                    let backup_path = self.backup_corrupt_store();
+                    let backup_path = backup_corrupt_store(path);
                     if let Err(backup_err) = backup_path {
                         eprintln!(
                             "warning: failed to backup corrupt session store {}: {}",
-                            self.path.display(),
+                            path.display(),
                             backup_err
                         );
                     }
@@ -188,7 +504,7 @@ impl SessionStore {
                         "warning: session store JSON invalid ({}), resetting to empty",
                         err
                     );
-                    SessionFile::default()
+                    F::default()
                 }
             }
         };
@@ -196,73 +512,308 @@ impl SessionStore {
         let out = f(&mut sf)?;
 
         let bytes = serde_json::to_vec_pretty(&sf).context("failed to serialize session store")?;
-        self.write_atomic(&bytes)?;
+        write_atomic(path, &bytes)?;
 
         lock_file.unlock().ok();
         Ok(out)
     }
 }
 
+/// Reads a store file without creating its directory or lock file, and without backing up a
+/// corrupt file. Writers replace the file by rename, so an unlocked read never sees half of it.
+fn read_json_file_readonly<F: Default + DeserializeOwned>(path: &Path) -> Result<F> {
+    let raw = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err).context("failed to read session store"),
+    };
+    if raw.trim().is_empty() {
+        return Ok(F::default());
+    }
+    Ok(serde_json::from_str(&raw).unwrap_or_else(|err| {
+        eprintln!(
+            "warning: session store JSON invalid ({}), treating it as empty",
+            err
+        );
+        F::default()
+    }))
+}
+
+fn backup_corrupt_store(path: &Path) -> Result<PathBuf> {
+    if !path.exists() {
+        return Ok(path.to_path_buf());
+    }
+    let file_name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let backup_name = format!("{}.bak.{}", file_name, now_unix_secs());
+    let backup_path = path.with_file_name(backup_name);
+    std::fs::rename(path, &backup_path).with_context(|| {
+        format!(
+            "failed to backup corrupt store to {}",
+            backup_path.display()
+        )
+    })?;
+    Ok(backup_path)
+}
+
+/// Writes a sibling temp file and renames it over the store, so a crash mid-write leaves
+/// either the old or the new file, never a truncated one.
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    // Named after the full file name plus pid, so it cannot collide with another store's
+    // temp file in the same directory (e.g. `sessions.json` vs `sessions.yaml`).
+    let file_name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let tmp_path = path.with_file_name(format!("{file_name}.{}.tmp", std::process::id()));
+    {
+        let mut tmp = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp_path)
+            .with_context(|| format!("failed to open temp store: {}", tmp_path.display()))?;
+        tmp.write_all(bytes).context("failed to write temp store")?;
+        tmp.write_all(b"\n").ok();
+        tmp.flush().ok();
+        tmp.sync_all().ok();
+    }
+
+    if let Err(err) = std::fs::rename(&tmp_path, path) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(err).with_context(|| format!("failed to replace store: {}", path.display()));
+    }
+
+    if let Some(parent) = path.parent() {
+        let _ = OpenOptions::new()
+            .read(true)
+            .open(parent)
+            .and_then(|dir| dir.sync_all());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
 impl SessionStore {
-    fn backup_corrupt_store(&self) -> Result<PathBuf> {
-        if !self.path.exists() {
-            return Ok(self.path.clone());
+    fn sqlite_open(&self) -> Result<rusqlite::Connection> {
+        if self.readonly {
+            return self.sqlite_open_readonly();
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create store dir: {}", parent.display()))?;
         }
-        let file_name = self
-            .path
-            .file_name()
-            .unwrap_or_default()
-            .to_string_lossy()
-            .to_string();
-        let backup_name = format!("{}.bak.{}", file_name, now_unix_secs());
-        let backup_path = self.path.with_file_name(backup_name);
-        std::fs::rename(&self.path, &backup_path).with_context(|| {
-            format!(
-                "failed to backup corrupt store to {}",
-                backup_path.display()
+        let conn = rusqlite::Connection::open(&self.path)
+            .with_context(|| format!("failed to open session db: {}", self.path.display()))?;
+        // Other processes may hold the write lock briefly; wait instead of failing.
+        conn.busy_timeout(std::time::Duration::from_secs(5))
+            .context("failed to set session db busy timeout")?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS sessions (
+                 key TEXT PRIMARY KEY,
+                 record TEXT NOT NULL,
+                 updated_at_unix_secs INTEGER NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS response_cache (
+                 key TEXT PRIMARY KEY,
+                 response TEXT NOT NULL,
+                 stored_at_unix_secs INTEGER NOT NULL
+             );",
+        )
+        .context("failed to initialize session db")?;
+        Ok(conn)
+    }
+
+    /// Opens the database without creating or migrating it. A missing database reads as an
+    /// empty in-memory one, so a read-only store never creates files.
+    fn sqlite_open_readonly(&self) -> Result<rusqlite::Connection> {
+        if !self.path.exists() {
+            let conn = rusqlite::Connection::open_in_memory()
+                .context("failed to open in-memory session db")?;
+            conn.execute_batch(
+                "CREATE TABLE sessions (
+                     key TEXT PRIMARY KEY,
+                     record TEXT NOT NULL,
+                     updated_at_unix_secs INTEGER NOT NULL
+                 );
+                 CREATE TABLE response_cache (
+                     key TEXT PRIMARY KEY,
+                     response TEXT NOT NULL,
+                     stored_at_unix_secs INTEGER NOT NULL
+                 );",
             )
-        })?;
-        Ok(backup_path)
-    }
-
-    fn write_atomic(&self, bytes: &[u8]) -> Result<()> {
-        let tmp_path = self.path.with_extension("tmp");
-        {
-            let mut tmp = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(&tmp_path)
-                .with_context(|| format!("failed to open temp store: {}", tmp_path.display()))?;
-            tmp.write_all(bytes).context("failed to write temp store")?;
-            tmp.write_all(b"\n").ok();
-            tmp.flush().ok();
-            tmp.sync_all().ok();
+            .context("failed to initialize session db")?;
+            return Ok(conn);
         }
+        let conn = rusqlite::Connection::open_with_flags(
+            &self.path,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )
+        .with_context(|| format!("failed to open session db: {}", self.path.display()))?;
+        conn.busy_timeout(std::time::Duration::from_secs(5))
+            .context("failed to set session db busy timeout")?;
+        Ok(conn)
+    }
 
-        std::fs::rename(&tmp_path, &self.path)
-            .with_context(|| format!("failed to replace store: {}", self.path.display()))?;
+    fn sqlite_get(&self, key: &str) -> Result<Option<SessionRecord>> {
+        use rusqlite::OptionalExtension;
 
-        if let Some(parent) = self.path.parent() {
-            let _ = OpenOptions::new()
-                .read(true)
-                .open(parent)
-                .and_then(|dir| dir.sync_all());
+        let raw: Option<String> = self
+            .sqlite_open()?
+            .query_row("SELECT record FROM sessions WHERE key = ?1", [key], |row| {
+                row.get(0)
+            })
+            .optional()
+            .context("failed to read session db")?;
+        raw.map(|raw| serde_json::from_str(&raw).context("invalid session record in db"))
+            .transpose()
+    }
+
+    fn sqlite_list(&self) -> Result<BTreeMap<String, SessionRecord>> {
+        let conn = self.sqlite_open()?;
+        let mut stmt = conn
+            .prepare("SELECT key, record FROM sessions")
+            .context("failed to read session db")?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .context("failed to read session db")?;
+        let mut records = BTreeMap::new();
+        for row in rows {
+            let (key, raw) = row.context("failed to read session db")?;
+            let rec = serde_json::from_str(&raw)
+                .with_context(|| format!("invalid session record in db: {key}"))?;
+            records.insert(key, rec);
         }
+        Ok(records)
+    }
+
+    fn sqlite_list_recent(
+        &self,
+        updated_after: Option<u64>,
+    ) -> Result<Vec<(String, SessionRecord)>> {
+        let conn = self.sqlite_open()?;
+        let mut stmt = conn
+            .prepare("SELECT key, record FROM sessions WHERE updated_at_unix_secs > ?1")
+            .context("failed to read session db")?;
+        let after = updated_after.map_or(-1, |secs| secs as i64);
+        let rows = stmt
+            .query_map([after], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .context("failed to read session db")?;
+        let mut records = Vec::new();
+        for row in rows {
+            let (key, raw) = row.context("failed to read session db")?;
+            let rec = serde_json::from_str(&raw)
+                .with_context(|| format!("invalid session record in db: {key}"))?;
+            records.push((key, rec));
+        }
+        Ok(records)
+    }
+
+    fn sqlite_put(&self, key: &str, record: &SessionRecord) -> Result<()> {
+        let raw = serde_json::to_string(record).context("failed to serialize session record")?;
+        self.sqlite_open()?
+            .execute(
+                "INSERT INTO sessions (key, record, updated_at_unix_secs) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(key) DO UPDATE SET
+                     record = excluded.record,
+                     updated_at_unix_secs = excluded.updated_at_unix_secs",
+                rusqlite::params![key, raw, record.updated_at_unix_secs as i64],
+            )
+            .context("failed to write session db")?;
+        Ok(())
+    }
+
+    fn sqlite_remove(&self, key: &str) -> Result<bool> {
+        let removed = self
+            .sqlite_open()?
+            .execute("DELETE FROM sessions WHERE key = ?1", [key])
+            .context("failed to write session db")?;
+        Ok(removed > 0)
+    }
+
+    fn sqlite_cache_get(&self, key: &str) -> Result<Option<CachedResponse>> {
+        use rusqlite::OptionalExtension;
+
+        let raw: Option<String> = self
+            .sqlite_open()?
+            .query_row(
+                "SELECT response FROM response_cache WHERE key = ?1",
+                [key],
+                |row| row.get(0),
+            )
+            .optional()
+            .context("failed to read session db")?;
+        raw.map(|raw| serde_json::from_str(&raw).context("invalid cached response in db"))
+            .transpose()
+    }
+
+    fn sqlite_cache_put(&self, key: &str, response: &CachedResponse, cutoff: u64) -> Result<()> {
+        let raw = serde_json::to_string(response).context("failed to serialize cached response")?;
+        let conn = self.sqlite_open()?;
+        conn.execute(
+            "INSERT INTO response_cache (key, response, stored_at_unix_secs) VALUES (?1, ?2, ?3)
+             ON CONFLICT(key) DO UPDATE SET
+                 response = excluded.response,
+                 stored_at_unix_secs = excluded.stored_at_unix_secs",
+            rusqlite::params![key, raw, response.stored_at_unix_secs as i64],
+        )
+        .context("failed to write session db")?;
+        conn.execute(
+            "DELETE FROM response_cache WHERE stored_at_unix_secs < ?1 OR key NOT IN (
+                 SELECT key FROM response_cache ORDER BY stored_at_unix_secs DESC LIMIT ?2
+             )",
+            rusqlite::params![cutoff as i64, RESPONSE_CACHE_MAX_ENTRIES as i64],
+        )
+        .context("failed to write session db")?;
         Ok(())
     }
+
+    /// Deletes rows last updated before `cutoff` (matches `is_expired`).
+    fn sqlite_prune(&self, cutoff: u64) -> Result<usize> {
+        self.sqlite_open()?
+            .execute(
+                "DELETE FROM sessions WHERE updated_at_unix_secs < ?1",
+                [cutoff as i64],
+            )
+            .context("failed to write session db")
+    }
 }
 
-pub struct KeyLock {
-    file: std::fs::File,
+pub enum KeyLock {
+    File(std::fs::File),
+    /// Held in `SessionStore::held_keys` (read-only stores create no lock files).
+    InProcess {
+        held_keys: Arc<Mutex<BTreeSet<String>>>,
+        key: String,
+    },
 }
 
 impl Drop for KeyLock {
     fn drop(&mut self) {
-        let _ = self.file.unlock();
+        match self {
+            KeyLock::File(file) => {
+                let _ = file.unlock();
+            }
+            KeyLock::InProcess { held_keys, key } => {
+                held_keys.lock().unwrap().remove(key.as_str());
+            }
+        }
     }
 }
 
+fn is_expired(rec: &SessionRecord, ttl_secs: u64, now: u64) -> bool {
+    now.saturating_sub(rec.updated_at_unix_secs) > ttl_secs
+}
+
 pub fn now_unix_secs() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -329,6 +880,7 @@ mod tests {
                     role_id: "codex:default:default".to_string(),
                     backend: Backend::Codex,
                     backend_session_id: "sess-1".to_string(),
+                    model: None,
                     sampling_history: Vec::new(),
                     updated_at_unix_secs: 1,
                 },
@@ -340,6 +892,238 @@ mod tests {
         assert_eq!(rec.backend, Backend::Codex);
     }
 
+    #[test]
+    fn response_cache_lives_beside_the_session_file_and_keys_backend_and_n() {
+        let td = tempfile::tempdir().unwrap();
+        let store_path = td.path().join("sessions.json");
+        let store = SessionStore::new(store_path.clone());
+        let repo = td.path().join("repo");
+        let options = BTreeMap::new();
+        let key = |backend_id: &str, n: Option<u32>| {
+            SessionStore::compute_cache_key(&repo, "oracle", backend_id, "m", n, "p", &options)
+        };
+        assert_ne!(key("codex", None), key("gemini", None));
+        assert_ne!(key("codex", None), key("codex", Some(2)));
+
+        store
+            .put_cached_response(
+                &key("codex", None),
+                CachedResponse {
+                    backend_id: "codex".to_string(),
+                    backend: Backend::Codex,
+                    model: "m".to_string(),
+                    agent_messages: "answer".to_string(),
+                    candidates: Vec::new(),
+                    stored_at_unix_secs: now_unix_secs(),
+                },
+            )
+            .unwrap();
+        let hit = store.cached_response(&key("codex", None)).unwrap().unwrap();
+        assert_eq!(hit.agent_messages, "answer");
+        assert!(store
+            .cached_response(&key("gemini", None))
+            .unwrap()
+            .is_none());
+
+        assert!(!store_path.exists());
+        let cache = fs::read_to_string(td.path().join("sessions.cache.json")).unwrap();
+        assert!(cache.contains("answer"));
+    }
+
+    #[test]
+    fn ttl_hides_expired_records_and_prune_removes_them() {
+        let td = tempfile::tempdir().unwrap();
+        let store_path = td.path().join("sessions.json");
+        let repo = td.path().join("repo");
+        let record = |updated_at_unix_secs: u64| SessionRecord {
+            repo_root: repo.to_string_lossy().to_string(),
+            role: "oracle".to_string(),
+            role_id: "oracle".to_string(),
+            backend: Backend::Codex,
+            backend_session_id: "sess-1".to_string(),
+            model: None,
+            sampling_history: Vec::new(),
+            updated_at_unix_secs,
+        };
+        let store = SessionStore::new(store_path.clone());
+        store.put("old", record(now_unix_secs() - 7200)).unwrap();
+        store.put("fresh", record(now_unix_secs())).unwrap();
+
+        let ttl_store = SessionStore::new(store_path).with_ttl_secs(3600);
+        assert!(ttl_store.get("old").unwrap().is_none());
+        assert!(ttl_store.get("fresh").unwrap().is_some());
+        let listed: Vec<String> = ttl_store.list().unwrap().into_keys().collect();
+        assert_eq!(listed, vec!["fresh".to_string()]);
+        let recent = ttl_store
+            .list_recent(&repo.to_string_lossy(), None)
+            .unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].0, "fresh");
+        // Without a TTL the expired record is still there until pruned.
+        assert!(store.get("old").unwrap().is_some());
+
+        assert_eq!(store.prune_expired(3600).unwrap(), 1);
+        assert!(store.get("old").unwrap().is_none());
+        assert_eq!(store.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn list_recent_filters_by_repo_and_time_newest_first() {
+        let td = tempfile::tempdir().unwrap();
+        let record = |repo: &str, updated_at_unix_secs: u64| SessionRecord {
+            repo_root: repo.to_string(),
+            role: "oracle".to_string(),
+            role_id: "oracle".to_string(),
+            backend: Backend::Codex,
+            backend_session_id: "sess-1".to_string(),
+            model: None,
+            sampling_history: Vec::new(),
+            updated_at_unix_secs,
+        };
+        let store = SessionStore::new(td.path().join("sessions.json"));
+        store.put("a", record("/repo", 10)).unwrap();
+        store.put("b", record("/repo", 30)).unwrap();
+        store.put("c", record("/repo", 20)).unwrap();
+        store.put("d", record("/other", 40)).unwrap();
+
+        let keys = |after| -> Vec<String> {
+            store
+                .list_recent("/repo", after)
+                .unwrap()
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect()
+        };
+        assert_eq!(keys(None), ["b", "c", "a"]);
+        assert_eq!(keys(Some(10)), ["b", "c"]);
+        assert!(keys(Some(30)).is_empty());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_store_keeps_concurrent_writers_and_supports_ttl() {
+        let td = tempfile::tempdir().unwrap();
+        let db_path = td.path().join("sessions.db");
+        let record = |sid: &str, updated_at_unix_secs: u64| SessionRecord {
+            repo_root: "/tmp/repo".to_string(),
+            role: "oracle".to_string(),
+            role_id: "oracle".to_string(),
+            backend: Backend::Codex,
+            backend_session_id: sid.to_string(),
+            model: None,
+            sampling_history: Vec::new(),
+            updated_at_unix_secs,
+        };
+
+        // Separate store handles (as in separate processes) writing different keys at once.
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let store = SessionStore::sqlite(db_path.clone());
+                let rec = record(&format!("sess-{i}"), now_unix_secs());
+                std::thread::spawn(move || store.put(&format!("key-{i}"), rec).unwrap())
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        let store = SessionStore::sqlite(db_path.clone());
+        assert_eq!(store.list().unwrap().len(), 8);
+        assert_eq!(
+            store.get("key-3").unwrap().unwrap().backend_session_id,
+            "sess-3"
+        );
+
+        store
+            .put("key-3", record("sess-3b", now_unix_secs()))
+            .unwrap();
+        assert_eq!(
+            store.get("key-3").unwrap().unwrap().backend_session_id,
+            "sess-3b"
+        );
+        assert!(store.remove("key-0").unwrap());
+        assert!(!store.remove("key-0").unwrap());
+
+        store
+            .put("old", record("sess-old", now_unix_secs() - 7200))
+            .unwrap();
+        let ttl_store = SessionStore::sqlite(db_path).with_ttl_secs(3600);
+        assert!(ttl_store.get("old").unwrap().is_none());
+        assert_eq!(store.prune_expired(3600).unwrap(), 1);
+        assert!(store.get("old").unwrap().is_none());
+        assert_eq!(store.list().unwrap().len(), 7);
+    }
+
+    #[test]
+    fn concurrent_puts_always_leave_a_parseable_store() {
+        let td = tempfile::tempdir().unwrap();
+        let store_path = td.path().join("sessions.json");
+        let record = |sid: String| SessionRecord {
+            repo_root: "/tmp/repo".to_string(),
+            role: "oracle".to_string(),
+            role_id: "oracle".to_string(),
+            backend: Backend::Codex,
+            backend_session_id: sid,
+            model: None,
+            sampling_history: Vec::new(),
+            updated_at_unix_secs: now_unix_secs(),
+        };
+
+        let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        // A reader that never takes the store lock sees the file exactly as a crash would leave it.
+        let reader = {
+            let path = store_path.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut checked = 0;
+                loop {
+                    // Checked before reading, so there is always one read after the writers end.
+                    let done = stop.load(std::sync::atomic::Ordering::SeqCst);
+                    if let Ok(raw) = fs::read_to_string(&path) {
+                        serde_json::from_str::<serde_json::Value>(&raw)
+                            .expect("store file must always be complete JSON");
+                        checked += 1;
+                    }
+                    if done {
+                        return checked;
+                    }
+                }
+            })
+        };
+        let writers: Vec<_> = (0..4)
+            .map(|w| {
+                let store = SessionStore::new(store_path.clone());
+                std::thread::spawn(move || {
+                    for i in 0..25 {
+                        store
+                            .put(&format!("key-{w}"), record(format!("sess-{w}-{i}")))
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for w in writers {
+            w.join().unwrap();
+        }
+        stop.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert!(reader.join().unwrap() > 0);
+
+        let records = SessionStore::new(store_path).list().unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records["key-2"].backend_session_id, "sess-2-24");
+        // No temp files are left behind.
+        let leftovers = fs::read_dir(td.path())
+            .unwrap()
+            .filter(|e| {
+                e.as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .ends_with(".tmp")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
     #[test]
     fn corrupt_store_is_backed_up_and_reset() {
         let td = tempfile::tempdir().unwrap();
@@ -365,4 +1149,38 @@ mod tests {
         }
         assert!(backups >= 1);
     }
+
+    #[tokio::test]
+    async fn readonly_store_creates_and_renames_nothing() {
+        let td = tempfile::tempdir().unwrap();
+
+        // Missing store directory: reads and key locks work without creating it.
+        let missing_dir = td.path().join("data");
+        let store = SessionStore::new(missing_dir.join("sessions.json")).with_readonly(true);
+        assert!(store.get("k").unwrap().is_none());
+        assert!(store.list().unwrap().is_empty());
+        assert!(store.cached_response("k").unwrap().is_none());
+        let lock = store.acquire_key_lock("k").await.unwrap();
+        let waiting = tokio::time::timeout(
+            std::time::Duration::from_millis(100),
+            store.acquire_key_lock("k"),
+        )
+        .await;
+        assert!(waiting.is_err(), "second lock on the same key must wait");
+        drop(lock);
+        drop(store.acquire_key_lock("k").await.unwrap());
+        assert!(!missing_dir.exists());
+
+        // A corrupt store is read as empty and left in place.
+        let store_path = td.path().join("sessions.json");
+        fs::write(&store_path, "{").unwrap();
+        let store = SessionStore::new(store_path.clone()).with_readonly(true);
+        assert!(store.get("k").unwrap().is_none());
+        let names: Vec<String> = fs::read_dir(td.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["sessions.json".to_string()]);
+        assert_eq!(fs::read_to_string(&store_path).unwrap(), "{");
+    }
 }
@@ -30,6 +30,11 @@ fn gemini_lock() -> &'static Mutex<()> {
     LOCK.get_or_init(|| Mutex::new(()))
 }
 
+fn kimi_lock() -> &'static Mutex<()> {
+    static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+    LOCK.get_or_init(|| Mutex::new(()))
+}
+
 pub fn scoped_codex_bin(path: &str) -> ScopedEnvVar {
     let lock = codex_lock().lock().unwrap();
     let prev = std::env::var("CODEX_BIN").ok();
@@ -56,6 +61,19 @@ pub fn scoped_gemini_bin(path: &str) -> ScopedEnvVar {
     }
 }
 
+pub fn scoped_kimi_bin(path: &str) -> ScopedEnvVar {
+    let lock = kimi_lock().lock().unwrap();
+    let prev = std::env::var("KIMI_BIN").ok();
+    unsafe {
+        std::env::set_var("KIMI_BIN", path);
+    }
+    ScopedEnvVar {
+        _lock: lock,
+        key: "KIMI_BIN",
+        prev,
+    }
+}
+
 pub fn example_config_path() -> PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR"))
         .join("..")
@@ -52,6 +52,11 @@ fn render_args_for_role(cfg_path: &Path, repo: &Path, role: &str, prompt: &str)
         capabilities: rp.profile.capabilities.clone(),
         fallback_error_patterns: Vec::new(),
         timeout_secs: 5,
+        n: None,
+        client_id: None,
+        env: rp.profile.env.clone(),
+        progress: None,
+        include_directories: Vec::new(),
     })
     .unwrap()
 }
@@ -89,9 +94,25 @@ async fn run_role(
                 timeout_secs: Some(300),
                 contract: None,
                 validate_patch: false,
+                strict_citations: false,
+                contract_output: None,
+                output_parser: None,
+                canonicalize_cd: None,
+                extract_code_lang: None,
+                n: None,
                 client: None,
 
                 conversation_id: None,
+                participant: None,
+                session_group: None,
+                ephemeral: false,
+                dry_run: false,
+                persona_override: None,
+                stream: false,
+                prompt_file: None,
+                prompt_transport: None,
+                include_directories: None,
+                cache: false,
             },
         )
         .await